@@ -15,12 +15,7 @@ use crate::state::{self, AppState};
 // ── Helper: build InferenceConfig from keeper Config ────────────────
 
 fn inference_config_from(cfg: &Config) -> InferenceConfig {
-    InferenceConfig {
-        api_key: cfg.openai_api_key.clone().unwrap_or_default(),
-        api_url: cfg.inference_url.clone(),
-        model: cfg.inference_model.clone(),
-        ..Default::default()
-    }
+    cfg.inference_config()
 }
 
 // ── IPC Commands ────────────────────────────────────────────────────
@@ -42,7 +37,7 @@ pub async fn execute_shell(
         name: "shell_exec".to_string(),
         arguments: serde_json::json!({ "command": command }),
     };
-    let result = executor::execute_tool(&data_dir, &sid, &tool_call).await;
+    let result = executor::execute_tool(&data_dir, &sid, &tool_call, executor::DEFAULT_MAX_OUTPUT_BYTES).await;
     if result.success {
         Ok(result.output)
     } else {
@@ -67,7 +62,7 @@ pub async fn read_file(
         name: "file_read".to_string(),
         arguments: serde_json::json!({ "path": path }),
     };
-    let result = executor::execute_tool(&data_dir, &sid, &tool_call).await;
+    let result = executor::execute_tool(&data_dir, &sid, &tool_call, executor::DEFAULT_MAX_OUTPUT_BYTES).await;
     if result.success {
         Ok(result.output)
     } else {
@@ -93,7 +88,7 @@ pub async fn write_file(
         name: "file_write".to_string(),
         arguments: serde_json::json!({ "path": path, "content": content }),
     };
-    let result = executor::execute_tool(&data_dir, &sid, &tool_call).await;
+    let result = executor::execute_tool(&data_dir, &sid, &tool_call, executor::DEFAULT_MAX_OUTPUT_BYTES).await;
     if result.success {
         Ok(result.output)
     } else {
@@ -5,13 +5,8 @@ mod commands;
 mod state;
 
 fn main() {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive("siphon_keeper=info".parse().unwrap())
-                .add_directive("siphon_desktop=info".parse().unwrap()),
-        )
-        .init();
+    let (verbose, quiet) = siphon_keeper::logging::flags_from_args(std::env::args().skip(1));
+    siphon_keeper::logging::init(&["siphon_keeper", "siphon_desktop"], verbose, quiet);
 
     let app_state = state::AppState::load();
 
@@ -1,8 +1,13 @@
+use futures::future::join_all;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::time::Instant;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Semaphore;
 
+use crate::db;
 use crate::executor;
-use crate::inference::{self, ChatMessage, InferenceConfig, InferenceResult, ToolDefinition};
+use crate::inference::{self, ChatMessage, InferenceConfig, InferenceResult, ToolCall, ToolDefinition};
 
 // ── Types ────────────────────────────────────────────────────────────
 
@@ -10,6 +15,127 @@ use crate::inference::{self, ChatMessage, InferenceConfig, InferenceResult, Tool
 pub struct AgentLoopConfig {
     pub max_turns: u32,
     pub turn_timeout_secs: u64,
+    /// Output cap for any tool without a per-tool override
+    #[serde(default = "default_max_output_bytes")]
+    pub max_output_bytes: usize,
+    /// Per-tool output caps, keyed by tool name
+    #[serde(default)]
+    pub tool_output_limits: HashMap<String, usize>,
+    /// Cap on the combined output of all tool calls in one run
+    #[serde(default = "default_max_total_output_bytes")]
+    pub max_total_output_bytes: usize,
+    /// Extra `code_eval` languages on top of the built-ins
+    #[serde(default)]
+    pub code_languages: HashMap<String, executor::CodeInterpreter>,
+    /// How sampling temperature changes from turn to turn
+    #[serde(default)]
+    pub temperature_schedule: TemperatureSchedule,
+    /// Required shape of the final answer; `Json` answers are validated
+    #[serde(default)]
+    pub response_format: Option<ResponseFormat>,
+    /// Most read-only tool calls from one turn run at once (0 is treated as 1)
+    #[serde(default = "default_max_parallel_tools")]
+    pub max_parallel_tools: usize,
+    /// Cap on the shard workspace's size enforced by `file_write` (0 = none)
+    #[serde(default = "default_workspace_quota_bytes")]
+    pub workspace_quota_bytes: u64,
+    /// How `recall_lessons` ranks the shard's lessons
+    #[serde(default = "default_prefilter_weights")]
+    pub prefilter_weights: db::RetrievalWeights,
+}
+
+/// Output format requested for a run's final response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Markdown,
+    Plain,
+    Json,
+}
+
+impl ResponseFormat {
+    /// System-prompt instruction describing the format.
+    pub fn instruction(self) -> &'static str {
+        match self {
+            Self::Markdown => "Format your final answer as Markdown.",
+            Self::Plain => {
+                "Format your final answer as plain text, without Markdown headings, lists, \
+                 emphasis, or code fences."
+            }
+            Self::Json => {
+                "Your final answer must be a single valid JSON value and nothing else: \
+                 no prose and no code fences."
+            }
+        }
+    }
+}
+
+/// The JSON document in `text`, tolerating a surrounding code fence. None
+/// if it does not parse.
+pub fn extract_json(text: &str) -> Option<String> {
+    let trimmed = text.trim();
+    let body = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .unwrap_or(trimmed)
+        .trim();
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .map(|_| body.to_string())
+}
+
+/// Per-turn sampling temperature, letting a run explore early and converge
+/// later.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TemperatureSchedule {
+    /// Every turn uses the inference config's temperature
+    #[default]
+    Constant,
+    /// Moves linearly from `start` on turn 1 to `end` on the last turn
+    Linear { start: f64, end: f64 },
+    /// `start * factor^(turn - 1)`, never below `min`
+    Exponential { start: f64, factor: f64, min: f64 },
+}
+
+impl TemperatureSchedule {
+    /// Temperature for `turn` (1-based) of a run capped at `max_turns`.
+    pub fn temperature_for(&self, base: f64, turn: u32, max_turns: u32) -> f64 {
+        match *self {
+            Self::Constant => base,
+            Self::Linear { start, end } => {
+                if max_turns <= 1 {
+                    return start;
+                }
+                let progress = (turn.clamp(1, max_turns) - 1) as f64 / (max_turns - 1) as f64;
+                start + (end - start) * progress
+            }
+            Self::Exponential { start, factor, min } => {
+                (start * factor.powi(turn.saturating_sub(1) as i32)).max(min)
+            }
+        }
+    }
+}
+
+fn default_max_output_bytes() -> usize {
+    executor::DEFAULT_MAX_OUTPUT_BYTES
+}
+
+fn default_max_total_output_bytes() -> usize {
+    200_000
+}
+
+fn default_max_parallel_tools() -> usize {
+    4
+}
+
+fn default_workspace_quota_bytes() -> u64 {
+    executor::DEFAULT_WORKSPACE_QUOTA_BYTES
+}
+
+fn default_prefilter_weights() -> db::RetrievalWeights {
+    db::RetrievalWeights::PREFILTER
 }
 
 impl Default for AgentLoopConfig {
@@ -17,16 +143,42 @@ impl Default for AgentLoopConfig {
         Self {
             max_turns: 5,
             turn_timeout_secs: 60,
+            max_output_bytes: default_max_output_bytes(),
+            tool_output_limits: HashMap::new(),
+            max_total_output_bytes: default_max_total_output_bytes(),
+            code_languages: HashMap::new(),
+            temperature_schedule: TemperatureSchedule::Constant,
+            response_format: None,
+            max_parallel_tools: default_max_parallel_tools(),
+            workspace_quota_bytes: default_workspace_quota_bytes(),
+            prefilter_weights: default_prefilter_weights(),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+impl AgentLoopConfig {
+    /// Output cap for a single call of `tool_name`.
+    pub fn output_limit_for(&self, tool_name: &str) -> usize {
+        self.tool_output_limits
+            .get(tool_name)
+            .copied()
+            .unwrap_or(self.max_output_bytes)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Turn {
     pub turn_number: u32,
     pub inference_result: InferenceResult,
     pub tool_results: Vec<executor::ToolResult>,
+    /// Total wall-clock time for the turn (inference + tools)
     pub duration_ms: u64,
+    /// Time spent waiting on the inference call
+    #[serde(default)]
+    pub inference_ms: u64,
+    /// Sum of the per-tool durations in `tool_results`
+    #[serde(default)]
+    pub tool_ms: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -35,6 +187,21 @@ pub enum StopReason {
     MaxTurns,
     TurnTimeout,
     InferenceError,
+    /// The final answer did not match the requested `ResponseFormat`
+    InvalidResponse,
+    /// The model replied with neither content nor tool calls, even after a
+    /// nudge
+    EmptyResponse,
+    /// The model called `ask_user`; continue the conversation with the
+    /// answer as that call's tool result
+    WaitingForInput,
+}
+
+/// An `ask_user` call awaiting the keeper's answer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingQuestion {
+    pub call_id: String,
+    pub question: String,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -45,6 +212,106 @@ pub struct AgentLoopResult {
     pub all_tool_results: Vec<executor::ToolResult>,
     pub all_success: bool,
     pub stop_reason: StopReason,
+    /// Whether any tool output was cut by a per-tool or per-run cap
+    pub output_truncated: bool,
+    /// Whether `final_response` was synthesized from tool output because the
+    /// run stopped early on an inference error or timeout
+    pub partial: bool,
+    /// Every message exchanged, ending with the final answer when there is
+    /// one; pass it to `run_agent_loop_from` to continue the run
+    #[serde(skip)]
+    pub conversation: Vec<ChatMessage>,
+    /// Set when the run stopped with `WaitingForInput`
+    pub question: Option<PendingQuestion>,
+}
+
+/// Most recent successful tool outputs kept in a partial response.
+const PARTIAL_TOOL_RESULTS: usize = 3;
+
+/// Characters kept from each tool output in a partial response.
+const PARTIAL_OUTPUT_CHARS: usize = 500;
+
+/// Best-effort answer for a run cut short by an inference error or timeout,
+/// built from its last successful tool outputs. None for other stop reasons
+/// or when no tool succeeded.
+pub fn partial_response(stop_reason: &StopReason, results: &[executor::ToolResult]) -> Option<String> {
+    if !matches!(stop_reason, StopReason::InferenceError | StopReason::TurnTimeout) {
+        return None;
+    }
+    let recent: Vec<&executor::ToolResult> = results
+        .iter()
+        .rev()
+        .filter(|r| r.success && !r.output.trim().is_empty())
+        .take(PARTIAL_TOOL_RESULTS)
+        .collect();
+    if recent.is_empty() {
+        return None;
+    }
+
+    let mut response = format!(
+        "Run stopped early ({:?}). Partial results from completed tool calls:",
+        stop_reason
+    );
+    for result in recent.into_iter().rev() {
+        let output = match executor::BinaryEnvelope::parse(&result.output) {
+            Some(envelope) => envelope.describe(),
+            None => {
+                let trimmed = result.output.trim();
+                match trimmed.char_indices().nth(PARTIAL_OUTPUT_CHARS) {
+                    Some((cut, _)) => format!("{}...", &trimmed[..cut]),
+                    None => trimmed.to_string(),
+                }
+            }
+        };
+        response.push_str(&format!("\n[{}] {}", result.tool_name, output));
+    }
+    Some(response)
+}
+
+/// Sent after a reply with no content and no tool calls.
+const EMPTY_RESPONSE_NUDGE: &str =
+    "Your last reply was empty. Continue the task: call a tool or give your final answer.";
+
+/// Keep `turn` and pass a copy to the sink; a closed sink is ignored.
+fn record_turn(turns: &mut Vec<Turn>, sink: Option<&UnboundedSender<Turn>>, turn: Turn) {
+    if let Some(sink) = sink {
+        let _ = sink.send(turn.clone());
+    }
+    turns.push(turn);
+}
+
+/// Run one tool call from the model, capping its output at `limit` bytes.
+async fn run_tool_call(
+    call: &ToolCall,
+    inference_config: &InferenceConfig,
+    loop_config: &AgentLoopConfig,
+    data_dir: &str,
+    shard_id: &str,
+    limit: usize,
+) -> executor::ToolResult {
+    if call.name == executor::BRAINSTORM_TOOL {
+        executor::execute_brainstorm(call, inference_config, limit).await
+    } else if call.name == executor::ASK_USER_TOOL {
+        executor::ToolResult {
+            tool_call_id: call.id.clone(),
+            tool_name: call.name.clone(),
+            success: false,
+            output: "Only one question can be asked at a time".to_string(),
+            duration_ms: 0,
+            truncated: false,
+        }
+    } else {
+        executor::execute_tool_with_languages(
+            data_dir,
+            shard_id,
+            call,
+            limit,
+            &loop_config.code_languages,
+            loop_config.workspace_quota_bytes,
+            &loop_config.prefilter_weights,
+        )
+        .await
+    }
 }
 
 // ── Core loop ────────────────────────────────────────────────────────
@@ -59,19 +326,58 @@ pub async fn run_agent_loop(
     data_dir: &str,
     shard_id: &str,
 ) -> AgentLoopResult {
-    let mut conversation = vec![ChatMessage::text("user", initial_message)];
+    run_agent_loop_from(
+        inference_config,
+        system_prompt,
+        vec![ChatMessage::text("user", initial_message)],
+        tools,
+        loop_config,
+        data_dir,
+        shard_id,
+        None,
+    )
+    .await
+}
+
+/// Like `run_agent_loop`, continuing from an earlier `conversation` (which
+/// should end with a user or tool message). Turn numbers restart at 1. Each
+/// finished turn is also sent to `turn_sink`, for streaming progress.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_agent_loop_from(
+    inference_config: &InferenceConfig,
+    system_prompt: &str,
+    mut conversation: Vec<ChatMessage>,
+    tools: &[ToolDefinition],
+    loop_config: &AgentLoopConfig,
+    data_dir: &str,
+    shard_id: &str,
+    turn_sink: Option<&UnboundedSender<Turn>>,
+) -> AgentLoopResult {
     let mut turns = Vec::new();
     let mut all_tool_results = Vec::new();
     let mut all_success = true;
     let mut final_response = None;
     let mut stop_reason = StopReason::MaxTurns;
+    let mut output_budget = loop_config.max_total_output_bytes;
+    let mut format_retry_used = false;
+    let mut empty_retry_used = false;
+    let mut question = None;
 
     for turn_number in 1..=loop_config.max_turns {
         let turn_start = Instant::now();
 
+        let turn_config = InferenceConfig {
+            temperature: loop_config.temperature_schedule.temperature_for(
+                inference_config.temperature,
+                turn_number,
+                loop_config.max_turns,
+            ),
+            ..inference_config.clone()
+        };
+
         // Apply per-turn timeout
         let inference_future =
-            inference::generate_with_tools(inference_config, system_prompt, &conversation, tools);
+            inference::generate_with_tools(&turn_config, system_prompt, &conversation, tools);
 
         let result = tokio::time::timeout(
             std::time::Duration::from_secs(loop_config.turn_timeout_secs),
@@ -79,7 +385,7 @@ pub async fn run_agent_loop(
         )
         .await;
 
-        let inference_result = match result {
+        let mut inference_result = match result {
             Ok(Ok(r)) => r,
             Ok(Err(e)) => {
                 tracing::warn!("Agent loop inference error on turn {}: {}", turn_number, e);
@@ -93,16 +399,70 @@ pub async fn run_agent_loop(
             }
         };
 
-        let duration_ms = turn_start.elapsed().as_millis() as u64;
+        let inference_ms = turn_start.elapsed().as_millis() as u64;
+
+        // Some providers reject a conversation whose tool results reference
+        // blank or ambiguous call ids
+        if let InferenceResult::ToolCalls { calls } = &mut inference_result {
+            inference::assign_unique_tool_call_ids(calls);
+        }
 
         match inference_result {
             InferenceResult::Text { ref content } => {
-                final_response = Some(content.clone());
-                turns.push(Turn {
+                // Some providers occasionally reply with neither content nor
+                // tool calls; that is not an answer
+                let empty = content.trim().is_empty();
+                let answer = match loop_config.response_format {
+                    _ if empty => None,
+                    Some(ResponseFormat::Json) => extract_json(content),
+                    _ => Some(content.clone()),
+                };
+                let Some(answer) = answer else {
+                    // One corrective turn per kind of unusable answer
+                    let retry_used = if empty { &mut empty_retry_used } else { &mut format_retry_used };
+                    let retry = !*retry_used && turn_number < loop_config.max_turns;
+                    if retry {
+                        *retry_used = true;
+                        if empty {
+                            conversation.push(ChatMessage::text("user", EMPTY_RESPONSE_NUDGE));
+                        } else {
+                            conversation.push(ChatMessage::text("assistant", content));
+                            conversation.push(ChatMessage::text(
+                                "user",
+                                "That answer was not valid JSON. Reply again with only the JSON value.",
+                            ));
+                        }
+                    }
+                    record_turn(&mut turns, turn_sink, Turn {
+                        turn_number,
+                        inference_result,
+                        tool_results: vec![],
+                        duration_ms: turn_start.elapsed().as_millis() as u64,
+                        inference_ms,
+                        tool_ms: 0,
+                    });
+                    if retry {
+                        continue;
+                    }
+                    all_success = false;
+                    stop_reason = if empty {
+                        tracing::warn!("Agent loop got an empty reply on turn {}", turn_number);
+                        StopReason::EmptyResponse
+                    } else {
+                        tracing::warn!("Agent loop final answer is not valid JSON");
+                        StopReason::InvalidResponse
+                    };
+                    break;
+                };
+                conversation.push(ChatMessage::text("assistant", content));
+                final_response = Some(answer);
+                record_turn(&mut turns, turn_sink, Turn {
                     turn_number,
                     inference_result,
                     tool_results: vec![],
-                    duration_ms,
+                    duration_ms: turn_start.elapsed().as_millis() as u64,
+                    inference_ms,
+                    tool_ms: 0,
                 });
                 stop_reason = StopReason::Completed;
                 break;
@@ -111,46 +471,133 @@ pub async fn run_agent_loop(
                 // Append assistant message with tool calls to conversation
                 conversation.push(ChatMessage::assistant_tool_calls(calls));
 
-                // Execute each tool and append results
-                let mut turn_results = Vec::new();
+                // The first question ends the run once this turn's other
+                // calls are done; its tool result is the answer
+                let mut runnable = Vec::with_capacity(calls.len());
                 for call in calls {
-                    let result = executor::execute_tool(data_dir, shard_id, call).await;
-                    conversation.push(ChatMessage::tool_result(
-                        &call.id,
-                        &call.name,
-                        &result.output,
-                    ));
+                    if call.name == executor::ASK_USER_TOOL && question.is_none() {
+                        question = Some(PendingQuestion {
+                            call_id: call.id.clone(),
+                            question: call.arguments["question"].as_str().unwrap_or_default().to_string(),
+                        });
+                    } else {
+                        runnable.push(call);
+                    }
+                }
+
+                // Runs of consecutive read-only calls go concurrently (join_all
+                // keeps them in call order); any other call runs on its own,
+                // so writes and commands happen in the order they were made
+                let permits = Semaphore::new(loop_config.max_parallel_tools.max(1));
+                let budget = output_budget;
+                let mut results = Vec::with_capacity(runnable.len());
+                let mut pending = runnable.as_slice();
+                while let Some(first) = pending.first() {
+                    let batch = if executor::READ_ONLY_TOOLS.contains(&first.name.as_str()) {
+                        pending
+                            .iter()
+                            .take_while(|call| executor::READ_ONLY_TOOLS.contains(&call.name.as_str()))
+                            .count()
+                    } else {
+                        1
+                    };
+                    let (batch, rest) = pending.split_at(batch);
+                    results.extend(
+                        join_all(batch.iter().map(|call| {
+                            let permits = &permits;
+                            async move {
+                                let _permit = permits.acquire().await.expect("semaphore is never closed");
+                                let limit = loop_config.output_limit_for(&call.name).min(budget);
+                                run_tool_call(call, inference_config, loop_config, data_dir, shard_id, limit).await
+                            }
+                        }))
+                        .await,
+                    );
+                    pending = rest;
+                }
+
+                // Images follow the turn's last tool result: providers expect
+                // every tool reply right after the assistant's tool calls
+                let mut images = Vec::new();
+                let mut turn_results = Vec::with_capacity(results.len());
+                for (call, mut result) in runnable.into_iter().zip(results) {
+                    // The run-wide budget is spent in call order, as if the
+                    // calls had run one after another
+                    if output_budget < budget && executor::truncate_output(&mut result.output, output_budget) {
+                        result.truncated = true;
+                    }
+                    output_budget = output_budget.saturating_sub(result.output.len());
+                    match executor::BinaryEnvelope::parse(&result.output) {
+                        Some(envelope) => {
+                            let summary = envelope.describe();
+                            conversation.push(ChatMessage::tool_result(&call.id, &call.name, &summary));
+                            if let (true, true, Some(url)) = (
+                                inference_config.supports_vision,
+                                envelope.is_image(),
+                                envelope.data_url(),
+                            ) {
+                                images.push(ChatMessage::user_image(
+                                    &format!("Image returned by {} ({})", call.name, call.id),
+                                    &url,
+                                ));
+                            }
+                        }
+                        None => conversation.push(ChatMessage::tool_result(
+                            &call.id,
+                            &call.name,
+                            &result.output,
+                        )),
+                    }
                     if !result.success {
                         all_success = false;
                     }
                     turn_results.push(result);
                 }
+                conversation.extend(images);
 
                 all_tool_results.extend(turn_results.clone());
+                let tool_ms = turn_results.iter().map(|r| r.duration_ms).sum();
 
-                turns.push(Turn {
+                record_turn(&mut turns, turn_sink, Turn {
                     turn_number,
                     inference_result,
                     tool_results: turn_results,
-                    duration_ms,
+                    duration_ms: turn_start.elapsed().as_millis() as u64,
+                    inference_ms,
+                    tool_ms,
                 });
+                if question.is_some() {
+                    stop_reason = StopReason::WaitingForInput;
+                    break;
+                }
             }
         }
     }
 
+    let partial = final_response.is_none();
+    let final_response = final_response.or_else(|| partial_response(&stop_reason, &all_tool_results));
+    let partial = partial && final_response.is_some();
+
     AgentLoopResult {
         turns,
         final_response,
+        partial,
         total_tool_calls: all_tool_results.len(),
+        output_truncated: all_tool_results.iter().any(|r| r.truncated),
         all_tool_results,
         all_success,
         stop_reason,
+        conversation,
+        question,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::{
+        mock_inference_sequence, serve, text_reply, tool_call, tool_call_reply, tool_calls_reply,
+    };
 
     #[test]
     fn agent_loop_config_defaults() {
@@ -159,6 +606,31 @@ mod tests {
         assert_eq!(cfg.turn_timeout_secs, 60);
     }
 
+    #[test]
+    fn temperature_schedule_per_turn() {
+        let constant = TemperatureSchedule::default();
+        assert_eq!(constant.temperature_for(0.3, 4, 5), 0.3);
+
+        let linear = TemperatureSchedule::Linear { start: 0.7, end: 0.1 };
+        let temps: Vec<f64> = (1..=4).map(|t| linear.temperature_for(0.3, t, 4)).collect();
+        for (got, want) in temps.iter().zip([0.7, 0.5, 0.3, 0.1]) {
+            assert!((got - want).abs() < 1e-9, "{:?}", temps);
+        }
+        assert_eq!(linear.temperature_for(0.3, 1, 1), 0.7);
+
+        let exp = TemperatureSchedule::Exponential { start: 0.8, factor: 0.5, min: 0.15 };
+        let temps: Vec<f64> = (1..=4).map(|t| exp.temperature_for(0.3, t, 4)).collect();
+        assert_eq!(temps, vec![0.8, 0.4, 0.2, 0.15]);
+
+        let parsed: AgentLoopConfig = serde_json::from_value(serde_json::json!({
+            "max_turns": 3,
+            "turn_timeout_secs": 10,
+            "temperature_schedule": {"kind": "linear", "start": 0.9, "end": 0.1}
+        }))
+        .unwrap();
+        assert_eq!(parsed.temperature_schedule, TemperatureSchedule::Linear { start: 0.9, end: 0.1 });
+    }
+
     #[test]
     fn stop_reason_serialization() {
         let sr = StopReason::Completed;
@@ -178,6 +650,8 @@ mod tests {
             },
             tool_results: vec![],
             duration_ms: 42,
+            inference_ms: 40,
+            tool_ms: 0,
         };
         let json = serde_json::to_string(&turn).unwrap();
         assert!(json.contains("\"turn_number\":1"));
@@ -193,9 +667,405 @@ mod tests {
             all_tool_results: vec![],
             all_success: true,
             stop_reason: StopReason::MaxTurns,
+            output_truncated: false,
+            partial: false,
+            conversation: vec![],
+            question: None,
         };
         let json = serde_json::to_string(&result).unwrap();
         assert!(json.contains("\"stop_reason\":\"MaxTurns\""));
         assert!(json.contains("\"total_tool_calls\":0"));
     }
+
+    #[tokio::test]
+    async fn turn_timing_splits_inference_and_tools() {
+        let tool_reply = tool_call_reply("call_1", "shell_exec", "{\"command\":\"sleep 0.1\"}");
+        let url = mock_inference_sequence(vec![tool_reply, text_reply("done")], 50).await.0;
+        let config = InferenceConfig {
+            api_url: url,
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+
+        let result = run_agent_loop(
+            &config,
+            "system",
+            "run it",
+            &inference::shard_tool_definitions(),
+            &AgentLoopConfig::default(),
+            &dir.path().to_string_lossy(),
+            "timing-shard",
+        )
+        .await;
+
+        assert_eq!(result.stop_reason, StopReason::Completed);
+        assert_eq!(result.turns.len(), 2);
+
+        let first = &result.turns[0];
+        assert!(first.inference_ms >= 50);
+        assert_eq!(first.tool_results.len(), 1);
+        assert!(first.tool_results[0].duration_ms >= 100);
+        assert_eq!(first.tool_ms, first.tool_results[0].duration_ms);
+
+        assert_eq!(result.turns[1].tool_ms, 0);
+
+        // Both parts are timed within the turn, so together they never exceed it
+        for turn in &result.turns {
+            assert!(turn.inference_ms + turn.tool_ms <= turn.duration_ms, "turn {:?}", turn);
+        }
+    }
+
+    /// Serve `GET /{name}` replying with the name once `barrier` requests are
+    /// in flight together (or after a second). Also returns the most requests
+    /// seen in flight at once.
+    async fn mock_fetch(barrier: usize) -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let barrier = Arc::new(tokio::sync::Barrier::new(barrier));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let max_seen = max_in_flight.clone();
+        let app = axum::Router::new().route(
+            "/{name}",
+            axum::routing::get(move |axum::extract::Path(name): axum::extract::Path<String>| {
+                let (barrier, in_flight, max_in_flight) = (barrier.clone(), in_flight.clone(), max_in_flight.clone());
+                async move {
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::timeout(std::time::Duration::from_secs(1), barrier.wait()).await.ok();
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    name
+                }
+            }),
+        );
+        (serve(app).await, max_seen)
+    }
+
+    #[tokio::test]
+    async fn parallel_tool_calls_keep_call_order() {
+        let (site, max_in_flight) = mock_fetch(3).await;
+        let fetch = |id: &str, page: &str| tool_call(id, "http_fetch", serde_json::json!({ "url": format!("{}/{}", site, page) }));
+        let tool_reply = tool_calls_reply(vec![fetch("call_a", "alpha"), fetch("call_b", "beta"), fetch("call_c", "gamma")]);
+        let url = mock_inference_sequence(vec![tool_reply, text_reply("done")], 0).await.0;
+        let config = InferenceConfig {
+            api_url: url,
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+
+        let result = run_agent_loop(
+            &config,
+            "system",
+            "run them",
+            &inference::shard_tool_definitions(),
+            &AgentLoopConfig::default(),
+            &dir.path().to_string_lossy(),
+            "parallel-shard",
+        )
+        .await;
+
+        assert_eq!(result.stop_reason, StopReason::Completed);
+        let first = &result.turns[0];
+        let ids: Vec<&str> = first.tool_results.iter().map(|r| r.tool_call_id.as_str()).collect();
+        assert_eq!(ids, vec!["call_a", "call_b", "call_c"]);
+        assert!(first.tool_results.iter().all(|r| r.success), "{:?}", first.tool_results);
+        let outputs: Vec<&str> = first.tool_results.iter().map(|r| r.output.as_str()).collect();
+        assert_eq!(outputs, vec!["alpha", "beta", "gamma"]);
+        assert_eq!(max_in_flight.load(std::sync::atomic::Ordering::SeqCst), 3, "fetches did not overlap");
+
+        let tool_messages: Vec<&ChatMessage> = result.conversation.iter().filter(|m| m.role == "tool").collect();
+        let message_ids: Vec<&str> = tool_messages.iter().map(|m| m.tool_call_id.as_deref().unwrap()).collect();
+        assert_eq!(message_ids, ids);
+        assert_eq!(tool_messages[1].content.as_deref(), Some("beta"));
+    }
+
+    #[tokio::test]
+    async fn slow_tools_overlap_and_keep_call_order() {
+        // `GET /{ms}` answers with its path after sleeping that long
+        let site = serve(axum::Router::new().route(
+            "/{ms}",
+            axum::routing::get(|axum::extract::Path(ms): axum::extract::Path<u64>| async move {
+                tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
+                ms.to_string()
+            }),
+        ))
+        .await;
+        let fetch = |id: &str, ms: u64| tool_call(id, "http_fetch", serde_json::json!({ "url": format!("{}/{}", site, ms) }));
+        // The first call finishes last
+        let tool_reply = tool_calls_reply(vec![fetch("call_slow", 1000), fetch("call_fast", 500)]);
+        let url = mock_inference_sequence(vec![tool_reply, text_reply("done")], 0).await.0;
+        let config = InferenceConfig {
+            api_url: url,
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+
+        let result = run_agent_loop(
+            &config,
+            "system",
+            "run both",
+            &inference::shard_tool_definitions(),
+            &AgentLoopConfig::default(),
+            &dir.path().to_string_lossy(),
+            "overlap-shard",
+        )
+        .await;
+
+        assert_eq!(result.stop_reason, StopReason::Completed);
+        let first = &result.turns[0];
+        let ids: Vec<&str> = first.tool_results.iter().map(|r| r.tool_call_id.as_str()).collect();
+        assert_eq!(ids, vec!["call_slow", "call_fast"]);
+        let outputs: Vec<&str> = first.tool_results.iter().map(|r| r.output.trim()).collect();
+        assert_eq!(outputs, vec!["1000", "500"]);
+        assert!(first.tool_results[0].duration_ms >= 1000, "{:?}", first.tool_results);
+        assert!(first.tool_results[1].duration_ms < first.tool_results[0].duration_ms);
+        // Run one after the other the tool phase would take at least the sum
+        let sum: u64 = first.tool_results.iter().map(|r| r.duration_ms).sum();
+        let tool_phase = first.duration_ms - first.inference_ms;
+        assert!(tool_phase < sum, "tool phase took {}ms for {}ms of tools", tool_phase, sum);
+    }
+
+    #[tokio::test]
+    async fn max_parallel_tools_bounds_concurrency() {
+        let (site, max_in_flight) = mock_fetch(2).await;
+        let calls = (0..4)
+            .map(|i| {
+                tool_call(
+                    &format!("call_{}", i),
+                    "http_fetch",
+                    serde_json::json!({ "url": format!("{}/page{}", site, i) }),
+                )
+            })
+            .collect();
+        let url = mock_inference_sequence(vec![tool_calls_reply(calls), text_reply("done")], 0).await.0;
+        let config = InferenceConfig {
+            api_url: url,
+            ..Default::default()
+        };
+        let loop_config = AgentLoopConfig {
+            max_parallel_tools: 2,
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+
+        let result = run_agent_loop(
+            &config,
+            "system",
+            "run them",
+            &inference::shard_tool_definitions(),
+            &loop_config,
+            &dir.path().to_string_lossy(),
+            "bounded-shard",
+        )
+        .await;
+
+        assert_eq!(result.turns[0].tool_results.len(), 4);
+        assert_eq!(max_in_flight.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn mutating_tool_calls_run_in_call_order() {
+        let write = |id: &str, content: &str| {
+            tool_call(id, "file_write", serde_json::json!({ "path": "note.txt", "content": content }))
+        };
+        let read = |id: &str| tool_call(id, "file_read", serde_json::json!({ "path": "note.txt" }));
+        let tool_reply = tool_calls_reply(vec![
+            write("call_1", "one"),
+            read("call_2"),
+            write("call_3", "two"),
+            read("call_4"),
+        ]);
+        let url = mock_inference_sequence(vec![tool_reply, text_reply("done")], 0).await.0;
+        let config = InferenceConfig {
+            api_url: url,
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+
+        let result = run_agent_loop(
+            &config,
+            "system",
+            "write then read",
+            &inference::shard_tool_definitions(),
+            &AgentLoopConfig::default(),
+            &dir.path().to_string_lossy(),
+            "ordered-shard",
+        )
+        .await;
+
+        let results = &result.turns[0].tool_results;
+        assert!(results.iter().all(|r| r.success), "{:?}", results);
+        assert_eq!(results[1].output, "one");
+        assert_eq!(results[3].output, "two");
+    }
+
+    #[tokio::test]
+    async fn images_follow_every_tool_result_of_the_turn() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        let workspace = dir.path().join("workspaces").join("vision-shard");
+        std::fs::create_dir_all(&workspace).unwrap();
+        std::fs::write(workspace.join("chart.png"), b"\x89PNG\r\n\x1a\n\xff\xfe").unwrap();
+        std::fs::write(workspace.join("note.txt"), "caption").unwrap();
+
+        let read = |id: &str, path: &str| tool_call(id, "file_read", serde_json::json!({ "path": path }));
+        let tool_reply = tool_calls_reply(vec![read("call_1", "chart.png"), read("call_2", "note.txt")]);
+        let url = mock_inference_sequence(vec![tool_reply, text_reply("a chart")], 0).await.0;
+        let config = InferenceConfig {
+            api_url: url,
+            supports_vision: true,
+            ..Default::default()
+        };
+
+        let result = run_agent_loop(
+            &config,
+            "system",
+            "describe it",
+            &inference::shard_tool_definitions(),
+            &AgentLoopConfig::default(),
+            &data_dir,
+            "vision-shard",
+        )
+        .await;
+
+        assert_eq!(result.stop_reason, StopReason::Completed);
+        let roles: Vec<&str> = result.conversation.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["user", "assistant", "tool", "tool", "user", "assistant"]);
+        assert!(result.conversation[4].parts.is_some());
+    }
+
+    #[tokio::test]
+    async fn inference_error_surfaces_partial_result() {
+        let tool_reply = tool_call_reply("call_1", "shell_exec", "{\"command\":\"echo 42\"}");
+        let error_reply = serde_json::json!({"error": {"message": "upstream overloaded"}});
+        let url = mock_inference_sequence(vec![tool_reply, error_reply], 0).await.0;
+        let config = InferenceConfig {
+            api_url: url,
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+
+        let result = run_agent_loop(
+            &config,
+            "system",
+            "compute it",
+            &inference::shard_tool_definitions(),
+            &AgentLoopConfig::default(),
+            &dir.path().to_string_lossy(),
+            "partial-shard",
+        )
+        .await;
+
+        assert_eq!(result.stop_reason, StopReason::InferenceError);
+        assert!(result.partial);
+        let response = result.final_response.unwrap();
+        assert!(response.starts_with("Run stopped early (InferenceError)"));
+        assert!(response.contains("[shell_exec] 42"));
+
+        assert!(partial_response(&StopReason::MaxTurns, &result.all_tool_results).is_none());
+        assert!(partial_response(&StopReason::TurnTimeout, &[]).is_none());
+    }
+
+    #[tokio::test]
+    async fn json_format_sets_flag_and_rejects_non_json() {
+        let (url, bodies) =
+            mock_inference_sequence(vec![text_reply("Sure! The answer is 42."), text_reply("```json\n{\"answer\": 42}\n```")], 0)
+                .await;
+        let config = InferenceConfig {
+            api_url: url,
+            json_mode: true,
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        let loop_config = AgentLoopConfig {
+            response_format: Some(ResponseFormat::Json),
+            ..Default::default()
+        };
+
+        // Invalid first answer gets one retry; the fenced JSON is accepted
+        let result = run_agent_loop(&config, "system", "answer", &[], &loop_config, &data_dir, "json-shard").await;
+        assert_eq!(result.stop_reason, StopReason::Completed);
+        assert_eq!(result.turns.len(), 2);
+        assert_eq!(result.final_response.as_deref(), Some("{\"answer\": 42}"));
+        {
+            let bodies = bodies.lock().unwrap();
+            assert_eq!(bodies[0]["response_format"]["type"], "json_object");
+            let retry_messages = bodies[1]["messages"].as_array().unwrap();
+            assert!(retry_messages.last().unwrap()["content"].as_str().unwrap().contains("not valid JSON"));
+        }
+
+        // A second invalid answer ends the run without a final response
+        let url = mock_inference_sequence(vec![text_reply("still not json"), text_reply("nor this")], 0).await.0;
+        let config = InferenceConfig { api_url: url, ..config };
+        let result = run_agent_loop(&config, "system", "answer", &[], &loop_config, &data_dir, "json-shard").await;
+        assert_eq!(result.stop_reason, StopReason::InvalidResponse);
+        assert!(result.final_response.is_none());
+        assert!(!result.all_success);
+        assert_eq!(result.turns.len(), 2);
+
+        assert!(extract_json("[1, 2]").is_some());
+        assert!(extract_json("{\"a\": }").is_none());
+    }
+
+    #[tokio::test]
+    async fn empty_reply_is_not_a_completed_answer() {
+        let no_content = text_reply("");
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        let loop_config = AgentLoopConfig::default();
+
+        // Empty twice: the nudge doesn't help, so the run stops without an answer
+        let url = mock_inference_sequence(vec![no_content.clone(), text_reply("  ")], 0).await.0;
+        let config = InferenceConfig { api_url: url, ..Default::default() };
+        let result = run_agent_loop(&config, "system", "answer", &[], &loop_config, &data_dir, "empty-shard").await;
+        assert_eq!(result.stop_reason, StopReason::EmptyResponse);
+        assert!(result.final_response.is_none());
+        assert!(!result.all_success);
+        assert_eq!(result.turns.len(), 2);
+
+        // A nudged retry that answers completes normally
+        let url = mock_inference_sequence(vec![no_content, text_reply("42")], 0).await.0;
+        let config = InferenceConfig { api_url: url, ..Default::default() };
+        let result = run_agent_loop(&config, "system", "answer", &[], &loop_config, &data_dir, "empty-shard").await;
+        assert_eq!(result.stop_reason, StopReason::Completed);
+        assert_eq!(result.final_response.as_deref(), Some("42"));
+    }
+
+    #[tokio::test]
+    async fn saved_conversation_resumes_with_prior_context() {
+        let tool_reply = tool_call_reply("call_1", "shell_exec", "{\"command\":\"echo 42\"}");
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        let tools = inference::shard_tool_definitions();
+        let loop_config = AgentLoopConfig::default();
+
+        let url = mock_inference_sequence(vec![tool_reply, text_reply("first answer")], 0).await.0;
+        let config = InferenceConfig { api_url: url, ..Default::default() };
+        let first = run_agent_loop(&config, "system", "compute it", &tools, &loop_config, &data_dir, "resume-shard").await;
+        assert_eq!(first.stop_reason, StopReason::Completed);
+        let roles: Vec<&str> = first.conversation.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["user", "assistant", "tool", "assistant"]);
+
+        let saved = serde_json::to_string(&first.conversation).unwrap();
+        let mut conversation: Vec<ChatMessage> = serde_json::from_str(&saved).unwrap();
+        conversation.push(ChatMessage::text("user", "now double it"));
+
+        let (url, requests) = mock_inference_sequence(vec![text_reply("84")], 0).await;
+        let config = InferenceConfig { api_url: url, ..Default::default() };
+        let resumed =
+            run_agent_loop_from(&config, "system", conversation, &tools, &loop_config, &data_dir, "resume-shard", None)
+                .await;
+        assert_eq!(resumed.stop_reason, StopReason::Completed);
+        assert_eq!(resumed.final_response.as_deref(), Some("84"));
+        assert_eq!(resumed.conversation.len(), 6);
+
+        let requests = requests.lock().unwrap();
+        let sent = requests[0]["messages"].to_string();
+        assert!(sent.contains("compute it"));
+        assert!(sent.contains("call_1"));
+        assert!(sent.contains("first answer"));
+        assert!(sent.contains("now double it"));
+    }
 }
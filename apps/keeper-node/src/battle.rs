@@ -0,0 +1,159 @@
+use serde::Serialize;
+
+use crate::rng::Rng;
+use crate::shard::{self, Shard, ShardType};
+
+/// Rounds needed to win a battle (best of `2 * ROUNDS_TO_WIN - 1`).
+pub const ROUNDS_TO_WIN: u32 = 2;
+
+/// Power multiplier for a shard with the type advantage.
+pub const TYPE_ADVANTAGE: f64 = 1.25;
+
+/// Elo rating a shard's power is measured against.
+const BASE_ELO: f64 = 1200.0;
+
+/// The type each shard type has the advantage over. Every type beats
+/// exactly one other, forming a cycle.
+pub fn beats(shard_type: ShardType) -> ShardType {
+    match shard_type {
+        ShardType::Sentinel => ShardType::Cipher,
+        ShardType::Cipher => ShardType::Oracle,
+        ShardType::Oracle => ShardType::Mirror,
+        ShardType::Mirror => ShardType::Muse,
+        ShardType::Muse => ShardType::Scribe,
+        ShardType::Scribe => ShardType::Advocate,
+        ShardType::Advocate => ShardType::Architect,
+        ShardType::Architect => ShardType::Sentinel,
+    }
+}
+
+/// Power multiplier for `attacker` facing `defender` from type matchup alone.
+/// Unknown types are treated as neutral.
+pub fn matchup(attacker: &str, defender: &str) -> f64 {
+    match (ShardType::from_name(attacker), ShardType::from_name(defender)) {
+        (Some(a), Some(d)) if beats(a) == d => TYPE_ADVANTAGE,
+        (Some(a), Some(d)) if beats(d) == a => 1.0 / TYPE_ADVANTAGE,
+        _ => 1.0,
+    }
+}
+
+/// One round of a battle.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BattleRound {
+    pub round: u32,
+    pub challenger_power: f64,
+    pub defender_power: f64,
+    pub winner_id: String,
+}
+
+/// Result of a battle between two shards.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BattleOutcome {
+    pub challenger_id: String,
+    pub defender_id: String,
+    pub winner_id: String,
+    pub seed: u64,
+    pub rounds: Vec<BattleRound>,
+    /// Elo change to apply to the challenger
+    pub challenger_elo_delta: i32,
+    /// Elo change to apply to the defender
+    pub defender_elo_delta: i32,
+}
+
+/// Base power before the per-round roll: stats scaled by rating and matchup.
+fn base_power(shard: &Shard, opponent: &Shard) -> f64 {
+    let rating = (1.0 + (shard.elo_rating as f64 - BASE_ELO) / 2000.0).clamp(0.5, 1.5);
+    shard.stats_sum().max(1) as f64 * rating * matchup(&shard.shard_type, &opponent.shard_type)
+}
+
+/// Fight `challenger` against `defender`. The same shards and seed always
+/// produce the same outcome.
+pub fn resolve_battle(challenger: &Shard, defender: &Shard, seed: u64) -> BattleOutcome {
+    let mut rng = Rng::from_seed(seed);
+    let challenger_base = base_power(challenger, defender);
+    let defender_base = base_power(defender, challenger);
+
+    let mut rounds = Vec::new();
+    let (mut challenger_wins, mut defender_wins) = (0, 0);
+    while challenger_wins < ROUNDS_TO_WIN && defender_wins < ROUNDS_TO_WIN {
+        // Each side rolls 75%..125% of its base power; ties go to the defender
+        let challenger_power = round2(challenger_base * (0.75 + rng.unit() * 0.5));
+        let defender_power = round2(defender_base * (0.75 + rng.unit() * 0.5));
+        let winner = if challenger_power > defender_power {
+            challenger_wins += 1;
+            challenger
+        } else {
+            defender_wins += 1;
+            defender
+        };
+        rounds.push(BattleRound {
+            round: rounds.len() as u32 + 1,
+            challenger_power,
+            defender_power,
+            winner_id: winner.id.clone(),
+        });
+    }
+
+    let challenger_won = challenger_wins == ROUNDS_TO_WIN;
+    let (winner, loser) = if challenger_won {
+        (challenger, defender)
+    } else {
+        (defender, challenger)
+    };
+    // The changes `shard::apply_elo` will make to the real shards
+    let (mut rated_winner, mut rated_loser) = (winner.clone(), loser.clone());
+    shard::apply_elo(&mut rated_winner, &mut rated_loser);
+    let gain = rated_winner.elo_rating as i64 - winner.elo_rating as i64;
+    let loss = rated_loser.elo_rating as i64 - loser.elo_rating as i64;
+    BattleOutcome {
+        challenger_id: challenger.id.clone(),
+        defender_id: defender.id.clone(),
+        winner_id: winner.id.clone(),
+        seed,
+        rounds,
+        challenger_elo_delta: if challenger_won { gain } else { loss } as i32,
+        defender_elo_delta: if challenger_won { loss } else { gain } as i32,
+    }
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shard_of(type_name: &str, seed: u64) -> Shard {
+        Shard::spawn_seeded(Some(type_name), seed)
+    }
+
+    #[test]
+    fn battles_are_deterministic_and_zero_sum() {
+        let (a, b) = (shard_of("oracle", 1), shard_of("muse", 2));
+        for seed in 0..20 {
+            let outcome = resolve_battle(&a, &b, seed);
+            assert_eq!(outcome, resolve_battle(&a, &b, seed));
+            assert_eq!(outcome.challenger_elo_delta, -outcome.defender_elo_delta);
+            assert!(outcome.rounds.len() >= 2 && outcome.rounds.len() <= 3);
+            assert_eq!(outcome.rounds.last().unwrap().winner_id, outcome.winner_id);
+        }
+    }
+
+    #[test]
+    fn type_advantage_tilts_even_fights() {
+        let mut sentinel = shard_of("sentinel", 3);
+        let mut cipher = shard_of("cipher", 4);
+        // Identical stats and ratings, so only the matchup differs
+        cipher.stats = sentinel.stats.clone();
+        sentinel.elo_rating = 1200;
+        cipher.elo_rating = 1200;
+
+        assert_eq!(matchup("Sentinel", "Cipher"), TYPE_ADVANTAGE);
+        assert_eq!(matchup("Cipher", "Sentinel"), 1.0 / TYPE_ADVANTAGE);
+        assert_eq!(matchup("Sentinel", "Muse"), 1.0);
+
+        let wins = (0..200).filter(|&seed| resolve_battle(&cipher, &sentinel, seed).winner_id == sentinel.id).count();
+        assert!(wins > 120, "sentinel only won {}/200", wins);
+    }
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+
+/// Version of `SUITE`. Bump whenever a task changes so scores from different
+/// suites are never compared.
+pub const SUITE_VERSION: &str = "v1";
+
+/// Turn cap for each benchmark task, so runs stay short and comparable.
+pub const MAX_TURNS: u32 = 3;
+
+/// A fixed benchmark task.
+#[derive(Debug, Clone, Copy)]
+pub struct BenchmarkTask {
+    pub task_type: &'static str,
+    pub task: &'static str,
+}
+
+/// The standard suite: one task per task type.
+pub const SUITE: [BenchmarkTask; 5] = [
+    BenchmarkTask {
+        task_type: "debug",
+        task: "Fix the bug in this Python function so it returns the sum of a list: def total(xs): return sum(xs[1:])",
+    },
+    BenchmarkTask {
+        task_type: "writing",
+        task: "Write a two-sentence product description for a reusable water bottle.",
+    },
+    BenchmarkTask {
+        task_type: "analysis",
+        task: "Compare bubble sort and merge sort by time complexity and say which suits 10,000 items.",
+    },
+    BenchmarkTask {
+        task_type: "coding",
+        task: "Implement a function that returns the first 10 Fibonacci numbers and print them.",
+    },
+    BenchmarkTask {
+        task_type: "general",
+        task: "What is 17 multiplied by 23? Give just the number.",
+    },
+];
+
+/// Outcome of one benchmark task.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskResult {
+    pub task_type: String,
+    pub success: bool,
+    pub duration_ms: u64,
+    pub tool_calls: usize,
+    pub tools_used: Vec<String>,
+}
+
+/// Aggregate over a full suite run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    pub suite_version: String,
+    /// Percentage of tasks that succeeded (0-100)
+    pub score: f64,
+    pub tasks_passed: u32,
+    pub tasks_total: u32,
+    pub total_duration_ms: u64,
+    pub results: Vec<TaskResult>,
+}
+
+/// Aggregate per-task results into a suite summary.
+pub fn summarize(results: Vec<TaskResult>) -> Summary {
+    let tasks_total = results.len() as u32;
+    let tasks_passed = results.iter().filter(|r| r.success).count() as u32;
+    let score = if tasks_total == 0 {
+        0.0
+    } else {
+        (tasks_passed as f64 / tasks_total as f64 * 1000.0).round() / 10.0
+    };
+    Summary {
+        suite_version: SUITE_VERSION.to_string(),
+        score,
+        tasks_passed,
+        tasks_total,
+        total_duration_ms: results.iter().map(|r| r.duration_ms).sum(),
+        results,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(task_type: &str, success: bool, duration_ms: u64) -> TaskResult {
+        TaskResult {
+            task_type: task_type.to_string(),
+            success,
+            duration_ms,
+            tool_calls: 0,
+            tools_used: vec![],
+        }
+    }
+
+    #[test]
+    fn summary_scores_pass_rate() {
+        let summary = summarize(vec![
+            result("debug", true, 100),
+            result("writing", false, 50),
+            result("coding", true, 25),
+        ]);
+        assert_eq!(summary.tasks_passed, 2);
+        assert_eq!(summary.tasks_total, 3);
+        assert_eq!(summary.score, 66.7);
+        assert_eq!(summary.total_duration_ms, 175);
+        assert_eq!(summary.suite_version, SUITE_VERSION);
+
+        assert_eq!(summarize(vec![]).score, 0.0);
+    }
+}
@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
 use uuid::Uuid;
 
-use crate::shard::{Shard, ShardType};
+use crate::rng::Rng;
+use crate::shard::{self, Shard, ShardType};
 
 /// A capture challenge presented to a player attempting to catch a wild shard.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,7 +17,7 @@ pub struct CaptureChallenge {
     pub time_limit_ms: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ChallengeType {
     PatternPrediction,
     Decode,
@@ -27,6 +29,22 @@ pub enum ChallengeType {
     EmotionalInterpretation,
 }
 
+impl ChallengeType {
+    /// The thematic challenge for each shard type.
+    pub fn for_shard_type(shard_type: ShardType) -> Self {
+        match shard_type {
+            ShardType::Oracle => ChallengeType::PatternPrediction,
+            ShardType::Cipher => ChallengeType::Decode,
+            ShardType::Scribe => ChallengeType::Summarize,
+            ShardType::Muse => ChallengeType::CreativePrompt,
+            ShardType::Architect => ChallengeType::Architecture,
+            ShardType::Advocate => ChallengeType::ArgumentAnalysis,
+            ShardType::Sentinel => ChallengeType::SecurityAudit,
+            ShardType::Mirror => ChallengeType::EmotionalInterpretation,
+        }
+    }
+}
+
 /// Result of evaluating a capture challenge answer.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChallengeResult {
@@ -75,30 +93,58 @@ const CREATIVE_PROMPTS: &[&str] = &[
     "Write a two-line poem about the last algorithm",
 ];
 
-/// Pick an index from an array based on the genome hash.
-fn pick_by_hash(genome_hash: &str, array_length: usize) -> usize {
-    // Parse the third byte (chars 4..6) of the hex hash
-    let hex_str = &genome_hash[4..6.min(genome_hash.len())];
-    let byte = u8::from_str_radix(hex_str, 16).unwrap_or(0);
-    (byte as usize) % array_length
+/// The genome byte challenges are drawn from: the third byte of the hash.
+/// A malformed hash (imported or corrupted data) falls back to a byte
+/// derived from the shard id and the raw hash, so bad genomes still spread
+/// across challenges instead of all landing on the first.
+fn challenge_byte(shard: &Shard) -> u8 {
+    if shard::is_valid_genome_hash(&shard.genome_hash) {
+        if let Ok(byte) = u8::from_str_radix(&shard.genome_hash[4..6], 16) {
+            return byte;
+        }
+    }
+    tracing::warn!(
+        "Shard {} has a malformed genome hash {:?}; deriving its capture challenge from its id",
+        shard.id,
+        shard.genome_hash
+    );
+    let digest = Keccak256::digest(format!("{}:{}", shard.id, shard.genome_hash).as_bytes());
+    digest[2]
 }
 
 /// Generate a capture challenge appropriate for the given shard.
 /// The challenge type is determined by the shard's type, and the specific
 /// challenge is selected deterministically from the genome hash.
 pub fn generate_challenge(shard: &Shard) -> CaptureChallenge {
-    let difficulty = {
-        let hex_str = &shard.genome_hash[4..6.min(shard.genome_hash.len())];
-        let byte = u8::from_str_radix(hex_str, 16).unwrap_or(0);
-        ((byte as u32 * 5) / 255) + 1
-    }
-    .min(10);
+    generate_challenge_with(shard, None)
+}
 
-    let shard_type = ShardType::from_name(&shard.shard_type)
-        .unwrap_or(ShardType::Oracle);
+/// Like `generate_challenge`, but the challenge (and its id) is drawn from
+/// `seed` instead of the genome hash, so one shard can get varied yet
+/// reproducible challenges.
+pub fn generate_challenge_seeded(shard: &Shard, seed: u64) -> CaptureChallenge {
+    generate_challenge_with(shard, Some(&mut Rng::from_seed(seed)))
+}
+
+fn generate_challenge_with(shard: &Shard, mut rng: Option<&mut Rng>) -> CaptureChallenge {
+    let id = match rng.as_deref_mut() {
+        Some(rng) => rng.uuid(),
+        None => Uuid::new_v4(),
+    };
+    let byte = challenge_byte(shard);
+    let mut pick = |len: usize| match rng.as_deref_mut() {
+        Some(rng) => rng.index(len),
+        None => (byte as usize) % len,
+    };
+    let difficulty = (((byte as u32 * 5) / 255) + 1).min(10);
+
+    let shard_type = ShardType::from_name(&shard.shard_type).unwrap_or_else(|| {
+        tracing::warn!("Shard {} has unknown type {:?}", shard.id, shard.shard_type);
+        ShardType::from_hash_byte(byte)
+    });
 
     let base = CaptureChallenge {
-        id: Uuid::new_v4().to_string(),
+        id: id.to_string(),
         shard_id: shard.id.clone(),
         challenge_type: ChallengeType::PatternPrediction,
         prompt: String::new(),
@@ -107,24 +153,9 @@ pub fn generate_challenge(shard: &Shard) -> CaptureChallenge {
         time_limit_ms: 60_000,
     };
 
-    match shard_type {
-        ShardType::Oracle => {
-            let idx = pick_by_hash(&shard.genome_hash, PATTERN_SEQUENCES.len());
-            let pattern = &PATTERN_SEQUENCES[idx];
-            let seq_str: Vec<String> = pattern.sequence.iter().map(|n| n.to_string()).collect();
-            CaptureChallenge {
-                challenge_type: ChallengeType::PatternPrediction,
-                prompt: format!(
-                    "What comes next in the sequence?\n\n{}, ?\n\nHint: {}",
-                    seq_str.join(", "),
-                    pattern.hint
-                ),
-                expected_answer: Some(pattern.answer.to_string()),
-                ..base
-            }
-        }
-        ShardType::Cipher => {
-            let idx = pick_by_hash(&shard.genome_hash, CIPHER_MESSAGES.len());
+    match ChallengeType::for_shard_type(shard_type) {
+        ChallengeType::Decode => {
+            let idx = pick(CIPHER_MESSAGES.len());
             let cipher = &CIPHER_MESSAGES[idx];
             CaptureChallenge {
                 challenge_type: ChallengeType::Decode,
@@ -136,8 +167,8 @@ pub fn generate_challenge(shard: &Shard) -> CaptureChallenge {
                 ..base
             }
         }
-        ShardType::Muse => {
-            let idx = pick_by_hash(&shard.genome_hash, CREATIVE_PROMPTS.len());
+        ChallengeType::CreativePrompt => {
+            let idx = pick(CREATIVE_PROMPTS.len());
             CaptureChallenge {
                 challenge_type: ChallengeType::CreativePrompt,
                 prompt: CREATIVE_PROMPTS[idx].to_string(),
@@ -146,8 +177,8 @@ pub fn generate_challenge(shard: &Shard) -> CaptureChallenge {
             }
         }
         _ => {
-            // Default to pattern prediction for other types
-            let idx = pick_by_hash(&shard.genome_hash, PATTERN_SEQUENCES.len());
+            // Pattern prediction, also used for types without their own challenge bank yet
+            let idx = pick(PATTERN_SEQUENCES.len());
             let pattern = &PATTERN_SEQUENCES[idx];
             let seq_str: Vec<String> = pattern.sequence.iter().map(|n| n.to_string()).collect();
             CaptureChallenge {
@@ -269,6 +300,19 @@ mod tests {
     use super::*;
     use crate::shard::Shard;
 
+    #[test]
+    fn seeded_challenges_are_reproducible() {
+        let shard = Shard::spawn_seeded(Some("cipher"), 9);
+        assert_eq!(Shard::spawn_seeded(Some("cipher"), 9).genome_hash, shard.genome_hash);
+        assert_ne!(Shard::spawn_seeded(Some("cipher"), 10).genome_hash, shard.genome_hash);
+
+        let a = generate_challenge_seeded(&shard, 1);
+        let b = generate_challenge_seeded(&shard, 1);
+        assert_eq!(a.id, b.id);
+        assert_eq!(a.prompt, b.prompt);
+        assert!(matches!(a.challenge_type, ChallengeType::Decode));
+    }
+
     #[test]
     fn generate_challenge_for_oracle() {
         let shard = Shard::spawn(Some("oracle"));
@@ -333,6 +377,30 @@ mod tests {
         assert_eq!(result.score, 0);
     }
 
+    #[test]
+    fn malformed_genome_gets_a_valid_challenge() {
+        let mut shard = Shard::spawn_seeded(Some("cipher"), 4);
+        assert!(shard::is_valid_genome_hash(&shard.genome_hash));
+        for bad in ["", "0x", "0xz", "0x1", "not-a-hash", "0xé1234"] {
+            assert!(!shard::is_valid_genome_hash(bad));
+            shard.genome_hash = bad.to_string();
+            let challenge = generate_challenge(&shard);
+            assert!(matches!(challenge.challenge_type, ChallengeType::Decode));
+            assert!(!challenge.prompt.is_empty());
+            assert!((1..=10).contains(&challenge.difficulty));
+        }
+
+        // Malformed genomes don't all collapse onto the first challenge
+        let prompts: std::collections::HashSet<String> = (0..12)
+            .map(|seed| {
+                let mut shard = Shard::spawn_seeded(Some("cipher"), seed);
+                shard.genome_hash = "0x12".to_string();
+                generate_challenge(&shard).prompt
+            })
+            .collect();
+        assert!(prompts.len() > 1);
+    }
+
     #[test]
     fn difficulty_is_bounded() {
         for _ in 0..20 {
@@ -341,4 +409,21 @@ mod tests {
             assert!(challenge.difficulty >= 1 && challenge.difficulty <= 10);
         }
     }
+
+    #[test]
+    fn challenge_type_mapping_is_thematic() {
+        let expected = [
+            (ShardType::Oracle, ChallengeType::PatternPrediction),
+            (ShardType::Cipher, ChallengeType::Decode),
+            (ShardType::Scribe, ChallengeType::Summarize),
+            (ShardType::Muse, ChallengeType::CreativePrompt),
+            (ShardType::Architect, ChallengeType::Architecture),
+            (ShardType::Advocate, ChallengeType::ArgumentAnalysis),
+            (ShardType::Sentinel, ChallengeType::SecurityAudit),
+            (ShardType::Mirror, ChallengeType::EmotionalInterpretation),
+        ];
+        for (shard_type, challenge) in expected {
+            assert_eq!(ChallengeType::for_shard_type(shard_type), challenge);
+        }
+    }
 }
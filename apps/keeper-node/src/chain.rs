@@ -1,13 +1,16 @@
 use alloy::{
+    consensus::Transaction as _,
     network::EthereumWallet,
     primitives::{Address, U256},
-    providers::ProviderBuilder,
-    signers::local::PrivateKeySigner,
+    providers::{Provider, ProviderBuilder},
+    signers::local::{coins_bip39::English, MnemonicBuilder, PrivateKeySigner},
     sol,
 };
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
 
-use crate::config::Config;
+use crate::config::{shellexpand, Config};
 
 // ABI bindings for the ShardRegistry contract
 sol! {
@@ -64,32 +67,133 @@ sol! {
     }
 }
 
-/// Load the keeper's private key signer from the configured key file.
-fn load_signer(config: &Config) -> Result<PrivateKeySigner, String> {
+/// Environment variable that, when set, supplies the keeper mnemonic instead of the key file.
+pub const MNEMONIC_ENV_VAR: &str = "SIPHON_KEEPER_MNEMONIC";
+
+/// Default BIP-44 derivation path for Ethereum accounts.
+pub const DEFAULT_DERIVATION_PATH: &str = "m/44'/60'/0'/0/0";
+
+/// Load the keeper's signer.
+///
+/// A mnemonic in `SIPHON_KEEPER_MNEMONIC` takes precedence; otherwise the key
+/// file is read and treated as a mnemonic if it looks like one, or as a raw
+/// hex private key.
+pub fn load_signer(config: &Config) -> Result<PrivateKeySigner, String> {
+    let derivation_path = config
+        .key_derivation_path
+        .as_deref()
+        .unwrap_or(DEFAULT_DERIVATION_PATH);
+
+    if let Ok(phrase) = std::env::var(MNEMONIC_ENV_VAR) {
+        if !phrase.trim().is_empty() {
+            return signer_from_mnemonic(&phrase, derivation_path);
+        }
+    }
+
     let key_path = shellexpand(config.private_key_path.as_str());
-    let key_hex = std::fs::read_to_string(&key_path)
+    let contents = std::fs::read_to_string(&key_path)
         .map_err(|e| format!("Failed to read private key from {}: {}", key_path, e))?;
 
-    let key_hex = key_hex.trim().trim_start_matches("0x");
+    if looks_like_mnemonic(&contents) {
+        return signer_from_mnemonic(&contents, derivation_path);
+    }
+
+    let key_hex = contents.trim().trim_start_matches("0x");
     PrivateKeySigner::from_str(key_hex)
         .map_err(|e| format!("Invalid private key: {}", e))
 }
 
+/// Return the keeper's address as a checksummed hex string.
+pub fn keeper_address(config: &Config) -> Result<String, String> {
+    Ok(load_signer(config)?.address().to_string())
+}
+
+/// Derive a signer from a BIP-39 mnemonic at the given HD path.
+/// Errors never include the phrase itself.
+fn signer_from_mnemonic(phrase: &str, derivation_path: &str) -> Result<PrivateKeySigner, String> {
+    let words = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+    MnemonicBuilder::<English>::default()
+        .phrase(words)
+        .derivation_path(derivation_path)
+        .map_err(|e| format!("Invalid derivation path {}: {}", derivation_path, e))?
+        .build()
+        .map_err(|_| "Invalid mnemonic in keeper key source".to_string())
+}
+
+/// A mnemonic is 12-24 whitespace-separated alphabetic words; a raw key is a
+/// single hex token.
+fn looks_like_mnemonic(contents: &str) -> bool {
+    let words: Vec<&str> = contents.split_whitespace().collect();
+    (12..=24).contains(&words.len())
+        && words.iter().all(|w| w.chars().all(|c| c.is_ascii_alphabetic()))
+}
+
 /// Create an alloy provider with the configured wallet and RPC URL.
-fn make_provider(config: &Config) -> Result<impl alloy::providers::Provider + Clone, String> {
+/// Also returns the signer address, which keys the nonce manager.
+fn make_provider(config: &Config) -> Result<(impl Provider + Clone, Address), String> {
     let signer = load_signer(config)?;
+    let sender = signer.address();
     let wallet = EthereumWallet::from(signer);
 
     let rpc_url: reqwest::Url = config.rpc_url.parse()
         .map_err(|e| format!("Invalid RPC URL: {}", e))?;
 
-    Ok(ProviderBuilder::new()
+    let provider = ProviderBuilder::new()
         .wallet(wallet)
-        .connect_http(rpc_url))
+        .connect_http(rpc_url);
+    Ok((provider, sender))
+}
+
+/// Tracks the next nonce per signer across sends, so back-to-back transactions
+/// (e.g. batch attestation while liquidations run) don't reuse a pending nonce.
+#[derive(Debug, Default)]
+pub struct NonceManager {
+    next: HashMap<Address, u64>,
+}
+
+impl NonceManager {
+    /// Reserve a nonce for `sender`. `chain_pending` is the node's pending
+    /// transaction count; the larger of it and the locally tracked value wins,
+    /// which covers both lagging nodes and transactions sent elsewhere.
+    pub fn reserve(&mut self, sender: Address, chain_pending: u64) -> u64 {
+        let nonce = self
+            .next
+            .get(&sender)
+            .copied()
+            .unwrap_or(chain_pending)
+            .max(chain_pending);
+        self.next.insert(sender, nonce + 1);
+        nonce
+    }
+
+    /// Forget the tracked nonce after a failed send so the next reservation
+    /// resyncs from the chain.
+    pub fn resync(&mut self, sender: Address) {
+        self.next.remove(&sender);
+    }
+}
+
+/// Process-wide nonce manager shared by every signing path.
+fn nonces() -> std::sync::MutexGuard<'static, NonceManager> {
+    static NONCES: OnceLock<Mutex<NonceManager>> = OnceLock::new();
+    NONCES
+        .get_or_init(|| Mutex::new(NonceManager::default()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Fetch the pending nonce from the chain and reserve the next one locally.
+async fn reserve_nonce(provider: &impl Provider, sender: Address) -> Result<u64, String> {
+    let pending = provider
+        .get_transaction_count(sender)
+        .pending()
+        .await
+        .map_err(|e| format!("Failed to fetch nonce: {}", e))?;
+    Ok(nonces().reserve(sender, pending))
 }
 
 /// Create a read-only alloy provider (no wallet needed).
-fn make_read_provider(config: &Config) -> Result<impl alloy::providers::Provider + Clone, String> {
+fn make_read_provider(config: &Config) -> Result<impl Provider + Clone, String> {
     let rpc_url: reqwest::Url = config.rpc_url.parse()
         .map_err(|e| format!("Invalid RPC URL: {}", e))?;
 
@@ -107,18 +211,23 @@ pub async fn stake(config: &Config, amount_eth: f64) -> Result<String, String> {
         .parse()
         .map_err(|e| format!("Invalid staking address: {}", e))?;
 
-    let provider = make_provider(config)?;
+    let (provider, sender) = make_provider(config)?;
     let contract = IKeeperStaking::new(address, &provider);
 
     // Convert ETH to Wei
     let amount_wei = U256::from((amount_eth * 1e18) as u128);
 
+    let nonce = reserve_nonce(&provider, sender).await?;
     let tx = contract
         .stake()
         .value(amount_wei)
+        .nonce(nonce)
         .send()
         .await
-        .map_err(|e| format!("Stake transaction failed: {}", e))?;
+        .map_err(|e| {
+            nonces().resync(sender);
+            format!("Stake transaction failed: {}", e)
+        })?;
 
     let receipt = tx
         .get_receipt()
@@ -139,14 +248,19 @@ pub async fn unstake(config: &Config) -> Result<String, String> {
         .parse()
         .map_err(|e| format!("Invalid staking address: {}", e))?;
 
-    let provider = make_provider(config)?;
+    let (provider, sender) = make_provider(config)?;
     let contract = IKeeperStaking::new(address, &provider);
 
+    let nonce = reserve_nonce(&provider, sender).await?;
     let tx = contract
         .requestUnstake()
+        .nonce(nonce)
         .send()
         .await
-        .map_err(|e| format!("Unstake request failed: {}", e))?;
+        .map_err(|e| {
+            nonces().resync(sender);
+            format!("Unstake request failed: {}", e)
+        })?;
 
     let receipt = tx
         .get_receipt()
@@ -183,12 +297,36 @@ pub async fn get_keeper_info(config: &Config, keeper_address: &str) -> Result<(U
     Ok((result.stakedAmount, result.unstakeRequestedAt, result.rewards, result.isActive))
 }
 
+/// Result of an idempotent shard registration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegisterOutcome {
+    /// A `register` transaction was sent and mined
+    Registered(String),
+    /// The shard is already registered to this keeper; nothing was sent
+    AlreadyRegistered,
+    /// The shard is registered to another address; nothing was sent
+    OwnedByOther(String),
+}
+
+/// What to do given the registry's current `getOwner` for a shard.
+/// Returns None when the shard is unregistered and should be registered.
+fn registration_decision(current_owner: Address, keeper: Address) -> Option<RegisterOutcome> {
+    if current_owner == Address::ZERO {
+        None
+    } else if current_owner == keeper {
+        Some(RegisterOutcome::AlreadyRegistered)
+    } else {
+        Some(RegisterOutcome::OwnedByOther(format!("{:?}", current_owner)))
+    }
+}
+
 /// Register a shard on-chain via the ShardRegistry contract.
+/// Checks `getOwner` first so re-registering is a no-op instead of a revert.
 pub async fn register_shard(
     config: &Config,
     shard_id: &str,
     genome_hash: &str,
-) -> Result<String, String> {
+) -> Result<RegisterOutcome, String> {
     let registry_address = config
         .shard_registry_address
         .as_ref()
@@ -198,27 +336,42 @@ pub async fn register_shard(
         .parse()
         .map_err(|e| format!("Invalid registry address: {}", e))?;
 
-    let provider = make_provider(config)?;
+    let (provider, sender) = make_provider(config)?;
     let contract = IShardRegistry::new(address, &provider);
 
     let shard_id_bytes = parse_bytes32(shard_id)?;
     let genome_hash_bytes = parse_bytes32(genome_hash)?;
 
+    match contract.getOwner(shard_id_bytes.into()).call().await {
+        Ok(owner) => {
+            if let Some(outcome) = registration_decision(owner, sender) {
+                return Ok(outcome);
+            }
+        }
+        // Some registries revert for unknown shards; fall through to register
+        Err(e) => tracing::debug!("getOwner check failed, registering anyway: {}", e),
+    }
+
+    let nonce = reserve_nonce(&provider, sender).await?;
     let tx = contract
         .register(shard_id_bytes.into(), genome_hash_bytes.into())
+        .nonce(nonce)
         .send()
         .await
-        .map_err(|e| format!("Register transaction failed: {}", e))?;
+        .map_err(|e| {
+            nonces().resync(sender);
+            format!("Register transaction failed: {}", e)
+        })?;
 
     let receipt = tx
         .get_receipt()
         .await
         .map_err(|e| format!("Failed to get receipt: {}", e))?;
 
-    Ok(format!(
+    Ok(RegisterOutcome::Registered(format!(
         "Shard registered on-chain. Tx: {:?}",
         receipt.transaction_hash
-    ))
+    )))
 }
 
 /// Set a shard to wild on-chain via the ShardRegistry contract.
@@ -232,16 +385,21 @@ pub async fn set_wild(config: &Config, shard_id: &str) -> Result<String, String>
         .parse()
         .map_err(|e| format!("Invalid registry address: {}", e))?;
 
-    let provider = make_provider(config)?;
+    let (provider, sender) = make_provider(config)?;
     let contract = IShardRegistry::new(address, &provider);
 
     let shard_id_bytes = parse_bytes32(shard_id)?;
 
+    let nonce = reserve_nonce(&provider, sender).await?;
     let tx = contract
         .setWild(shard_id_bytes.into())
+        .nonce(nonce)
         .send()
         .await
-        .map_err(|e| format!("setWild transaction failed: {}", e))?;
+        .map_err(|e| {
+            nonces().resync(sender);
+            format!("setWild transaction failed: {}", e)
+        })?;
 
     let receipt = tx
         .get_receipt()
@@ -254,6 +412,107 @@ pub async fn set_wild(config: &Config, shard_id: &str) -> Result<String, String>
     ))
 }
 
+/// Transfer a shard to `to` on-chain via the ShardRegistry contract.
+pub async fn transfer_ownership(config: &Config, shard_id: &str, to: &str) -> Result<String, String> {
+    let registry_address = config
+        .shard_registry_address
+        .as_ref()
+        .ok_or("shard_registry_address not configured")?;
+
+    let address: Address = registry_address
+        .parse()
+        .map_err(|e| format!("Invalid registry address: {}", e))?;
+    let to: Address = to
+        .parse()
+        .map_err(|e| format!("Invalid recipient address: {}", e))?;
+
+    let (provider, sender) = make_provider(config)?;
+    let contract = IShardRegistry::new(address, &provider);
+
+    let shard_id_bytes = parse_bytes32(shard_id)?;
+
+    let nonce = reserve_nonce(&provider, sender).await?;
+    let tx = contract
+        .transferOwnership(shard_id_bytes.into(), to)
+        .nonce(nonce)
+        .send()
+        .await
+        .map_err(|e| {
+            nonces().resync(sender);
+            format!("transferOwnership transaction failed: {}", e)
+        })?;
+
+    let receipt = tx
+        .get_receipt()
+        .await
+        .map_err(|e| format!("Failed to get receipt: {}", e))?;
+
+    Ok(format!(
+        "Shard ownership transferred on-chain. Tx: {:?}",
+        receipt.transaction_hash
+    ))
+}
+
+/// A settled value transfer, as read back from the chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payment {
+    pub from: Address,
+    pub to: Option<Address>,
+    pub value_wei: U256,
+}
+
+/// Look up a transaction and its receipt. Fails unless the transaction is
+/// mined and succeeded.
+pub async fn get_settled_payment(config: &Config, tx_hash: &str) -> Result<Payment, String> {
+    let hash = alloy::primitives::B256::from(parse_bytes32(tx_hash)?);
+    let provider = make_read_provider(config)?;
+
+    let receipt = provider
+        .get_transaction_receipt(hash)
+        .await
+        .map_err(|e| format!("Failed to fetch receipt: {}", e))?
+        .ok_or("Payment transaction is not mined")?;
+    if !receipt.status() {
+        return Err("Payment transaction reverted".to_string());
+    }
+    let tx = provider
+        .get_transaction_by_hash(hash)
+        .await
+        .map_err(|e| format!("Failed to fetch transaction: {}", e))?
+        .ok_or("Payment transaction not found")?;
+
+    Ok(Payment {
+        from: tx.inner.signer(),
+        to: tx.to(),
+        value_wei: tx.value(),
+    })
+}
+
+/// Check that `payment` is `buyer` paying `seller` at least `price_wei`.
+pub fn payment_settles(payment: &Payment, buyer: &str, seller: &str, price_wei: U256) -> Result<(), String> {
+    let buyer: Address = buyer.parse().map_err(|e| format!("Invalid buyer address: {}", e))?;
+    let seller: Address = seller.parse().map_err(|e| format!("Invalid seller address: {}", e))?;
+    if payment.from != buyer {
+        return Err("Payment was not sent by the buyer".to_string());
+    }
+    if payment.to != Some(seller) {
+        return Err("Payment was not sent to the seller".to_string());
+    }
+    if payment.value_wei < price_wei {
+        return Err(format!(
+            "Payment of {} wei is below the asking price of {} wei",
+            payment.value_wei, price_wei
+        ));
+    }
+    Ok(())
+}
+
+/// Whether a shard at `level` is due for automatic re-attestation, given the
+/// level it was last attested at and the configured delta (0 = disabled).
+pub fn attestation_due(level: u32, last_attested_level: u32, delta: u32) -> bool {
+    delta > 0 && level >= last_attested_level.saturating_add(delta)
+}
+
 /// Attest a shard's stats to the ShardValuation contract.
 pub async fn attest_shard_value(
     config: &Config,
@@ -271,11 +530,12 @@ pub async fn attest_shard_value(
         .parse()
         .map_err(|e| format!("Invalid valuation address: {}", e))?;
 
-    let provider = make_provider(config)?;
+    let (provider, sender) = make_provider(config)?;
     let contract = IShardValuation::new(address, &provider);
 
     let hash_bytes = parse_bytes32(genome_hash)?;
 
+    let nonce = reserve_nonce(&provider, sender).await?;
     let tx = contract
         .attest(
             hash_bytes.into(),
@@ -283,9 +543,13 @@ pub async fn attest_shard_value(
             U256::from(elo),
             U256::from(stats_sum),
         )
+        .nonce(nonce)
         .send()
         .await
-        .map_err(|e| format!("Attest transaction failed: {}", e))?;
+        .map_err(|e| {
+            nonces().resync(sender);
+            format!("Attest transaction failed: {}", e)
+        })?;
 
     let receipt = tx
         .get_receipt()
@@ -323,6 +587,74 @@ pub async fn check_liquidatable(config: &Config, loan_id: &str) -> Result<bool,
     Ok(result)
 }
 
+/// Aggregated view of a LoanVault loan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoanStatus {
+    pub loan_id: String,
+    /// Principal plus interest, in wei
+    pub repayment_amount_wei: U256,
+    /// Past due but possibly still within the grace period
+    pub is_expired: bool,
+    /// Past due and the grace period; can be liquidated
+    pub is_liquidatable: bool,
+}
+
+/// Combine the vault's view results. The vault returns zero for unknown
+/// loans (and rejects zero-principal loans), so a zero repayment amount
+/// means the loan does not exist.
+fn loan_status_from(
+    loan_id: &str,
+    repayment_amount_wei: U256,
+    is_expired: bool,
+    is_liquidatable: bool,
+) -> Option<LoanStatus> {
+    if repayment_amount_wei.is_zero() {
+        return None;
+    }
+    Some(LoanStatus {
+        loan_id: loan_id.to_string(),
+        repayment_amount_wei,
+        is_expired,
+        is_liquidatable,
+    })
+}
+
+/// Read a loan's repayment amount, expiry, and liquidation state from the
+/// LoanVault contract. Returns None if the loan is not registered.
+pub async fn get_loan_status(config: &Config, loan_id: &str) -> Result<Option<LoanStatus>, String> {
+    let vault_address = config
+        .loan_vault_address
+        .as_ref()
+        .ok_or("loan_vault_address not configured")?;
+
+    let address: Address = vault_address
+        .parse()
+        .map_err(|e| format!("Invalid vault address: {}", e))?;
+
+    let provider = make_read_provider(config)?;
+    let contract = ILoanVault::new(address, &provider);
+
+    let loan_id_bytes = parse_bytes32(loan_id)?;
+
+    let repayment = contract
+        .getRepaymentAmount(loan_id_bytes.into())
+        .call()
+        .await
+        .map_err(|e| format!("getRepaymentAmount call failed: {}", e))?;
+    let expired = contract
+        .isExpired(loan_id_bytes.into())
+        .call()
+        .await
+        .map_err(|e| format!("isExpired call failed: {}", e))?;
+    let liquidatable = contract
+        .isLiquidatable(loan_id_bytes.into())
+        .call()
+        .await
+        .map_err(|e| format!("isLiquidatable call failed: {}", e))?;
+
+    Ok(loan_status_from(loan_id, repayment, expired, liquidatable))
+}
+
 /// Liquidate a defaulted loan via the LoanVault contract.
 pub async fn liquidate_loan(config: &Config, loan_id: &str) -> Result<String, String> {
     let vault_address = config
@@ -334,16 +666,21 @@ pub async fn liquidate_loan(config: &Config, loan_id: &str) -> Result<String, St
         .parse()
         .map_err(|e| format!("Invalid vault address: {}", e))?;
 
-    let provider = make_provider(config)?;
+    let (provider, sender) = make_provider(config)?;
     let contract = ILoanVault::new(address, &provider);
 
     let loan_id_bytes = parse_bytes32(loan_id)?;
 
+    let nonce = reserve_nonce(&provider, sender).await?;
     let tx = contract
         .liquidate(loan_id_bytes.into())
+        .nonce(nonce)
         .send()
         .await
-        .map_err(|e| format!("Liquidate transaction failed: {}", e))?;
+        .map_err(|e| {
+            nonces().resync(sender);
+            format!("Liquidate transaction failed: {}", e)
+        })?;
 
     let receipt = tx
         .get_receipt()
@@ -353,21 +690,31 @@ pub async fn liquidate_loan(config: &Config, loan_id: &str) -> Result<String, St
     Ok(format!("Loan liquidated. Tx: {:?}", receipt.transaction_hash))
 }
 
-/// Parse a hex string into a 32-byte array.
+/// Parse a hex string (optionally `0x`-prefixed) into a 32-byte array.
+/// Anything other than exactly 64 hex digits is rejected rather than
+/// truncated or padded, since a wrong bytes32 names a different shard or loan.
 fn parse_bytes32(hex_str: &str) -> Result<[u8; 32], String> {
-    let hex = hex_str.trim_start_matches("0x");
-    let mut bytes = [0u8; 32];
-    let decoded = hex_decode(hex)?;
-    let len = decoded.len().min(32);
-    bytes[..len].copy_from_slice(&decoded[..len]);
-    Ok(bytes)
+    let hex = hex_str.strip_prefix("0x").unwrap_or(hex_str);
+    let decoded = hex_decode(hex).map_err(|e| format!("Invalid bytes32 '{}': {}", hex_str, e))?;
+    decoded.try_into().map_err(|d: Vec<u8>| {
+        format!(
+            "Invalid bytes32 '{}': expected 32 bytes (64 hex digits), got {}",
+            hex_str,
+            d.len()
+        )
+    })
 }
 
 /// Simple hex decoding without adding another dependency.
 fn hex_decode(hex: &str) -> Result<Vec<u8>, String> {
-    if hex.len() % 2 != 0 {
+    if !hex.len().is_multiple_of(2) {
         return Err("Odd-length hex string".to_string());
     }
+    // from_str_radix alone would accept a leading '+' and byte-slicing
+    // non-ASCII input could split a char
+    if let Some(pos) = hex.find(|c: char| !c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid hex at position {}", pos));
+    }
     (0..hex.len())
         .step_by(2)
         .map(|i| {
@@ -377,12 +724,155 @@ fn hex_decode(hex: &str) -> Result<Vec<u8>, String> {
         .collect()
 }
 
-/// Expand ~ to home directory in paths.
-fn shellexpand(path: &str) -> String {
-    if path.starts_with("~/") {
-        if let Ok(home) = std::env::var("HOME") {
-            return format!("{}{}", home, &path[1..]);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payment_must_come_from_buyer_to_seller_at_price() {
+        let buyer = "0x00000000000000000000000000000000000000b0";
+        let seller = "0x00000000000000000000000000000000000000a0";
+        let payment = Payment {
+            from: buyer.parse().unwrap(),
+            to: Some(seller.parse().unwrap()),
+            value_wei: U256::from(1000),
+        };
+        assert!(payment_settles(&payment, buyer, seller, U256::from(1000)).is_ok());
+        assert!(payment_settles(&payment, buyer, seller, U256::from(1001))
+            .unwrap_err()
+            .contains("below the asking price"));
+        assert!(payment_settles(&payment, seller, seller, U256::from(1)).is_err());
+        let elsewhere = Payment { to: Some(buyer.parse().unwrap()), ..payment };
+        assert!(payment_settles(&elsewhere, buyer, seller, U256::from(1)).is_err());
+    }
+
+    #[test]
+    fn parse_bytes32_requires_exactly_32_bytes() {
+        let hash = format!("0x{}", "ab".repeat(32));
+        assert_eq!(parse_bytes32(&hash).unwrap(), [0xab; 32]);
+        assert_eq!(parse_bytes32(&"01".repeat(32)).unwrap(), [0x01; 32]);
+
+        let too_long = parse_bytes32(&format!("0x{}", "ab".repeat(33))).unwrap_err();
+        assert!(too_long.contains("got 33"), "{}", too_long);
+        let too_short = parse_bytes32("0xabcd").unwrap_err();
+        assert!(too_short.contains("got 2"), "{}", too_short);
+        assert!(parse_bytes32("").is_err());
+
+        let non_hex = parse_bytes32(&format!("0x{}zz", "ab".repeat(31))).unwrap_err();
+        assert!(non_hex.contains("Invalid hex"), "{}", non_hex);
+        assert!(parse_bytes32(&"a".repeat(63)).unwrap_err().contains("Odd-length"));
+        assert!(parse_bytes32(&format!("+f{}", "ab".repeat(31))).is_err());
+        assert!(parse_bytes32(&format!("€a{}", "ab".repeat(30))).is_err());
+    }
+
+    const TEST_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+    #[test]
+    fn derives_known_address_from_test_mnemonic() {
+        let signer = signer_from_mnemonic(TEST_MNEMONIC, DEFAULT_DERIVATION_PATH).unwrap();
+        assert_eq!(
+            signer.address().to_string(),
+            "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+        );
+
+        let second = signer_from_mnemonic(TEST_MNEMONIC, "m/44'/60'/0'/0/1").unwrap();
+        assert_eq!(
+            second.address().to_string(),
+            "0x70997970C51812dc3A010C7d01b50e0d17dc79C8"
+        );
+    }
+
+    #[test]
+    fn key_file_mnemonic_detection() {
+        assert!(looks_like_mnemonic(&format!("{}\n", TEST_MNEMONIC)));
+        assert!(!looks_like_mnemonic(
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+        ));
+    }
+
+    #[test]
+    fn loads_mnemonic_from_key_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("keeper.key");
+        std::fs::write(&key_path, TEST_MNEMONIC).unwrap();
+
+        let config = Config {
+            private_key_path: key_path.to_string_lossy().to_string(),
+            key_derivation_path: Some("m/44'/60'/0'/0/1".to_string()),
+            ..Config::default()
+        };
+        assert_eq!(
+            keeper_address(&config).unwrap(),
+            "0x70997970C51812dc3A010C7d01b50e0d17dc79C8"
+        );
+    }
+
+    #[test]
+    fn invalid_mnemonic_error_does_not_leak_phrase() {
+        let bad = "alpha beta gamma delta epsilon zeta eta theta iota kappa lambda mu";
+        let err = signer_from_mnemonic(bad, DEFAULT_DERIVATION_PATH).unwrap_err();
+        assert!(!err.contains("alpha"));
+    }
+
+    #[test]
+    fn nonce_manager_tracks_and_resyncs() {
+        let a: Address = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266".parse().unwrap();
+        let b: Address = "0x70997970C51812dc3A010C7d01b50e0d17dc79C8".parse().unwrap();
+        let mut nonces = NonceManager::default();
+
+        // Rapid sends while the node still reports the same pending count
+        assert_eq!(nonces.reserve(a, 5), 5);
+        assert_eq!(nonces.reserve(a, 5), 6);
+        assert_eq!(nonces.reserve(a, 5), 7);
+
+        // Signers are tracked independently
+        assert_eq!(nonces.reserve(b, 0), 0);
+
+        // A transaction sent elsewhere moves the chain ahead of us
+        assert_eq!(nonces.reserve(a, 10), 10);
+
+        // After a failure the local view is dropped and the chain is trusted again
+        nonces.resync(a);
+        assert_eq!(nonces.reserve(a, 9), 9);
+        assert_eq!(nonces.reserve(b, 0), 1);
+    }
+
+    #[test]
+    fn registration_decision_from_owner_lookup() {
+        let keeper: Address = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266".parse().unwrap();
+        let other: Address = "0x70997970C51812dc3A010C7d01b50e0d17dc79C8".parse().unwrap();
+
+        assert_eq!(registration_decision(Address::ZERO, keeper), None);
+        assert_eq!(
+            registration_decision(keeper, keeper),
+            Some(RegisterOutcome::AlreadyRegistered)
+        );
+        match registration_decision(other, keeper) {
+            Some(RegisterOutcome::OwnedByOther(owner)) => {
+                assert_eq!(owner.to_lowercase(), format!("{:?}", other).to_lowercase())
+            }
+            d => panic!("unexpected decision: {:?}", d),
         }
     }
-    path.to_string()
+
+    #[test]
+    fn loan_status_aggregates_view_results() {
+        assert_eq!(loan_status_from("0x01", U256::ZERO, false, false), None);
+
+        let status = loan_status_from("0x01", U256::from(1_050u64), true, false).unwrap();
+        assert_eq!(status.loan_id, "0x01");
+        assert_eq!(status.repayment_amount_wei, U256::from(1_050u64));
+        assert!(status.is_expired);
+        assert!(!status.is_liquidatable);
+    }
+
+    #[test]
+    fn attestation_due_after_level_delta() {
+        assert!(!attestation_due(10, 0, 0), "disabled");
+        assert!(!attestation_due(4, 0, 5));
+        assert!(attestation_due(5, 0, 5));
+        assert!(!attestation_due(9, 5, 5));
+        assert!(attestation_due(12, 5, 5));
+        assert!(!attestation_due(3, 8, 5), "prestige reset stays below");
+    }
 }
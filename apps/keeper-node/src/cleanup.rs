@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::config::shellexpand;
+use crate::db;
+
+/// Parent directories (relative to the data dir) that hold one subdirectory
+/// per shard, named by shard id.
+pub const SHARD_DIR_ROOTS: [&str; 3] = ["workspaces", "memories/tasks", "artifacts"];
+
+/// Per-shard directories, relative to a data dir.
+pub fn shard_dirs(shard_id: &str) -> Vec<PathBuf> {
+    SHARD_DIR_ROOTS.iter().map(|root| Path::new(root).join(shard_id)).collect()
+}
+
+/// Remove a shard's workspace, memory artifacts and artifacts. Returns how
+/// many directories were removed; missing ones are skipped.
+pub fn remove_shard_dirs(data_dir: &str, shard_id: &str) -> Result<usize, String> {
+    let root = data_root(data_dir)?;
+    let mut removed = 0;
+    for dir in shard_dirs(shard_id) {
+        if remove_within(&root, &root.join(dir))? {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Remove per-shard directories whose shard is no longer in the database.
+/// Returns the directories removed.
+pub fn gc_orphaned_dirs(data_dir: &str) -> Result<Vec<PathBuf>, String> {
+    let root = data_root(data_dir)?;
+    let known: HashSet<String> = db::get_shards(data_dir)
+        .map_err(|e| format!("DB error: {}", e))?
+        .into_iter()
+        .map(|s| s.id)
+        .collect();
+
+    let mut removed = Vec::new();
+    for parent in SHARD_DIR_ROOTS.iter().map(|r| root.join(r)) {
+        let Ok(entries) = std::fs::read_dir(&parent) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if known.contains(&name) {
+                continue;
+            }
+            let path = entry.path();
+            if remove_within(&root, &path)? {
+                removed.push(path);
+            }
+        }
+    }
+    Ok(removed)
+}
+
+fn data_root(data_dir: &str) -> Result<PathBuf, String> {
+    Path::new(&shellexpand(data_dir))
+        .canonicalize()
+        .map_err(|e| format!("Data dir {}: {}", data_dir, e))
+}
+
+/// Remove the directory `path` if it resolves to somewhere strictly inside
+/// `root`. Symlinks are resolved first, so a link can't redirect the removal.
+fn remove_within(root: &Path, path: &Path) -> Result<bool, String> {
+    let Ok(resolved) = path.canonicalize() else {
+        return Ok(false);
+    };
+    if resolved == root || !resolved.starts_with(root) {
+        return Err(format!("Refusing to remove {} outside the data dir", path.display()));
+    }
+    if !resolved.is_dir() {
+        return Ok(false);
+    }
+    std::fs::remove_dir_all(&resolved).map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shard::Shard;
+
+    #[test]
+    fn gc_removes_only_orphaned_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        db::init_db(&data_dir).unwrap();
+        let shard = Shard::spawn(None);
+        db::insert_shard(&data_dir, &shard).unwrap();
+        for id in [shard.id.as_str(), "gone"] {
+            for sub in shard_dirs(id) {
+                std::fs::create_dir_all(dir.path().join(sub)).unwrap();
+            }
+        }
+
+        let removed = gc_orphaned_dirs(&data_dir).unwrap();
+        assert_eq!(removed.len(), SHARD_DIR_ROOTS.len());
+        assert!(!dir.path().join("workspaces/gone").exists());
+        assert!(dir.path().join("workspaces").join(&shard.id).is_dir());
+
+        assert!(remove_shard_dirs(&data_dir, "../..").is_err());
+        assert_eq!(remove_shard_dirs(&data_dir, &shard.id).unwrap(), SHARD_DIR_ROOTS.len());
+        assert!(!dir.path().join("memories/tasks").join(&shard.id).exists());
+    }
+}
@@ -1,22 +1,63 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 
+use crate::db::RetrievalWeights;
+use crate::executor::{self, CodeInterpreter};
+use crate::inference::{self, AuthStyle, InferenceConfig, ToolProtocol};
+use crate::reputation::PenaltyPolicy;
+use crate::shard::{FailureCooldown, Shard, StatLimits, StatOverflow};
+
+/// Source of the `user` field sent with inference requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InferenceUser {
+    #[default]
+    None,
+    Shard,
+    Owner,
+}
+
+impl InferenceUser {
+    /// The `user` value for a request on `shard_id` made by `owner`.
+    pub fn resolve(self, shard_id: &str, owner: Option<&str>) -> Option<String> {
+        match self {
+            InferenceUser::None => None,
+            InferenceUser::Shard => Some(shard_id.to_string()),
+            InferenceUser::Owner => owner.map(str::to_string),
+        }
+    }
+}
+
 /// Keeper node configuration, loaded from ~/.siphon/config.toml
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     /// JSON-RPC URL for Base Sepolia
     pub rpc_url: String,
 
-    /// Path to the file containing the keeper's private key
+    /// Path to the file containing the keeper's private key or BIP-39 mnemonic
     pub private_key_path: String,
 
+    /// HD derivation path used when the key file (or `SIPHON_KEEPER_MNEMONIC`)
+    /// holds a mnemonic. Defaults to m/44'/60'/0'/0/0.
+    #[serde(default)]
+    pub key_derivation_path: Option<String>,
+
     /// Directory for local data (SQLite DB, logs, etc.)
     pub data_dir: String,
 
     /// Port to listen for P2P connections
     pub listen_port: u16,
 
+    /// Port for the P2P WebSocket transport (0 disables it)
+    #[serde(default = "default_ws_port")]
+    pub ws_port: u16,
+
+    /// Close P2P connections idle for this many seconds
+    #[serde(default = "default_p2p_idle_timeout_secs")]
+    pub p2p_idle_timeout_secs: u64,
+
     /// Multiaddrs of bootstrap peers
     #[serde(default)]
     pub bootstrap_peers: Vec<String>,
@@ -46,6 +87,12 @@ pub struct Config {
     #[serde(default)]
     pub loan_vault_address: Option<String>,
 
+    /// Named provider preset ("openai", "ollama", "groq", "together",
+    /// "openrouter", "azure") that fills in the provider, URL, model and
+    /// auth style the config file leaves out. Applied by `resolve_provider`.
+    #[serde(default)]
+    pub provider_preset: Option<String>,
+
     /// Inference provider: "openai", "ollama", or any OpenAI-compatible service
     #[serde(default = "default_inference_provider")]
     pub inference_provider: String,
@@ -58,9 +105,276 @@ pub struct Config {
     #[serde(default = "default_inference_model")]
     pub inference_model: String,
 
+    /// How `openai_api_key` is sent: "bearer" (`Authorization: Bearer`) or
+    /// "api_key" (an `api-key` header, for Azure OpenAI)
+    #[serde(default)]
+    pub inference_auth: AuthStyle,
+
+    /// Extra headers sent with every inference request, for gateways that
+    /// want e.g. `HTTP-Referer`/`X-Title` (OpenRouter) or their own key header
+    #[serde(default)]
+    pub inference_headers: HashMap<String, String>,
+
+    /// Whether the inference model accepts images (binary tool output is
+    /// then forwarded as image content instead of a text description)
+    #[serde(default)]
+    pub inference_vision: bool,
+
+    /// How tools are offered to the model: "native" function calling or
+    /// "prompt_embedded" for models without it
+    #[serde(default)]
+    pub tool_protocol: ToolProtocol,
+
+    /// What to send as the inference `user` field: "none", "shard" (the
+    /// shard id) or "owner" (the `x-owner-id` header, when present)
+    #[serde(default)]
+    pub inference_user: InferenceUser,
+
+    /// Maximum tokens per chat/training reply
+    #[serde(default = "default_inference_max_tokens")]
+    pub inference_max_tokens: u32,
+
+    /// Sampling temperature for chat/training replies
+    #[serde(default = "default_inference_temperature")]
+    pub inference_temperature: f64,
+
+    /// Fence training messages off as quoted user input and remind the model
+    /// to stay in persona; obvious override attempts are logged
+    #[serde(default)]
+    pub persona_lock: bool,
+
+    /// Most completion and embedding requests in flight at once across all
+    /// executions, training and journals (0 = unlimited)
+    #[serde(default = "default_max_concurrent_inference")]
+    pub max_concurrent_inference: usize,
+
     /// Port for the HTTP API server
     #[serde(default = "default_http_port")]
     pub http_port: u16,
+
+    /// Highest level a shard can reach before it must prestige
+    #[serde(default = "default_level_cap")]
+    pub level_cap: u32,
+
+    /// Ceiling for each individual shard stat
+    #[serde(default = "default_stat_cap")]
+    pub stat_cap: u32,
+
+    /// What happens to stat bonuses past `stat_cap`
+    #[serde(default)]
+    pub stat_overflow: StatOverflow,
+
+    /// Lower each shard's stat cap to its genome-derived potential
+    /// (70-100% of `stat_cap`)
+    #[serde(default)]
+    pub genome_stat_caps: bool,
+
+    /// Consecutive failed executions before a shard is put on cooldown
+    /// (0 disables)
+    #[serde(default = "default_failure_cooldown_threshold")]
+    pub failure_cooldown_threshold: u32,
+
+    /// First cooldown, in seconds; doubles with each further failure
+    #[serde(default = "default_failure_cooldown_secs")]
+    pub failure_cooldown_secs: u64,
+
+    /// Longest cooldown, in seconds
+    #[serde(default = "default_failure_cooldown_max_secs")]
+    pub failure_cooldown_max_secs: u64,
+
+    /// Consecutive failed liquidations or attestations before each further
+    /// failure costs reputation (0 disables)
+    #[serde(default = "default_penalty_failure_threshold")]
+    pub penalty_failure_threshold: u32,
+
+    /// Reputation lost per penalized failure
+    #[serde(default = "default_penalty_reputation")]
+    pub penalty_reputation: u64,
+
+    /// Reputation regained per successful duty, up to the starting value
+    #[serde(default = "default_reputation_recovery")]
+    pub reputation_recovery: u64,
+
+    /// Reputation below which the keeper gossips a warning (0 disables)
+    #[serde(default = "default_reputation_warning_below")]
+    pub reputation_warning_below: u64,
+
+    /// Gossip messages held for retry while no peers are subscribed, before
+    /// the oldest is dropped
+    #[serde(default = "default_publish_queue_capacity")]
+    pub publish_queue_capacity: usize,
+
+    /// Hosts that per-request `inference_url` overrides may target.
+    /// When unset, any override is accepted.
+    #[serde(default)]
+    pub allowed_inference_hosts: Option<Vec<String>>,
+
+    /// Maximum shard spawns per minute across the whole keeper (0 = unlimited)
+    #[serde(default = "default_spawn_limit_per_minute")]
+    pub spawn_limit_per_minute: u32,
+
+    /// Maximum shard spawns per minute for a single owner (0 = unlimited)
+    #[serde(default = "default_spawn_limit_per_owner_per_minute")]
+    pub spawn_limit_per_owner_per_minute: u32,
+
+    /// Maximum shards a single owner may hold (0 = unlimited)
+    #[serde(default)]
+    pub max_shards_per_owner: u32,
+
+    /// Owner ids (`x-owner-id`) allowed to use the `/api/admin` routes, read
+    /// `GET /api/config` and the log stream, and run `POST /api/maintenance/dedupe`
+    #[serde(default)]
+    pub admin_owners: Vec<String>,
+
+    /// PEM certificate chain for serving the API over TLS (plain HTTP when unset)
+    #[serde(default)]
+    pub tls_cert_path: Option<String>,
+
+    /// PEM private key for `tls_cert_path`
+    #[serde(default)]
+    pub tls_key_path: Option<String>,
+
+    /// PEM CA bundle; when set, clients must present a certificate it issued
+    #[serde(default)]
+    pub tls_client_ca_path: Option<String>,
+
+    /// Default cap on a single tool call's output, in bytes
+    #[serde(default = "default_max_tool_output_bytes")]
+    pub max_tool_output_bytes: usize,
+
+    /// Per-tool output caps overriding `max_tool_output_bytes`
+    #[serde(default)]
+    pub tool_output_limits: HashMap<String, usize>,
+
+    /// Extra `code_eval` languages, keyed by language name. Interpreter
+    /// commands must be on `executor::ALLOWED_INTERPRETERS`.
+    #[serde(default)]
+    pub code_languages: HashMap<String, CodeInterpreter>,
+
+    /// Tools no shard may use during execution, whatever its capabilities
+    #[serde(default)]
+    pub disabled_tools: Vec<String>,
+
+    /// Turns an execution runs when the request sets no `max_turns`
+    #[serde(default = "default_max_turns")]
+    pub default_max_turns: u32,
+
+    /// Most turns any execution may run; larger `max_turns` requests are
+    /// clamped to this
+    #[serde(default = "default_max_turns_ceiling")]
+    pub max_turns_ceiling: u32,
+
+    /// Cap on the combined tool output of one execution, in bytes
+    #[serde(default = "default_max_execution_output_bytes")]
+    pub max_execution_output_bytes: usize,
+
+    /// Most read-only tool calls (file_read, search_files, http_fetch,
+    /// recall_lessons) from one turn that run at once; other tools always
+    /// run one at a time
+    #[serde(default = "default_max_parallel_tools")]
+    pub max_parallel_tools: usize,
+
+    /// Wall-clock limit for a background job, in seconds, after which the
+    /// watchdog flags it (0 disables)
+    #[serde(default = "default_max_job_secs")]
+    pub max_job_secs: u64,
+
+    /// Also abort jobs the watchdog flags, mark them failed and return their
+    /// shards to idle
+    #[serde(default)]
+    pub watchdog_cancel: bool,
+
+    /// Store tool outputs verbatim in the action log, lessons and memory
+    /// artifacts. When off only a size-and-hash summary is stored; the model
+    /// still sees the full output during the run.
+    #[serde(default = "default_true")]
+    pub persist_tool_outputs: bool,
+
+    /// Save the conversation of each background job so it can be resumed
+    /// with `POST /api/jobs/{id}/resume`
+    #[serde(default = "default_true")]
+    pub persist_job_conversations: bool,
+
+    /// Longest accepted execute `task`, in characters
+    #[serde(default = "default_max_task_chars")]
+    pub max_task_chars: usize,
+
+    /// Longest accepted train `message`, in characters
+    #[serde(default = "default_max_message_chars")]
+    pub max_message_chars: usize,
+
+    /// Weights for the final, embedding-aware lesson ranking
+    #[serde(default = "default_retrieval_weights")]
+    pub retrieval_weights: RetrievalWeights,
+
+    /// Weights for the DB prefilter that picks ranking candidates
+    #[serde(default = "default_prefilter_weights")]
+    pub prefilter_weights: RetrievalWeights,
+
+    /// Token ceiling for the prior-lesson context added to execution prompts
+    #[serde(default = "default_memory_context_token_budget")]
+    pub memory_context_token_budget: usize,
+
+    /// Longest text embedded for one lesson, in characters; goal and
+    /// approach are kept first (0 = unbounded)
+    #[serde(default = "default_max_embedding_input_chars")]
+    pub max_embedding_input_chars: usize,
+
+    /// Lessons kept per shard; after each new lesson the lowest-scoring
+    /// unpinned ones past this count are pruned (0 = keep all)
+    #[serde(default)]
+    pub max_lessons_per_shard: u32,
+
+    /// Write a shard journal entry after every N successful executions (0 = off)
+    #[serde(default = "default_journal_every")]
+    pub journal_every: u32,
+
+    /// Offer wild, unowned shards idle this long (seconds) to other keepers (0 = off)
+    #[serde(default)]
+    pub wild_drift_idle_secs: u64,
+
+    /// Accept wild shards drifting in from other keepers
+    #[serde(default)]
+    pub accept_wild_drift: bool,
+
+    /// Re-bootstrap the DHT and reconcile published shard records with
+    /// hosted shards every N seconds (0 = off)
+    #[serde(default = "default_dht_audit_interval_secs")]
+    pub dht_audit_interval_secs: u64,
+
+    /// Attest a shard's value on-chain in the background once it has gained
+    /// this many levels since its last attestation (0 = off)
+    #[serde(default)]
+    pub auto_attest_level_delta: u32,
+
+    /// Keep raw action log rows this many days after they are rolled up into
+    /// daily totals (0 = keep them forever)
+    #[serde(default)]
+    pub action_retention_days: u32,
+
+    /// Embed each shard's most recent lessons on startup so the first
+    /// retrieval doesn't pay for the whole candidate set
+    #[serde(default)]
+    pub prewarm_embeddings: bool,
+
+    /// Remove a shard's workspace, memory and artifact directories when it
+    /// is released to the wild
+    #[serde(default)]
+    pub cleanup_on_release: bool,
+
+    /// Remove a shard's workspace, memory and artifact directories when it
+    /// is deleted
+    #[serde(default = "default_true")]
+    pub cleanup_on_delete: bool,
+
+    /// Shards kept in the API's in-memory lookup cache (0 disables it)
+    #[serde(default = "default_shard_cache_size")]
+    pub shard_cache_size: usize,
+
+    /// Largest a shard's workspace may grow through `file_write`, in bytes
+    /// (0 = unlimited)
+    #[serde(default = "default_workspace_quota_bytes")]
+    pub workspace_quota_bytes: u64,
 }
 
 fn default_inference_provider() -> String {
@@ -75,17 +389,233 @@ fn default_inference_model() -> String {
     "gpt-4o-mini".to_string()
 }
 
+fn default_inference_max_tokens() -> u32 {
+    512
+}
+
+fn default_inference_temperature() -> f64 {
+    0.7
+}
+
+fn default_max_concurrent_inference() -> usize {
+    8
+}
+
+fn default_dht_audit_interval_secs() -> u64 {
+    600
+}
+
+fn default_ws_port() -> u16 {
+    9001
+}
+
+fn default_p2p_idle_timeout_secs() -> u64 {
+    60
+}
+
 fn default_http_port() -> u16 {
     3001
 }
 
+fn default_level_cap() -> u32 {
+    100
+}
+
+fn default_failure_cooldown_threshold() -> u32 {
+    3
+}
+
+fn default_penalty_failure_threshold() -> u32 {
+    3
+}
+
+fn default_penalty_reputation() -> u64 {
+    5
+}
+
+fn default_reputation_recovery() -> u64 {
+    1
+}
+
+fn default_reputation_warning_below() -> u64 {
+    50
+}
+
+fn default_publish_queue_capacity() -> usize {
+    crate::gossip::PUBLISH_QUEUE_CAPACITY
+}
+
+fn default_failure_cooldown_secs() -> u64 {
+    60
+}
+
+fn default_failure_cooldown_max_secs() -> u64 {
+    3600
+}
+
+fn default_stat_cap() -> u32 {
+    crate::shard::DEFAULT_STAT_CAP
+}
+
+fn default_max_tool_output_bytes() -> usize {
+    50_000
+}
+
+fn default_max_execution_output_bytes() -> usize {
+    200_000
+}
+
+fn default_max_parallel_tools() -> usize {
+    4
+}
+
+fn default_max_turns() -> u32 {
+    5
+}
+
+fn default_max_turns_ceiling() -> u32 {
+    20
+}
+
+fn default_max_job_secs() -> u64 {
+    1800
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_task_chars() -> usize {
+    8_000
+}
+
+fn default_max_message_chars() -> usize {
+    4_000
+}
+
+fn default_retrieval_weights() -> RetrievalWeights {
+    RetrievalWeights::HYBRID
+}
+
+fn default_prefilter_weights() -> RetrievalWeights {
+    RetrievalWeights::PREFILTER
+}
+
+fn default_max_embedding_input_chars() -> usize {
+    crate::inference::DEFAULT_MAX_EMBEDDING_INPUT_CHARS
+}
+
+fn default_memory_context_token_budget() -> usize {
+    800
+}
+
+fn default_shard_cache_size() -> usize {
+    256
+}
+
+fn default_workspace_quota_bytes() -> u64 {
+    crate::executor::DEFAULT_WORKSPACE_QUOTA_BYTES
+}
+
+fn default_journal_every() -> u32 {
+    10
+}
+
+fn default_spawn_limit_per_minute() -> u32 {
+    30
+}
+
+fn default_spawn_limit_per_owner_per_minute() -> u32 {
+    5
+}
+
+/// Known-good inference settings for a hosted or local provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProviderPreset {
+    pub name: &'static str,
+    pub provider: &'static str,
+    pub url: &'static str,
+    pub model: &'static str,
+    pub auth: AuthStyle,
+}
+
+pub const PROVIDER_PRESETS: [ProviderPreset; 6] = [
+    ProviderPreset {
+        name: "openai",
+        provider: "openai",
+        url: "https://api.openai.com/v1/chat/completions",
+        model: "gpt-4o-mini",
+        auth: AuthStyle::Bearer,
+    },
+    ProviderPreset {
+        name: "ollama",
+        provider: "ollama",
+        url: "http://localhost:11434/v1/chat/completions",
+        model: "llama3.2",
+        auth: AuthStyle::Bearer,
+    },
+    ProviderPreset {
+        name: "groq",
+        provider: "groq",
+        url: "https://api.groq.com/openai/v1/chat/completions",
+        model: "llama-3.1-8b-instant",
+        auth: AuthStyle::Bearer,
+    },
+    ProviderPreset {
+        name: "together",
+        provider: "together",
+        url: "https://api.together.xyz/v1/chat/completions",
+        model: "meta-llama/Meta-Llama-3.1-8B-Instruct-Turbo",
+        auth: AuthStyle::Bearer,
+    },
+    ProviderPreset {
+        name: "openrouter",
+        provider: "openrouter",
+        url: "https://openrouter.ai/api/v1/chat/completions",
+        model: "openai/gpt-4o-mini",
+        auth: AuthStyle::Bearer,
+    },
+    // Azure URLs name the resource and deployment, so inference_url must be
+    // set alongside this preset
+    ProviderPreset {
+        name: "azure",
+        provider: "azure",
+        url: "https://YOUR-RESOURCE.openai.azure.com/openai/deployments/YOUR-DEPLOYMENT/chat/completions?api-version=2024-10-21",
+        model: "gpt-4o-mini",
+        auth: AuthStyle::ApiKey,
+    },
+];
+
+/// The inference fields a preset can fill, as written in the config file:
+/// None where the file leaves them out.
+#[derive(Debug, Default, Deserialize)]
+pub struct InferenceOverrides {
+    pub inference_provider: Option<String>,
+    pub inference_url: Option<String>,
+    pub inference_model: Option<String>,
+    pub inference_auth: Option<AuthStyle>,
+}
+
+impl ProviderPreset {
+    /// Look up a preset by name (case-insensitive).
+    pub fn named(name: &str) -> Option<Self> {
+        PROVIDER_PRESETS
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name.trim()))
+            .copied()
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             rpc_url: "https://sepolia.base.org".to_string(),
             private_key_path: "~/.siphon/keeper.key".to_string(),
+            key_derivation_path: None,
             data_dir: "~/.siphon/data".to_string(),
             listen_port: 9000,
+            ws_port: default_ws_port(),
+            p2p_idle_timeout_secs: default_p2p_idle_timeout_secs(),
             bootstrap_peers: vec![],
             openai_api_key: None,
             api_key: None,
@@ -93,15 +623,147 @@ impl Default for Config {
             keeper_staking_address: None,
             shard_valuation_address: None,
             loan_vault_address: None,
+            provider_preset: None,
             inference_provider: default_inference_provider(),
             inference_url: default_inference_url(),
             inference_model: default_inference_model(),
+            inference_auth: AuthStyle::default(),
+            inference_headers: HashMap::new(),
+            inference_vision: false,
+            tool_protocol: ToolProtocol::default(),
+            inference_user: InferenceUser::default(),
+            inference_max_tokens: default_inference_max_tokens(),
+            inference_temperature: default_inference_temperature(),
+            persona_lock: false,
+            max_concurrent_inference: default_max_concurrent_inference(),
             http_port: default_http_port(),
+            level_cap: default_level_cap(),
+            stat_cap: default_stat_cap(),
+            stat_overflow: StatOverflow::default(),
+            genome_stat_caps: false,
+            failure_cooldown_threshold: default_failure_cooldown_threshold(),
+            penalty_failure_threshold: default_penalty_failure_threshold(),
+            penalty_reputation: default_penalty_reputation(),
+            reputation_recovery: default_reputation_recovery(),
+            reputation_warning_below: default_reputation_warning_below(),
+            publish_queue_capacity: default_publish_queue_capacity(),
+            failure_cooldown_secs: default_failure_cooldown_secs(),
+            failure_cooldown_max_secs: default_failure_cooldown_max_secs(),
+            allowed_inference_hosts: None,
+            spawn_limit_per_minute: default_spawn_limit_per_minute(),
+            spawn_limit_per_owner_per_minute: default_spawn_limit_per_owner_per_minute(),
+            max_shards_per_owner: 0,
+            admin_owners: vec![],
+            tls_cert_path: None,
+            tls_key_path: None,
+            tls_client_ca_path: None,
+            max_tool_output_bytes: default_max_tool_output_bytes(),
+            tool_output_limits: HashMap::new(),
+            code_languages: HashMap::new(),
+            disabled_tools: Vec::new(),
+            default_max_turns: default_max_turns(),
+            max_turns_ceiling: default_max_turns_ceiling(),
+            max_execution_output_bytes: default_max_execution_output_bytes(),
+            max_parallel_tools: default_max_parallel_tools(),
+            max_job_secs: default_max_job_secs(),
+            watchdog_cancel: false,
+            persist_tool_outputs: true,
+            persist_job_conversations: true,
+            max_task_chars: default_max_task_chars(),
+            max_message_chars: default_max_message_chars(),
+            retrieval_weights: default_retrieval_weights(),
+            prefilter_weights: default_prefilter_weights(),
+            memory_context_token_budget: default_memory_context_token_budget(),
+            max_embedding_input_chars: default_max_embedding_input_chars(),
+            max_lessons_per_shard: 0,
+            journal_every: default_journal_every(),
+            wild_drift_idle_secs: 0,
+            accept_wild_drift: false,
+            dht_audit_interval_secs: default_dht_audit_interval_secs(),
+            auto_attest_level_delta: 0,
+            action_retention_days: 0,
+            prewarm_embeddings: false,
+            cleanup_on_release: false,
+            cleanup_on_delete: true,
+            shard_cache_size: default_shard_cache_size(),
+            workspace_quota_bytes: default_workspace_quota_bytes(),
         }
     }
 }
 
 impl Config {
+    /// Inference settings (endpoint, model, key, and reply limits) from this
+    /// config. No limiter is attached; the API adds its shared one.
+    pub fn inference_config(&self) -> InferenceConfig {
+        InferenceConfig {
+            api_key: self.openai_api_key.clone().unwrap_or_default(),
+            api_url: self.inference_url.clone(),
+            model: self.inference_model.clone(),
+            max_tokens: self.inference_max_tokens,
+            temperature: self.inference_temperature,
+            supports_vision: self.inference_vision,
+            tool_protocol: self.tool_protocol,
+            max_embedding_input_chars: self.max_embedding_input_chars,
+            auth_style: self.inference_auth,
+            headers: self.inference_headers.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// A copy that is safe to return over the API: key values and
+    /// secret-looking inference headers are replaced, credentials and query
+    /// strings are stripped from URLs, and the RPC URL (which often carries a
+    /// provider key in its path) is cut to its origin. Key file paths are
+    /// kept; they name files rather than hold secrets.
+    pub fn redacted(&self) -> Config {
+        let hide = |value: &Option<String>| value.as_ref().map(|_| REDACTED.to_string());
+        let inference_headers = self
+            .inference_headers
+            .iter()
+            .map(|(name, value)| {
+                let value = if inference::is_secret_header(name, value) { REDACTED } else { value.as_str() };
+                (name.clone(), value.to_string())
+            })
+            .collect();
+        Config {
+            openai_api_key: hide(&self.openai_api_key),
+            api_key: hide(&self.api_key),
+            inference_headers,
+            rpc_url: redact_url(&self.rpc_url, true),
+            inference_url: redact_url(&self.inference_url, false),
+            ..self.clone()
+        }
+    }
+
+    /// Stat cap and overflow policy for stat gains.
+    pub fn stat_limits(&self) -> StatLimits {
+        StatLimits {
+            cap: self.stat_cap,
+            overflow: self.stat_overflow,
+        }
+    }
+
+    /// `stat_limits` for one shard, honoring `genome_stat_caps`.
+    pub fn stat_limits_for(&self, shard: &Shard) -> StatLimits {
+        shard.stat_limits(self.stat_limits(), self.genome_stat_caps)
+    }
+
+    pub fn penalty_policy(&self) -> PenaltyPolicy {
+        PenaltyPolicy {
+            threshold: self.penalty_failure_threshold,
+            penalty: self.penalty_reputation,
+            recovery: self.reputation_recovery,
+        }
+    }
+
+    pub fn failure_cooldown(&self) -> FailureCooldown {
+        FailureCooldown {
+            threshold: self.failure_cooldown_threshold,
+            base_secs: self.failure_cooldown_secs,
+            max_secs: self.failure_cooldown_max_secs,
+        }
+    }
+
     /// Returns the path to the config file: ~/.siphon/config.toml
     pub fn config_path() -> PathBuf {
         let home = dirs_fallback();
@@ -122,12 +784,57 @@ impl Config {
         let contents = fs::read_to_string(&path)
             .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
 
-        let config: Config = toml::from_str(&contents)
-            .map_err(|e| format!("Failed to parse config: {}", e))?;
+        let config = Self::parse(&contents)?;
+        for (language, interpreter) in &config.code_languages {
+            executor::validate_code_language(language, interpreter)?;
+        }
+        config.retrieval_weights.validate("retrieval_weights")?;
+        config.prefilter_weights.validate("prefilter_weights")?;
+        validate_inference_headers(&config.inference_headers)?;
 
         Ok(config)
     }
 
+    /// Parse config file contents and apply `provider_preset`.
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut config: Config =
+            toml::from_str(contents).map_err(|e| format!("Failed to parse config: {}", e))?;
+        let overrides: InferenceOverrides =
+            toml::from_str(contents).map_err(|e| format!("Failed to parse config: {}", e))?;
+        config.resolve_provider(&overrides)?;
+        Ok(config)
+    }
+
+    /// Apply `provider_preset` to the inference fields `overrides` leaves
+    /// unset; anything configured explicitly is kept, even when it matches
+    /// the built-in default.
+    pub fn resolve_provider(&mut self, overrides: &InferenceOverrides) -> Result<(), String> {
+        let Some(name) = self.provider_preset.as_deref() else {
+            return Ok(());
+        };
+        let preset = ProviderPreset::named(name).ok_or_else(|| {
+            format!(
+                "Unknown provider_preset '{}' (expected one of: {})",
+                name,
+                PROVIDER_PRESETS.iter().map(|p| p.name).collect::<Vec<_>>().join(", ")
+            )
+        })?;
+
+        if overrides.inference_provider.is_none() {
+            self.inference_provider = preset.provider.to_string();
+        }
+        if overrides.inference_url.is_none() {
+            self.inference_url = preset.url.to_string();
+        }
+        if overrides.inference_model.is_none() {
+            self.inference_model = preset.model.to_string();
+        }
+        if overrides.inference_auth.is_none() {
+            self.inference_auth = preset.auth;
+        }
+        Ok(())
+    }
+
     /// Create a default configuration file at ~/.siphon/config.toml.
     /// Also creates the data directory if it doesn't exist.
     /// Returns the path to the created file.
@@ -143,15 +850,26 @@ impl Config {
 # JSON-RPC URL for Base Sepolia
 rpc_url = "https://sepolia.base.org"
 
-# Path to the keeper's private key file
+# Path to the keeper's private key file. The file may contain either a raw
+# hex private key or a BIP-39 mnemonic (SIPHON_KEEPER_MNEMONIC overrides it).
 private_key_path = "~/.siphon/keeper.key"
 
+# HD derivation path used when the key is a mnemonic
+# key_derivation_path = "m/44'/60'/0'/0/0"
+
 # Local data directory for SQLite and logs
 data_dir = "~/.siphon/data"
 
 # P2P listen port
 listen_port = 9000
 
+# P2P WebSocket listen port (0 disables). Skipped with a warning if it clashes
+# with the P2P or HTTP port or is already in use.
+ws_port = 9001
+
+# Seconds before an idle P2P connection is closed
+p2p_idle_timeout_secs = 60
+
 # Bootstrap peer multiaddrs (add peers to join the network)
 bootstrap_peers = []
 
@@ -174,23 +892,240 @@ bootstrap_peers = []
 # LoanVault contract address on Base Sepolia
 # loan_vault_address = "0x..."
 
+# Provider preset: "openai", "ollama", "groq", "together", "openrouter" or
+# "azure". Fills in whichever of the provider, URL, model and auth style
+# below are left unset; any you set win, even at their default values. With
+# "azure", also set inference_url to your deployment's chat completions URL.
+# provider_preset = "ollama"
+
 # Inference provider: "openai", "ollama", or any OpenAI-compatible service
-inference_provider = "openai"
+# (default "openai")
+# inference_provider = "openai"
 
 # Base URL for the inference API (chat completions endpoint)
-inference_url = "https://api.openai.com/v1/chat/completions"
+# inference_url = "https://api.openai.com/v1/chat/completions"
 
 # Model name for inference requests
-inference_model = "gpt-4o-mini"
+# inference_model = "gpt-4o-mini"
+
+# How openai_api_key is sent: "bearer" (Authorization: Bearer) or "api_key"
+# (an api-key header, as Azure OpenAI expects)
+# inference_auth = "bearer"
+
+# Extra headers for every inference request. Secret-looking values are
+# redacted from logs and GET /api/config.
+# inference_headers = { "HTTP-Referer" = "https://example.com", "X-Title" = "Siphon" }
+
+# Set to true if the model accepts image inputs (e.g. gpt-4o); image tool
+# output is then shown to the model instead of summarized
+inference_vision = false
+
+# "native" sends tools via the API's function calling; "prompt_embedded"
+# describes them in the system prompt and parses calls out of the reply,
+# for local models without function calling
+tool_protocol = "native"
+
+# Inference "user" field for provider-side abuse monitoring: "none", "shard"
+# (the shard id) or "owner" (the caller's x-owner-id header)
+inference_user = "none"
+
+# Reply length and sampling temperature for chat/training replies
+inference_max_tokens = 512
+inference_temperature = 0.7
+
+# Wrap training messages in delimiters and reinforce the shard's persona so
+# "ignore previous instructions" style messages can't rewrite it. Obvious
+# override attempts are logged.
+persona_lock = false
+
+# Most model calls in flight at once, shared by sync and background
+# executions, training and journals (0 = unlimited)
+max_concurrent_inference = 8
 
 # HTTP API port for the keeper's REST API
 http_port = 3001
 
+# Highest shard level; shards at the cap can prestige back to level 1
+# with a permanent XP multiplier
+level_cap = 100
+
+# Ceiling for each shard stat. Bonuses past it are redistributed to the
+# lowest stats ("redistribute") or converted to XP ("xp")
+stat_cap = 250
+stat_overflow = "redistribute"
+# Give each shard its own ceiling, 70-100% of stat_cap, derived from its genome
+genome_stat_caps = false
+
+# After this many failed executions in a row a shard cools down and refuses
+# executions (HTTP 429) until it elapses. The cooldown starts at
+# failure_cooldown_secs and doubles per further failure, up to the max; a
+# success resets it. 0 disables.
+failure_cooldown_threshold = 3
+failure_cooldown_secs = 60
+failure_cooldown_max_secs = 3600
+
+# Keeper reputation (starts at 100). From penalty_failure_threshold failed
+# liquidations or attestations in a row, each further failure costs
+# penalty_reputation and each successful one wins back reputation_recovery,
+# up to 100; below reputation_warning_below the keeper gossips a warning.
+# 0 disables any of them.
+penalty_failure_threshold = 3
+penalty_reputation = 5
+reputation_recovery = 1
+reputation_warning_below = 50
+
+# Gossip messages held for retry while no peers are subscribed yet; when
+# full the oldest is dropped. Only the newest heartbeat is ever kept.
+publish_queue_capacity = 64
+
+# Hosts that per-request inference_url overrides may point at. Leave unset to
+# accept any host (not recommended when serving untrusted clients).
+# allowed_inference_hosts = ["api.openai.com", "localhost"]
+
+# Spawn rate limits (per minute, 0 = unlimited). Requests over the limit are
+# rejected with HTTP 429 and a Retry-After header.
+spawn_limit_per_minute = 30
+spawn_limit_per_owner_per_minute = 5
+
+# Maximum shards one owner (x-owner-id) may hold, counting spawns and market
+# purchases. Requests past the quota are rejected with HTTP 409. 0 = unlimited.
+max_shards_per_owner = 0
+
+# Owner ids (x-owner-id) allowed to pause and resume executions and change
+# read-only policy under /api/admin, read GET /api/config and
+# /api/logs/stream, and run POST /api/maintenance/dedupe. Empty = nobody.
+# admin_owners = ["0x..."]
+
+# Optional TLS for the HTTP API. Set tls_client_ca_path as well to require
+# client certificates (mTLS); verified clients skip the bearer token check.
+# tls_cert_path = "~/.siphon/tls/server.pem"
+# tls_key_path = "~/.siphon/tls/server.key"
+# tls_client_ca_path = "~/.siphon/tls/clients-ca.pem"
+
+# Tool output caps (bytes). Longer output is cut with a truncation marker.
+max_tool_output_bytes = 50000
+max_execution_output_bytes = 200000
+
+# Read-only tool calls from one turn run concurrently, at most this many at
+# once; file_write, shell_exec and code_eval always run one at a time
+max_parallel_tools = 4
+# tool_output_limits = { shell_exec = 20000 }
+
+# Largest a shard's workspace may grow through file_write, in bytes
+# (0 = unlimited)
+workspace_quota_bytes = 104857600
+
+# Tools withheld from every execution, regardless of shard capabilities
+# disabled_tools = ["shell_exec"]
+
+# Agent-loop turns for executions that don't set max_turns
+default_max_turns = 5
+# Requests asking for more turns than this are clamped to it
+max_turns_ceiling = 20
+
+# Background jobs running longer than this many seconds are logged (0 disables);
+# set watchdog_cancel to abort them and return their shard to idle
+max_job_secs = 1800
+watchdog_cancel = false
+
+# Set to false to store only a size/hash summary of tool output (fetched
+# pages, file contents, command output) instead of the full text. Executions
+# can override this with "persist_tool_outputs" in the request body.
+persist_tool_outputs = true
+
+# Save background job conversations so POST /api/jobs/{id}/resume can continue
+# them for more turns
+persist_job_conversations = true
+
+# Longest execute task / train message accepted, in characters; longer
+# input is rejected with 400
+max_task_chars = 8000
+max_message_chars = 4000
+
+# Token ceiling for prior lessons injected into execution prompts; lessons
+# past the budget are left out
+memory_context_token_budget = 800
+
+# Lesson text sent for embedding is cut to this many characters, keeping the
+# goal and approach ahead of outcome, errors and fixes (0 = no limit)
+max_embedding_input_chars = 2000
+
+# Lessons kept per shard. Past this count the lowest-scoring lessons are
+# pruned as new ones are drawn; pinned lessons are always kept (0 = keep all)
+max_lessons_per_shard = 0
+
+# Shards write a short reflective journal entry every N successful
+# executions (0 disables; skipped when inference is not configured)
+journal_every = 10
+
+# Wild drift: offer wild, unowned shards idle longer than this many seconds
+# to other keepers (0 disables), and whether to take in drifting shards
+wild_drift_idle_secs = 0
+accept_wild_drift = false
+
+# Every N seconds, re-bootstrap the DHT, publish records for shards that have
+# none (or a stale one) and stop serving records of removed shards (0 disables)
+dht_audit_interval_secs = 600
+
+# Re-attest a shard's value on-chain after it gains this many levels since
+# its last attestation (0 disables; needs shard_valuation_address)
+auto_attest_level_delta = 0
+
+# Finished actions are rolled up into per-day totals hourly. Raw action rows
+# older than this many days are then pruned (0 keeps them forever)
+action_retention_days = 0
+
+# Embed recent lessons in the background on startup so the first execution
+# retrieves from a warm embedding cache
+prewarm_embeddings = false
+
+# Remove a shard's workspaces/, memories/tasks/ and artifacts/ directories
+# when it is released or deleted. Leftovers from shards that no longer exist
+# can be removed with `siphon-keeper maintenance gc-workspaces`
+cleanup_on_release = false
+cleanup_on_delete = true
+
+# Shards kept in memory for the API's hot lookups (0 disables). Entries are
+# refreshed whenever this keeper writes the shard
+shard_cache_size = 256
+
 # --- Ollama example (uncomment to use local inference) ---
 # inference_provider = "ollama"
 # inference_url = "http://localhost:11434/v1/chat/completions"
 # inference_model = "llama3.2"
 # openai_api_key = ""
+
+# --- Extra code_eval languages (python and javascript are built in) ---
+# The interpreter runs as `command [args...] _eval.<extension>` and must be
+# one of: python3, python, node, deno, bun, ruby, perl, php, lua, bash, sh, Rscript
+# [code_languages.ruby]
+# command = "ruby"
+# extension = "rb"
+# [code_languages.typescript]
+# command = "deno"
+# args = ["run", "--quiet"]
+# extension = "ts"
+
+# Lesson retrieval weights (all non-negative; set every field when overriding).
+# The prefilter ranks candidates without embeddings, so its semantic weight
+# is unused; retrieval_weights rank the candidates that make it through.
+# [retrieval_weights]
+# semantic = 0.55
+# lexical = 0.20
+# score = 0.17
+# helpful = 0.08
+# recency = 0.0
+# type_match = 0.08
+# pinned = 0.05
+#
+# [prefilter_weights]
+# semantic = 0.0
+# lexical = 0.45
+# score = 0.30
+# helpful = 0.15
+# recency = 0.10
+# type_match = 0.2
+# pinned = 0.05
 "#;
 
         fs::write(&path, default_toml)
@@ -205,6 +1140,28 @@ http_port = 3001
     }
 }
 
+/// Reject `inference_headers` entries that are not valid HTTP headers, so
+/// they fail at startup rather than on every inference request.
+fn validate_inference_headers(headers: &HashMap<String, String>) -> Result<(), String> {
+    for (name, value) in headers {
+        reqwest::header::HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| format!("inference_headers: invalid header name '{}'", name))?;
+        reqwest::header::HeaderValue::from_str(value)
+            .map_err(|_| format!("inference_headers: invalid value for '{}'", name))?;
+    }
+    Ok(())
+}
+
+/// Expand ~ to home directory in paths.
+pub fn shellexpand(path: &str) -> String {
+    if path.starts_with("~/") {
+        if let Ok(home) = std::env::var("HOME") {
+            return format!("{}{}", home, &path[1..]);
+        }
+    }
+    path.to_string()
+}
+
 /// Simple fallback for getting the home directory without adding another dependency.
 fn dirs_fallback() -> PathBuf {
     if let Ok(home) = std::env::var("HOME") {
@@ -216,6 +1173,23 @@ fn dirs_fallback() -> PathBuf {
     }
 }
 
+/// Placeholder shown in place of secret values.
+pub const REDACTED: &str = "[redacted]";
+
+fn redact_url(url: &str, origin_only: bool) -> String {
+    let Ok(mut parsed) = reqwest::Url::parse(url) else {
+        return if url.is_empty() { String::new() } else { REDACTED.to_string() };
+    };
+    let _ = parsed.set_username("");
+    let _ = parsed.set_password(None);
+    parsed.set_query(None);
+    parsed.set_fragment(None);
+    if origin_only && parsed.path() != "/" {
+        parsed.set_path(REDACTED);
+    }
+    parsed.to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -263,4 +1237,91 @@ mod tests {
         assert_eq!(cfg.inference_model, "llama3.2");
         assert_eq!(cfg.http_port, 8080);
     }
+
+    #[test]
+    fn provider_presets_fill_inference_fields() {
+        for (name, provider, url) in [
+            ("openai", "openai", "https://api.openai.com/v1/chat/completions"),
+            ("ollama", "ollama", "http://localhost:11434/v1/chat/completions"),
+            ("groq", "groq", "https://api.groq.com/openai/v1/chat/completions"),
+            ("Together", "together", "https://api.together.xyz/v1/chat/completions"),
+        ] {
+            let mut cfg = Config {
+                provider_preset: Some(name.to_string()),
+                ..Config::default()
+            };
+            cfg.resolve_provider(&InferenceOverrides::default()).unwrap();
+            assert_eq!(cfg.inference_provider, provider, "{}", name);
+            assert_eq!(cfg.inference_url, url, "{}", name);
+        }
+
+        let mut cfg = Config {
+            provider_preset: Some("nope".to_string()),
+            ..Config::default()
+        };
+        assert!(cfg.resolve_provider(&InferenceOverrides::default()).unwrap_err().contains("nope"));
+    }
+
+    #[test]
+    fn azure_preset_sends_key_as_api_key_header() {
+        let mut cfg = Config {
+            provider_preset: Some("azure".to_string()),
+            ..Config::default()
+        };
+        cfg.resolve_provider(&InferenceOverrides::default()).unwrap();
+        assert_eq!(cfg.inference_auth, AuthStyle::ApiKey);
+        assert_eq!(cfg.inference_config().auth_style, AuthStyle::ApiKey);
+    }
+
+    #[test]
+    fn redacted_hides_secret_inference_headers() {
+        let cfg = Config {
+            inference_headers: HashMap::from([
+                ("X-Title".to_string(), "Siphon".to_string()),
+                ("X-Api-Key".to_string(), "hunter2".to_string()),
+            ]),
+            ..Config::default()
+        };
+        let redacted = cfg.redacted();
+        assert_eq!(redacted.inference_headers["X-Title"], "Siphon");
+        assert_eq!(redacted.inference_headers["X-Api-Key"], REDACTED);
+        assert!(validate_inference_headers(&cfg.inference_headers).is_ok());
+        let bad = HashMap::from([("bad header".to_string(), "x".to_string())]);
+        assert!(validate_inference_headers(&bad).is_err());
+    }
+
+    #[test]
+    fn explicit_fields_override_preset() {
+        let toml_str = r#"
+            rpc_url = "https://sepolia.base.org"
+            private_key_path = "~/.siphon/keeper.key"
+            data_dir = "~/.siphon/data"
+            listen_port = 9000
+            provider_preset = "ollama"
+            inference_url = "http://gpu-box:11434/v1/chat/completions"
+        "#;
+        let cfg = Config::parse(toml_str).unwrap();
+        assert_eq!(cfg.inference_provider, "ollama");
+        assert_eq!(cfg.inference_url, "http://gpu-box:11434/v1/chat/completions");
+        assert_eq!(cfg.inference_model, "llama3.2");
+    }
+
+    #[test]
+    fn explicit_default_values_override_preset() {
+        let toml_str = r#"
+            rpc_url = "https://sepolia.base.org"
+            private_key_path = "~/.siphon/keeper.key"
+            data_dir = "~/.siphon/data"
+            listen_port = 9000
+            provider_preset = "azure"
+            inference_provider = "openai"
+            inference_url = "https://api.openai.com/v1/chat/completions"
+            inference_model = "gpt-4o-mini"
+            inference_auth = "bearer"
+        "#;
+        let cfg = Config::parse(toml_str).unwrap();
+        assert_eq!(cfg.inference_provider, "openai");
+        assert_eq!(cfg.inference_url, "https://api.openai.com/v1/chat/completions");
+        assert_eq!(cfg.inference_auth, AuthStyle::Bearer);
+    }
 }
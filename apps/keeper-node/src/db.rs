@@ -1,7 +1,11 @@
 use rusqlite::{Connection, Result as SqliteResult, params};
+use std::collections::HashMap;
 use std::path::Path;
 
-use crate::shard::Shard;
+use crate::battle::BattleOutcome;
+use crate::benchmark::Summary;
+use crate::config::shellexpand;
+use crate::shard::{FailureCooldown, Shard, ShardStats, StatLimits};
 
 /// Get the path to the SQLite database file within the data directory.
 fn db_path(data_dir: &str) -> String {
@@ -13,7 +17,10 @@ fn db_path(data_dir: &str) -> String {
 /// Open a connection to the keeper database.
 fn open_db(data_dir: &str) -> SqliteResult<Connection> {
     let path = db_path(data_dir);
-    Connection::open(&path)
+    let conn = Connection::open(&path)?;
+    // Concurrent writers wait for the lock instead of failing with SQLITE_BUSY
+    conn.busy_timeout(std::time::Duration::from_secs(5))?;
+    Ok(conn)
 }
 
 /// Initialize the database, creating tables if they don't exist.
@@ -45,7 +52,10 @@ pub fn init_db(data_dir: &str) -> SqliteResult<()> {
             execution_state TEXT NOT NULL DEFAULT 'idle',
             capabilities_json TEXT NOT NULL DEFAULT '{}',
             tasks_completed INTEGER NOT NULL DEFAULT 0,
-            tasks_failed INTEGER NOT NULL DEFAULT 0
+            tasks_failed INTEGER NOT NULL DEFAULT 0,
+            prestige INTEGER NOT NULL DEFAULT 0,
+            xp_multiplier REAL NOT NULL DEFAULT 1.0,
+            read_only INTEGER NOT NULL DEFAULT 0
         );
 
         CREATE TABLE IF NOT EXISTS interactions (
@@ -74,7 +84,54 @@ pub fn init_db(data_dir: &str) -> SqliteResult<()> {
             xp_awarded INTEGER NOT NULL DEFAULT 0,
             stat_bonuses TEXT,
             started_at INTEGER NOT NULL,
-            completed_at INTEGER
+            completed_at INTEGER,
+            inference_ms INTEGER,
+            tool_ms INTEGER,
+            request_id TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS jobs (
+            id TEXT PRIMARY KEY,
+            shard_id TEXT NOT NULL,
+            task TEXT NOT NULL,
+            status TEXT NOT NULL,
+            result_json TEXT,
+            error TEXT,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS shard_snapshots (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            shard_id TEXT NOT NULL,
+            label TEXT,
+            shard_json TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS pending_questions (
+            shard_id TEXT PRIMARY KEY,
+            task TEXT NOT NULL,
+            call_id TEXT NOT NULL,
+            question TEXT NOT NULL,
+            conversation_json TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS journal (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            shard_id TEXT NOT NULL,
+            content TEXT NOT NULL,
+            tasks_completed INTEGER NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS archived_shards (
+            id TEXT PRIMARY KEY,
+            genome_hash TEXT NOT NULL,
+            merged_into TEXT NOT NULL,
+            shard_json TEXT NOT NULL,
+            archived_at INTEGER NOT NULL
         );
 
         CREATE TABLE IF NOT EXISTS tracked_loans (
@@ -83,6 +140,41 @@ pub fn init_db(data_dir: &str) -> SqliteResult<()> {
             added_at INTEGER NOT NULL
         );
 
+        CREATE TABLE IF NOT EXISTS listings (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            shard_id TEXT NOT NULL,
+            price_wei TEXT NOT NULL,
+            seller TEXT NOT NULL,
+            buyer TEXT,
+            status TEXT NOT NULL DEFAULT 'open',
+            created_at INTEGER NOT NULL,
+            closed_at INTEGER
+        );
+
+        CREATE TABLE IF NOT EXISTS benchmarks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            shard_id TEXT NOT NULL,
+            suite_version TEXT NOT NULL,
+            score REAL NOT NULL,
+            tasks_passed INTEGER NOT NULL,
+            tasks_total INTEGER NOT NULL,
+            total_duration_ms INTEGER NOT NULL,
+            results_json TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS battles (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            shard_id TEXT NOT NULL,
+            opponent_id TEXT NOT NULL,
+            won INTEGER NOT NULL,
+            rounds INTEGER NOT NULL,
+            elo_before INTEGER NOT NULL,
+            elo_after INTEGER NOT NULL,
+            seed TEXT NOT NULL,
+            fought_at INTEGER NOT NULL
+        );
+
         CREATE TABLE IF NOT EXISTS task_lessons (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             shard_id TEXT NOT NULL REFERENCES shards(id),
@@ -105,7 +197,17 @@ pub fn init_db(data_dir: &str) -> SqliteResult<()> {
             times_helpful INTEGER NOT NULL DEFAULT 0,
             times_unhelpful INTEGER NOT NULL DEFAULT 0,
             created_at INTEGER NOT NULL,
-            updated_at INTEGER NOT NULL
+            updated_at INTEGER NOT NULL,
+            pinned INTEGER NOT NULL DEFAULT 0
+        );
+
+        CREATE TABLE IF NOT EXISTS lesson_embeddings (
+            lesson_id INTEGER NOT NULL,
+            model TEXT NOT NULL,
+            text_hash TEXT NOT NULL,
+            vector_json TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (lesson_id, model)
         );
 
         CREATE TABLE IF NOT EXISTS lesson_retrieval_events (
@@ -123,12 +225,40 @@ pub fn init_db(data_dir: &str) -> SqliteResult<()> {
             helpful INTEGER
         );
 
+        CREATE TABLE IF NOT EXISTS action_daily (
+            shard_id TEXT NOT NULL,
+            day TEXT NOT NULL,
+            tool_name TEXT NOT NULL,
+            actions INTEGER NOT NULL DEFAULT 0,
+            succeeded INTEGER NOT NULL DEFAULT 0,
+            failed INTEGER NOT NULL DEFAULT 0,
+            xp INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (shard_id, day, tool_name)
+        );
+
+        CREATE TABLE IF NOT EXISTS shard_relationships (
+            shard_id TEXT NOT NULL,
+            other_id TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            count INTEGER NOT NULL DEFAULT 0,
+            first_at INTEGER NOT NULL,
+            last_at INTEGER NOT NULL,
+            PRIMARY KEY (shard_id, other_id, kind)
+        );
+
         CREATE INDEX IF NOT EXISTS idx_shards_type ON shards(shard_type);
         CREATE INDEX IF NOT EXISTS idx_shards_wild ON shards(is_wild);
         CREATE INDEX IF NOT EXISTS idx_interactions_shard ON interactions(shard_id);
         CREATE INDEX IF NOT EXISTS idx_action_log_shard ON action_log(shard_id);
         CREATE INDEX IF NOT EXISTS idx_action_log_status ON action_log(status);
         CREATE INDEX IF NOT EXISTS idx_tracked_loans_state ON tracked_loans(state);
+        CREATE INDEX IF NOT EXISTS idx_listings_status ON listings(status);
+        CREATE INDEX IF NOT EXISTS idx_shard_relationships_other ON shard_relationships(other_id);
+        CREATE INDEX IF NOT EXISTS idx_benchmarks_shard_created ON benchmarks(shard_id, created_at DESC);
+        CREATE INDEX IF NOT EXISTS idx_battles_shard_fought ON battles(shard_id, fought_at DESC);
+        CREATE INDEX IF NOT EXISTS idx_shard_snapshots_shard_created ON shard_snapshots(shard_id, created_at DESC);
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_listings_open_shard ON listings(shard_id) WHERE status = 'open';
+        CREATE INDEX IF NOT EXISTS idx_jobs_shard_created ON jobs(shard_id, created_at DESC);
         CREATE INDEX IF NOT EXISTS idx_task_lessons_shard_created ON task_lessons(shard_id, created_at DESC);
         CREATE INDEX IF NOT EXISTS idx_task_lessons_shard_type ON task_lessons(shard_id, task_type);
         CREATE INDEX IF NOT EXISTS idx_task_lessons_score ON task_lessons(score DESC);
@@ -161,6 +291,28 @@ pub fn init_db(data_dir: &str) -> SqliteResult<()> {
         "tasks_failed",
         "INTEGER NOT NULL DEFAULT 0",
     )?;
+    ensure_column_exists(&conn, "shards", "prestige", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column_exists(&conn, "shards", "xp_multiplier", "REAL NOT NULL DEFAULT 1.0")?;
+    ensure_column_exists(&conn, "action_log", "inference_ms", "INTEGER")?;
+    ensure_column_exists(&conn, "action_log", "tool_ms", "INTEGER")?;
+    ensure_column_exists(&conn, "action_log", "request_id", "TEXT")?;
+    ensure_column_exists(&conn, "action_log", "aggregated", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column_exists(&conn, "task_lessons", "pinned", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column_exists(&conn, "shards", "last_attested_level", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column_exists(&conn, "jobs", "conversation_json", "TEXT")?;
+    ensure_column_exists(&conn, "jobs", "request_json", "TEXT")?;
+    ensure_column_exists(&conn, "pending_questions", "request_json", "TEXT")?;
+    ensure_column_exists(&conn, "pending_questions", "action_id", "INTEGER")?;
+    ensure_column_exists(&conn, "shards", "consecutive_failures", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column_exists(&conn, "shards", "cooldown_until", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column_exists(&conn, "shards", "read_only", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column_exists(&conn, "listings", "payment_tx", "TEXT")?;
+    // A payment settles one sale only
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_listings_payment_tx ON listings(payment_tx)
+         WHERE payment_tx IS NOT NULL",
+        [],
+    )?;
 
     tracing::info!("Database initialized at {}", db_path(data_dir));
     Ok(())
@@ -191,7 +343,40 @@ fn ensure_column_exists(
 /// Insert a new shard into the database.
 pub fn insert_shard(data_dir: &str, shard: &Shard) -> SqliteResult<()> {
     let conn = open_db(data_dir)?;
+    insert_shard_row(&conn, shard)
+}
+
+/// Insert a new shard unless its owner already holds `max_per_owner` shards
+/// (0 = no limit). The count and the insert share a transaction, so
+/// concurrent inserts can't both squeeze under the limit. Returns whether
+/// the shard was inserted.
+pub fn insert_shard_within_quota(data_dir: &str, shard: &Shard, max_per_owner: u32) -> SqliteResult<bool> {
+    let mut conn = open_db(data_dir)?;
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+    if let Some(owner) = &shard.owner_id {
+        if owner_at_quota(&tx, owner, max_per_owner)? {
+            return Ok(false);
+        }
+    }
+    insert_shard_row(&tx, shard)?;
+    tx.commit()?;
+    Ok(true)
+}
 
+/// Whether `owner` already holds `max` shards (0 = no limit).
+fn owner_at_quota(conn: &Connection, owner: &str, max: u32) -> SqliteResult<bool> {
+    if max == 0 {
+        return Ok(false);
+    }
+    let held: u32 = conn.query_row(
+        "SELECT COUNT(*) FROM main.shards WHERE lower(owner_id) = lower(?1)",
+        params![owner],
+        |row| row.get(0),
+    )?;
+    Ok(held >= max)
+}
+
+fn insert_shard_row(conn: &Connection, shard: &Shard) -> SqliteResult<()> {
     let avatar_json = serde_json::to_string(&shard.avatar).unwrap_or_default();
     let stats_json = serde_json::to_string(&shard.stats).unwrap_or_default();
 
@@ -206,8 +391,9 @@ pub fn insert_shard(data_dir: &str, shard: &Shard) -> SqliteResult<()> {
             id, genome_hash, shard_type, species, name, level, xp,
             owner_id, is_wild, avatar_json, personality, stats_json,
             decay_factor, created_at, last_interaction, elo_rating,
-            execution_state, capabilities_json, tasks_completed, tasks_failed
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
+            execution_state, capabilities_json, tasks_completed, tasks_failed,
+            prestige, xp_multiplier, read_only
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23)",
         params![
             shard.id,
             shard.genome_hash,
@@ -229,6 +415,9 @@ pub fn insert_shard(data_dir: &str, shard: &Shard) -> SqliteResult<()> {
             capabilities_json,
             shard.tasks_completed,
             shard.tasks_failed,
+            shard.prestige,
+            shard.xp_multiplier,
+            shard.read_only as i32,
         ],
     )?;
 
@@ -244,20 +433,29 @@ pub fn get_shards(data_dir: &str) -> SqliteResult<Vec<Shard>> {
         "SELECT id, genome_hash, shard_type, species, name, level, xp,
                 owner_id, is_wild, avatar_json, personality, stats_json,
                 decay_factor, created_at, last_interaction, elo_rating,
-                execution_state, capabilities_json, tasks_completed, tasks_failed
+                execution_state, capabilities_json, tasks_completed, tasks_failed,
+                prestige, xp_multiplier, read_only
          FROM shards
          ORDER BY created_at DESC",
     )?;
 
     let shards = stmt
-        .query_map([], |row| {
-            Ok(row_to_shard(row)?)
-        })?
+        .query_map([], row_to_shard)?
         .collect::<SqliteResult<Vec<_>>>()?;
 
     Ok(shards)
 }
 
+/// Number of shards held by `owner_id` (case-insensitive).
+pub fn count_shards_by_owner(data_dir: &str, owner_id: &str) -> SqliteResult<u32> {
+    let conn = open_db(data_dir)?;
+    conn.query_row(
+        "SELECT COUNT(*) FROM shards WHERE lower(owner_id) = lower(?1)",
+        params![owner_id],
+        |row| row.get(0),
+    )
+}
+
 /// Parse a shard from a database row (shared between get_shards and get_shard_by_id).
 fn row_to_shard(row: &rusqlite::Row) -> SqliteResult<Shard> {
     let avatar_json: String = row.get(9)?;
@@ -312,6 +510,9 @@ fn row_to_shard(row: &rusqlite::Row) -> SqliteResult<Shard> {
         capabilities,
         tasks_completed: row.get(18)?,
         tasks_failed: row.get(19)?,
+        prestige: row.get(20)?,
+        xp_multiplier: row.get(21)?,
+        read_only: row.get::<_, i32>(22)? != 0,
     })
 }
 
@@ -339,8 +540,10 @@ pub fn update_shard(data_dir: &str, shard: &Shard) -> SqliteResult<()> {
             execution_state = ?9,
             capabilities_json = ?10,
             tasks_completed = ?11,
-            tasks_failed = ?12
-         WHERE id = ?13",
+            tasks_failed = ?12,
+            prestige = ?13,
+            xp_multiplier = ?14
+         WHERE id = ?15",
         params![
             shard.level,
             shard.xp,
@@ -354,6 +557,8 @@ pub fn update_shard(data_dir: &str, shard: &Shard) -> SqliteResult<()> {
             capabilities_json,
             shard.tasks_completed,
             shard.tasks_failed,
+            shard.prestige,
+            shard.xp_multiplier,
             shard.id,
         ],
     )?;
@@ -362,210 +567,1260 @@ pub fn update_shard(data_dir: &str, shard: &Shard) -> SqliteResult<()> {
     Ok(())
 }
 
-/// Delete a shard from the database by ID.
-pub fn delete_shard(data_dir: &str, shard_id: &str) -> SqliteResult<()> {
+/// Mark a shard executing if it is idle or its failure cooldown is over by
+/// `now`. Returns whether it was claimed.
+pub fn claim_shard_for_execution(data_dir: &str, shard_id: &str, now: u64) -> SqliteResult<bool> {
     let conn = open_db(data_dir)?;
-    conn.execute("DELETE FROM shards WHERE id = ?1", params![shard_id])?;
-    tracing::debug!("Deleted shard {} from database", &shard_id[..8.min(shard_id.len())]);
-    Ok(())
+    let changed = conn.execute(
+        "UPDATE shards SET execution_state = 'executing'
+         WHERE id = ?1
+           AND (execution_state = 'idle' OR (execution_state = 'cooldown' AND cooldown_until <= ?2))",
+        params![shard_id, now],
+    )?;
+    Ok(changed > 0)
 }
 
-/// A single interaction (message) in a shard's training history.
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct Interaction {
-    pub id: i64,
-    pub shard_id: String,
-    pub role: String,
-    pub content: String,
-    pub xp_gained: u32,
-    pub timestamp: u64,
+/// Mark a shard executing again to answer its pending question, if it is
+/// still waiting for input. Returns whether it was claimed.
+pub fn claim_waiting_shard(data_dir: &str, shard_id: &str) -> SqliteResult<bool> {
+    let conn = open_db(data_dir)?;
+    let changed = conn.execute(
+        "UPDATE shards SET execution_state = 'executing' WHERE id = ?1 AND execution_state = 'waiting_for_input'",
+        params![shard_id],
+    )?;
+    Ok(changed > 0)
 }
 
-/// Insert a new interaction into the database.
-pub fn insert_interaction(
-    data_dir: &str,
-    shard_id: &str,
-    role: &str,
-    content: &str,
-    xp_gained: u32,
-) -> SqliteResult<i64> {
+/// Park an executing shard until its question is answered. Returns whether
+/// it was still executing.
+pub fn set_waiting_for_input(data_dir: &str, shard_id: &str) -> SqliteResult<bool> {
     let conn = open_db(data_dir)?;
-    let timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis() as u64;
+    let changed = conn.execute(
+        "UPDATE shards SET execution_state = 'waiting_for_input' WHERE id = ?1 AND execution_state = 'executing'",
+        params![shard_id],
+    )?;
+    Ok(changed > 0)
+}
 
-    conn.execute(
-        "INSERT INTO interactions (shard_id, role, content, xp_gained, timestamp) VALUES (?1, ?2, ?3, ?4, ?5)",
-        params![shard_id, role, content, xp_gained, timestamp],
+/// Return a shard to idle if it is still marked executing. Returns whether
+/// it was.
+pub fn release_executing_shard(data_dir: &str, shard_id: &str) -> SqliteResult<bool> {
+    let conn = open_db(data_dir)?;
+    let changed = conn.execute(
+        "UPDATE shards SET execution_state = 'idle' WHERE id = ?1 AND execution_state = 'executing'",
+        params![shard_id],
     )?;
+    Ok(changed > 0)
+}
 
-    Ok(conn.last_insert_rowid())
+/// Counters applied to a shard when an execution finishes.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionOutcome {
+    /// XP to add (already scaled by the shard's multiplier)
+    pub xp: u32,
+    pub stat_bonuses: HashMap<String, u32>,
+    /// Cap and overflow policy for `stat_bonuses`
+    pub stat_limits: StatLimits,
+    pub success: bool,
+    pub finished_at: u64,
+    /// Throttle applied when this failure extends a failing streak
+    pub failure_cooldown: FailureCooldown,
 }
 
-/// Get interactions for a shard, ordered by timestamp ASC, with an optional limit.
-pub fn get_interactions(data_dir: &str, shard_id: &str, limit: u32) -> SqliteResult<Vec<Interaction>> {
-    let conn = open_db(data_dir)?;
+/// Apply an execution's XP, stat bonuses, and task counters inside one
+/// immediate transaction, so concurrent executions on one shard never
+/// overwrite each other's gains. Stat bonuses respect `stat_limits`; overflow
+/// converted to XP is added on top of `xp`. A failure extends the shard's
+/// failing streak and may put it on cooldown; a success clears both. Returns
+/// the updated shard, or None if it no longer exists.
+pub fn apply_execution_outcome(
+    data_dir: &str,
+    shard_id: &str,
+    outcome: &ExecutionOutcome,
+    level_cap: u32,
+) -> SqliteResult<Option<Shard>> {
+    let mut conn = open_db(data_dir)?;
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+    let stats_json: Option<String> = {
+        let mut stmt = tx.prepare("SELECT stats_json FROM shards WHERE id = ?1")?;
+        let mut rows = stmt.query_map(params![shard_id], |row| row.get(0))?;
+        rows.next().transpose()?
+    };
+    let Some(stats_json) = stats_json else {
+        return Ok(None);
+    };
+    let mut stats: ShardStats = serde_json::from_str(&stats_json).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+    })?;
+    let overflow_xp = stats.apply_bonuses(&outcome.stat_bonuses, outcome.stat_limits);
 
-    let mut stmt = conn.prepare(
-        "SELECT id, shard_id, role, content, xp_gained, timestamp
-         FROM interactions
-         WHERE shard_id = ?1
-         ORDER BY timestamp ASC
-         LIMIT ?2",
+    tx.execute(
+        "UPDATE shards SET
+            xp = xp + ?1,
+            level = MIN((xp + ?1) / ?2 + 1, ?3),
+            tasks_completed = tasks_completed + ?4,
+            tasks_failed = tasks_failed + ?5,
+            last_interaction = MAX(last_interaction, ?6),
+            decay_factor = 1.0,
+            execution_state = 'idle',
+            stats_json = ?7,
+            consecutive_failures = CASE WHEN ?4 = 1 THEN 0 ELSE consecutive_failures + 1 END,
+            cooldown_until = CASE WHEN ?4 = 1 THEN 0 ELSE cooldown_until END
+         WHERE id = ?8",
+        params![
+            outcome.xp.saturating_add(overflow_xp),
+            crate::shard::XP_PER_LEVEL,
+            level_cap.max(1),
+            outcome.success as u32,
+            !outcome.success as u32,
+            outcome.finished_at,
+            serde_json::to_string(&stats).unwrap_or_default(),
+            shard_id
+        ],
     )?;
 
-    let interactions = stmt
-        .query_map(params![shard_id, limit], |row| {
-            Ok(Interaction {
-                id: row.get(0)?,
-                shard_id: row.get(1)?,
-                role: row.get(2)?,
-                content: row.get(3)?,
-                xp_gained: row.get(4)?,
-                timestamp: row.get(5)?,
-            })
-        })?
-        .collect::<SqliteResult<Vec<_>>>()?;
+    if !outcome.success {
+        let failures: u32 = tx.query_row(
+            "SELECT consecutive_failures FROM shards WHERE id = ?1",
+            params![shard_id],
+            |row| row.get(0),
+        )?;
+        let cooldown_secs = outcome.failure_cooldown.cooldown_secs(failures);
+        if cooldown_secs > 0 {
+            tx.execute(
+                "UPDATE shards SET execution_state = 'cooldown', cooldown_until = ?1 WHERE id = ?2",
+                params![outcome.finished_at + cooldown_secs * 1000, shard_id],
+            )?;
+            tracing::warn!(
+                "Shard {} failed {} executions in a row; cooling down for {}s",
+                &shard_id[..8.min(shard_id.len())],
+                failures,
+                cooldown_secs
+            );
+        }
+    }
 
-    Ok(interactions)
+    let shard = refresh_capabilities(&tx, shard_id)?;
+    tx.commit()?;
+    Ok(Some(shard))
 }
 
-/// Get a single shard by ID.
-pub fn get_shard_by_id(data_dir: &str, shard_id: &str) -> SqliteResult<Option<Shard>> {
-    let conn = open_db(data_dir)?;
-
+/// Read a shard through an open connection or transaction.
+fn select_shard(conn: &Connection, shard_id: &str) -> SqliteResult<Option<Shard>> {
     let mut stmt = conn.prepare(
         "SELECT id, genome_hash, shard_type, species, name, level, xp,
                 owner_id, is_wild, avatar_json, personality, stats_json,
                 decay_factor, created_at, last_interaction, elo_rating,
-                execution_state, capabilities_json, tasks_completed, tasks_failed
+                execution_state, capabilities_json, tasks_completed, tasks_failed,
+                prestige, xp_multiplier, read_only
          FROM shards
          WHERE id = ?1",
     )?;
+    let mut rows = stmt.query_map(params![shard_id], row_to_shard)?;
+    rows.next().transpose()
+}
 
-    let mut rows = stmt.query_map(params![shard_id], |row| row_to_shard(row))?;
-
-    match rows.next() {
-        Some(Ok(shard)) => Ok(Some(shard)),
-        Some(Err(e)) => Err(e),
-        None => Ok(None),
-    }
+/// Capabilities derive from the level, so recompute them from the stored
+/// row after a level change. Returns the updated shard.
+fn refresh_capabilities(conn: &Connection, shard_id: &str) -> SqliteResult<Shard> {
+    let mut shard = select_shard(conn, shard_id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+    shard.capabilities.update_for_level(shard.level);
+    conn.execute(
+        "UPDATE shards SET capabilities_json = ?1 WHERE id = ?2",
+        params![
+            serde_json::to_string(&shard.capabilities).unwrap_or_default(),
+            shard_id
+        ],
+    )?;
+    Ok(shard)
 }
 
-// ── Action log (task execution tracking) ────────────────────────────
+/// Award a training exchange's `base_xp`, scaled by the shard's prestige
+/// multiplier, and count it as an interaction at `at`, which also undoes
+/// idle decay. Only XP, level, capabilities, `last_interaction` and
+/// `decay_factor` are written. Returns the XP awarded and the updated shard,
+/// or None if it no longer exists.
+pub fn apply_training_xp(
+    data_dir: &str,
+    shard_id: &str,
+    base_xp: u32,
+    level_cap: u32,
+    at: u64,
+) -> SqliteResult<Option<(u32, Shard)>> {
+    let mut conn = open_db(data_dir)?;
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+    let multiplier: Option<f64> = {
+        let mut stmt = tx.prepare("SELECT xp_multiplier FROM shards WHERE id = ?1")?;
+        let mut rows = stmt.query_map(params![shard_id], |row| row.get(0))?;
+        rows.next().transpose()?
+    };
+    let Some(multiplier) = multiplier else {
+        return Ok(None);
+    };
+    let awarded = crate::shard::scale_xp(base_xp, multiplier);
 
-/// A logged action from shard task execution.
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct ActionLog {
-    pub id: i64,
-    pub shard_id: String,
-    pub task_description: String,
-    pub tool_name: Option<String>,
-    pub tool_input: Option<String>,
-    pub tool_output: Option<String>,
-    pub status: String, // "pending", "running", "success", "failed"
-    pub xp_awarded: u32,
-    pub stat_bonuses: Option<String>, // JSON: {"intelligence": 2, "precision": 1}
-    pub started_at: u64,
-    pub completed_at: Option<u64>,
+    tx.execute(
+        "UPDATE shards SET
+            xp = xp + ?1,
+            level = MIN((xp + ?1) / ?2 + 1, ?3),
+            last_interaction = MAX(last_interaction, ?4),
+            decay_factor = 1.0
+         WHERE id = ?5",
+        params![awarded, crate::shard::XP_PER_LEVEL, level_cap.max(1), at, shard_id],
+    )?;
+
+    let shard = refresh_capabilities(&tx, shard_id)?;
+    tx.commit()?;
+    Ok(Some((awarded, shard)))
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct TaskLesson {
-    pub id: i64,
-    pub shard_id: String,
-    pub action_id: i64,
-    pub task_type: String,
-    pub goal: String,
-    pub approach: String,
-    pub tools_used: Vec<String>,
-    pub outcome: String,
-    pub errors: Vec<String>,
-    pub fixes: Vec<String>,
-    pub duration_ms: u64,
-    pub success: bool,
-    pub extractor_confidence: f64,
-    pub applicability_confidence: f64,
-    pub reusability: f64,
-    pub score: f64,
-    pub artifact_path: String,
-    pub times_retrieved: u32,
-    pub times_helpful: u32,
-    pub times_unhelpful: u32,
-    pub created_at: u64,
-    pub updated_at: u64,
+/// Release a shard to the wild: clear its owner, leaving the rest of the row
+/// alone. Returns false if the shard doesn't exist.
+pub fn set_wild(data_dir: &str, shard_id: &str) -> SqliteResult<bool> {
+    let conn = open_db(data_dir)?;
+    let updated = conn.execute(
+        "UPDATE shards SET is_wild = 1, owner_id = NULL WHERE id = ?1",
+        params![shard_id],
+    )?;
+    Ok(updated > 0)
 }
 
-#[derive(Debug, Clone)]
-pub struct NewTaskLesson<'a> {
-    pub shard_id: &'a str,
-    pub action_id: i64,
-    pub task_type: &'a str,
-    pub goal: &'a str,
-    pub approach: &'a str,
-    pub tools_used: &'a [String],
-    pub outcome: &'a str,
-    pub errors: &'a [String],
-    pub fixes: &'a [String],
-    pub duration_ms: u64,
-    pub success: bool,
-    pub extractor_confidence: f64,
-    pub applicability_confidence: f64,
-    pub reusability: f64,
-    pub artifact_path: &'a str,
+/// Set a shard's read-only policy. Returns false if the shard doesn't exist.
+pub fn set_shard_read_only(data_dir: &str, shard_id: &str, read_only: bool) -> SqliteResult<bool> {
+    let conn = open_db(data_dir)?;
+    let updated = conn.execute(
+        "UPDATE shards SET read_only = ?1 WHERE id = ?2",
+        params![read_only as i32, shard_id],
+    )?;
+    Ok(updated > 0)
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct LessonRetrievalEvent {
-    pub id: i64,
-    pub shard_id: String,
-    pub action_id: i64,
-    pub task: String,
-    pub task_type: String,
-    pub lesson_ids: Vec<i64>,
-    pub created_at: u64,
-    pub completed_at: Option<u64>,
-    pub success: Option<bool>,
-    pub duration_ms: Option<u64>,
-    pub latency_delta_ms: Option<i64>,
-    pub helpful: Option<bool>,
+/// Consecutive failed executions and the end of any failure cooldown (unix
+/// millis, 0 = none) for a shard.
+pub fn get_failure_streak(data_dir: &str, shard_id: &str) -> SqliteResult<(u32, u64)> {
+    let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare("SELECT consecutive_failures, cooldown_until FROM shards WHERE id = ?1")?;
+    let mut rows = stmt.query_map(params![shard_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.next().unwrap_or(Ok((0, 0)))
 }
 
-/// Insert a new action log entry (status=pending). Returns the row ID.
-pub fn insert_action(
+/// Set a shard's `decay_factor`, unless it was interacted with after
+/// `last_interaction`. Returns whether the row was updated.
+pub fn set_decay_factor(
     data_dir: &str,
     shard_id: &str,
-    task_description: &str,
-) -> SqliteResult<i64> {
+    decay_factor: f64,
+    last_interaction: u64,
+) -> SqliteResult<bool> {
     let conn = open_db(data_dir)?;
-    let now = now_millis();
-
-    conn.execute(
-        "INSERT INTO action_log (shard_id, task_description, status, started_at)
-         VALUES (?1, ?2, 'pending', ?3)",
-        params![shard_id, task_description, now],
+    let updated = conn.execute(
+        "UPDATE shards SET decay_factor = ?1 WHERE id = ?2 AND last_interaction = ?3",
+        params![decay_factor, shard_id, last_interaction],
     )?;
-
-    Ok(conn.last_insert_rowid())
+    Ok(updated > 0)
 }
 
-/// Update an action log entry with tool call info and result.
-pub fn complete_action(
+/// Apply a battle result to both shards' Elo ratings. Both ratings are read
+/// and written in one transaction, so concurrent battles sharing a shard each
+/// build on the other's result. Returns each side's rating before and after,
+/// winner first, or None if either shard no longer exists.
+pub fn apply_battle_elo(
     data_dir: &str,
-    action_id: i64,
-    tool_name: &str,
-    tool_input: &str,
-    tool_output: &str,
-    status: &str,
-    xp_awarded: u32,
-    stat_bonuses: Option<&str>,
-) -> SqliteResult<()> {
-    let conn = open_db(data_dir)?;
-    let now = now_millis();
+    winner_id: &str,
+    loser_id: &str,
+) -> SqliteResult<Option<[(u32, u32); 2]>> {
+    let mut conn = open_db(data_dir)?;
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+    let (Some(mut winner), Some(mut loser)) = (select_shard(&tx, winner_id)?, select_shard(&tx, loser_id)?) else {
+        return Ok(None);
+    };
+    let before = (winner.elo_rating, loser.elo_rating);
+    crate::shard::apply_elo(&mut winner, &mut loser);
+    for shard in [&winner, &loser] {
+        tx.execute(
+            "UPDATE shards SET elo_rating = ?1 WHERE id = ?2",
+            params![shard.elo_rating, shard.id],
+        )?;
+    }
+    tx.commit()?;
+    Ok(Some([(before.0, winner.elo_rating), (before.1, loser.elo_rating)]))
+}
 
-    conn.execute(
-        "UPDATE action_log SET
+/// Outcome of `prestige_shard` for an existing shard.
+#[derive(Debug, Clone)]
+pub enum PrestigeOutcome {
+    Prestiged(Box<Shard>),
+    /// The shard is not idle
+    Busy(crate::shard::ExecutionState),
+    /// The shard is below the level cap
+    Refused(String),
+}
+
+/// Prestige an idle shard at `level_cap`: reset its level and XP, bump its
+/// prestige count and multiplier, and restart auto-attestation from zero.
+/// Only those columns and the capabilities are written, in one transaction.
+/// Returns None if the shard doesn't exist.
+pub fn prestige_shard(data_dir: &str, shard_id: &str, level_cap: u32) -> SqliteResult<Option<PrestigeOutcome>> {
+    let mut conn = open_db(data_dir)?;
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+    let Some(mut shard) = select_shard(&tx, shard_id)? else {
+        return Ok(None);
+    };
+    if shard.execution_state != crate::shard::ExecutionState::Idle {
+        return Ok(Some(PrestigeOutcome::Busy(shard.execution_state)));
+    }
+    if let Err(e) = shard.prestige(level_cap) {
+        return Ok(Some(PrestigeOutcome::Refused(e)));
+    }
+
+    tx.execute(
+        "UPDATE shards SET
+            level = ?1,
+            xp = ?2,
+            prestige = ?3,
+            xp_multiplier = ?4,
+            capabilities_json = ?5,
+            last_attested_level = 0
+         WHERE id = ?6 AND execution_state = 'idle' AND level >= ?7",
+        params![
+            shard.level,
+            shard.xp,
+            shard.prestige,
+            shard.xp_multiplier,
+            serde_json::to_string(&shard.capabilities).unwrap_or_default(),
+            shard_id,
+            level_cap
+        ],
+    )?;
+    tx.commit()?;
+    Ok(Some(PrestigeOutcome::Prestiged(Box::new(shard))))
+}
+
+/// Level at which the shard's value was last attested on-chain (0 = never).
+pub fn get_last_attested_level(data_dir: &str, shard_id: &str) -> SqliteResult<u32> {
+    let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare("SELECT last_attested_level FROM shards WHERE id = ?1")?;
+    let mut rows = stmt.query_map(params![shard_id], |row| row.get(0))?;
+
+    match rows.next() {
+        Some(Ok(level)) => Ok(level),
+        Some(Err(e)) => Err(e),
+        None => Ok(0),
+    }
+}
+
+/// Record the level at which the shard's value was attested on-chain.
+pub fn set_last_attested_level(data_dir: &str, shard_id: &str, level: u32) -> SqliteResult<()> {
+    let conn = open_db(data_dir)?;
+    conn.execute(
+        "UPDATE shards SET last_attested_level = ?1 WHERE id = ?2",
+        params![level, shard_id],
+    )?;
+    Ok(())
+}
+
+/// Delete a shard from the database by ID.
+pub fn delete_shard(data_dir: &str, shard_id: &str) -> SqliteResult<()> {
+    let mut conn = open_db(data_dir)?;
+    let tx = conn.transaction()?;
+    delete_shard_rows(&tx, shard_id)?;
+    tx.execute("DELETE FROM shards WHERE id = ?1", params![shard_id])?;
+    tx.commit()?;
+    tracing::debug!("Deleted shard {} from database", &shard_id[..8.min(shard_id.len())]);
+    Ok(())
+}
+
+/// Groups of shards sharing a genome (by `Shard::fingerprint`), each ordered most-trained first
+/// (highest XP, then most completed tasks, then oldest).
+pub fn find_genome_duplicates(data_dir: &str) -> SqliteResult<Vec<Vec<Shard>>> {
+    let mut by_genome: std::collections::BTreeMap<String, Vec<Shard>> = std::collections::BTreeMap::new();
+    for shard in get_shards(data_dir)? {
+        by_genome.entry(shard.fingerprint()).or_default().push(shard);
+    }
+
+    Ok(by_genome
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .map(|mut group| {
+            group.sort_by(|a, b| {
+                b.xp.cmp(&a.xp)
+                    .then(b.tasks_completed.cmp(&a.tasks_completed))
+                    .then(a.created_at.cmp(&b.created_at))
+                    .then(a.id.cmp(&b.id))
+            });
+            group
+        })
+        .collect())
+}
+
+/// What was moved from one duplicate shard into the kept instance.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct MergedShard {
+    pub shard_id: String,
+    pub interactions: usize,
+    pub actions: usize,
+    pub lessons: usize,
+    pub journal_entries: usize,
+}
+
+/// Result of deduplicating one genome.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DedupeResult {
+    pub genome_hash: String,
+    pub kept_id: String,
+    pub merged: Vec<MergedShard>,
+}
+
+/// A duplicated genome `dedupe_genomes` left alone.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SkippedDuplicates {
+    pub genome_hash: String,
+    pub shard_ids: Vec<String>,
+    pub reason: SkipReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// A shard is executing, waiting for input or cooling down
+    Busy,
+    /// The shards belong to different owners
+    OwnersDiffer,
+    /// A shard was claimed, sold or frozen while the group was being merged
+    Changed,
+}
+
+/// Outcome of `dedupe_genomes`.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct DedupeReport {
+    pub merged: Vec<DedupeResult>,
+    pub skipped: Vec<SkippedDuplicates>,
+}
+
+/// Move `dup`'s history onto `keep_id`, fold its task counters in, archive its
+/// row in `archived_shards`, and delete it. Every table in `SHARD_TABLES` is
+/// carried over by its `ShardRows` policy. Runs in one transaction, and only
+/// while both shards are idle, writable and owned by `dup.owner_id`; returns
+/// None otherwise.
+pub fn merge_duplicate_shard(data_dir: &str, keep_id: &str, dup: &Shard) -> SqliteResult<Option<MergedShard>> {
+    let mut conn = open_db(data_dir)?;
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+    let mergeable: i64 = tx.query_row(
+        "SELECT COUNT(*) FROM shards
+         WHERE id IN (?1, ?2) AND execution_state = 'idle' AND read_only = 0 AND owner_id IS ?3",
+        params![keep_id, dup.id, dup.owner_id],
+        |row| row.get(0),
+    )?;
+    if mergeable != 2 {
+        return Ok(None);
+    }
+
+    let reassign = |table: &str| {
+        tx.execute(
+            &format!("UPDATE {} SET shard_id = ?1 WHERE shard_id = ?2", table),
+            params![keep_id, dup.id],
+        )
+    };
+    let mut moved: HashMap<&str, usize> = HashMap::new();
+    for &(table, rows) in SHARD_TABLES {
+        let count = match rows {
+            ShardRows::Log { .. } | ShardRows::Keyed => reassign(table)?,
+            ShardRows::Listings => {
+                // Nobody can buy the duplicate any more
+                tx.execute(
+                    "DELETE FROM listings WHERE shard_id = ?1 AND status = 'open'",
+                    params![dup.id],
+                )?;
+                reassign(table)?
+            }
+            ShardRows::Tally => {
+                let count = tx.execute(
+                    "INSERT INTO action_daily (shard_id, day, tool_name, actions, succeeded, failed, xp)
+                     SELECT ?1, day, tool_name, actions, succeeded, failed, xp FROM action_daily WHERE shard_id = ?2
+                     ON CONFLICT(shard_id, day, tool_name) DO UPDATE SET
+                        actions = actions + excluded.actions,
+                        succeeded = succeeded + excluded.succeeded,
+                        failed = failed + excluded.failed,
+                        xp = xp + excluded.xp",
+                    params![keep_id, dup.id],
+                )?;
+                tx.execute("DELETE FROM action_daily WHERE shard_id = ?1", params![dup.id])?;
+                count
+            }
+            ShardRows::Paused => {
+                // The duplicate's pending question goes with it, so its paused
+                // run can no longer be answered
+                tx.execute(
+                    "UPDATE action_log SET status = 'failed', completed_at = ?2
+                     WHERE shard_id = ?1 AND status = 'waiting_for_input'",
+                    params![dup.id, now_millis()],
+                )?;
+                tx.execute("DELETE FROM pending_questions WHERE shard_id = ?1", params![dup.id])?
+            }
+            ShardRows::Edges => repoint_relationships(&tx, &dup.id, keep_id)?,
+        };
+        moved.insert(table, count);
+    }
+
+    tx.execute(
+        "UPDATE shards SET tasks_completed = tasks_completed + ?1, tasks_failed = tasks_failed + ?2
+         WHERE id = ?3",
+        params![dup.tasks_completed, dup.tasks_failed, keep_id],
+    )?;
+    archive_and_delete(&tx, dup, keep_id)?;
+    tx.commit()?;
+
+    let moved = |table| moved.get(table).copied().unwrap_or(0);
+    Ok(Some(MergedShard {
+        shard_id: dup.id.clone(),
+        interactions: moved("interactions"),
+        actions: moved("action_log"),
+        lessons: moved("task_lessons"),
+        journal_entries: moved("journal"),
+    }))
+}
+
+/// Copy a shard row into `archived_shards` (recording where it went) and
+/// remove it from `shards`.
+fn archive_and_delete(conn: &Connection, shard: &Shard, destination: &str) -> SqliteResult<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO archived_shards (id, genome_hash, merged_into, shard_json, archived_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            shard.id,
+            shard.genome_hash,
+            destination,
+            serde_json::to_string(shard).unwrap_or_default(),
+            now_millis()
+        ],
+    )?;
+    delete_shard_rows(conn, &shard.id)?;
+    conn.execute("DELETE FROM shards WHERE id = ?1", params![shard.id])?;
+    Ok(())
+}
+
+/// How a shard-keyed table's rows follow their shard when it is merged into
+/// another shard or migrated to another data dir.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShardRows {
+    /// History rows with autoincrement ids, optionally referencing an action
+    Log { action_ref: bool },
+    /// Sale listings; an open one is withdrawn when its shard merges away
+    Listings,
+    /// Rows with their own key
+    Keyed,
+    /// Daily counters, summed into the kept shard on merge
+    Tally,
+    /// The pending `ask_user` question, pointing at its paused action
+    Paused,
+    /// Relationship edges, keyed on either end
+    Edges,
+}
+
+impl ShardRows {
+    /// SQL condition selecting a shard's rows (`?1` is its id).
+    fn filter(self) -> &'static str {
+        match self {
+            Self::Edges => "shard_id = ?1 OR other_id = ?1",
+            _ => "shard_id = ?1",
+        }
+    }
+}
+
+/// Every table keyed on a shard id, children before parents. Deleting,
+/// merging and migrating a shard all walk this list; `lesson_embeddings`
+/// follow their lessons and the shard's pause flag lives in `keeper_state`.
+const SHARD_TABLES: &[(&str, ShardRows)] = &[
+    ("lesson_retrieval_events", ShardRows::Log { action_ref: true }),
+    ("pending_questions", ShardRows::Paused),
+    ("task_lessons", ShardRows::Log { action_ref: true }),
+    ("action_log", ShardRows::Log { action_ref: false }),
+    ("interactions", ShardRows::Log { action_ref: false }),
+    ("journal", ShardRows::Log { action_ref: false }),
+    ("benchmarks", ShardRows::Log { action_ref: false }),
+    ("battles", ShardRows::Log { action_ref: false }),
+    ("shard_snapshots", ShardRows::Log { action_ref: false }),
+    ("listings", ShardRows::Listings),
+    ("jobs", ShardRows::Keyed),
+    ("action_daily", ShardRows::Tally),
+    ("shard_relationships", ShardRows::Edges),
+];
+
+/// Delete every row keyed on `shard_id` outside `shards` itself, children
+/// before parents so the foreign keys hold. Whatever should survive the
+/// shard must be re-pointed before this runs.
+fn delete_shard_rows(conn: &Connection, shard_id: &str) -> SqliteResult<()> {
+    conn.execute(
+        "DELETE FROM lesson_embeddings WHERE lesson_id IN (SELECT id FROM task_lessons WHERE shard_id = ?1)",
+        params![shard_id],
+    )?;
+    for &(table, rows) in SHARD_TABLES {
+        conn.execute(&format!("DELETE FROM {} WHERE {}", table, rows.filter()), params![shard_id])?;
+    }
+    conn.execute("DELETE FROM keeper_state WHERE key = ?1", params![pause_key(Some(shard_id))])?;
+    Ok(())
+}
+
+/// Archive a shard that has left this keeper (e.g. drifted to a peer).
+/// `destination` records where it went.
+pub fn archive_shard(data_dir: &str, shard: &Shard, destination: &str) -> SqliteResult<()> {
+    let mut conn = open_db(data_dir)?;
+    let tx = conn.transaction()?;
+    archive_and_delete(&tx, shard, destination)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Enforce genome-as-identity: for each duplicated genome keep the
+/// most-trained shard and merge the others into it. Read-only shards are
+/// neither merged away nor merged into. Groups with a shard that is not idle,
+/// or whose shards have different owners, are skipped and reported.
+pub fn dedupe_genomes(data_dir: &str) -> SqliteResult<DedupeReport> {
+    let mut report = DedupeReport::default();
+    for group in find_genome_duplicates(data_dir)? {
+        let group: Vec<_> = group.into_iter().filter(|s| !s.read_only).collect();
+        let Some((keep, dups)) = group.split_first().filter(|(_, dups)| !dups.is_empty()) else {
+            continue;
+        };
+        let skip = |shards: &[Shard], reason| SkippedDuplicates {
+            genome_hash: keep.genome_hash.clone(),
+            shard_ids: shards.iter().map(|s| s.id.clone()).collect(),
+            reason,
+        };
+        if group.iter().any(|s| s.execution_state != crate::shard::ExecutionState::Idle) {
+            report.skipped.push(skip(&group, SkipReason::Busy));
+            continue;
+        }
+        if dups.iter().any(|s| s.owner_id != keep.owner_id) {
+            report.skipped.push(skip(&group, SkipReason::OwnersDiffer));
+            continue;
+        }
+
+        let mut merged = Vec::new();
+        for (i, dup) in dups.iter().enumerate() {
+            match merge_duplicate_shard(data_dir, &keep.id, dup)? {
+                Some(m) => merged.push(m),
+                None => {
+                    let mut rest = vec![keep.clone()];
+                    rest.extend_from_slice(&dups[i..]);
+                    report.skipped.push(skip(&rest, SkipReason::Changed));
+                    break;
+                }
+            }
+        }
+        if !merged.is_empty() {
+            report.merged.push(DedupeResult {
+                genome_hash: keep.genome_hash.clone(),
+                kept_id: keep.id.clone(),
+                merged,
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// What was copied for one shard by `migrate_shards`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct MigratedShard {
+    pub shard_id: String,
+    pub interactions: usize,
+    pub actions: usize,
+    pub lessons: usize,
+    pub journal_entries: usize,
+    pub benchmarks: usize,
+}
+
+/// Copy shards (all of them, or just `only`) and their rows in every
+/// `SHARD_TABLES` table from the keeper DB in `from_dir` into the one in
+/// `to_dir`. Autoincrement rows get
+/// fresh ids in the destination, with action and lesson references remapped,
+/// and lesson artifact paths are rebased onto `to_dir`. Row counts are
+/// checked against the source before committing; on any mismatch, id
+/// collision or owner past `max_per_owner` shards (0 = no limit) nothing is
+/// written.
+pub fn migrate_shards(
+    from_dir: &str,
+    to_dir: &str,
+    only: Option<&str>,
+    max_per_owner: u32,
+) -> SqliteResult<Vec<MigratedShard>> {
+    init_db(to_dir)?;
+    let mut conn = open_db(to_dir)?;
+    conn.execute("ATTACH DATABASE ?1 AS src", params![db_path(from_dir)])?;
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+    let shard_ids: Vec<String> = {
+        let mut stmt = tx.prepare("SELECT id FROM src.shards WHERE ?1 IS NULL OR id = ?1 ORDER BY created_at")?;
+        let rows = stmt.query_map(params![only], |row| row.get(0))?;
+        rows.collect::<SqliteResult<_>>()?
+    };
+    if let Some(id) = only {
+        if shard_ids.is_empty() {
+            return Err(migration_error(format!("shard {} not found in {}", id, from_dir)));
+        }
+    }
+
+    // Refuse imports of a shard the destination already holds under another id
+    let fingerprints = |db: &str| -> SqliteResult<HashMap<String, String>> {
+        let mut stmt = tx.prepare(&format!("SELECT id, genome_hash, shard_type, species FROM {}.shards", db))?;
+        let rows = stmt.query_map([], |row| {
+            let (genome, shard_type, species): (String, String, String) = (row.get(1)?, row.get(2)?, row.get(3)?);
+            Ok((crate::shard::fingerprint_of(&genome, &shard_type, &species), row.get(0)?))
+        })?;
+        rows.collect()
+    };
+    let existing = fingerprints("main")?;
+    for (fingerprint, id) in fingerprints("src")? {
+        if !shard_ids.contains(&id) {
+            continue;
+        }
+        if let Some(held) = existing.get(&fingerprint).filter(|held| **held != id) {
+            return Err(migration_error(format!(
+                "shard {} duplicates {} in {} (same genome)",
+                id, held, to_dir
+            )));
+        }
+    }
+
+    let shard_columns = shared_columns(&tx, "shards")?.join(", ");
+    let (from_root, to_root) = (shellexpand(from_dir), shellexpand(to_dir));
+    let mut migrated = Vec::new();
+    for shard_id in &shard_ids {
+        // The primary key rejects collisions, but say which shard it was
+        let exists: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM main.shards WHERE id = ?1)",
+            params![shard_id],
+            |row| row.get(0),
+        )?;
+        if exists {
+            return Err(migration_error(format!("shard {} already exists in {}", shard_id, to_dir)));
+        }
+        let owner: Option<String> = tx.query_row(
+            "SELECT owner_id FROM src.shards WHERE id = ?1",
+            params![shard_id],
+            |row| row.get(0),
+        )?;
+        if let Some(owner) = owner {
+            if owner_at_quota(&tx, &owner, max_per_owner)? {
+                return Err(migration_error(format!(
+                    "shard {} would put owner {} past max_shards_per_owner ({}) in {}",
+                    shard_id, owner, max_per_owner, to_dir
+                )));
+            }
+        }
+        tx.execute(
+            &format!(
+                "INSERT INTO main.shards ({c}) SELECT {c} FROM src.shards WHERE id = ?1",
+                c = shard_columns
+            ),
+            params![shard_id],
+        )?;
+
+        // Parents first, so references can be pointed at the copied rows
+        let mut copied: HashMap<&str, usize> = HashMap::new();
+        let (mut actions, mut lessons, mut events) = (HashMap::new(), HashMap::new(), HashMap::new());
+        for &(table, rows) in SHARD_TABLES.iter().rev() {
+            // Older source DBs lack some tables
+            if shared_columns(&tx, table)?.is_empty() {
+                continue;
+            }
+            let count = match rows {
+                ShardRows::Log { action_ref } => {
+                    let ids = copy_shard_rows(&tx, table, shard_id, action_ref.then_some(("action_id", &actions)))?;
+                    let count = ids.len();
+                    match table {
+                        "action_log" => actions = ids,
+                        "task_lessons" => lessons = ids,
+                        "lesson_retrieval_events" => events = ids,
+                        _ => {}
+                    }
+                    count
+                }
+                ShardRows::Listings => copy_shard_rows(&tx, table, shard_id, None)?.len(),
+                ShardRows::Keyed | ShardRows::Tally => copy_keyed_rows(&tx, table, rows, shard_id)?,
+                ShardRows::Paused => {
+                    let count = copy_keyed_rows(&tx, table, rows, shard_id)?;
+                    let paused: Option<i64> = tx.query_row(
+                        "SELECT (SELECT action_id FROM main.pending_questions WHERE shard_id = ?1)",
+                        params![shard_id],
+                        |row| row.get(0),
+                    )?;
+                    if let Some(old) = paused {
+                        tx.execute(
+                            "UPDATE main.pending_questions SET action_id = ?1 WHERE shard_id = ?2",
+                            params![actions.get(&old).copied(), shard_id],
+                        )?;
+                    }
+                    count
+                }
+                ShardRows::Edges => {
+                    // Edges to another migrated shard are copied with the first
+                    copy_keyed_rows(&tx, table, rows, shard_id)?;
+                    tx.query_row(
+                        "SELECT COUNT(*) FROM src.shard_relationships s
+                         JOIN main.shard_relationships m USING (shard_id, other_id, kind)
+                         WHERE s.shard_id = ?1 OR s.other_id = ?1",
+                        params![shard_id],
+                        |row| row.get(0),
+                    )?
+                }
+            };
+
+            // Verify every row made it across before committing
+            let source: usize = tx.query_row(
+                &format!("SELECT COUNT(*) FROM src.{} WHERE {}", table, rows.filter()),
+                params![shard_id],
+                |row| row.get(0),
+            )?;
+            if source != count {
+                return Err(migration_error(format!(
+                    "{} for shard {}: copied {} of {} rows",
+                    table, shard_id, count, source
+                )));
+            }
+            copied.insert(table, count);
+        }
+
+        for (old, new) in &lessons {
+            tx.execute(
+                "INSERT INTO main.lesson_embeddings (lesson_id, model, text_hash, vector_json, created_at)
+                 SELECT ?2, model, text_hash, vector_json, created_at FROM src.lesson_embeddings
+                 WHERE lesson_id = ?1",
+                params![old, new],
+            )?;
+            let path: String = tx.query_row(
+                "SELECT artifact_path FROM main.task_lessons WHERE id = ?1",
+                params![new],
+                |row| row.get(0),
+            )?;
+            if let Some(rest) = path.strip_prefix(from_root.as_str()) {
+                tx.execute(
+                    "UPDATE main.task_lessons SET artifact_path = ?1 WHERE id = ?2",
+                    params![format!("{}{}", to_root, rest), new],
+                )?;
+            }
+        }
+        for new in events.values() {
+            let ids_json: String = tx.query_row(
+                "SELECT lesson_ids_json FROM main.lesson_retrieval_events WHERE id = ?1",
+                params![new],
+                |row| row.get(0),
+            )?;
+            let ids: Vec<i64> = serde_json::from_str::<Vec<i64>>(&ids_json)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|id| lessons.get(&id).copied().unwrap_or(id))
+                .collect();
+            tx.execute(
+                "UPDATE main.lesson_retrieval_events SET lesson_ids_json = ?1 WHERE id = ?2",
+                params![serde_json::to_string(&ids).unwrap_or_default(), new],
+            )?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO main.keeper_state (key, value, updated_at)
+             SELECT key, value, updated_at FROM src.keeper_state WHERE key = ?1",
+            params![pause_key(Some(shard_id))],
+        )?;
+
+        let copied = |table| copied.get(table).copied().unwrap_or(0);
+        migrated.push(MigratedShard {
+            shard_id: shard_id.clone(),
+            interactions: copied("interactions"),
+            actions: copied("action_log"),
+            lessons: copied("task_lessons"),
+            journal_entries: copied("journal"),
+            benchmarks: copied("benchmarks"),
+        });
+    }
+
+    tx.commit()?;
+    conn.execute("DETACH DATABASE src", [])?;
+    Ok(migrated)
+}
+
+fn migration_error(message: String) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT),
+        Some(message),
+    )
+}
+
+/// Columns `table` has in both the destination and the attached `src` DB,
+/// so an older source schema still copies.
+fn shared_columns(conn: &Connection, table: &str) -> SqliteResult<Vec<String>> {
+    let columns = |schema: &str| -> SqliteResult<Vec<String>> {
+        let mut stmt = conn.prepare(&format!("PRAGMA {}.table_info({})", schema, table))?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(1))?;
+        rows.collect()
+    };
+    let source = columns("src")?;
+    Ok(columns("main")?
+        .into_iter()
+        .filter(|c| source.contains(c))
+        .collect())
+}
+
+/// Copy one shard's rows of an autoincrement table from `src`, giving each a
+/// fresh id. With `remap`, that column is pointed at the new ids of rows
+/// copied earlier. Returns old id -> new id.
+fn copy_shard_rows(
+    conn: &Connection,
+    table: &str,
+    shard_id: &str,
+    remap: Option<(&str, &HashMap<i64, i64>)>,
+) -> SqliteResult<HashMap<i64, i64>> {
+    let columns: Vec<String> = shared_columns(conn, table)?
+        .into_iter()
+        .filter(|c| c != "id")
+        .collect();
+    let remapped = remap.map(|(column, _)| column);
+    let values = columns
+        .iter()
+        .map(|c| if Some(c.as_str()) == remapped { "?2" } else { c.as_str() })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let insert = format!(
+        "INSERT INTO main.{t} ({c}) SELECT {v} FROM src.{t} WHERE id = ?1",
+        t = table,
+        c = columns.join(", "),
+        v = values
+    );
+
+    let old_rows: Vec<(i64, Option<i64>)> = {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, {} FROM src.{} WHERE shard_id = ?1 ORDER BY id",
+            remapped.unwrap_or("NULL"),
+            table
+        ))?;
+        let rows = stmt.query_map(params![shard_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<SqliteResult<_>>()?
+    };
+    let mut ids = HashMap::new();
+    for (old, reference) in old_rows {
+        match remap {
+            Some((_, mapping)) => {
+                let new_ref = reference.map(|r| mapping.get(&r).copied().unwrap_or(r));
+                conn.execute(&insert, params![old, new_ref])?;
+            }
+            None => {
+                conn.execute(&insert, params![old])?;
+            }
+        }
+        ids.insert(old, conn.last_insert_rowid());
+    }
+    Ok(ids)
+}
+
+/// Copy one shard's rows of a table that has its own (non-autoincrement) key
+/// from `src` as they are. Returns how many rows were inserted; rows already
+/// present are left alone.
+fn copy_keyed_rows(conn: &Connection, table: &str, rows: ShardRows, shard_id: &str) -> SqliteResult<usize> {
+    let columns = shared_columns(conn, table)?.join(", ");
+    conn.execute(
+        &format!(
+            "INSERT OR IGNORE INTO main.{t} ({c}) SELECT {c} FROM src.{t} WHERE {f}",
+            t = table,
+            c = columns,
+            f = rows.filter()
+        ),
+        params![shard_id],
+    )
+}
+
+/// Set a value in the `keeper_state` key-value table.
+pub fn set_keeper_state(data_dir: &str, key: &str, value: &str) -> SqliteResult<()> {
+    let conn = open_db(data_dir)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO keeper_state (key, value, updated_at) VALUES (?1, ?2, ?3)",
+        params![key, value, now_millis()],
+    )?;
+    Ok(())
+}
+
+/// Add `delta` to an integer in the `keeper_state` table (treated as
+/// `initial` when unset), clamped to `0..=max`, in a single statement so
+/// concurrent adjustments don't lose updates. Returns the new value.
+pub fn adjust_keeper_state(data_dir: &str, key: &str, initial: i64, delta: i64, max: i64) -> SqliteResult<i64> {
+    let conn = open_db(data_dir)?;
+    conn.query_row(
+        "INSERT INTO keeper_state (key, value, updated_at) VALUES (?1, MAX(0, MIN(?4, ?2 + ?3)), ?5)
+         ON CONFLICT(key) DO UPDATE SET
+             value = MAX(0, MIN(?4, CAST(value AS INTEGER) + ?3)),
+             updated_at = ?5
+         RETURNING CAST(value AS INTEGER)",
+        params![key, initial, delta, max, now_millis()],
+        |row| row.get(0),
+    )
+}
+
+/// Read a value from the `keeper_state` key-value table.
+pub fn get_keeper_state(data_dir: &str, key: &str) -> SqliteResult<Option<String>> {
+    let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare("SELECT value FROM keeper_state WHERE key = ?1")?;
+    let mut rows = stmt.query_map(params![key], |row| row.get(0))?;
+
+    match rows.next() {
+        Some(Ok(value)) => Ok(Some(value)),
+        Some(Err(e)) => Err(e),
+        None => Ok(None),
+    }
+}
+
+fn pause_key(shard_id: Option<&str>) -> String {
+    match shard_id {
+        Some(id) => format!("paused:{}", id),
+        None => "paused".to_string(),
+    }
+}
+
+/// Pause or resume executions keeper-wide (`shard_id` None) or for one shard.
+pub fn set_paused(data_dir: &str, shard_id: Option<&str>, paused: bool) -> SqliteResult<()> {
+    set_keeper_state(data_dir, &pause_key(shard_id), if paused { "1" } else { "0" })
+}
+
+/// Whether executions are paused keeper-wide (`shard_id` None) or for one shard.
+pub fn is_paused(data_dir: &str, shard_id: Option<&str>) -> SqliteResult<bool> {
+    Ok(get_keeper_state(data_dir, &pause_key(shard_id))?.as_deref() == Some("1"))
+}
+
+/// Whether `shard_id` may not start executions: paused keeper-wide or on its own.
+pub fn executions_paused(data_dir: &str, shard_id: &str) -> SqliteResult<bool> {
+    Ok(is_paused(data_dir, None)? || is_paused(data_dir, Some(shard_id))?)
+}
+
+/// A single interaction (message) in a shard's training history.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Interaction {
+    pub id: i64,
+    pub shard_id: String,
+    pub role: String,
+    pub content: String,
+    pub xp_gained: u32,
+    pub timestamp: u64,
+}
+
+/// Insert a new interaction into the database.
+pub fn insert_interaction(
+    data_dir: &str,
+    shard_id: &str,
+    role: &str,
+    content: &str,
+    xp_gained: u32,
+) -> SqliteResult<i64> {
+    let conn = open_db(data_dir)?;
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+
+    conn.execute(
+        "INSERT INTO interactions (shard_id, role, content, xp_gained, timestamp) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![shard_id, role, content, xp_gained, timestamp],
+    )?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+/// Get interactions for a shard, ordered by timestamp ASC, with an optional limit.
+pub fn get_interactions(data_dir: &str, shard_id: &str, limit: u32) -> SqliteResult<Vec<Interaction>> {
+    let conn = open_db(data_dir)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, shard_id, role, content, xp_gained, timestamp
+         FROM interactions
+         WHERE shard_id = ?1
+         ORDER BY timestamp ASC
+         LIMIT ?2",
+    )?;
+
+    let interactions = stmt
+        .query_map(params![shard_id, limit], |row| {
+            Ok(Interaction {
+                id: row.get(0)?,
+                shard_id: row.get(1)?,
+                role: row.get(2)?,
+                content: row.get(3)?,
+                xp_gained: row.get(4)?,
+                timestamp: row.get(5)?,
+            })
+        })?
+        .collect::<SqliteResult<Vec<_>>>()?;
+
+    Ok(interactions)
+}
+
+/// Get a single shard by ID.
+pub fn get_shard_by_id(data_dir: &str, shard_id: &str) -> SqliteResult<Option<Shard>> {
+    let conn = open_db(data_dir)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, genome_hash, shard_type, species, name, level, xp,
+                owner_id, is_wild, avatar_json, personality, stats_json,
+                decay_factor, created_at, last_interaction, elo_rating,
+                execution_state, capabilities_json, tasks_completed, tasks_failed,
+                prestige, xp_multiplier, read_only
+         FROM shards
+         WHERE id = ?1",
+    )?;
+
+    let mut rows = stmt.query_map(params![shard_id], row_to_shard)?;
+
+    match rows.next() {
+        Some(Ok(shard)) => Ok(Some(shard)),
+        Some(Err(e)) => Err(e),
+        None => Ok(None),
+    }
+}
+
+/// Get a single shard by genome hash.
+pub fn get_shard_by_genome(data_dir: &str, genome_hash: &str) -> SqliteResult<Option<Shard>> {
+    let conn = open_db(data_dir)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, genome_hash, shard_type, species, name, level, xp,
+                owner_id, is_wild, avatar_json, personality, stats_json,
+                decay_factor, created_at, last_interaction, elo_rating,
+                execution_state, capabilities_json, tasks_completed, tasks_failed,
+                prestige, xp_multiplier, read_only
+         FROM shards
+         WHERE genome_hash = ?1",
+    )?;
+
+    let mut rows = stmt.query_map(params![genome_hash], row_to_shard)?;
+
+    match rows.next() {
+        Some(Ok(shard)) => Ok(Some(shard)),
+        Some(Err(e)) => Err(e),
+        None => Ok(None),
+    }
+}
+
+// ── Action log (task execution tracking) ────────────────────────────
+
+/// A logged action from shard task execution.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ActionLog {
+    pub id: i64,
+    pub shard_id: String,
+    pub task_description: String,
+    pub tool_name: Option<String>,
+    pub tool_input: Option<String>,
+    pub tool_output: Option<String>,
+    pub status: String, // "pending", "running", "success", "failed"
+    pub xp_awarded: u32,
+    pub stat_bonuses: Option<String>, // JSON: {"intelligence": 2, "precision": 1}
+    pub started_at: u64,
+    pub completed_at: Option<u64>,
+    pub inference_ms: Option<u64>, // total time waiting on the model
+    pub tool_ms: Option<u64>,      // total time spent in tool calls
+    pub request_id: Option<String>, // X-Request-Id of the originating HTTP request
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct TaskLesson {
+    pub id: i64,
+    pub shard_id: String,
+    pub action_id: i64,
+    pub task_type: String,
+    pub goal: String,
+    pub approach: String,
+    pub tools_used: Vec<String>,
+    pub outcome: String,
+    pub errors: Vec<String>,
+    pub fixes: Vec<String>,
+    pub duration_ms: u64,
+    pub success: bool,
+    pub extractor_confidence: f64,
+    pub applicability_confidence: f64,
+    pub reusability: f64,
+    pub score: f64,
+    pub artifact_path: String,
+    pub times_retrieved: u32,
+    pub times_helpful: u32,
+    pub times_unhelpful: u32,
+    pub created_at: u64,
+    pub updated_at: u64,
+    /// Pinned lessons are never pruned and get a small retrieval boost
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewTaskLesson<'a> {
+    pub shard_id: &'a str,
+    pub action_id: i64,
+    pub task_type: &'a str,
+    pub goal: &'a str,
+    pub approach: &'a str,
+    pub tools_used: &'a [String],
+    pub outcome: &'a str,
+    pub errors: &'a [String],
+    pub fixes: &'a [String],
+    pub duration_ms: u64,
+    pub success: bool,
+    pub extractor_confidence: f64,
+    pub applicability_confidence: f64,
+    pub reusability: f64,
+    pub artifact_path: &'a str,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LessonRetrievalEvent {
+    pub id: i64,
+    pub shard_id: String,
+    pub action_id: i64,
+    pub task: String,
+    pub task_type: String,
+    pub lesson_ids: Vec<i64>,
+    pub created_at: u64,
+    pub completed_at: Option<u64>,
+    pub success: Option<bool>,
+    pub duration_ms: Option<u64>,
+    pub latency_delta_ms: Option<i64>,
+    pub helpful: Option<bool>,
+}
+
+/// Insert a new action log entry (status=pending). Returns the row ID.
+pub fn insert_action(
+    data_dir: &str,
+    shard_id: &str,
+    task_description: &str,
+    request_id: Option<&str>,
+) -> SqliteResult<i64> {
+    let conn = open_db(data_dir)?;
+    let now = now_millis();
+
+    conn.execute(
+        "INSERT INTO action_log (shard_id, task_description, status, started_at, request_id)
+         VALUES (?1, ?2, 'pending', ?3, ?4)",
+        params![shard_id, task_description, now, request_id],
+    )?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+/// Update an action log entry with tool call info and result.
+#[allow(clippy::too_many_arguments)]
+pub fn complete_action(
+    data_dir: &str,
+    action_id: i64,
+    tool_name: &str,
+    tool_input: &str,
+    tool_output: &str,
+    status: &str,
+    xp_awarded: u32,
+    stat_bonuses: Option<&str>,
+) -> SqliteResult<()> {
+    let conn = open_db(data_dir)?;
+    let now = now_millis();
+
+    conn.execute(
+        "UPDATE action_log SET
             tool_name = ?1,
             tool_input = ?2,
             tool_output = ?3,
@@ -580,364 +1835,1535 @@ pub fn complete_action(
     Ok(())
 }
 
-/// Get recent actions for a shard.
-pub fn get_actions(data_dir: &str, shard_id: &str, limit: u32) -> SqliteResult<Vec<ActionLog>> {
+/// Put a `waiting_for_input` action back to `pending` so an answered run can
+/// continue it. Returns the turns (JSON) recorded when it paused, or `None`
+/// if the action is not waiting.
+pub fn reopen_action(data_dir: &str, action_id: i64) -> SqliteResult<Option<String>> {
+    let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare(
+        "UPDATE action_log SET status = 'pending', completed_at = NULL
+         WHERE id = ?1 AND status = 'waiting_for_input'
+         RETURNING tool_output",
+    )?;
+    let mut rows = stmt.query_map(params![action_id], |row| row.get::<_, Option<String>>(0))?;
+    Ok(rows.next().transpose()?.map(Option::unwrap_or_default))
+}
+
+/// Record a finished action that stands in for work done elsewhere (e.g.
+/// lessons imported from a pack), so rows that need an action have one.
+pub fn insert_synthetic_action(
+    data_dir: &str,
+    shard_id: &str,
+    task_description: &str,
+    tool_name: &str,
+) -> SqliteResult<i64> {
+    let conn = open_db(data_dir)?;
+    let now = now_millis();
+    conn.execute(
+        "INSERT INTO action_log (shard_id, task_description, tool_name, status, started_at, completed_at)
+         VALUES (?1, ?2, ?3, 'imported', ?4, ?4)",
+        params![shard_id, task_description, tool_name, now],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Record how an action's time split between inference and tool execution.
+pub fn record_action_timing(
+    data_dir: &str,
+    action_id: i64,
+    inference_ms: u64,
+    tool_ms: u64,
+) -> SqliteResult<()> {
+    let conn = open_db(data_dir)?;
+    conn.execute(
+        "UPDATE action_log SET inference_ms = ?1, tool_ms = ?2 WHERE id = ?3",
+        params![inference_ms, tool_ms, action_id],
+    )?;
+    Ok(())
+}
+
+/// Get recent actions for a shard.
+pub fn get_actions(data_dir: &str, shard_id: &str, limit: u32) -> SqliteResult<Vec<ActionLog>> {
+    let conn = open_db(data_dir)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, shard_id, task_description, tool_name, tool_input, tool_output,
+                status, xp_awarded, stat_bonuses, started_at, completed_at,
+                inference_ms, tool_ms, request_id
+         FROM action_log
+         WHERE shard_id = ?1
+         ORDER BY started_at DESC
+         LIMIT ?2",
+    )?;
+
+    let actions = stmt
+        .query_map(params![shard_id, limit], row_to_action)?
+        .collect::<SqliteResult<Vec<_>>>()?;
+
+    Ok(actions)
+}
+
+/// Get a single action by id.
+pub fn get_action(data_dir: &str, action_id: i64) -> SqliteResult<Option<ActionLog>> {
+    let conn = open_db(data_dir)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, shard_id, task_description, tool_name, tool_input, tool_output,
+                status, xp_awarded, stat_bonuses, started_at, completed_at,
+                inference_ms, tool_ms, request_id
+         FROM action_log
+         WHERE id = ?1",
+    )?;
+
+    let mut rows = stmt.query_map(params![action_id], row_to_action)?;
+
+    match rows.next() {
+        Some(Ok(action)) => Ok(Some(action)),
+        Some(Err(e)) => Err(e),
+        None => Ok(None),
+    }
+}
+
+/// Id of the lesson extracted from an action, if any.
+pub fn get_lesson_id_for_action(data_dir: &str, action_id: i64) -> SqliteResult<Option<i64>> {
+    let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare("SELECT id FROM task_lessons WHERE action_id = ?1 ORDER BY id DESC LIMIT 1")?;
+    let mut rows = stmt.query_map(params![action_id], |row| row.get(0))?;
+
+    match rows.next() {
+        Some(Ok(id)) => Ok(Some(id)),
+        Some(Err(e)) => Err(e),
+        None => Ok(None),
+    }
+}
+
+fn row_to_action(row: &rusqlite::Row) -> SqliteResult<ActionLog> {
+    Ok(ActionLog {
+        id: row.get(0)?,
+        shard_id: row.get(1)?,
+        task_description: row.get(2)?,
+        tool_name: row.get(3)?,
+        tool_input: row.get(4)?,
+        tool_output: row.get(5)?,
+        status: row.get(6)?,
+        xp_awarded: row.get(7)?,
+        stat_bonuses: row.get(8)?,
+        started_at: row.get(9)?,
+        completed_at: row.get(10)?,
+        inference_ms: row.get(11)?,
+        tool_ms: row.get(12)?,
+        request_id: row.get(13)?,
+    })
+}
+
+/// One entry in a shard's activity timeline, tagged by `kind` when serialized.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TimelineEntry {
+    Interaction(Interaction),
+    Action(ActionLog),
+    Battle(BattleRecord),
+}
+
+impl TimelineEntry {
+    /// Timestamp (ms) used to order the timeline.
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            TimelineEntry::Interaction(i) => i.timestamp,
+            TimelineEntry::Action(a) => a.started_at,
+            TimelineEntry::Battle(b) => b.fought_at,
+        }
+    }
+
+    fn sort_key(&self) -> (u64, u8, i64) {
+        match self {
+            TimelineEntry::Interaction(i) => (i.timestamp, 0, i.id),
+            TimelineEntry::Action(a) => (a.started_at, 1, a.id),
+            TimelineEntry::Battle(b) => (b.fought_at, 2, b.id),
+        }
+    }
+}
+
+/// Get a shard's merged activity timeline, newest first.
+///
+/// Each source is queried for at most `limit` rows older than `before`
+/// (exclusive), then the results are merged and truncated to `limit`.
+pub fn get_timeline(
+    data_dir: &str,
+    shard_id: &str,
+    limit: u32,
+    before: Option<u64>,
+) -> SqliteResult<Vec<TimelineEntry>> {
+    let conn = open_db(data_dir)?;
+    let before = before.unwrap_or(i64::MAX as u64);
+
+    let mut stmt = conn.prepare(
+        "SELECT id, shard_id, role, content, xp_gained, timestamp
+         FROM interactions
+         WHERE shard_id = ?1 AND timestamp < ?2
+         ORDER BY timestamp DESC
+         LIMIT ?3",
+    )?;
+    let interactions = stmt
+        .query_map(params![shard_id, before, limit], |row| {
+            Ok(TimelineEntry::Interaction(Interaction {
+                id: row.get(0)?,
+                shard_id: row.get(1)?,
+                role: row.get(2)?,
+                content: row.get(3)?,
+                xp_gained: row.get(4)?,
+                timestamp: row.get(5)?,
+            }))
+        })?
+        .collect::<SqliteResult<Vec<_>>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, shard_id, task_description, tool_name, tool_input, tool_output,
+                status, xp_awarded, stat_bonuses, started_at, completed_at,
+                inference_ms, tool_ms, request_id
+         FROM action_log
+         WHERE shard_id = ?1 AND started_at < ?2
+         ORDER BY started_at DESC
+         LIMIT ?3",
+    )?;
+    let actions = stmt
+        .query_map(params![shard_id, before, limit], |row| {
+            row_to_action(row).map(TimelineEntry::Action)
+        })?
+        .collect::<SqliteResult<Vec<_>>>()?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM battles
+         WHERE shard_id = ?1 AND fought_at < ?2
+         ORDER BY fought_at DESC
+         LIMIT ?3",
+        BATTLE_COLUMNS
+    ))?;
+    let battles = stmt
+        .query_map(params![shard_id, before, limit], |row| {
+            row_to_battle(row).map(TimelineEntry::Battle)
+        })?
+        .collect::<SqliteResult<Vec<_>>>()?;
+
+    let mut entries: Vec<TimelineEntry> = interactions.into_iter().chain(actions).chain(battles).collect();
+    entries.sort_by_key(|e| std::cmp::Reverse(e.sort_key()));
+    entries.truncate(limit as usize);
+    Ok(entries)
+}
+
+/// Get action counts by status for a shard (for stats).
+pub fn get_action_summary(data_dir: &str, shard_id: &str) -> SqliteResult<(u32, u32, u32)> {
+    let conn = open_db(data_dir)?;
+
+    let total: u32 = conn.query_row(
+        "SELECT COUNT(*) FROM action_log WHERE shard_id = ?1",
+        params![shard_id],
+        |row| row.get(0),
+    )?;
+
+    let success: u32 = conn.query_row(
+        "SELECT COUNT(*) FROM action_log WHERE shard_id = ?1 AND status = 'success'",
+        params![shard_id],
+        |row| row.get(0),
+    )?;
+
+    let failed: u32 = conn.query_row(
+        "SELECT COUNT(*) FROM action_log WHERE shard_id = ?1 AND status = 'failed'",
+        params![shard_id],
+        |row| row.get(0),
+    )?;
+
+    Ok((total, success, failed))
+}
+
+pub fn insert_task_lesson(data_dir: &str, lesson: &NewTaskLesson) -> SqliteResult<i64> {
+    let success_f = if lesson.success { 1.0 } else { 0.0 };
+    let initial_score = (0.15 * success_f
+        + 0.30 * lesson.extractor_confidence
+        + 0.30 * lesson.applicability_confidence
+        + 0.25 * lesson.reusability)
+        .clamp(0.0, 1.0);
+    insert_lesson_row(data_dir, lesson, initial_score)
+}
+
+/// Insert a lesson carried over from another shard with an explicit score
+/// instead of the one derived from its confidences.
+pub fn import_task_lesson(data_dir: &str, lesson: &NewTaskLesson, score: f64) -> SqliteResult<i64> {
+    insert_lesson_row(data_dir, lesson, score.clamp(0.0, 1.0))
+}
+
+fn insert_lesson_row(data_dir: &str, lesson: &NewTaskLesson, score: f64) -> SqliteResult<i64> {
+    let conn = open_db(data_dir)?;
+    let now = now_millis();
+    let tools_used_json = serde_json::to_string(lesson.tools_used).unwrap_or_else(|_| "[]".to_string());
+    let errors_json = serde_json::to_string(lesson.errors).unwrap_or_else(|_| "[]".to_string());
+    let fixes_json = serde_json::to_string(lesson.fixes).unwrap_or_else(|_| "[]".to_string());
+
+    conn.execute(
+        "INSERT INTO task_lessons (
+            shard_id, action_id, task_type, goal, approach, tools_used_json, outcome,
+            errors_json, fixes_json, duration_ms, success, extractor_confidence,
+            applicability_confidence, reusability, score, artifact_path,
+            times_retrieved, times_helpful, times_unhelpful, created_at, updated_at
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, 0, 0, 0, ?17, ?18)",
+        params![
+            lesson.shard_id,
+            lesson.action_id,
+            lesson.task_type,
+            lesson.goal,
+            lesson.approach,
+            tools_used_json,
+            lesson.outcome,
+            errors_json,
+            fixes_json,
+            lesson.duration_ms,
+            if lesson.success { 1 } else { 0 },
+            lesson.extractor_confidence,
+            lesson.applicability_confidence,
+            lesson.reusability,
+            score,
+            lesson.artifact_path,
+            now,
+            now
+        ],
+    )?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+/// Pin or unpin a lesson. Returns false if the lesson doesn't belong to the shard.
+pub fn set_lesson_pinned(
+    data_dir: &str,
+    shard_id: &str,
+    lesson_id: i64,
+    pinned: bool,
+) -> SqliteResult<bool> {
+    let conn = open_db(data_dir)?;
+    let updated = conn.execute(
+        "UPDATE task_lessons SET pinned = ?1, updated_at = ?2 WHERE id = ?3 AND shard_id = ?4",
+        params![pinned as i64, now_millis(), lesson_id, shard_id],
+    )?;
+    Ok(updated > 0)
+}
+
+/// Delete a shard's lowest-scoring unpinned lessons, keeping the best `keep`.
+/// Pinned lessons are always retained. Returns the number deleted.
+pub fn prune_task_lessons(data_dir: &str, shard_id: &str, keep: u32) -> SqliteResult<usize> {
+    let conn = open_db(data_dir)?;
+    let deleted = conn.execute(
+        "DELETE FROM task_lessons
+         WHERE shard_id = ?1 AND pinned = 0 AND id NOT IN (
+            SELECT id FROM task_lessons
+            WHERE shard_id = ?1 AND pinned = 0
+            ORDER BY score DESC, created_at DESC
+            LIMIT ?2
+         )",
+        params![shard_id, keep],
+    )?;
+    if deleted > 0 {
+        conn.execute(
+            "DELETE FROM lesson_embeddings WHERE lesson_id NOT IN (SELECT id FROM task_lessons)",
+            [],
+        )?;
+    }
+    Ok(deleted)
+}
+
+pub fn get_recent_task_lessons(
+    data_dir: &str,
+    shard_id: &str,
+    limit: u32,
+) -> SqliteResult<Vec<TaskLesson>> {
+    let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare(
+        "SELECT id, shard_id, action_id, task_type, goal, approach, tools_used_json, outcome,
+                errors_json, fixes_json, duration_ms, success, extractor_confidence,
+                applicability_confidence, reusability, score, artifact_path,
+                times_retrieved, times_helpful, times_unhelpful, created_at, updated_at, pinned
+         FROM task_lessons
+         WHERE shard_id = ?1
+         ORDER BY created_at DESC
+         LIMIT ?2",
+    )?;
+
+    let lessons = stmt
+        .query_map(params![shard_id, limit], row_to_task_lesson)?
+        .collect::<SqliteResult<Vec<_>>>()?;
+    Ok(lessons)
+}
+
+/// Stored lesson embeddings for `model`, keyed by lesson id. Only vectors
+/// whose text hash still matches are returned.
+pub fn get_lesson_embeddings(
+    data_dir: &str,
+    model: &str,
+    lessons: &[(i64, String)],
+) -> SqliteResult<HashMap<i64, Vec<f32>>> {
+    let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare(
+        "SELECT vector_json FROM lesson_embeddings
+         WHERE lesson_id = ?1 AND model = ?2 AND text_hash = ?3",
+    )?;
+
+    let mut vectors = HashMap::new();
+    for (lesson_id, text_hash) in lessons {
+        let mut rows = stmt.query_map(params![lesson_id, model, text_hash], |row| {
+            row.get::<_, String>(0)
+        })?;
+        if let Some(json) = rows.next().transpose()? {
+            if let Ok(vector) = serde_json::from_str::<Vec<f32>>(&json) {
+                vectors.insert(*lesson_id, vector);
+            }
+        }
+    }
+    Ok(vectors)
+}
+
+/// Store lesson embeddings for `model`, replacing any stale vectors.
+pub fn upsert_lesson_embeddings(
+    data_dir: &str,
+    model: &str,
+    embeddings: &[(i64, String, Vec<f32>)],
+) -> SqliteResult<()> {
+    let mut conn = open_db(data_dir)?;
+    let tx = conn.transaction()?;
+    let now = now_millis();
+    for (lesson_id, text_hash, vector) in embeddings {
+        tx.execute(
+            "INSERT OR REPLACE INTO lesson_embeddings (lesson_id, model, text_hash, vector_json, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                lesson_id,
+                model,
+                text_hash,
+                serde_json::to_string(vector).unwrap_or_default(),
+                now
+            ],
+        )?;
+    }
+    tx.commit()
+}
+
+/// Drop every stored lesson embedding, so lessons are re-embedded on next
+/// use. Returns the number of vectors removed.
+pub fn delete_lesson_embeddings(data_dir: &str) -> SqliteResult<usize> {
+    let conn = open_db(data_dir)?;
+    conn.execute("DELETE FROM lesson_embeddings", [])
+}
+
+pub fn retrieve_relevant_lessons(
+    data_dir: &str,
+    shard_id: &str,
+    task: &str,
+    task_type: &str,
+    max_lessons: usize,
+    weights: &RetrievalWeights,
+) -> SqliteResult<Vec<TaskLesson>> {
+    let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare(
+        "SELECT id, shard_id, action_id, task_type, goal, approach, tools_used_json, outcome,
+                errors_json, fixes_json, duration_ms, success, extractor_confidence,
+                applicability_confidence, reusability, score, artifact_path,
+                times_retrieved, times_helpful, times_unhelpful, created_at, updated_at, pinned
+         FROM task_lessons
+         WHERE shard_id = ?1
+         ORDER BY created_at DESC
+         LIMIT 300",
+    )?;
+    let mut lessons = stmt
+        .query_map(params![shard_id], row_to_task_lesson)?
+        .collect::<SqliteResult<Vec<_>>>()?;
+
+    let query_tokens = tokenize(task);
+    let now = now_millis();
+    let mut ranked: Vec<(TaskLesson, f64)> = lessons
+        .drain(..)
+        .map(|l| {
+            let score = lesson_rank(&l, &query_tokens, task_type, now, weights);
+            (l, score)
+        })
+        .collect();
+
+    ranked.sort_by(|a, b| {
+        let sa = a.1;
+        let sb = b.1;
+        sb.partial_cmp(&sa).unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let mut selected: Vec<TaskLesson> = Vec::new();
+    let target_max = max_lessons.min(7);
+    for (lesson, rank) in &ranked {
+        if selected.len() >= target_max {
+            break;
+        }
+        // Quality floor once we already have enough context candidates.
+        if selected.len() >= 3 && *rank < 0.22 {
+            break;
+        }
+        if selected.iter().any(|s| near_duplicate(s, lesson)) {
+            continue;
+        }
+        selected.push(lesson.clone());
+    }
+
+    // Fallback fill to ensure up to `target_max` when corpus is small/noisy.
+    if selected.len() < target_max {
+        for (lesson, _) in ranked {
+            if selected.len() >= target_max {
+                break;
+            }
+            if selected.iter().any(|s| near_duplicate(s, &lesson)) {
+                continue;
+            }
+            selected.push(lesson);
+        }
+    }
+
+    Ok(selected)
+}
+
+pub fn start_lesson_retrieval_event(
+    data_dir: &str,
+    shard_id: &str,
+    action_id: i64,
+    task: &str,
+    task_type: &str,
+    lesson_ids: &[i64],
+) -> SqliteResult<i64> {
+    let conn = open_db(data_dir)?;
+    let now = now_millis();
+    let lesson_ids_json = serde_json::to_string(lesson_ids).unwrap_or_else(|_| "[]".to_string());
+    conn.execute(
+        "INSERT INTO lesson_retrieval_events (
+            shard_id, action_id, task, task_type, lesson_ids_json, created_at
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![shard_id, action_id, task, task_type, lesson_ids_json, now],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn complete_lesson_retrieval_event(
+    data_dir: &str,
+    event_id: i64,
+    success: bool,
+    duration_ms: u64,
+    latency_delta_ms: Option<i64>,
+    helpful: bool,
+) -> SqliteResult<()> {
+    let conn = open_db(data_dir)?;
+    let now = now_millis();
+    conn.execute(
+        "UPDATE lesson_retrieval_events
+         SET completed_at = ?1, success = ?2, duration_ms = ?3, latency_delta_ms = ?4, helpful = ?5
+         WHERE id = ?6",
+        params![
+            now,
+            if success { 1 } else { 0 },
+            duration_ms,
+            latency_delta_ms,
+            if helpful { 1 } else { 0 },
+            event_id
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn apply_lesson_feedback(
+    data_dir: &str,
+    lesson_ids: &[i64],
+    helpful: bool,
+) -> SqliteResult<()> {
+    if lesson_ids.is_empty() {
+        return Ok(());
+    }
+    let conn = open_db(data_dir)?;
+    let now = now_millis();
+    let delta = if helpful { 0.08 } else { -0.05 };
+    for id in lesson_ids {
+        conn.execute(
+            "UPDATE task_lessons
+             SET times_retrieved = times_retrieved + 1,
+                 times_helpful = times_helpful + ?1,
+                 times_unhelpful = times_unhelpful + ?2,
+                 score = MIN(1.0, MAX(0.0, score + ?3)),
+                 updated_at = ?4
+             WHERE id = ?5",
+            params![
+                if helpful { 1 } else { 0 },
+                if helpful { 0 } else { 1 },
+                delta,
+                now,
+                id
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn avg_success_duration_by_task_type(
+    data_dir: &str,
+    shard_id: &str,
+    task_type: &str,
+) -> SqliteResult<Option<u64>> {
     let conn = open_db(data_dir)?;
+    let avg: Option<f64> = conn.query_row(
+        "SELECT AVG(duration_ms) FROM task_lessons
+         WHERE shard_id = ?1 AND task_type = ?2 AND success = 1",
+        params![shard_id, task_type],
+        |row| row.get(0),
+    )?;
+    Ok(avg.map(|v| v.max(0.0) as u64))
+}
 
+pub fn get_recent_lesson_retrieval_events(
+    data_dir: &str,
+    shard_id: &str,
+    limit: u32,
+) -> SqliteResult<Vec<LessonRetrievalEvent>> {
+    let conn = open_db(data_dir)?;
     let mut stmt = conn.prepare(
-        "SELECT id, shard_id, task_description, tool_name, tool_input, tool_output,
-                status, xp_awarded, stat_bonuses, started_at, completed_at
-         FROM action_log
+        "SELECT id, shard_id, action_id, task, task_type, lesson_ids_json, created_at,
+                completed_at, success, duration_ms, latency_delta_ms, helpful
+         FROM lesson_retrieval_events
          WHERE shard_id = ?1
-         ORDER BY started_at DESC
+         ORDER BY created_at DESC
          LIMIT ?2",
     )?;
 
-    let actions = stmt
+    let events = stmt
         .query_map(params![shard_id, limit], |row| {
-            Ok(ActionLog {
+            let lesson_ids_json: String = row.get(5)?;
+            Ok(LessonRetrievalEvent {
                 id: row.get(0)?,
                 shard_id: row.get(1)?,
-                task_description: row.get(2)?,
-                tool_name: row.get(3)?,
-                tool_input: row.get(4)?,
-                tool_output: row.get(5)?,
-                status: row.get(6)?,
-                xp_awarded: row.get(7)?,
-                stat_bonuses: row.get(8)?,
-                started_at: row.get(9)?,
-                completed_at: row.get(10)?,
+                action_id: row.get(2)?,
+                task: row.get(3)?,
+                task_type: row.get(4)?,
+                lesson_ids: serde_json::from_str(&lesson_ids_json).unwrap_or_default(),
+                created_at: row.get(6)?,
+                completed_at: row.get(7)?,
+                success: row.get::<_, Option<i64>>(8)?.map(|v| v != 0),
+                duration_ms: row.get(9)?,
+                latency_delta_ms: row.get(10)?,
+                helpful: row.get::<_, Option<i64>>(11)?.map(|v| v != 0),
             })
         })?
         .collect::<SqliteResult<Vec<_>>>()?;
 
-    Ok(actions)
+    Ok(events)
 }
 
-/// Get action counts by status for a shard (for stats).
-pub fn get_action_summary(data_dir: &str, shard_id: &str) -> SqliteResult<(u32, u32, u32)> {
+/// Aggregate effect of lesson retrieval on a shard's executions.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LessonImpactSummary {
+    /// Start of the summarized window (ms)
+    pub since: u64,
+    pub retrievals: u32,
+    /// Retrievals whose execution has finished
+    pub completed: u32,
+    pub helpful: u32,
+    /// `helpful / completed`, when any have completed
+    pub helpful_rate: Option<f64>,
+    pub success_rate: Option<f64>,
+    /// Mean duration change against the shard's baseline for the task type;
+    /// negative means retrieval made executions faster
+    pub avg_latency_delta_ms: Option<f64>,
+}
+
+/// Summarize retrieval events for a shard created at or after `since` (ms).
+pub fn lesson_impact_summary(data_dir: &str, shard_id: &str, since: u64) -> SqliteResult<LessonImpactSummary> {
     let conn = open_db(data_dir)?;
+    conn.query_row(
+        "SELECT COUNT(*), COUNT(completed_at), COALESCE(SUM(helpful), 0), COALESCE(SUM(success), 0),
+                AVG(latency_delta_ms)
+         FROM lesson_retrieval_events
+         WHERE shard_id = ?1 AND created_at >= ?2",
+        params![shard_id, since],
+        |row| {
+            let completed: u32 = row.get(1)?;
+            let helpful: u32 = row.get(2)?;
+            let successes: u32 = row.get(3)?;
+            let rate = |n: u32| (completed > 0).then(|| n as f64 / completed as f64);
+            Ok(LessonImpactSummary {
+                since,
+                retrievals: row.get(0)?,
+                completed,
+                helpful,
+                helpful_rate: rate(helpful),
+                success_rate: rate(successes),
+                avg_latency_delta_ms: row.get(4)?,
+            })
+        },
+    )
+}
 
-    let total: u32 = conn.query_row(
-        "SELECT COUNT(*) FROM action_log WHERE shard_id = ?1",
-        params![shard_id],
-        |row| row.get(0),
-    )?;
+/// Get all tracked loan IDs with state = 'Funded' for liquidation checks.
+pub fn get_funded_loans(data_dir: &str) -> SqliteResult<Vec<String>> {
+    let conn = open_db(data_dir)?;
 
-    let success: u32 = conn.query_row(
-        "SELECT COUNT(*) FROM action_log WHERE shard_id = ?1 AND status = 'success'",
-        params![shard_id],
-        |row| row.get(0),
+    let mut stmt = conn.prepare(
+        "SELECT loan_id FROM tracked_loans WHERE state = 'Funded'"
     )?;
 
-    let failed: u32 = conn.query_row(
-        "SELECT COUNT(*) FROM action_log WHERE shard_id = ?1 AND status = 'failed'",
-        params![shard_id],
-        |row| row.get(0),
-    )?;
+    let loans = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<SqliteResult<Vec<String>>>()?;
 
-    Ok((total, success, failed))
+    Ok(loans)
 }
 
-pub fn insert_task_lesson(data_dir: &str, lesson: &NewTaskLesson) -> SqliteResult<i64> {
-    let conn = open_db(data_dir)?;
-    let now = now_millis();
-    let tools_used_json = serde_json::to_string(lesson.tools_used).unwrap_or_else(|_| "[]".to_string());
-    let errors_json = serde_json::to_string(lesson.errors).unwrap_or_else(|_| "[]".to_string());
-    let fixes_json = serde_json::to_string(lesson.fixes).unwrap_or_else(|_| "[]".to_string());
-    let success_f = if lesson.success { 1.0 } else { 0.0 };
-    let initial_score = (0.15 * success_f
-        + 0.30 * lesson.extractor_confidence
-        + 0.30 * lesson.applicability_confidence
-        + 0.25 * lesson.reusability)
-        .clamp(0.0, 1.0);
+/// A persisted background execution job. `result_json` holds the serialized
+/// execution response once the job completes.
+#[derive(Debug, Clone)]
+pub struct JobRecord {
+    pub id: String,
+    pub shard_id: String,
+    pub task: String,
+    pub status: String, // "running", "completed", "failed"
+    pub result_json: Option<String>,
+    pub error: Option<String>,
+    pub created_at: u64,
+}
 
+/// Insert or update a job row.
+pub fn upsert_job(data_dir: &str, job: &JobRecord) -> SqliteResult<()> {
+    let conn = open_db(data_dir)?;
     conn.execute(
-        "INSERT INTO task_lessons (
-            shard_id, action_id, task_type, goal, approach, tools_used_json, outcome,
-            errors_json, fixes_json, duration_ms, success, extractor_confidence,
-            applicability_confidence, reusability, score, artifact_path,
-            times_retrieved, times_helpful, times_unhelpful, created_at, updated_at
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, 0, 0, 0, ?17, ?18)",
+        "INSERT INTO jobs (id, shard_id, task, status, result_json, error, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+         ON CONFLICT(id) DO UPDATE SET
+            status = excluded.status,
+            result_json = excluded.result_json,
+            error = excluded.error,
+            updated_at = excluded.updated_at",
         params![
-            lesson.shard_id,
-            lesson.action_id,
-            lesson.task_type,
-            lesson.goal,
-            lesson.approach,
-            tools_used_json,
-            lesson.outcome,
-            errors_json,
-            fixes_json,
-            lesson.duration_ms,
-            if lesson.success { 1 } else { 0 },
-            lesson.extractor_confidence,
-            lesson.applicability_confidence,
-            lesson.reusability,
-            initial_score,
-            lesson.artifact_path,
-            now,
-            now
+            job.id,
+            job.shard_id,
+            job.task,
+            job.status,
+            job.result_json,
+            job.error,
+            job.created_at,
+            now_millis()
         ],
     )?;
+    Ok(())
+}
 
-    Ok(conn.last_insert_rowid())
+fn row_to_job(row: &rusqlite::Row) -> SqliteResult<JobRecord> {
+    Ok(JobRecord {
+        id: row.get(0)?,
+        shard_id: row.get(1)?,
+        task: row.get(2)?,
+        status: row.get(3)?,
+        result_json: row.get(4)?,
+        error: row.get(5)?,
+        created_at: row.get(6)?,
+    })
 }
 
-pub fn get_recent_task_lessons(
-    data_dir: &str,
-    shard_id: &str,
-    limit: u32,
-) -> SqliteResult<Vec<TaskLesson>> {
+/// Get a job by ID.
+pub fn get_job(data_dir: &str, job_id: &str) -> SqliteResult<Option<JobRecord>> {
     let conn = open_db(data_dir)?;
     let mut stmt = conn.prepare(
-        "SELECT id, shard_id, action_id, task_type, goal, approach, tools_used_json, outcome,
-                errors_json, fixes_json, duration_ms, success, extractor_confidence,
-                applicability_confidence, reusability, score, artifact_path,
-                times_retrieved, times_helpful, times_unhelpful, created_at, updated_at
-         FROM task_lessons
-         WHERE shard_id = ?1
-         ORDER BY created_at DESC
-         LIMIT ?2",
+        "SELECT id, shard_id, task, status, result_json, error, created_at
+         FROM jobs WHERE id = ?1",
     )?;
-
-    let lessons = stmt
-        .query_map(params![shard_id, limit], row_to_task_lesson)?
-        .collect::<SqliteResult<Vec<_>>>()?;
-    Ok(lessons)
+    let mut rows = stmt.query_map(params![job_id], row_to_job)?;
+    rows.next().transpose()
 }
 
-pub fn retrieve_relevant_lessons(
-    data_dir: &str,
-    shard_id: &str,
-    task: &str,
-    task_type: &str,
-    max_lessons: usize,
-) -> SqliteResult<Vec<TaskLesson>> {
+/// List recent jobs, newest first, optionally filtered by shard.
+pub fn list_jobs(data_dir: &str, shard_id: Option<&str>, limit: u32) -> SqliteResult<Vec<JobRecord>> {
     let conn = open_db(data_dir)?;
     let mut stmt = conn.prepare(
-        "SELECT id, shard_id, action_id, task_type, goal, approach, tools_used_json, outcome,
-                errors_json, fixes_json, duration_ms, success, extractor_confidence,
-                applicability_confidence, reusability, score, artifact_path,
-                times_retrieved, times_helpful, times_unhelpful, created_at, updated_at
-         FROM task_lessons
-         WHERE shard_id = ?1
+        "SELECT id, shard_id, task, status, result_json, error, created_at
+         FROM jobs
+         WHERE ?1 IS NULL OR shard_id = ?1
          ORDER BY created_at DESC
-         LIMIT 300",
+         LIMIT ?2",
     )?;
-    let mut lessons = stmt
-        .query_map(params![shard_id], row_to_task_lesson)?
+    let jobs = stmt
+        .query_map(params![shard_id, limit], row_to_job)?
         .collect::<SqliteResult<Vec<_>>>()?;
+    Ok(jobs)
+}
 
-    let query_tokens = tokenize(task);
-    let now = now_millis();
-    let mut ranked: Vec<(TaskLesson, f64)> = lessons
-        .drain(..)
-        .map(|l| {
-            let score = lesson_rank(&l, &query_tokens, task_type, now);
-            (l, score)
-        })
-        .collect();
+/// Store the agent-loop conversation (JSON messages) of a job, so it can be
+/// resumed.
+pub fn set_job_conversation(data_dir: &str, job_id: &str, conversation_json: &str) -> SqliteResult<()> {
+    let conn = open_db(data_dir)?;
+    conn.execute(
+        "UPDATE jobs SET conversation_json = ?1 WHERE id = ?2",
+        params![conversation_json, job_id],
+    )?;
+    Ok(())
+}
 
-    ranked.sort_by(|a, b| {
-        let sa = a.1;
-        let sb = b.1;
-        sb.partial_cmp(&sa).unwrap_or(std::cmp::Ordering::Equal)
-    });
+/// A job's saved conversation, if it has one.
+pub fn get_job_conversation(data_dir: &str, job_id: &str) -> SqliteResult<Option<String>> {
+    let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare("SELECT conversation_json FROM jobs WHERE id = ?1")?;
+    let mut rows = stmt.query_map(params![job_id], |row| row.get::<_, Option<String>>(0))?;
+    Ok(rows.next().transpose()?.flatten())
+}
+
+/// Store the execute request (JSON, without secrets) a job was started with,
+/// so a resumed run keeps its tools, overrides and output settings.
+pub fn set_job_request(data_dir: &str, job_id: &str, request_json: &str) -> SqliteResult<()> {
+    let conn = open_db(data_dir)?;
+    conn.execute(
+        "UPDATE jobs SET request_json = ?1 WHERE id = ?2",
+        params![request_json, job_id],
+    )?;
+    Ok(())
+}
+
+/// The execute request a job was started with, if it was saved.
+pub fn get_job_request(data_dir: &str, job_id: &str) -> SqliteResult<Option<String>> {
+    let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare("SELECT request_json FROM jobs WHERE id = ?1")?;
+    let mut rows = stmt.query_map(params![job_id], |row| row.get::<_, Option<String>>(0))?;
+    Ok(rows.next().transpose()?.flatten())
+}
+
+/// An execution paused on `ask_user`, waiting for the keeper's answer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingQuestionRecord {
+    pub shard_id: String,
+    pub task: String,
+    /// Id of the `ask_user` call the answer is the result of
+    pub call_id: String,
+    pub question: String,
+    pub conversation_json: String,
+    /// The paused execute request (JSON, without secrets)
+    pub request_json: Option<String>,
+    /// The `waiting_for_input` action the answer continues
+    pub action_id: Option<i64>,
+    pub created_at: u64,
+}
+
+/// Store a shard's pending question, replacing any earlier one.
+pub fn set_pending_question(data_dir: &str, record: &PendingQuestionRecord) -> SqliteResult<()> {
+    let conn = open_db(data_dir)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO pending_questions
+            (shard_id, task, call_id, question, conversation_json, request_json, action_id, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            record.shard_id,
+            record.task,
+            record.call_id,
+            record.question,
+            record.conversation_json,
+            record.request_json,
+            record.action_id,
+            record.created_at
+        ],
+    )?;
+    Ok(())
+}
 
-    let mut selected: Vec<TaskLesson> = Vec::new();
-    let target_max = max_lessons.min(7);
-    for (lesson, rank) in &ranked {
-        if selected.len() >= target_max {
-            break;
-        }
-        // Quality floor once we already have enough context candidates.
-        if selected.len() >= 3 && *rank < 0.22 {
-            break;
-        }
-        if selected.iter().any(|s| near_duplicate(s, lesson)) {
-            continue;
-        }
-        selected.push(lesson.clone());
-    }
+/// A shard's pending question, if it has one.
+pub fn get_pending_question(data_dir: &str, shard_id: &str) -> SqliteResult<Option<PendingQuestionRecord>> {
+    let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare(
+        "SELECT shard_id, task, call_id, question, conversation_json, request_json, action_id, created_at
+         FROM pending_questions WHERE shard_id = ?1",
+    )?;
+    let mut rows = stmt.query_map(params![shard_id], |row| {
+        Ok(PendingQuestionRecord {
+            shard_id: row.get(0)?,
+            task: row.get(1)?,
+            call_id: row.get(2)?,
+            question: row.get(3)?,
+            conversation_json: row.get(4)?,
+            request_json: row.get(5)?,
+            action_id: row.get(6)?,
+            created_at: row.get(7)?,
+        })
+    })?;
+    rows.next().transpose()
+}
 
-    // Fallback fill to ensure up to `target_max` when corpus is small/noisy.
-    if selected.len() < target_max {
-        for (lesson, _) in ranked {
-            if selected.len() >= target_max {
-                break;
-            }
-            if selected.iter().any(|s| near_duplicate(s, &lesson)) {
-                continue;
-            }
-            selected.push(lesson);
-        }
-    }
+/// Drop a shard's pending question once it has been answered.
+pub fn clear_pending_question(data_dir: &str, shard_id: &str) -> SqliteResult<()> {
+    let conn = open_db(data_dir)?;
+    conn.execute("DELETE FROM pending_questions WHERE shard_id = ?1", params![shard_id])?;
+    Ok(())
+}
 
-    Ok(selected)
+/// Mark jobs still `running` as failed. Called at startup, since any job
+/// running when the previous process exited can no longer complete.
+pub fn fail_interrupted_jobs(data_dir: &str) -> SqliteResult<usize> {
+    let conn = open_db(data_dir)?;
+    conn.execute(
+        "UPDATE jobs SET status = 'failed', error = 'Keeper restarted before the job finished',
+                updated_at = ?1
+         WHERE status = 'running'",
+        params![now_millis()],
+    )
 }
 
-pub fn start_lesson_retrieval_event(
+/// A first-person reflection written by a shard about its recent work.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct JournalEntry {
+    pub id: i64,
+    pub shard_id: String,
+    pub content: String,
+    /// The shard's `tasks_completed` when the entry was written
+    pub tasks_completed: u32,
+    pub created_at: u64,
+}
+
+/// Store a journal entry and return it with its assigned id.
+pub fn insert_journal_entry(
     data_dir: &str,
     shard_id: &str,
-    action_id: i64,
-    task: &str,
-    task_type: &str,
-    lesson_ids: &[i64],
-) -> SqliteResult<i64> {
+    content: &str,
+    tasks_completed: u32,
+) -> SqliteResult<JournalEntry> {
     let conn = open_db(data_dir)?;
     let now = now_millis();
-    let lesson_ids_json = serde_json::to_string(lesson_ids).unwrap_or_else(|_| "[]".to_string());
     conn.execute(
-        "INSERT INTO lesson_retrieval_events (
-            shard_id, action_id, task, task_type, lesson_ids_json, created_at
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![shard_id, action_id, task, task_type, lesson_ids_json, now],
+        "INSERT INTO journal (shard_id, content, tasks_completed, created_at)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![shard_id, content, tasks_completed, now],
     )?;
-    Ok(conn.last_insert_rowid())
+    Ok(JournalEntry {
+        id: conn.last_insert_rowid(),
+        shard_id: shard_id.to_string(),
+        content: content.to_string(),
+        tasks_completed,
+        created_at: now,
+    })
 }
 
-pub fn complete_lesson_retrieval_event(
-    data_dir: &str,
-    event_id: i64,
-    success: bool,
-    duration_ms: u64,
-    latency_delta_ms: Option<i64>,
-    helpful: bool,
-) -> SqliteResult<()> {
+/// Get a shard's journal entries, newest first.
+pub fn get_journal_entries(data_dir: &str, shard_id: &str, limit: u32) -> SqliteResult<Vec<JournalEntry>> {
+    let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare(
+        "SELECT id, shard_id, content, tasks_completed, created_at
+         FROM journal
+         WHERE shard_id = ?1
+         ORDER BY created_at DESC, id DESC
+         LIMIT ?2",
+    )?;
+    let entries = stmt
+        .query_map(params![shard_id, limit], |row| {
+            Ok(JournalEntry {
+                id: row.get(0)?,
+                shard_id: row.get(1)?,
+                content: row.get(2)?,
+                tasks_completed: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })?
+        .collect::<SqliteResult<Vec<_>>>()?;
+    Ok(entries)
+}
+
+/// Track a funded loan for periodic liquidation checks.
+pub fn track_loan(data_dir: &str, loan_id: &str) -> SqliteResult<()> {
     let conn = open_db(data_dir)?;
     let now = now_millis();
     conn.execute(
-        "UPDATE lesson_retrieval_events
-         SET completed_at = ?1, success = ?2, duration_ms = ?3, latency_delta_ms = ?4, helpful = ?5
-         WHERE id = ?6",
-        params![
-            now,
-            if success { 1 } else { 0 },
-            duration_ms,
-            latency_delta_ms,
-            if helpful { 1 } else { 0 },
-            event_id
-        ],
+        "INSERT OR REPLACE INTO tracked_loans (loan_id, state, added_at) VALUES (?1, 'Funded', ?2)",
+        params![loan_id, now],
     )?;
     Ok(())
 }
 
-pub fn apply_lesson_feedback(
+/// Remove a loan from tracking (e.g., after repayment or liquidation).
+pub fn untrack_loan(data_dir: &str, loan_id: &str) -> SqliteResult<()> {
+    let conn = open_db(data_dir)?;
+    conn.execute("DELETE FROM tracked_loans WHERE loan_id = ?1", params![loan_id])?;
+    Ok(())
+}
+
+// ── Daily action rollups ────────────────────────────────────────────
+
+/// One shard's finished actions for one UTC day and first tool.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DailyActionStats {
+    /// UTC date, `YYYY-MM-DD`
+    pub day: String,
+    /// First tool the action called, or "none"
+    pub tool_name: String,
+    pub actions: u32,
+    pub succeeded: u32,
+    pub failed: u32,
+    pub xp: u64,
+}
+
+/// What one `aggregate_actions_daily` pass did.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct ActionRollup {
+    /// Action rows folded into `action_daily`
+    pub aggregated: usize,
+    /// Raw rows deleted past the retention window
+    pub pruned: usize,
+    /// Rows past the window kept because a lesson or retrieval event points at
+    /// them; their tool input and output are cleared instead
+    pub trimmed: usize,
+}
+
+/// Fold finished actions started before `before` (ms) into per-day totals in
+/// `action_daily`. Each row is counted once. With `prune_before`, already
+/// aggregated rows older than it are deleted, or stripped of tool input and
+/// output when lessons still reference them. Runs in one transaction.
+pub fn aggregate_actions_daily(
     data_dir: &str,
-    lesson_ids: &[i64],
-    helpful: bool,
-) -> SqliteResult<()> {
-    if lesson_ids.is_empty() {
-        return Ok(());
+    before: u64,
+    prune_before: Option<u64>,
+) -> SqliteResult<ActionRollup> {
+    let mut conn = open_db(data_dir)?;
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+    tx.execute(
+        "INSERT INTO action_daily (shard_id, day, tool_name, actions, succeeded, failed, xp)
+         SELECT shard_id, date(started_at / 1000, 'unixepoch'), COALESCE(tool_name, 'none'),
+                COUNT(*), SUM(status = 'success'), SUM(status = 'failed'), SUM(xp_awarded)
+         FROM action_log
+         WHERE aggregated = 0 AND status != 'pending' AND started_at < ?1
+         GROUP BY 1, 2, 3
+         ON CONFLICT(shard_id, day, tool_name) DO UPDATE SET
+            actions = actions + excluded.actions,
+            succeeded = succeeded + excluded.succeeded,
+            failed = failed + excluded.failed,
+            xp = xp + excluded.xp",
+        params![before],
+    )?;
+    let aggregated = tx.execute(
+        "UPDATE action_log SET aggregated = 1
+         WHERE aggregated = 0 AND status != 'pending' AND started_at < ?1",
+        params![before],
+    )?;
+
+    let (mut pruned, mut trimmed) = (0, 0);
+    if let Some(cutoff) = prune_before {
+        pruned = tx.execute(
+            "DELETE FROM action_log
+             WHERE aggregated = 1 AND started_at < ?1
+               AND id NOT IN (SELECT action_id FROM task_lessons)
+               AND id NOT IN (SELECT action_id FROM lesson_retrieval_events)",
+            params![cutoff],
+        )?;
+        trimmed = tx.execute(
+            "UPDATE action_log SET tool_input = NULL, tool_output = NULL
+             WHERE aggregated = 1 AND started_at < ?1
+               AND (tool_input IS NOT NULL OR tool_output IS NOT NULL)",
+            params![cutoff],
+        )?;
     }
+
+    tx.commit()?;
+    Ok(ActionRollup { aggregated, pruned, trimmed })
+}
+
+/// A shard's daily action totals, newest day first.
+pub fn get_daily_actions(data_dir: &str, shard_id: &str, limit: u32) -> SqliteResult<Vec<DailyActionStats>> {
     let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare(
+        "SELECT day, tool_name, actions, succeeded, failed, xp
+         FROM action_daily
+         WHERE shard_id = ?1
+         ORDER BY day DESC, tool_name
+         LIMIT ?2",
+    )?;
+    let days = stmt
+        .query_map(params![shard_id, limit], |row| {
+            Ok(DailyActionStats {
+                day: row.get(0)?,
+                tool_name: row.get(1)?,
+                actions: row.get(2)?,
+                succeeded: row.get(3)?,
+                failed: row.get(4)?,
+                xp: row.get(5)?,
+            })
+        })?
+        .collect::<SqliteResult<Vec<_>>>()?;
+    Ok(days)
+}
+
+// ── Battles ─────────────────────────────────────────────────────────
+
+/// One side of a battle: the shard, who it fought, and how its rating moved.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BattleRecord {
+    pub id: i64,
+    pub shard_id: String,
+    pub opponent_id: String,
+    pub won: bool,
+    pub rounds: u32,
+    pub elo_before: u32,
+    pub elo_after: u32,
+    /// Replays the battle with the same outcome
+    pub seed: u64,
+    pub fought_at: u64,
+}
+
+const BATTLE_COLUMNS: &str = "id, shard_id, opponent_id, won, rounds, elo_before, elo_after, seed, fought_at";
+
+fn row_to_battle(row: &rusqlite::Row) -> SqliteResult<BattleRecord> {
+    let seed: String = row.get(7)?;
+    Ok(BattleRecord {
+        id: row.get(0)?,
+        shard_id: row.get(1)?,
+        opponent_id: row.get(2)?,
+        won: row.get::<_, i32>(3)? != 0,
+        rounds: row.get(4)?,
+        elo_before: row.get(5)?,
+        elo_after: row.get(6)?,
+        seed: seed.parse().unwrap_or_default(),
+        fought_at: row.get(8)?,
+    })
+}
+
+/// Record a battle once for each side, so it shows in both shards'
+/// timelines. `elo` is each side's rating before and after, challenger first.
+pub fn insert_battle(data_dir: &str, outcome: &BattleOutcome, elo: [(u32, u32); 2]) -> SqliteResult<()> {
+    let mut conn = open_db(data_dir)?;
+    let tx = conn.transaction()?;
     let now = now_millis();
-    let delta = if helpful { 0.08 } else { -0.05 };
-    for id in lesson_ids {
-        conn.execute(
-            "UPDATE task_lessons
-             SET times_retrieved = times_retrieved + 1,
-                 times_helpful = times_helpful + ?1,
-                 times_unhelpful = times_unhelpful + ?2,
-                 score = MIN(1.0, MAX(0.0, score + ?3)),
-                 updated_at = ?4
-             WHERE id = ?5",
+    let sides = [
+        (&outcome.challenger_id, &outcome.defender_id, elo[0]),
+        (&outcome.defender_id, &outcome.challenger_id, elo[1]),
+    ];
+    for (shard_id, opponent_id, (before, after)) in sides {
+        tx.execute(
+            "INSERT INTO battles (shard_id, opponent_id, won, rounds, elo_before, elo_after, seed, fought_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
             params![
-                if helpful { 1 } else { 0 },
-                if helpful { 0 } else { 1 },
-                delta,
-                now,
-                id
+                shard_id,
+                opponent_id,
+                (outcome.winner_id == *shard_id) as i32,
+                outcome.rounds.len() as u32,
+                before,
+                after,
+                outcome.seed.to_string(),
+                now
             ],
         )?;
     }
-    Ok(())
+    tx.commit()
 }
 
-pub fn avg_success_duration_by_task_type(
-    data_dir: &str,
-    shard_id: &str,
-    task_type: &str,
-) -> SqliteResult<Option<u64>> {
+// ── Benchmarks ──────────────────────────────────────────────────────
+
+/// A stored benchmark run.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BenchmarkRun {
+    pub id: i64,
+    pub shard_id: String,
+    pub created_at: u64,
+    #[serde(flatten)]
+    pub summary: Summary,
+}
+
+/// Record a benchmark run for a shard. Returns the row id.
+pub fn insert_benchmark(data_dir: &str, shard_id: &str, summary: &Summary) -> SqliteResult<i64> {
     let conn = open_db(data_dir)?;
-    let avg: Option<f64> = conn.query_row(
-        "SELECT AVG(duration_ms) FROM task_lessons
-         WHERE shard_id = ?1 AND task_type = ?2 AND success = 1",
-        params![shard_id, task_type],
-        |row| row.get(0),
+    let results_json = serde_json::to_string(&summary.results).unwrap_or_else(|_| "[]".to_string());
+    conn.execute(
+        "INSERT INTO benchmarks
+            (shard_id, suite_version, score, tasks_passed, tasks_total, total_duration_ms, results_json, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            shard_id,
+            summary.suite_version,
+            summary.score,
+            summary.tasks_passed,
+            summary.tasks_total,
+            summary.total_duration_ms,
+            results_json,
+            now_millis()
+        ],
     )?;
-    Ok(avg.map(|v| v.max(0.0) as u64))
+    Ok(conn.last_insert_rowid())
 }
 
-pub fn get_recent_lesson_retrieval_events(
-    data_dir: &str,
-    shard_id: &str,
-    limit: u32,
-) -> SqliteResult<Vec<LessonRetrievalEvent>> {
+/// Recent benchmark runs for a shard, newest first.
+pub fn get_benchmarks(data_dir: &str, shard_id: &str, limit: u32) -> SqliteResult<Vec<BenchmarkRun>> {
     let conn = open_db(data_dir)?;
     let mut stmt = conn.prepare(
-        "SELECT id, shard_id, action_id, task, task_type, lesson_ids_json, created_at,
-                completed_at, success, duration_ms, latency_delta_ms, helpful
-         FROM lesson_retrieval_events
+        "SELECT id, shard_id, created_at, suite_version, score, tasks_passed, tasks_total,
+                total_duration_ms, results_json
+         FROM benchmarks
          WHERE shard_id = ?1
-         ORDER BY created_at DESC
+         ORDER BY created_at DESC, id DESC
          LIMIT ?2",
     )?;
-
-    let events = stmt
+    let runs = stmt
         .query_map(params![shard_id, limit], |row| {
-            let lesson_ids_json: String = row.get(5)?;
-            Ok(LessonRetrievalEvent {
+            let results_json: String = row.get(8)?;
+            Ok(BenchmarkRun {
                 id: row.get(0)?,
                 shard_id: row.get(1)?,
-                action_id: row.get(2)?,
-                task: row.get(3)?,
-                task_type: row.get(4)?,
-                lesson_ids: serde_json::from_str(&lesson_ids_json).unwrap_or_default(),
-                created_at: row.get(6)?,
-                completed_at: row.get(7)?,
-                success: row.get::<_, Option<i64>>(8)?.map(|v| v != 0),
-                duration_ms: row.get(9)?,
-                latency_delta_ms: row.get(10)?,
-                helpful: row.get::<_, Option<i64>>(11)?.map(|v| v != 0),
+                created_at: row.get(2)?,
+                summary: Summary {
+                    suite_version: row.get(3)?,
+                    score: row.get(4)?,
+                    tasks_passed: row.get(5)?,
+                    tasks_total: row.get(6)?,
+                    total_duration_ms: row.get(7)?,
+                    results: serde_json::from_str(&results_json).unwrap_or_default(),
+                },
             })
         })?
         .collect::<SqliteResult<Vec<_>>>()?;
+    Ok(runs)
+}
+
+// ── Shard snapshots ─────────────────────────────────────────────────
+
+/// A saved copy of a shard's state.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ShardSnapshot {
+    pub id: i64,
+    pub shard_id: String,
+    pub label: Option<String>,
+    pub created_at: u64,
+    pub shard: Shard,
+}
+
+/// Save the shard's current state. Returns the snapshot id.
+pub fn insert_snapshot(data_dir: &str, shard: &Shard, label: Option<&str>) -> SqliteResult<i64> {
+    let conn = open_db(data_dir)?;
+    let shard_json = serde_json::to_string(shard)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    conn.execute(
+        "INSERT INTO shard_snapshots (shard_id, label, shard_json, created_at) VALUES (?1, ?2, ?3, ?4)",
+        params![shard.id, label, shard_json, now_millis()],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn row_to_snapshot(row: &rusqlite::Row) -> SqliteResult<ShardSnapshot> {
+    let shard_json: String = row.get(3)?;
+    Ok(ShardSnapshot {
+        id: row.get(0)?,
+        shard_id: row.get(1)?,
+        label: row.get(2)?,
+        shard: serde_json::from_str(&shard_json).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, Box::new(e))
+        })?,
+        created_at: row.get(4)?,
+    })
+}
+
+/// One of a shard's snapshots by id.
+pub fn get_snapshot(data_dir: &str, shard_id: &str, snapshot_id: i64) -> SqliteResult<Option<ShardSnapshot>> {
+    let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare(
+        "SELECT id, shard_id, label, shard_json, created_at
+         FROM shard_snapshots WHERE id = ?1 AND shard_id = ?2",
+    )?;
+    let mut rows = stmt.query_map(params![snapshot_id, shard_id], row_to_snapshot)?;
+    rows.next().transpose()
+}
+
+/// A shard's snapshots, newest first.
+pub fn get_snapshots(data_dir: &str, shard_id: &str, limit: u32) -> SqliteResult<Vec<ShardSnapshot>> {
+    let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare(
+        "SELECT id, shard_id, label, shard_json, created_at
+         FROM shard_snapshots
+         WHERE shard_id = ?1
+         ORDER BY created_at DESC, id DESC
+         LIMIT ?2",
+    )?;
+    let snapshots = stmt
+        .query_map(params![shard_id, limit], row_to_snapshot)?
+        .collect::<SqliteResult<Vec<_>>>()?;
+    Ok(snapshots)
+}
+
+// ── Shard relationships ─────────────────────────────────────────────
+
+/// How two shards have interacted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelationshipKind {
+    Battled,
+    Delegated,
+    Bred,
+}
+
+impl RelationshipKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Battled => "battled",
+            Self::Delegated => "delegated",
+            Self::Bred => "bred",
+        }
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "battled" => Some(Self::Battled),
+            "delegated" => Some(Self::Delegated),
+            "bred" => Some(Self::Bred),
+            _ => None,
+        }
+    }
+
+    /// Delegation has a direction; battles and breeding don't.
+    fn is_mutual(self) -> bool {
+        !matches!(self, Self::Delegated)
+    }
+}
+
+/// One edge of a shard's relationship graph, seen from that shard.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Relationship {
+    pub other_id: String,
+    pub kind: RelationshipKind,
+    /// "outgoing", "incoming", or "mutual"
+    pub direction: &'static str,
+    pub count: u32,
+    pub first_at: u64,
+    pub last_at: u64,
+}
+
+/// Record an interaction from `shard_id` to `other_id`, bumping the edge's
+/// count. Mutual kinds are stored once per pair regardless of who started it.
+pub fn record_relationship(
+    data_dir: &str,
+    shard_id: &str,
+    other_id: &str,
+    kind: RelationshipKind,
+) -> SqliteResult<()> {
+    let conn = open_db(data_dir)?;
+    let now = now_millis();
+    add_relationship(&conn, shard_id, other_id, kind, 1, now, now)
+}
+
+/// Add `count` interactions seen between `first_at` and `last_at` to an edge.
+fn add_relationship(
+    conn: &Connection,
+    shard_id: &str,
+    other_id: &str,
+    kind: RelationshipKind,
+    count: u32,
+    first_at: u64,
+    last_at: u64,
+) -> SqliteResult<()> {
+    let (from, to) = if kind.is_mutual() && other_id < shard_id {
+        (other_id, shard_id)
+    } else {
+        (shard_id, other_id)
+    };
+    conn.execute(
+        "INSERT INTO shard_relationships (shard_id, other_id, kind, count, first_at, last_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(shard_id, other_id, kind) DO UPDATE SET
+            count = count + excluded.count,
+            first_at = MIN(first_at, excluded.first_at),
+            last_at = MAX(last_at, excluded.last_at)",
+        params![from, to, kind.as_str(), count, first_at, last_at],
+    )?;
+    Ok(())
+}
 
-    Ok(events)
+/// Move `from_id`'s edges onto `to_id`, folding them into edges `to_id`
+/// already has. Edges between the two are dropped. Returns how many moved.
+fn repoint_relationships(conn: &Connection, from_id: &str, to_id: &str) -> SqliteResult<usize> {
+    let edges: Vec<(String, String, String, u32, u64, u64)> = {
+        let mut stmt = conn.prepare(
+            "SELECT shard_id, other_id, kind, count, first_at, last_at
+             FROM shard_relationships WHERE shard_id = ?1 OR other_id = ?1",
+        )?;
+        let rows = stmt.query_map(params![from_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+        })?;
+        rows.collect::<SqliteResult<_>>()?
+    };
+    conn.execute(
+        "DELETE FROM shard_relationships WHERE shard_id = ?1 OR other_id = ?1",
+        params![from_id],
+    )?;
+
+    let repoint = |id: String| if id == from_id { to_id.to_string() } else { id };
+    let mut moved = 0;
+    for (from, to, kind, count, first_at, last_at) in edges {
+        let (from, to) = (repoint(from), repoint(to));
+        let Some(kind) = RelationshipKind::from_str(&kind) else {
+            continue;
+        };
+        if from == to {
+            continue;
+        }
+        add_relationship(conn, &from, &to, kind, count, first_at, last_at)?;
+        moved += 1;
+    }
+    Ok(moved)
 }
 
-/// Get all tracked loan IDs with state = 'Funded' for liquidation checks.
-pub fn get_funded_loans(data_dir: &str) -> SqliteResult<Vec<String>> {
+/// All relationships involving a shard, most recent first.
+pub fn get_relationships(data_dir: &str, shard_id: &str) -> SqliteResult<Vec<Relationship>> {
     let conn = open_db(data_dir)?;
-
     let mut stmt = conn.prepare(
-        "SELECT loan_id FROM tracked_loans WHERE state = 'Funded'"
+        "SELECT shard_id, other_id, kind, count, first_at, last_at
+         FROM shard_relationships
+         WHERE shard_id = ?1 OR other_id = ?1
+         ORDER BY last_at DESC",
     )?;
+    let rows = stmt
+        .query_map(params![shard_id], |row| {
+            let from: String = row.get(0)?;
+            let to: String = row.get(1)?;
+            let kind: String = row.get(2)?;
+            Ok((from, to, kind, row.get(3)?, row.get(4)?, row.get(5)?))
+        })?
+        .collect::<SqliteResult<Vec<(String, String, String, u32, u64, u64)>>>()?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|(from, to, kind, count, first_at, last_at)| {
+            let kind = RelationshipKind::from_str(&kind)?;
+            let outgoing = from == shard_id;
+            let direction = match (kind.is_mutual(), outgoing) {
+                (true, _) => "mutual",
+                (false, true) => "outgoing",
+                (false, false) => "incoming",
+            };
+            Some(Relationship {
+                other_id: if outgoing { to } else { from },
+                kind,
+                direction,
+                count,
+                first_at,
+                last_at,
+            })
+        })
+        .collect())
+}
 
-    let loans = stmt
-        .query_map([], |row| row.get(0))?
-        .collect::<SqliteResult<Vec<String>>>()?;
+// ── Marketplace listings ────────────────────────────────────────────
 
-    Ok(loans)
+/// A shard offered for sale. `price_wei` is a decimal string.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Listing {
+    pub id: i64,
+    pub shard_id: String,
+    pub price_wei: String,
+    pub seller: String,
+    pub buyer: Option<String>,
+    pub status: String, // "open", "sold"
+    pub created_at: u64,
+    pub closed_at: Option<u64>,
+    /// Hash of the buyer's payment transaction, once sold
+    pub payment_tx: Option<String>,
 }
 
-/// Track a funded loan for periodic liquidation checks.
-pub fn track_loan(data_dir: &str, loan_id: &str) -> SqliteResult<()> {
+const LISTING_COLUMNS: &str = "id, shard_id, price_wei, seller, buyer, status, created_at, closed_at, payment_tx";
+
+fn row_to_listing(row: &rusqlite::Row) -> SqliteResult<Listing> {
+    Ok(Listing {
+        id: row.get(0)?,
+        shard_id: row.get(1)?,
+        price_wei: row.get(2)?,
+        seller: row.get(3)?,
+        buyer: row.get(4)?,
+        status: row.get(5)?,
+        created_at: row.get(6)?,
+        closed_at: row.get(7)?,
+        payment_tx: row.get(8)?,
+    })
+}
+
+/// Open a listing for a shard. Fails if the shard already has an open listing.
+pub fn create_listing(data_dir: &str, shard_id: &str, price_wei: &str, seller: &str) -> SqliteResult<Listing> {
     let conn = open_db(data_dir)?;
-    let now = now_millis();
     conn.execute(
-        "INSERT OR REPLACE INTO tracked_loans (loan_id, state, added_at) VALUES (?1, 'Funded', ?2)",
-        params![loan_id, now],
+        "INSERT INTO listings (shard_id, price_wei, seller, status, created_at)
+         VALUES (?1, ?2, ?3, 'open', ?4)",
+        params![shard_id, price_wei, seller, now_millis()],
     )?;
-    Ok(())
+    let id = conn.last_insert_rowid();
+    conn.query_row(
+        &format!("SELECT {} FROM listings WHERE id = ?1", LISTING_COLUMNS),
+        params![id],
+        row_to_listing,
+    )
 }
 
-/// Remove a loan from tracking (e.g., after repayment or liquidation).
-pub fn untrack_loan(data_dir: &str, loan_id: &str) -> SqliteResult<()> {
+pub fn get_listing(data_dir: &str, listing_id: i64) -> SqliteResult<Option<Listing>> {
     let conn = open_db(data_dir)?;
-    conn.execute("DELETE FROM tracked_loans WHERE loan_id = ?1", params![loan_id])?;
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM listings WHERE id = ?1", LISTING_COLUMNS))?;
+    let mut rows = stmt.query_map(params![listing_id], row_to_listing)?;
+    rows.next().transpose()
+}
+
+/// Open listing for a shard, if any.
+pub fn get_open_listing_for_shard(data_dir: &str, shard_id: &str) -> SqliteResult<Option<Listing>> {
+    let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM listings WHERE shard_id = ?1 AND status = 'open'",
+        LISTING_COLUMNS
+    ))?;
+    let mut rows = stmt.query_map(params![shard_id], row_to_listing)?;
+    rows.next().transpose()
+}
+
+/// Listings with the given status (all when None), newest first.
+pub fn list_listings(data_dir: &str, status: Option<&str>) -> SqliteResult<Vec<Listing>> {
+    let conn = open_db(data_dir)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM listings
+         WHERE ?1 IS NULL OR status = ?1
+         ORDER BY created_at DESC, id DESC",
+        LISTING_COLUMNS
+    ))?;
+    let listings = stmt
+        .query_map(params![status], row_to_listing)?
+        .collect::<SqliteResult<Vec<_>>>()?;
+    Ok(listings)
+}
+
+/// Close an open listing as sold to `buyer` against `payment_tx` and hand the
+/// shard to them, in one transaction. Returns false if the listing was no
+/// longer open; a payment already used for another sale is a constraint error.
+pub fn complete_listing_sale(data_dir: &str, listing_id: i64, buyer: &str, payment_tx: &str) -> SqliteResult<bool> {
+    let mut conn = open_db(data_dir)?;
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+    let shard_id: Option<String> = {
+        let mut stmt = tx.prepare("SELECT shard_id FROM listings WHERE id = ?1 AND status = 'open'")?;
+        let mut rows = stmt.query_map(params![listing_id], |row| row.get(0))?;
+        rows.next().transpose()?
+    };
+    let Some(shard_id) = shard_id else {
+        return Ok(false);
+    };
+    tx.execute(
+        "UPDATE listings SET status = 'sold', buyer = ?1, closed_at = ?2, payment_tx = ?3 WHERE id = ?4",
+        params![buyer, now_millis(), payment_tx, listing_id],
+    )?;
+    tx.execute(
+        "UPDATE shards SET owner_id = ?1, is_wild = 0 WHERE id = ?2",
+        params![buyer, shard_id],
+    )?;
+    tx.commit()?;
+    Ok(true)
+}
+
+/// Undo `complete_listing_sale` after a failed on-chain transfer: reopen the
+/// listing and give the shard back to the seller.
+pub fn revert_listing_sale(data_dir: &str, listing_id: i64) -> SqliteResult<()> {
+    let mut conn = open_db(data_dir)?;
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+    tx.execute(
+        "UPDATE shards SET owner_id = (SELECT seller FROM listings WHERE id = ?1)
+         WHERE id = (SELECT shard_id FROM listings WHERE id = ?1 AND status = 'sold')",
+        params![listing_id],
+    )?;
+    tx.execute(
+        "UPDATE listings SET status = 'open', buyer = NULL, closed_at = NULL, payment_tx = NULL
+         WHERE id = ?1 AND status = 'sold'",
+        params![listing_id],
+    )?;
+    tx.commit()?;
     Ok(())
 }
 
@@ -975,24 +3401,147 @@ fn row_to_task_lesson(row: &rusqlite::Row) -> SqliteResult<TaskLesson> {
         times_unhelpful: row.get(19)?,
         created_at: row.get(20)?,
         updated_at: row.get(21)?,
+        pinned: row.get::<_, i64>(22)? != 0,
     })
 }
 
+/// Weights for combining lesson relevance signals into one rank. The same
+/// struct drives the DB prefilter (`lesson_rank`, which has no embeddings, so
+/// `semantic` is unused there) and the final hybrid ranking.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RetrievalWeights {
+    pub semantic: f64,
+    pub lexical: f64,
+    pub score: f64,
+    pub helpful: f64,
+    pub recency: f64,
+    /// Flat boost when the lesson's task type matches the query's
+    pub type_match: f64,
+    /// Flat boost for pinned lessons
+    pub pinned: f64,
+}
+
+/// Per-lesson inputs to `RetrievalWeights::rank`, each in 0..=1.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RankSignals {
+    pub semantic: f64,
+    pub lexical: f64,
+    pub score: f64,
+    pub helpful_rate: f64,
+    pub recency: f64,
+    pub type_match: bool,
+    pub pinned: bool,
+}
+
+/// Weighted contribution of each signal to a lesson's rank; they add up to
+/// the unclamped rank.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RankBreakdown {
+    pub semantic: f64,
+    pub lexical: f64,
+    pub score: f64,
+    pub helpful_rate: f64,
+    pub recency: f64,
+    pub type_boost: f64,
+    pub pinned_boost: f64,
+}
+
+impl RankBreakdown {
+    pub fn total(&self) -> f64 {
+        self.semantic
+            + self.lexical
+            + self.score
+            + self.helpful_rate
+            + self.recency
+            + self.type_boost
+            + self.pinned_boost
+    }
+}
+
+impl RetrievalWeights {
+    /// Defaults for the final embedding-aware ranking.
+    pub const HYBRID: Self = Self {
+        semantic: 0.55,
+        lexical: 0.20,
+        score: 0.17,
+        helpful: 0.08,
+        recency: 0.0,
+        type_match: 0.08,
+        pinned: 0.05,
+    };
+
+    /// Defaults for the DB prefilter.
+    pub const PREFILTER: Self = Self {
+        semantic: 0.0,
+        lexical: 0.45,
+        score: 0.30,
+        helpful: 0.15,
+        recency: 0.10,
+        type_match: 0.2,
+        pinned: 0.05,
+    };
+
+    /// Every weight must be a finite, non-negative number.
+    pub fn validate(&self, name: &str) -> Result<(), String> {
+        let weights = [
+            ("semantic", self.semantic),
+            ("lexical", self.lexical),
+            ("score", self.score),
+            ("helpful", self.helpful),
+            ("recency", self.recency),
+            ("type_match", self.type_match),
+            ("pinned", self.pinned),
+        ];
+        for (field, weight) in weights {
+            if !weight.is_finite() || weight < 0.0 {
+                return Err(format!("{}.{} must be a non-negative number, got {}", name, field, weight));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn rank(&self, signals: &RankSignals) -> f64 {
+        self.breakdown(signals).total()
+    }
+
+    /// Each signal's weighted contribution to `rank`.
+    pub fn breakdown(&self, signals: &RankSignals) -> RankBreakdown {
+        RankBreakdown {
+            semantic: signals.semantic * self.semantic,
+            lexical: signals.lexical * self.lexical,
+            score: signals.score * self.score,
+            helpful_rate: signals.helpful_rate * self.helpful,
+            recency: signals.recency * self.recency,
+            type_boost: if signals.type_match { self.type_match } else { 0.0 },
+            pinned_boost: if signals.pinned { self.pinned } else { 0.0 },
+        }
+    }
+}
+
+/// Signals that don't need embeddings: lexical overlap with the query, the
+/// lesson's own score, helpfulness, recency, type match and pinning.
+pub fn lesson_signals(lesson: &TaskLesson, query_tokens: &[String], task_type: &str, now_ms: u64) -> RankSignals {
+    let corpus = format!("{} {} {}", lesson.goal, lesson.approach, lesson.outcome);
+    let age_days = ((now_ms.saturating_sub(lesson.created_at)) as f64 / 86_400_000.0).max(0.0);
+    RankSignals {
+        semantic: 0.0,
+        lexical: jaccard(query_tokens, &tokenize(&corpus)),
+        score: lesson.score,
+        helpful_rate: (lesson.times_helpful as f64 + 1.0) / (lesson.times_retrieved as f64 + 2.0),
+        recency: 1.0 / (1.0 + age_days / 14.0),
+        type_match: lesson.task_type == task_type,
+        pinned: lesson.pinned,
+    }
+}
+
 fn lesson_rank(
     lesson: &TaskLesson,
     query_tokens: &[String],
     task_type: &str,
     now_ms: u64,
+    weights: &RetrievalWeights,
 ) -> f64 {
-    let corpus = format!("{} {} {}", lesson.goal, lesson.approach, lesson.outcome);
-    let lesson_tokens = tokenize(&corpus);
-    let lexical = jaccard(query_tokens, &lesson_tokens);
-    let type_boost = if lesson.task_type == task_type { 0.2 } else { 0.0 };
-    let helpful_rate = (lesson.times_helpful as f64 + 1.0)
-        / (lesson.times_retrieved as f64 + 2.0);
-    let age_days = ((now_ms.saturating_sub(lesson.created_at)) as f64 / 86_400_000.0).max(0.0);
-    let recency = 1.0 / (1.0 + age_days / 14.0);
-    lexical * 0.45 + lesson.score * 0.30 + helpful_rate * 0.15 + recency * 0.10 + type_boost
+    weights.rank(&lesson_signals(lesson, query_tokens, task_type, now_ms))
 }
 
 fn tokenize(input: &str) -> Vec<String> {
@@ -1014,7 +3563,8 @@ fn jaccard(a: &[String], b: &[String]) -> f64 {
     if union == 0.0 { 0.0 } else { inter / union }
 }
 
-fn near_duplicate(a: &TaskLesson, b: &TaskLesson) -> bool {
+/// Same task type and near-identical goal and approach wording.
+pub fn near_duplicate(a: &TaskLesson, b: &TaskLesson) -> bool {
     if a.task_type != b.task_type {
         return false;
     }
@@ -1023,15 +3573,6 @@ fn near_duplicate(a: &TaskLesson, b: &TaskLesson) -> bool {
     jaccard(&ta, &tb) >= 0.82
 }
 
-/// Expand ~ to home directory in paths.
-fn shellexpand(path: &str) -> String {
-    if path.starts_with("~/") {
-        if let Ok(home) = std::env::var("HOME") {
-            return format!("{}{}", home, &path[1..]);
-        }
-    }
-    path.to_string()
-}
 
 #[cfg(test)]
 mod tests {
@@ -1106,9 +3647,13 @@ mod tests {
         let shard = Shard::spawn(None);
         insert_shard(&path, &shard).unwrap();
         assert_eq!(get_shards(&path).unwrap().len(), 1);
+        // History rows reference the shard; deleting must not trip the foreign keys
+        insert_interaction(&path, &shard.id, "user", "Hello shard", 0).unwrap();
+        insert_action(&path, &shard.id, "Analyze this CSV file", None).unwrap();
 
         delete_shard(&path, &shard.id).unwrap();
         assert_eq!(get_shards(&path).unwrap().len(), 0);
+        assert!(get_interactions(&path, &shard.id, 10).unwrap().is_empty());
     }
 
     #[test]
@@ -1137,7 +3682,8 @@ mod tests {
         let shard = Shard::spawn(None);
         insert_shard(&path, &shard).unwrap();
 
-        let action_id = insert_action(&path, &shard.id, "Analyze this CSV file").unwrap();
+        let action_id =
+            insert_action(&path, &shard.id, "Analyze this CSV file", Some("req-42")).unwrap();
         assert!(action_id > 0);
 
         complete_action(
@@ -1157,6 +3703,80 @@ mod tests {
         assert_eq!(actions[0].status, "success");
         assert_eq!(actions[0].xp_awarded, 25);
         assert_eq!(actions[0].tool_name.as_deref(), Some("code_eval"));
+        assert_eq!(actions[0].inference_ms, None);
+        assert_eq!(actions[0].request_id.as_deref(), Some("req-42"));
+
+        record_action_timing(&path, action_id, 820, 140).unwrap();
+        let actions = get_actions(&path, &shard.id, 10).unwrap();
+        assert_eq!(actions[0].inference_ms, Some(820));
+        assert_eq!(actions[0].tool_ms, Some(140));
+    }
+
+    #[test]
+    fn daily_rollup_counts_days_and_prunes_past_retention() {
+        let (_dir, path) = temp_data_dir();
+        init_db(&path).unwrap();
+        let shard = Shard::spawn(None);
+        insert_shard(&path, &shard).unwrap();
+
+        const DAY: u64 = 86_400_000;
+        let jan1 = 1_704_067_200_000; // 2024-01-01T00:00:00Z
+        let conn = open_db(&path).unwrap();
+        let log = |started_at: u64, tool: &str, status: &str, xp: u32| {
+            let id = insert_action(&path, &shard.id, "task", None).unwrap();
+            if status != "pending" {
+                complete_action(&path, id, tool, "{}", "out", status, xp, None).unwrap();
+            }
+            conn.execute("UPDATE action_log SET started_at = ?1 WHERE id = ?2", params![started_at, id])
+                .unwrap();
+            id
+        };
+        log(jan1 + 1000, "code_eval", "success", 20);
+        log(jan1 + DAY - 1, "code_eval", "failed", 5);
+        let with_lesson = log(jan1 + 2000, "http_fetch", "success", 10);
+        log(jan1 + DAY + 1000, "code_eval", "success", 20);
+        log(jan1 + 2 * DAY + 1000, "code_eval", "success", 20);
+        log(jan1 + 1000, "none", "pending", 0);
+        let lesson = NewTaskLesson {
+            shard_id: &shard.id,
+            action_id: with_lesson,
+            task_type: "research",
+            goal: "fetch",
+            approach: "fetch",
+            tools_used: &[],
+            outcome: "ok",
+            errors: &[],
+            fixes: &[],
+            duration_ms: 10,
+            success: true,
+            extractor_confidence: 0.5,
+            applicability_confidence: 0.5,
+            reusability: 0.5,
+            artifact_path: "memory://test",
+        };
+        insert_task_lesson(&path, &lesson).unwrap();
+
+        // Days before Jan 3 roll up; the pending action waits
+        let rollup = aggregate_actions_daily(&path, jan1 + 2 * DAY, None).unwrap();
+        assert_eq!(rollup, ActionRollup { aggregated: 4, pruned: 0, trimmed: 0 });
+        let days = get_daily_actions(&path, &shard.id, 10).unwrap();
+        let row = |day: &str, tool: &str| days.iter().find(|d| d.day == day && d.tool_name == tool).unwrap();
+        assert_eq!(days.len(), 3);
+        let jan1_code = row("2024-01-01", "code_eval");
+        assert_eq!((jan1_code.actions, jan1_code.succeeded, jan1_code.failed, jan1_code.xp), (2, 1, 1, 25));
+        assert_eq!(row("2024-01-01", "http_fetch").actions, 1);
+        assert_eq!(row("2024-01-02", "code_eval").actions, 1);
+
+        // A second pass doesn't double count; pruning only touches Jan 1, and
+        // keeps the row a lesson points at
+        let rollup = aggregate_actions_daily(&path, jan1 + 3 * DAY, Some(jan1 + DAY)).unwrap();
+        assert_eq!(rollup, ActionRollup { aggregated: 1, pruned: 2, trimmed: 1 });
+        assert_eq!(row("2024-01-01", "code_eval").actions, 2);
+        assert_eq!(get_daily_actions(&path, &shard.id, 10).unwrap().len(), 4);
+        let remaining = get_actions(&path, &shard.id, 10).unwrap();
+        assert_eq!(remaining.len(), 4);
+        let kept = remaining.iter().find(|a| a.id == with_lesson).unwrap();
+        assert!(kept.tool_output.is_none());
     }
 
     #[test]
@@ -1167,13 +3787,13 @@ mod tests {
         let shard = Shard::spawn(None);
         insert_shard(&path, &shard).unwrap();
 
-        let a1 = insert_action(&path, &shard.id, "task 1").unwrap();
+        let a1 = insert_action(&path, &shard.id, "task 1", None).unwrap();
         complete_action(&path, a1, "shell_exec", "{}", "ok", "success", 10, None).unwrap();
 
-        let a2 = insert_action(&path, &shard.id, "task 2").unwrap();
+        let a2 = insert_action(&path, &shard.id, "task 2", None).unwrap();
         complete_action(&path, a2, "http_fetch", "{}", "err", "failed", 0, None).unwrap();
 
-        let a3 = insert_action(&path, &shard.id, "task 3").unwrap();
+        let a3 = insert_action(&path, &shard.id, "task 3", None).unwrap();
         complete_action(&path, a3, "code_eval", "{}", "done", "success", 15, None).unwrap();
 
         let (total, success, failed) = get_action_summary(&path, &shard.id).unwrap();
@@ -1205,7 +3825,7 @@ mod tests {
 
         let shard = Shard::spawn(None);
         insert_shard(&path, &shard).unwrap();
-        let action_id = insert_action(&path, &shard.id, "Fix failing tests in parser").unwrap();
+        let action_id = insert_action(&path, &shard.id, "Fix failing tests in parser", None).unwrap();
         let tools = vec!["shell_exec".to_string(), "code_eval".to_string()];
         let errors = vec!["shell_exec: initial command failed".to_string()];
         let fixes = vec!["Recovered by narrowing command scope".to_string()];
@@ -1236,6 +3856,7 @@ mod tests {
             "parser tests are failing",
             "debug",
             5,
+            &RetrievalWeights::PREFILTER,
         )
         .unwrap();
         assert!(!retrieved.is_empty());
@@ -1247,4 +3868,515 @@ mod tests {
         assert_eq!(recent[0].times_retrieved, 1);
         assert_eq!(recent[0].times_helpful, 1);
     }
+
+    #[test]
+    fn timeline_merges_sources_and_respects_cursor() {
+        let (_dir, path) = temp_data_dir();
+        init_db(&path).unwrap();
+
+        let shard = Shard::spawn(None);
+        insert_shard(&path, &shard).unwrap();
+
+        let conn = open_db(&path).unwrap();
+        for ts in [100u64, 300, 500] {
+            conn.execute(
+                "INSERT INTO interactions (shard_id, role, content, xp_gained, timestamp)
+                 VALUES (?1, 'user', 'hi', 0, ?2)",
+                params![shard.id, ts],
+            )
+            .unwrap();
+        }
+        for ts in [200u64, 400] {
+            conn.execute(
+                "INSERT INTO action_log (shard_id, task_description, status, started_at)
+                 VALUES (?1, 'task', 'success', ?2)",
+                params![shard.id, ts],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "INSERT INTO battles (shard_id, opponent_id, won, rounds, elo_before, elo_after, seed, fought_at)
+             VALUES (?1, 'rival', 1, 3, 1000, 1016, '42', 250)",
+            params![shard.id],
+        )
+        .unwrap();
+
+        let timeline = get_timeline(&path, &shard.id, 10, None).unwrap();
+        let stamps: Vec<u64> = timeline.iter().map(|e| e.timestamp()).collect();
+        assert_eq!(stamps, vec![500, 400, 300, 250, 200, 100]);
+        assert!(matches!(timeline[1], TimelineEntry::Action(_)));
+        assert!(matches!(&timeline[3], TimelineEntry::Battle(b) if b.won && b.seed == 42));
+
+        let page = get_timeline(&path, &shard.id, 2, Some(400)).unwrap();
+        let stamps: Vec<u64> = page.iter().map(|e| e.timestamp()).collect();
+        assert_eq!(stamps, vec![300, 250]);
+
+        let json = serde_json::to_value(&page[0]).unwrap();
+        assert_eq!(json["kind"], "interaction");
+    }
+
+    #[test]
+    fn job_roundtrip_and_interrupted_jobs() {
+        let (_dir, path) = temp_data_dir();
+        init_db(&path).unwrap();
+
+        let mut job = JobRecord {
+            id: "job-1".to_string(),
+            shard_id: "shard-a".to_string(),
+            task: "do it".to_string(),
+            status: "running".to_string(),
+            result_json: None,
+            error: None,
+            created_at: 10,
+        };
+        upsert_job(&path, &job).unwrap();
+
+        job.status = "completed".to_string();
+        job.result_json = Some("{}".to_string());
+        upsert_job(&path, &job).unwrap();
+
+        let stored = get_job(&path, "job-1").unwrap().unwrap();
+        assert_eq!(stored.status, "completed");
+        assert_eq!(stored.result_json.as_deref(), Some("{}"));
+        assert!(get_job(&path, "missing").unwrap().is_none());
+
+        assert!(get_job_conversation(&path, "job-1").unwrap().is_none());
+        set_job_conversation(&path, "job-1", "[]").unwrap();
+        upsert_job(&path, &job).unwrap();
+        assert_eq!(get_job_conversation(&path, "job-1").unwrap().as_deref(), Some("[]"));
+
+        upsert_job(
+            &path,
+            &JobRecord {
+                id: "job-2".to_string(),
+                status: "running".to_string(),
+                result_json: None,
+                created_at: 20,
+                ..job.clone()
+            },
+        )
+        .unwrap();
+        assert_eq!(fail_interrupted_jobs(&path).unwrap(), 1);
+
+        let all = list_jobs(&path, None, 10).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].id, "job-2");
+        assert_eq!(all[0].status, "failed");
+        assert!(list_jobs(&path, Some("other"), 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn pinned_lesson_survives_prune() {
+        let (_dir, path) = temp_data_dir();
+        init_db(&path).unwrap();
+
+        let shard = Shard::spawn(None);
+        insert_shard(&path, &shard).unwrap();
+        let action_id = insert_action(&path, &shard.id, "task", None).unwrap();
+
+        let mut ids = Vec::new();
+        for confidence in [0.1, 0.5, 0.9] {
+            let lesson = NewTaskLesson {
+                shard_id: &shard.id,
+                action_id,
+                task_type: "general",
+                goal: "goal",
+                approach: "approach",
+                tools_used: &[],
+                outcome: "outcome",
+                errors: &[],
+                fixes: &[],
+                duration_ms: 100,
+                success: true,
+                extractor_confidence: confidence,
+                applicability_confidence: confidence,
+                reusability: confidence,
+                artifact_path: "/tmp/memory.json",
+            };
+            ids.push(insert_task_lesson(&path, &lesson).unwrap());
+        }
+        let (low, mid, high) = (ids[0], ids[1], ids[2]);
+
+        assert!(set_lesson_pinned(&path, &shard.id, low, true).unwrap());
+        assert!(!set_lesson_pinned(&path, "other-shard", mid, true).unwrap());
+
+        let removed = prune_task_lessons(&path, &shard.id, 1).unwrap();
+        assert_eq!(removed, 1);
+
+        let remaining: Vec<i64> = get_recent_task_lessons(&path, &shard.id, 10)
+            .unwrap()
+            .iter()
+            .map(|l| l.id)
+            .collect();
+        assert!(remaining.contains(&low));
+        assert!(remaining.contains(&high));
+        assert!(!remaining.contains(&mid));
+    }
+
+    #[test]
+    fn genome_duplicates_are_merged_into_most_trained() {
+        let (_dir, path) = temp_data_dir();
+        init_db(&path).unwrap();
+
+        let mut keep = Shard::spawn(None);
+        keep.xp = 250;
+        keep.tasks_completed = 3;
+        let mut dup = keep.clone();
+        dup.id = "dup-shard".to_string();
+        dup.xp = 40;
+        dup.tasks_completed = 2;
+        let mut frozen = keep.clone();
+        frozen.id = "frozen-shard".to_string();
+        frozen.xp = 10;
+        frozen.read_only = true;
+        let other = Shard::spawn(None);
+        insert_shard(&path, &keep).unwrap();
+        insert_shard(&path, &dup).unwrap();
+        insert_shard(&path, &frozen).unwrap();
+        insert_shard(&path, &other).unwrap();
+
+        insert_interaction(&path, &dup.id, "user", "hello from the copy", 1).unwrap();
+        insert_interaction(&path, &keep.id, "user", "hello from the original", 1).unwrap();
+
+        let groups = find_genome_duplicates(&path).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0][0].id, keep.id);
+
+        let report = dedupe_genomes(&path).unwrap();
+        assert!(report.skipped.is_empty());
+        let results = report.merged;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].kept_id, keep.id);
+        assert_eq!(results[0].merged[0].shard_id, dup.id);
+        assert_eq!(results[0].merged[0].interactions, 1);
+
+        assert!(get_shard_by_id(&path, &dup.id).unwrap().is_none());
+        let kept = get_shard_by_id(&path, &keep.id).unwrap().unwrap();
+        assert_eq!(kept.tasks_completed, 5);
+        assert_eq!(get_interactions(&path, &keep.id, 10).unwrap().len(), 2);
+        // The read-only copy is left alone
+        assert!(get_shard_by_id(&path, &frozen.id).unwrap().is_some());
+        assert_eq!(get_shards(&path).unwrap().len(), 3);
+        assert!(dedupe_genomes(&path).unwrap().merged.is_empty());
+    }
+
+    #[test]
+    fn dedupe_skips_busy_and_cross_owner_duplicates() {
+        let (_dir, path) = temp_data_dir();
+        init_db(&path).unwrap();
+
+        let mut running = Shard::spawn(None);
+        running.execution_state = crate::shard::ExecutionState::Executing;
+        let mut idle_copy = running.clone();
+        idle_copy.id = "idle-copy".to_string();
+        idle_copy.execution_state = crate::shard::ExecutionState::Idle;
+        let mut mine = Shard::spawn(None);
+        mine.owner_id = Some("0xalice".to_string());
+        mine.xp = 100;
+        let mut theirs = mine.clone();
+        theirs.id = "their-copy".to_string();
+        theirs.owner_id = Some("0xbob".to_string());
+        theirs.xp = 0;
+        for shard in [&running, &idle_copy, &mine, &theirs] {
+            insert_shard(&path, shard).unwrap();
+        }
+
+        let report = dedupe_genomes(&path).unwrap();
+        assert!(report.merged.is_empty());
+        let mut reasons: Vec<_> = report.skipped.iter().map(|s| (s.reason, s.shard_ids.len())).collect();
+        reasons.sort_by_key(|(reason, _)| *reason as u8);
+        assert_eq!(reasons, vec![(SkipReason::Busy, 2), (SkipReason::OwnersDiffer, 2)]);
+        assert_eq!(get_shards(&path).unwrap().len(), 4);
+
+        // A shard claimed after the group was read is not merged away either
+        let mut claimed = mine.clone();
+        claimed.id = "claimed-copy".to_string();
+        insert_shard(&path, &claimed).unwrap();
+        assert!(claim_shard_for_execution(&path, &claimed.id, 0).unwrap());
+        assert!(merge_duplicate_shard(&path, &mine.id, &claimed).unwrap().is_none());
+        assert!(get_shard_by_id(&path, &claimed.id).unwrap().is_some());
+    }
+
+    #[test]
+    fn every_shard_keyed_table_is_listed() {
+        let (_dir, path) = temp_data_dir();
+        init_db(&path).unwrap();
+        let conn = open_db(&path).unwrap();
+        let mut keyed: Vec<String> = conn
+            .prepare(
+                "SELECT m.name FROM sqlite_master m, pragma_table_info(m.name) c
+                 WHERE m.type = 'table' AND c.name = 'shard_id'",
+            )
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<SqliteResult<_>>()
+            .unwrap();
+        keyed.sort();
+        let mut listed: Vec<String> = SHARD_TABLES.iter().map(|(table, _)| table.to_string()).collect();
+        listed.sort();
+        assert_eq!(keyed, listed);
+    }
+
+    #[test]
+    fn merging_carries_every_shard_table() {
+        let (_dir, path) = temp_data_dir();
+        init_db(&path).unwrap();
+        let keep = Shard::spawn(None);
+        let mut dup = keep.clone();
+        dup.id = "dup-shard".to_string();
+        let other = Shard::spawn(None);
+        for shard in [&keep, &dup, &other] {
+            insert_shard(&path, shard).unwrap();
+        }
+        insert_snapshot(&path, &dup, Some("before")).unwrap();
+        create_listing(&path, &dup.id, "1000", "0xseller").unwrap();
+        record_relationship(&path, &dup.id, &other.id, RelationshipKind::Battled).unwrap();
+        record_relationship(&path, &keep.id, &other.id, RelationshipKind::Battled).unwrap();
+        record_relationship(&path, &keep.id, &dup.id, RelationshipKind::Battled).unwrap();
+        set_paused(&path, Some(&dup.id), true).unwrap();
+
+        merge_duplicate_shard(&path, &keep.id, &dup).unwrap().unwrap();
+        assert_eq!(get_snapshots(&path, &keep.id, 10).unwrap().len(), 1);
+        assert!(list_listings(&path, None).unwrap().is_empty());
+        // The two battles with `other` fold into one edge; the one between
+        // the merged shards is gone
+        let relationships = get_relationships(&path, &keep.id).unwrap();
+        assert_eq!(relationships.len(), 1);
+        assert_eq!((relationships[0].other_id.as_str(), relationships[0].count), (other.id.as_str(), 2));
+        assert!(!is_paused(&path, Some(&dup.id)).unwrap());
+    }
+
+    #[test]
+    fn merging_a_duplicate_closes_its_paused_run() {
+        let (_dir, path) = temp_data_dir();
+        init_db(&path).unwrap();
+        let keep = Shard::spawn(None);
+        let mut dup = keep.clone();
+        dup.id = "dup-shard".to_string();
+        insert_shard(&path, &keep).unwrap();
+        insert_shard(&path, &dup).unwrap();
+        let paused = insert_action(&path, &dup.id, "summarize the file", None).unwrap();
+        complete_action(&path, paused, "ask_user", "summarize the file", "[]", "waiting_for_input", 0, None).unwrap();
+        set_pending_question(
+            &path,
+            &PendingQuestionRecord {
+                shard_id: dup.id.clone(),
+                task: "summarize the file".to_string(),
+                call_id: "call_ask".to_string(),
+                question: "Which file?".to_string(),
+                conversation_json: "[]".to_string(),
+                request_json: None,
+                action_id: Some(paused),
+                created_at: 1,
+            },
+        )
+        .unwrap();
+
+        merge_duplicate_shard(&path, &keep.id, &dup).unwrap().unwrap();
+        assert!(get_pending_question(&path, &dup.id).unwrap().is_none());
+        let action = get_action(&path, paused).unwrap().unwrap();
+        assert_eq!(action.shard_id, keep.id);
+        assert_eq!(action.status, "failed");
+    }
+
+    #[test]
+    fn interleaved_battles_keep_every_elo_change() {
+        let (_dir, path) = temp_data_dir();
+        init_db(&path).unwrap();
+        let shards: Vec<Shard> = (0..3).map(|_| Shard::spawn(None)).collect();
+        for shard in &shards {
+            insert_shard(&path, shard).unwrap();
+        }
+        let total = |path: &str| -> u32 {
+            shards.iter().map(|s| get_shard_by_id(path, &s.id).unwrap().unwrap().elo_rating).sum()
+        };
+        let start = total(&path);
+
+        // Both threads fight the shared middle shard, in lockstep
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(2));
+        let handles: Vec<_> = [(&shards[0], &shards[1]), (&shards[1], &shards[2])]
+            .into_iter()
+            .map(|(winner, loser)| {
+                let (path, barrier) = (path.clone(), barrier.clone());
+                let (winner, loser) = (winner.id.clone(), loser.id.clone());
+                std::thread::spawn(move || {
+                    for _ in 0..10 {
+                        barrier.wait();
+                        apply_battle_elo(&path, &winner, &loser).unwrap().unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // Every point one side gained the other lost
+        assert_eq!(total(&path), start);
+        let rating = |i: usize| get_shard_by_id(&path, &shards[i].id).unwrap().unwrap().elo_rating;
+        assert!(rating(0) > shards[0].elo_rating);
+        assert!(rating(2) < shards[2].elo_rating);
+    }
+
+    #[test]
+    fn concurrent_execution_outcomes_both_apply() {
+        let (_dir, path) = temp_data_dir();
+        init_db(&path).unwrap();
+        let shard = Shard::spawn(None);
+        insert_shard(&path, &shard).unwrap();
+
+        let handles: Vec<_> = [("creativity", true), ("precision", false)]
+            .into_iter()
+            .map(|(stat, success)| {
+                let path = path.clone();
+                let id = shard.id.clone();
+                std::thread::spawn(move || {
+                    let outcome = ExecutionOutcome {
+                        xp: 60,
+                        stat_bonuses: HashMap::from([(stat.to_string(), 2), ("bogus".to_string(), 9)]),
+                        stat_limits: StatLimits::default(),
+                        success,
+                        finished_at: 1,
+                        failure_cooldown: Default::default(),
+                    };
+                    apply_execution_outcome(&path, &id, &outcome, 100).unwrap().unwrap()
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let updated = get_shard_by_id(&path, &shard.id).unwrap().unwrap();
+        assert_eq!(updated.xp, shard.xp + 120);
+        assert_eq!(updated.level, (updated.xp / crate::shard::XP_PER_LEVEL) as u32 + 1);
+        assert_eq!(updated.stats.creativity, shard.stats.creativity + 2);
+        assert_eq!(updated.stats.precision, shard.stats.precision + 2);
+        assert_eq!(updated.stats.intelligence, shard.stats.intelligence);
+        assert_eq!((updated.tasks_completed, updated.tasks_failed), (1, 1));
+
+        assert!(apply_execution_outcome(&path, "missing", &ExecutionOutcome::default(), 100)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn battle_edges_show_up_for_both_shards() {
+        let (_dir, path) = temp_data_dir();
+        init_db(&path).unwrap();
+
+        record_relationship(&path, "b", "a", RelationshipKind::Battled).unwrap();
+        record_relationship(&path, "a", "b", RelationshipKind::Battled).unwrap();
+        record_relationship(&path, "a", "c", RelationshipKind::Delegated).unwrap();
+
+        let a = get_relationships(&path, "a").unwrap();
+        assert_eq!(a.len(), 2);
+        let battle = a.iter().find(|r| r.kind == RelationshipKind::Battled).unwrap();
+        assert_eq!((battle.other_id.as_str(), battle.direction, battle.count), ("b", "mutual", 2));
+        let delegation = a.iter().find(|r| r.kind == RelationshipKind::Delegated).unwrap();
+        assert_eq!((delegation.other_id.as_str(), delegation.direction), ("c", "outgoing"));
+
+        let b = get_relationships(&path, "b").unwrap();
+        assert_eq!(b.len(), 1);
+        assert_eq!((b[0].other_id.as_str(), b[0].count), ("a", 2));
+        assert_eq!(get_relationships(&path, "c").unwrap()[0].direction, "incoming");
+        assert!(get_relationships(&path, "d").unwrap().is_empty());
+    }
+
+    #[test]
+    fn lesson_impact_summarizes_retrieval_events() {
+        let (_dir, path) = temp_data_dir();
+        init_db(&path).unwrap();
+        let shard = Shard::spawn(None);
+        insert_shard(&path, &shard).unwrap();
+
+        let empty = lesson_impact_summary(&path, &shard.id, 0).unwrap();
+        assert_eq!((empty.retrievals, empty.completed), (0, 0));
+        assert!(empty.helpful_rate.is_none() && empty.avg_latency_delta_ms.is_none());
+
+        // Three finished retrievals and one still running
+        let outcomes = [(true, Some(-200), true), (true, Some(100), false), (false, None, false)];
+        for (success, delta, helpful) in outcomes {
+            let action = insert_action(&path, &shard.id, "task", None).unwrap();
+            let event = start_lesson_retrieval_event(&path, &shard.id, action, "task", "coding", &[1]).unwrap();
+            complete_lesson_retrieval_event(&path, event, success, 500, delta, helpful).unwrap();
+        }
+        let action = insert_action(&path, &shard.id, "task", None).unwrap();
+        start_lesson_retrieval_event(&path, &shard.id, action, "task", "coding", &[1]).unwrap();
+
+        let summary = lesson_impact_summary(&path, &shard.id, 0).unwrap();
+        assert_eq!((summary.retrievals, summary.completed, summary.helpful), (4, 3, 1));
+        assert!((summary.helpful_rate.unwrap() - 1.0 / 3.0).abs() < 1e-9);
+        assert!((summary.success_rate.unwrap() - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(summary.avg_latency_delta_ms, Some(-50.0));
+
+        // Events before the window are left out
+        let later = lesson_impact_summary(&path, &shard.id, now_millis() + 1).unwrap();
+        assert_eq!(later.retrievals, 0);
+    }
+
+    #[test]
+    fn execution_outcome_respects_stat_cap() {
+        let (_dir, path) = temp_data_dir();
+        init_db(&path).unwrap();
+        let shard = Shard::spawn(None);
+        insert_shard(&path, &shard).unwrap();
+
+        let cap = shard.stats.precision + 1;
+        let outcome = ExecutionOutcome {
+            xp: 10,
+            stat_bonuses: HashMap::from([("precision".to_string(), 4)]),
+            stat_limits: StatLimits {
+                cap,
+                overflow: crate::shard::StatOverflow::Xp,
+            },
+            success: true,
+            finished_at: 1,
+            failure_cooldown: Default::default(),
+        };
+        let updated = apply_execution_outcome(&path, &shard.id, &outcome, 100).unwrap().unwrap();
+        assert_eq!(updated.stats.precision, cap);
+        assert_eq!(updated.xp, shard.xp + 10 + 3);
+    }
+
+    #[test]
+    fn failing_streak_triggers_cooldown_until_success() {
+        let (_dir, path) = temp_data_dir();
+        init_db(&path).unwrap();
+        let shard = Shard::spawn(None);
+        insert_shard(&path, &shard).unwrap();
+
+        let cooldown = FailureCooldown {
+            threshold: 3,
+            base_secs: 60,
+            max_secs: 100,
+        };
+        let finish = |success: bool, at: u64| {
+            let outcome = ExecutionOutcome {
+                success,
+                finished_at: at,
+                failure_cooldown: cooldown,
+                ..Default::default()
+            };
+            apply_execution_outcome(&path, &shard.id, &outcome, 100).unwrap().unwrap()
+        };
+
+        for at in 1..=2 {
+            assert_eq!(finish(false, at).execution_state, crate::shard::ExecutionState::Idle);
+        }
+        assert_eq!(get_failure_streak(&path, &shard.id).unwrap(), (2, 0));
+
+        let cooled = finish(false, 1_000);
+        assert_eq!(cooled.execution_state, crate::shard::ExecutionState::Cooldown);
+        assert_eq!(get_failure_streak(&path, &shard.id).unwrap(), (3, 61_000));
+
+        // Each further failure doubles the cooldown, up to the max
+        finish(false, 2_000);
+        assert_eq!(get_failure_streak(&path, &shard.id).unwrap(), (4, 102_000));
+
+        let recovered = finish(true, 3_000);
+        assert_eq!(recovered.execution_state, crate::shard::ExecutionState::Idle);
+        assert_eq!(get_failure_streak(&path, &shard.id).unwrap(), (0, 0));
+    }
 }
@@ -1,5 +1,6 @@
 use libp2p::kad::{self, RecordKey};
 use libp2p::Swarm;
+use std::collections::HashMap;
 
 use crate::node::KeeperBehaviour;
 use crate::shard::Shard;
@@ -33,6 +34,40 @@ pub fn publish_shard_record(
     Ok(())
 }
 
+/// Stop serving a shard's record from the local store. Copies held by other
+/// peers are no longer republished and lapse at their TTL.
+pub fn unpublish_shard_record(swarm: &mut Swarm<KeeperBehaviour>, shard_id: &str) {
+    let key = RecordKey::new(&format!("/siphon/shard/{}", shard_id));
+    swarm.behaviour_mut().kademlia.remove_record(&key);
+}
+
+/// Changes needed to bring published shard records in line with local state.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RecordAudit {
+    /// Shards with no record yet, or whose record predates their last change
+    pub publish: Vec<String>,
+    /// Published shard ids no longer hosted locally
+    pub expire: Vec<String>,
+}
+
+/// Diff hosted shards against `published` (shard id -> `last_interaction` at
+/// publish time). Results are sorted for stable ordering.
+pub fn audit_records(local: &[Shard], published: &HashMap<String, u64>) -> RecordAudit {
+    let mut publish: Vec<String> = local
+        .iter()
+        .filter(|s| published.get(&s.id) != Some(&s.last_interaction))
+        .map(|s| s.id.clone())
+        .collect();
+    let mut expire: Vec<String> = published
+        .keys()
+        .filter(|id| !local.iter().any(|s| &s.id == *id))
+        .cloned()
+        .collect();
+    publish.sort();
+    expire.sort();
+    RecordAudit { publish, expire }
+}
+
 /// Look up a shard record from the Kademlia DHT by shard ID.
 ///
 /// This initiates an async DHT query. The result will arrive as a
@@ -86,3 +121,29 @@ pub fn lookup_record(
         .kademlia
         .get_record(RecordKey::new(&key))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audit_publishes_missing_and_stale_and_expires_removed() {
+        let mut fresh = Shard::spawn(None);
+        fresh.id = "a-fresh".to_string();
+        let mut current = Shard::spawn(None);
+        current.id = "b-current".to_string();
+        let mut changed = Shard::spawn(None);
+        changed.id = "c-changed".to_string();
+
+        let mut published = HashMap::new();
+        published.insert(current.id.clone(), current.last_interaction);
+        published.insert(changed.id.clone(), changed.last_interaction.saturating_sub(1));
+        published.insert("d-deleted".to_string(), 0);
+
+        let audit = audit_records(&[fresh, current, changed], &published);
+        assert_eq!(audit.publish, vec!["a-fresh", "c-changed"]);
+        assert_eq!(audit.expire, vec!["d-deleted"]);
+
+        assert_eq!(audit_records(&[], &HashMap::new()), RecordAudit::default());
+    }
+}
@@ -0,0 +1,299 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::shard::Shard;
+
+/// How long an unanswered offer stays open before the shard can be re-offered.
+pub const OFFER_TIMEOUT_MS: u64 = 5 * 60 * 1000;
+
+/// Messages exchanged on `TOPIC_WILD_DRIFT`.
+///
+/// Handoff: the origin publishes the shard record to the DHT and sends an
+/// `Offer`; interested keepers reply `Accept`; the origin confirms exactly one
+/// acceptor with `Confirm`; the confirmed keeper pulls the record from the
+/// DHT, inserts it, and replies `Received`; only then does the origin archive
+/// its copy.
+///
+/// Gossipsub signs every message with its author's key, so each message is
+/// only honoured when its sender is the peer it names: `origin` for `Offer`
+/// and `Confirm`, `keeper` for `Accept` and `Received`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DriftMessage {
+    Offer {
+        shard_id: String,
+        genome_hash: String,
+        origin: String,
+    },
+    Accept {
+        shard_id: String,
+        origin: String,
+        keeper: String,
+    },
+    Confirm {
+        shard_id: String,
+        origin: String,
+        keeper: String,
+    },
+    Received {
+        shard_id: String,
+        origin: String,
+        keeper: String,
+    },
+}
+
+/// Side effects the keeper should carry out after handling a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DriftAction {
+    Publish(DriftMessage),
+    /// The new host has stored the shard; archive the local copy
+    Archive { shard_id: String, keeper: String },
+    /// We were confirmed as the new host; fetch the shard record from the DHT
+    FetchRecord(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Outgoing {
+    Offered { at: u64 },
+    /// Waiting for the chosen keeper to report the shard stored
+    Confirmed { keeper: String },
+    HandedOff,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Phase {
+    Accepted,
+    AwaitingRecord,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Incoming {
+    origin: String,
+    genome_hash: String,
+    phase: Phase,
+}
+
+/// Drift handoff state for one keeper.
+#[derive(Debug, Default)]
+pub struct DriftState {
+    outgoing: HashMap<String, Outgoing>,
+    incoming: HashMap<String, Incoming>,
+}
+
+/// Wild, unowned, writable shards idle for at least `idle_ms`.
+pub fn candidates(shards: &[Shard], idle_ms: u64, now: u64) -> Vec<&Shard> {
+    shards
+        .iter()
+        .filter(|s| s.is_wild && s.owner_id.is_none() && !s.read_only)
+        .filter(|s| now.saturating_sub(s.last_interaction) >= idle_ms)
+        .collect()
+}
+
+impl DriftState {
+    /// Offer a shard to other keepers. Returns None if the shard may not
+    /// drift or already has an open offer.
+    pub fn offer(&mut self, shard: &Shard, local: &str, now: u64) -> Option<DriftMessage> {
+        if !shard.is_wild || shard.owner_id.is_some() || shard.read_only {
+            return None;
+        }
+        match self.outgoing.get(&shard.id) {
+            Some(Outgoing::Offered { at }) if now.saturating_sub(*at) < OFFER_TIMEOUT_MS => return None,
+            Some(Outgoing::Confirmed { .. } | Outgoing::HandedOff) => return None,
+            _ => {}
+        }
+        self.outgoing.insert(shard.id.clone(), Outgoing::Offered { at: now });
+        Some(DriftMessage::Offer {
+            shard_id: shard.id.clone(),
+            genome_hash: shard.genome_hash.clone(),
+            origin: local.to_string(),
+        })
+    }
+
+    /// Handle a drift message authored by `sender`. `accept_incoming`
+    /// controls whether this keeper takes in shards offered by others.
+    pub fn handle(
+        &mut self,
+        msg: &DriftMessage,
+        sender: &str,
+        local: &str,
+        accept_incoming: bool,
+    ) -> Vec<DriftAction> {
+        match msg {
+            DriftMessage::Offer { shard_id, genome_hash, origin } => {
+                if origin != sender || origin == local || !accept_incoming || self.incoming.contains_key(shard_id) {
+                    return vec![];
+                }
+                self.incoming.insert(
+                    shard_id.clone(),
+                    Incoming {
+                        origin: origin.clone(),
+                        genome_hash: genome_hash.clone(),
+                        phase: Phase::Accepted,
+                    },
+                );
+                vec![DriftAction::Publish(DriftMessage::Accept {
+                    shard_id: shard_id.clone(),
+                    origin: origin.clone(),
+                    keeper: local.to_string(),
+                })]
+            }
+            DriftMessage::Accept { shard_id, origin, keeper } => {
+                // Only the first acceptor of our own open offer wins
+                if keeper != sender
+                    || origin != local
+                    || !matches!(self.outgoing.get(shard_id), Some(Outgoing::Offered { .. }))
+                {
+                    return vec![];
+                }
+                self.outgoing
+                    .insert(shard_id.clone(), Outgoing::Confirmed { keeper: keeper.clone() });
+                vec![DriftAction::Publish(DriftMessage::Confirm {
+                    shard_id: shard_id.clone(),
+                    origin: local.to_string(),
+                    keeper: keeper.clone(),
+                })]
+            }
+            DriftMessage::Confirm { shard_id, origin, keeper } => {
+                match self.incoming.get(shard_id) {
+                    Some(i) if i.phase == Phase::Accepted && i.origin == *origin && origin == sender => {}
+                    _ => return vec![],
+                }
+                if keeper == local {
+                    if let Some(incoming) = self.incoming.get_mut(shard_id) {
+                        incoming.phase = Phase::AwaitingRecord;
+                    }
+                    vec![DriftAction::FetchRecord(shard_id.clone())]
+                } else {
+                    // Another keeper won the handoff
+                    self.incoming.remove(shard_id);
+                    vec![]
+                }
+            }
+            DriftMessage::Received { shard_id, origin, keeper } => {
+                let confirmed = Outgoing::Confirmed { keeper: keeper.clone() };
+                if keeper != sender || origin != local || self.outgoing.get(shard_id) != Some(&confirmed) {
+                    return vec![];
+                }
+                self.outgoing.insert(shard_id.clone(), Outgoing::HandedOff);
+                vec![DriftAction::Archive {
+                    shard_id: shard_id.clone(),
+                    keeper: keeper.clone(),
+                }]
+            }
+        }
+    }
+
+    /// Whether any confirmed handoff is waiting for its DHT record.
+    pub fn awaiting_records(&self) -> bool {
+        self.incoming.values().any(|i| i.phase == Phase::AwaitingRecord)
+    }
+
+    /// Called when a shard record arrives from the DHT. Returns the shard to
+    /// insert locally if we are its confirmed new host and it matches the
+    /// genome that was offered.
+    pub fn record_received(&self, shard: Shard) -> Option<Shard> {
+        let incoming = self.incoming.get(&shard.id)?;
+        let expected = incoming.phase == Phase::AwaitingRecord
+            && incoming.genome_hash == shard.genome_hash
+            && shard.is_wild
+            && shard.owner_id.is_none();
+        expected.then_some(shard)
+    }
+
+    /// The shard from `record_received` has been stored: finish the handoff
+    /// and return the `Received` reply that lets the origin archive it.
+    pub fn stored(&mut self, shard_id: &str, local: &str) -> Option<DriftMessage> {
+        let incoming = self.incoming.remove(shard_id)?;
+        Some(DriftMessage::Received {
+            shard_id: shard_id.to_string(),
+            origin: incoming.origin,
+            keeper: local.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handoff_state_machine() {
+        let shard = Shard::spawn(None);
+        let mut origin = DriftState::default();
+        let mut taker = DriftState::default();
+        let mut rival = DriftState::default();
+
+        let offer = origin.offer(&shard, "origin", 1_000).unwrap();
+        assert!(origin.offer(&shard, "origin", 2_000).is_none(), "no duplicate open offers");
+        assert!(origin.handle(&offer, "origin", "origin", true).is_empty(), "ignore own offer");
+        assert!(DriftState::default().handle(&offer, "origin", "closed", false).is_empty());
+        assert!(
+            DriftState::default().handle(&offer, "mallory", "other", true).is_empty(),
+            "offers must come from their origin"
+        );
+
+        let accept = match taker.handle(&offer, "origin", "taker", true).as_slice() {
+            [DriftAction::Publish(m @ DriftMessage::Accept { .. })] => m.clone(),
+            other => panic!("expected accept, got {:?}", other),
+        };
+        let rival_accept = match rival.handle(&offer, "origin", "rival", true).pop() {
+            Some(DriftAction::Publish(m)) => m,
+            other => panic!("expected accept, got {:?}", other),
+        };
+        assert!(origin.handle(&accept, "rival", "origin", true).is_empty(), "spoofed acceptor ignored");
+
+        // Confirming the taker does not archive anything yet
+        let confirm = match origin.handle(&accept, "taker", "origin", true).as_slice() {
+            [DriftAction::Publish(m @ DriftMessage::Confirm { .. })] => m.clone(),
+            other => panic!("expected confirm only, got {:?}", other),
+        };
+        assert!(origin.handle(&rival_accept, "rival", "origin", true).is_empty(), "second acceptor ignored");
+        assert!(origin.offer(&shard, "origin", OFFER_TIMEOUT_MS * 2).is_none());
+
+        assert!(taker.handle(&confirm, "rival", "taker", true).is_empty(), "spoofed confirm ignored");
+        assert!(!taker.awaiting_records());
+        assert_eq!(
+            taker.handle(&confirm, "origin", "taker", true),
+            vec![DriftAction::FetchRecord(shard.id.clone())]
+        );
+        assert!(taker.awaiting_records());
+        assert!(rival.handle(&confirm, "origin", "rival", true).is_empty());
+        assert!(rival.record_received(shard.clone()).is_none());
+
+        let mut forged = shard.clone();
+        forged.genome_hash = "forged".to_string();
+        assert!(taker.record_received(forged).is_none(), "record must match the offered genome");
+        assert_eq!(taker.record_received(shard.clone()).unwrap().id, shard.id);
+        let received = taker.stored(&shard.id, "taker").unwrap();
+        assert!(taker.record_received(shard.clone()).is_none(), "inserted only once");
+
+        assert!(origin.handle(&received, "rival", "origin", true).is_empty(), "spoofed receipt ignored");
+        assert_eq!(
+            origin.handle(&received, "taker", "origin", true),
+            vec![DriftAction::Archive { shard_id: shard.id.clone(), keeper: "taker".to_string() }]
+        );
+        assert!(origin.handle(&received, "taker", "origin", true).is_empty(), "archived once");
+    }
+
+    #[test]
+    fn only_idle_unowned_wild_shards_drift() {
+        let mut idle = Shard::spawn(None);
+        idle.last_interaction = 0;
+        let mut owned = idle.clone();
+        owned.id = "owned".to_string();
+        owned.owner_id = Some("0xabc".to_string());
+        let mut fresh = idle.clone();
+        fresh.id = "fresh".to_string();
+        fresh.last_interaction = 9_000;
+        let mut read_only = idle.clone();
+        read_only.id = "read-only".to_string();
+        read_only.read_only = true;
+
+        let shards = vec![idle.clone(), owned.clone(), fresh, read_only.clone()];
+        let picked: Vec<_> = candidates(&shards, 5_000, 10_000).iter().map(|s| s.id.clone()).collect();
+        assert_eq!(picked, vec![idle.id]);
+
+        assert!(DriftState::default().offer(&owned, "origin", 0).is_none());
+        assert!(DriftState::default().offer(&read_only, "origin", 0).is_none());
+    }
+}
@@ -0,0 +1,482 @@
+use sha3::{Digest, Keccak256};
+
+use crate::db::{self, TaskLesson};
+use crate::inference::{self, InferenceConfig};
+
+/// Recent lessons per shard embedded by `prewarm`.
+pub const PREWARM_LESSONS_PER_SHARD: u32 = 20;
+
+/// `keeper_state` keys for the embedding model and vector dimension the
+/// store was built with, and the flag set when it needs rebuilding.
+const EMBEDDING_MODEL_KEY: &str = "embedding_model";
+const EMBEDDING_DIMENSION_KEY: &str = "embedding_dimension";
+const EMBEDDING_REINDEX_KEY: &str = "embedding_reindex";
+
+/// Text embedded by `probe_dimension`.
+const PROBE_TEXT: &str = "dimension probe";
+
+/// The embedding model and dimension now on record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddingDimension {
+    pub model: String,
+    pub dimension: usize,
+    /// The dimension differed from the recorded one, so stored vectors were
+    /// dropped and lessons must be re-embedded
+    pub reindex: bool,
+}
+
+/// The model and dimension recorded in `keeper_state`, if any.
+pub fn recorded_dimension(data_dir: &str) -> Result<Option<(String, usize)>, String> {
+    let read = |key| db::get_keeper_state(data_dir, key).map_err(|e| format!("DB error: {}", e));
+    let model = read(EMBEDDING_MODEL_KEY)?;
+    let dimension = read(EMBEDDING_DIMENSION_KEY)?.and_then(|d| d.parse().ok());
+    Ok(model.zip(dimension))
+}
+
+/// Record the model and dimension embeddings now come back with. A dimension
+/// different from the recorded one drops every stored vector and flags a
+/// reindex, so vectors of two sizes are never compared.
+pub fn record_dimension(data_dir: &str, model: &str, dimension: usize) -> Result<EmbeddingDimension, String> {
+    let db_err = |e: rusqlite::Error| format!("DB error: {}", e);
+    let reindex = match recorded_dimension(data_dir)? {
+        Some((old_model, old_dimension)) if old_dimension != dimension => {
+            tracing::warn!(
+                "Embedding dimension changed from {} ({}) to {} ({}); rebuilding lesson embeddings",
+                old_dimension,
+                old_model,
+                dimension,
+                model
+            );
+            db::delete_lesson_embeddings(data_dir).map_err(db_err)?;
+            db::set_keeper_state(data_dir, EMBEDDING_REINDEX_KEY, "1").map_err(db_err)?;
+            true
+        }
+        _ => false,
+    };
+    db::set_keeper_state(data_dir, EMBEDDING_MODEL_KEY, model).map_err(db_err)?;
+    db::set_keeper_state(data_dir, EMBEDDING_DIMENSION_KEY, &dimension.to_string()).map_err(db_err)?;
+    Ok(EmbeddingDimension {
+        model: model.to_string(),
+        dimension,
+        reindex,
+    })
+}
+
+/// Embed a short probe string and record the dimension it comes back with.
+pub async fn probe_dimension(data_dir: &str, config: &InferenceConfig) -> Result<EmbeddingDimension, String> {
+    let model = inference::embedding_model_for(&config.model);
+    let vector = inference::embed_texts(config, &[PROBE_TEXT.to_string()])
+        .await?
+        .pop()
+        .unwrap_or_default();
+    if vector.is_empty() {
+        return Err("Embedding probe returned an empty vector".to_string());
+    }
+    record_dimension(data_dir, &model, vector.len())
+}
+
+/// Check fresh vectors against the recorded dimension. The first vectors
+/// seen set it; a mismatch is recorded (flagging a reindex) and returned as
+/// an error so the vectors are not used.
+pub fn check_dimension(data_dir: &str, model: &str, vectors: &[Vec<f32>]) -> Result<(), String> {
+    let Some(dimension) = vectors.first().map(Vec::len) else {
+        return Ok(());
+    };
+    if let Some(other) = vectors.iter().map(Vec::len).find(|&len| len != dimension) {
+        return Err(format!("Embedding batch mixes dimensions {} and {}", dimension, other));
+    }
+    match recorded_dimension(data_dir)? {
+        Some((_, recorded)) if recorded == dimension => Ok(()),
+        Some((_, recorded)) => {
+            record_dimension(data_dir, model, dimension)?;
+            Err(format!(
+                "Embedding dimension changed from {} to {}; lesson embeddings will be rebuilt",
+                recorded, dimension
+            ))
+        }
+        None => record_dimension(data_dir, model, dimension).map(|_| ()),
+    }
+}
+
+/// Whether stored embeddings were dropped and still need rebuilding.
+pub fn reindex_pending(data_dir: &str) -> bool {
+    matches!(db::get_keeper_state(data_dir, EMBEDDING_REINDEX_KEY), Ok(Some(flag)) if flag == "1")
+}
+
+/// Probe the embedding dimension when none is on record for the configured
+/// model, then re-embed recent lessons if a reindex is pending (or
+/// `prewarm_all` is set).
+pub async fn sync_dimension(data_dir: &str, config: &InferenceConfig, prewarm_all: bool) -> Result<usize, String> {
+    let model = inference::embedding_model_for(&config.model);
+    if recorded_dimension(data_dir)?.is_none_or(|(recorded, _)| recorded != model) {
+        let probed = probe_dimension(data_dir, config).await?;
+        tracing::info!("Embedding model {} returns {}-dimensional vectors", probed.model, probed.dimension);
+    }
+    let reindex = reindex_pending(data_dir);
+    if !reindex && !prewarm_all {
+        return Ok(0);
+    }
+    let warmed = prewarm(data_dir, config).await?;
+    if reindex {
+        db::set_keeper_state(data_dir, EMBEDDING_REINDEX_KEY, "0").map_err(|e| format!("DB error: {}", e))?;
+    }
+    Ok(warmed)
+}
+
+/// Text embedded for a lesson, at most `max_chars` characters (0 = no
+/// limit). Over budget, fields are filled in order, the goal taking at most
+/// half of what's left so the approach always gets room; outcome, errors and
+/// fixes get whatever remains.
+pub fn lesson_text(lesson: &TaskLesson, max_chars: usize) -> String {
+    let (errors, fixes) = (lesson.errors.join(" | "), lesson.fixes.join(" | "));
+    let fields = [
+        ("type", lesson.task_type.as_str()),
+        ("goal", lesson.goal.as_str()),
+        ("approach", lesson.approach.as_str()),
+        ("outcome", lesson.outcome.as_str()),
+        ("errors", errors.as_str()),
+        ("fixes", fixes.as_str()),
+    ];
+    let full = fields
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if max_chars == 0 || full.chars().count() <= max_chars {
+        return full;
+    }
+
+    let mut text = String::new();
+    let mut remaining = max_chars;
+    for (key, value) in fields {
+        let label = if text.is_empty() { format!("{}=", key) } else { format!(" {}=", key) };
+        if label.len() >= remaining {
+            break;
+        }
+        remaining -= label.len();
+        let share = if key == "goal" { remaining / 2 } else { remaining };
+        let value = take_chars(value, share);
+        remaining -= value.chars().count();
+        text.push_str(&label);
+        text.push_str(value);
+    }
+    text
+}
+
+/// The first `n` characters of `s`.
+fn take_chars(s: &str, n: usize) -> &str {
+    s.char_indices().nth(n).map_or(s, |(i, _)| &s[..i])
+}
+
+fn text_hash(text: &str) -> String {
+    format!("{:x}", Keccak256::digest(text.as_bytes()))
+}
+
+/// Embedding vectors for `lessons`, in order. Stored vectors are reused and
+/// only lessons without one (or whose text changed) are sent to the
+/// embedding endpoint; new vectors are persisted.
+pub async fn lesson_vectors(
+    data_dir: &str,
+    config: &InferenceConfig,
+    lessons: &[TaskLesson],
+) -> Result<Vec<Vec<f32>>, String> {
+    let model = inference::embedding_model_for(&config.model);
+    let texts: Vec<String> = lessons
+        .iter()
+        .map(|lesson| lesson_text(lesson, config.max_embedding_input_chars))
+        .collect();
+    let keys: Vec<(i64, String)> = lessons
+        .iter()
+        .zip(&texts)
+        .map(|(lesson, text)| (lesson.id, text_hash(text)))
+        .collect();
+
+    let mut cached = db::get_lesson_embeddings(data_dir, &model, &keys)
+        .map_err(|e| format!("Failed to read lesson embeddings: {}", e))?;
+
+    let missing: Vec<usize> = (0..lessons.len())
+        .filter(|&i| !cached.contains_key(&lessons[i].id))
+        .collect();
+    if !missing.is_empty() {
+        let inputs: Vec<String> = missing.iter().map(|&i| texts[i].clone()).collect();
+        let vectors = inference::embed_texts(config, &inputs).await?;
+        if vectors.len() != inputs.len() {
+            return Err(format!(
+                "Embedding endpoint returned {} vectors for {} lessons",
+                vectors.len(),
+                inputs.len()
+            ));
+        }
+        check_dimension(data_dir, &model, &vectors)?;
+
+        let fresh: Vec<(i64, String, Vec<f32>)> = missing
+            .iter()
+            .zip(vectors)
+            .map(|(&i, vector)| (keys[i].0, keys[i].1.clone(), vector))
+            .collect();
+        if let Err(e) = db::upsert_lesson_embeddings(data_dir, &model, &fresh) {
+            tracing::warn!("Failed to store lesson embeddings: {}", e);
+        }
+        for (id, _, vector) in fresh {
+            cached.insert(id, vector);
+        }
+    }
+
+    lessons
+        .iter()
+        .map(|lesson| {
+            cached
+                .remove(&lesson.id)
+                .ok_or_else(|| format!("No embedding for lesson {}", lesson.id))
+        })
+        .collect()
+}
+
+/// Embed every shard's most recent lessons so the first retrieval after
+/// startup is served from the store. A shard whose batch fails is retried
+/// lesson by lesson, so one bad lesson (or shard) is logged and skipped
+/// rather than ending the pass. Returns the number of lessons covered.
+pub async fn prewarm(data_dir: &str, config: &InferenceConfig) -> Result<usize, String> {
+    let shards = db::get_shards(data_dir).map_err(|e| format!("Failed to read shards: {}", e))?;
+
+    let mut warmed = 0;
+    for shard in shards {
+        let lessons = match db::get_recent_task_lessons(data_dir, &shard.id, PREWARM_LESSONS_PER_SHARD) {
+            Ok(lessons) => lessons,
+            Err(e) => {
+                tracing::warn!("Prewarm skipped shard {}: failed to read lessons: {}", shard.id, e);
+                continue;
+            }
+        };
+        if lessons.is_empty() {
+            continue;
+        }
+        match lesson_vectors(data_dir, config, &lessons).await {
+            Ok(_) => warmed += lessons.len(),
+            Err(e) => {
+                tracing::warn!("Prewarm batch for shard {} failed, embedding lessons one by one: {}", shard.id, e);
+                for lesson in &lessons {
+                    match lesson_vectors(data_dir, config, std::slice::from_ref(lesson)).await {
+                        Ok(_) => warmed += 1,
+                        Err(e) => tracing::warn!("Prewarm skipped lesson {}: {}", lesson.id, e),
+                    }
+                }
+            }
+        }
+    }
+    Ok(warmed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shard::Shard;
+    use crate::test_support::{seed_lesson, serve};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn prewarm_populates_embedding_store() {
+        let requested = Arc::new(AtomicUsize::new(0));
+        let counter = requested.clone();
+        let app = axum::Router::new().route(
+            "/v1/embeddings",
+            axum::routing::post(move |axum::Json(body): axum::Json<serde_json::Value>| {
+                let counter = counter.clone();
+                async move {
+                    let inputs = body["input"].as_array().cloned().unwrap_or_default();
+                    counter.fetch_add(inputs.len(), Ordering::SeqCst);
+                    let data: Vec<_> = inputs
+                        .iter()
+                        .enumerate()
+                        .map(|(i, _)| serde_json::json!({"embedding": [i as f32, 1.0]}))
+                        .collect();
+                    axum::Json(serde_json::json!({ "data": data }))
+                }
+            }),
+        );
+        let url = format!("{}/v1/chat/completions", serve(app).await);
+
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        db::init_db(&data_dir).unwrap();
+        let shard = Shard::spawn(None);
+        db::insert_shard(&data_dir, &shard).unwrap();
+        let action_id = db::insert_action(&data_dir, &shard.id, "warm up", None).unwrap();
+        for goal in ["parse logs", "fetch prices"] {
+            let lesson = db::NewTaskLesson {
+                shard_id: &shard.id,
+                action_id,
+                task_type: "general",
+                goal,
+                approach: "step by step",
+                tools_used: &[],
+                outcome: "done",
+                errors: &[],
+                fixes: &[],
+                duration_ms: 10,
+                success: true,
+                extractor_confidence: 0.8,
+                applicability_confidence: 0.8,
+                reusability: 0.8,
+                artifact_path: "",
+            };
+            db::insert_task_lesson(&data_dir, &lesson).unwrap();
+        }
+
+        let config = InferenceConfig {
+            api_url: url,
+            model: "nomic-embed-text".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(prewarm(&data_dir, &config).await.unwrap(), 2);
+        assert_eq!(requested.load(Ordering::SeqCst), 2);
+
+        let lessons = db::get_recent_task_lessons(&data_dir, &shard.id, 10).unwrap();
+        let keys: Vec<_> = lessons.iter().map(|l| (l.id, text_hash(&lesson_text(l, config.max_embedding_input_chars)))).collect();
+        let stored = db::get_lesson_embeddings(&data_dir, "nomic-embed-text", &keys).unwrap();
+        assert_eq!(stored.len(), 2);
+
+        // A warm store serves retrieval without another embedding request
+        let vectors = lesson_vectors(&data_dir, &config, &lessons).await.unwrap();
+        assert_eq!(vectors.len(), 2);
+        assert!(vectors.iter().all(|v| v.len() == 2));
+        assert_eq!(requested.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn prewarm_skips_lessons_the_endpoint_rejects() {
+        let app = axum::Router::new().route(
+            "/v1/embeddings",
+            axum::routing::post(|axum::Json(body): axum::Json<serde_json::Value>| async move {
+                let inputs = body["input"].as_array().cloned().unwrap_or_default();
+                if inputs.iter().any(|input| input.as_str().is_some_and(|s| s.contains("poison"))) {
+                    return Err(axum::http::StatusCode::BAD_REQUEST);
+                }
+                let data: Vec<_> = inputs.iter().map(|_| serde_json::json!({"embedding": [1.0, 0.0]})).collect();
+                Ok(axum::Json(serde_json::json!({ "data": data })))
+            }),
+        );
+        let url = format!("{}/v1/chat/completions", serve(app).await);
+
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        db::init_db(&data_dir).unwrap();
+        let (mixed, clean) = (Shard::spawn(None), Shard::spawn(None));
+        db::insert_shard(&data_dir, &mixed).unwrap();
+        db::insert_shard(&data_dir, &clean).unwrap();
+        seed_lesson(&data_dir, &mixed.id, "general", "parse logs");
+        seed_lesson(&data_dir, &mixed.id, "general", "poison the cache");
+        seed_lesson(&data_dir, &clean.id, "general", "fetch prices");
+        let config = InferenceConfig {
+            api_url: url,
+            model: "nomic-embed-text".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(prewarm(&data_dir, &config).await.unwrap(), 2);
+
+        // Retrieval over the bad lesson reports the failure instead of an empty vector
+        let lessons = db::get_recent_task_lessons(&data_dir, &mixed.id, 10).unwrap();
+        assert!(lesson_vectors(&data_dir, &config, &lessons).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn dimension_change_flags_reindex() {
+        let dimension = Arc::new(AtomicUsize::new(3));
+        let served = dimension.clone();
+        let app = axum::Router::new().route(
+            "/v1/embeddings",
+            axum::routing::post(move || {
+                let served = served.clone();
+                async move {
+                    let vector = vec![0.5f32; served.load(Ordering::SeqCst)];
+                    axum::Json(serde_json::json!({ "data": [{ "embedding": vector }] }))
+                }
+            }),
+        );
+        let url = format!("{}/v1/chat/completions", serve(app).await);
+
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        db::init_db(&data_dir).unwrap();
+        let config = InferenceConfig {
+            api_url: url,
+            model: "nomic-embed-text".to_string(),
+            ..Default::default()
+        };
+
+        let first = probe_dimension(&data_dir, &config).await.unwrap();
+        assert_eq!(first.dimension, 3);
+        assert!(!first.reindex);
+        assert_eq!(recorded_dimension(&data_dir).unwrap(), Some(("nomic-embed-text".to_string(), 3)));
+        db::upsert_lesson_embeddings(&data_dir, "nomic-embed-text", &[(1, "h".to_string(), vec![0.5; 3])])
+            .unwrap();
+
+        // Same model name, different vectors: the store is dropped and flagged
+        dimension.store(5, Ordering::SeqCst);
+        let second = probe_dimension(&data_dir, &config).await.unwrap();
+        assert_eq!(second.dimension, 5);
+        assert!(second.reindex);
+        assert!(reindex_pending(&data_dir));
+        let keys = [(1, "h".to_string())];
+        assert!(db::get_lesson_embeddings(&data_dir, "nomic-embed-text", &keys).unwrap().is_empty());
+
+        assert!(check_dimension(&data_dir, "nomic-embed-text", &[vec![0.0; 5]]).is_ok());
+        assert!(check_dimension(&data_dir, "nomic-embed-text", &[vec![0.0; 4]]).is_err());
+        assert_eq!(recorded_dimension(&data_dir).unwrap().unwrap().1, 4);
+
+        // Startup rebuilds the flagged store and clears the flag
+        sync_dimension(&data_dir, &config, false).await.unwrap();
+        assert!(!reindex_pending(&data_dir));
+    }
+
+    #[test]
+    fn oversized_lesson_text_is_cut_to_budget() {
+        let lesson = TaskLesson {
+            id: 1,
+            shard_id: "s".to_string(),
+            action_id: 1,
+            task_type: "coding".to_string(),
+            goal: "é".repeat(5000),
+            approach: "step ".repeat(1000),
+            tools_used: vec![],
+            outcome: "done".repeat(500),
+            errors: vec!["boom".repeat(500)],
+            fixes: vec![],
+            duration_ms: 0,
+            success: true,
+            extractor_confidence: 0.5,
+            applicability_confidence: 0.5,
+            reusability: 0.5,
+            score: 0.5,
+            artifact_path: String::new(),
+            times_retrieved: 0,
+            times_helpful: 0,
+            times_unhelpful: 0,
+            created_at: 0,
+            updated_at: 0,
+            pinned: false,
+        };
+
+        let text = lesson_text(&lesson, 300);
+        assert_eq!(text.chars().count(), 300);
+        assert!(text.starts_with("type=coding goal=é"));
+        // The multi-byte goal can't crowd out the approach
+        assert!(text.contains(" approach=step step"));
+        assert!(!text.contains("outcome="));
+
+        let short = TaskLesson {
+            goal: "short".to_string(),
+            approach: "brief".to_string(),
+            outcome: "ok".to_string(),
+            errors: vec![],
+            ..lesson
+        };
+        assert_eq!(
+            lesson_text(&short, 300),
+            "type=coding goal=short approach=brief outcome=ok errors= fixes="
+        );
+        assert_eq!(lesson_text(&short, 0), lesson_text(&short, 300));
+    }
+}
@@ -1,6 +1,12 @@
-use crate::inference::ToolCall;
+use crate::config::{shellexpand, Config};
+use crate::db;
+use crate::inference::{self, ChatMessage, InferenceConfig, ToolCall};
+use base64::Engine;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
 use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::{AsyncRead, AsyncReadExt};
 use tokio::process::Command;
 
 /// Result of executing a single tool call.
@@ -10,41 +16,354 @@ pub struct ToolResult {
     pub tool_name: String,
     pub success: bool,
     pub output: String,
+    /// Wall-clock time spent executing the tool
+    #[serde(default)]
+    pub duration_ms: u64,
+    /// Whether `output` was cut to fit an output size cap
+    #[serde(default)]
+    pub truncated: bool,
 }
 
-/// Execute a tool call within a shard's workspace.
+/// Tool output carrying binary data (images, PDFs, ...) that isn't valid
+/// UTF-8. Serialized as JSON into `ToolResult::output`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BinaryEnvelope {
+    /// Always "binary"; distinguishes envelopes from ordinary JSON output
+    pub envelope: String,
+    pub mime_type: String,
+    pub size_bytes: usize,
+    /// Base64 (standard alphabet) payload; omitted when over the output cap
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+}
+
+impl BinaryEnvelope {
+    /// Wrap raw bytes, sniffing the MIME type from magic bytes or `name`.
+    pub fn from_bytes(bytes: &[u8], name: Option<&str>) -> Self {
+        Self {
+            envelope: "binary".to_string(),
+            mime_type: sniff_mime(bytes, name).to_string(),
+            size_bytes: bytes.len(),
+            data: Some(base64::engine::general_purpose::STANDARD.encode(bytes)),
+        }
+    }
+
+    /// Parse a tool output back into an envelope, if it is one.
+    pub fn parse(output: &str) -> Option<Self> {
+        if !output.starts_with('{') {
+            return None;
+        }
+        serde_json::from_str::<Self>(output)
+            .ok()
+            .filter(|e| e.envelope == "binary")
+    }
+
+    pub fn to_output(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn is_image(&self) -> bool {
+        self.mime_type.starts_with("image/")
+    }
+
+    /// `data:` URL for image content parts, when the payload is present.
+    pub fn data_url(&self) -> Option<String> {
+        self.data
+            .as_ref()
+            .map(|d| format!("data:{};base64,{}", self.mime_type, d))
+    }
+
+    /// Short textual stand-in for models that can't take the payload.
+    pub fn describe(&self) -> String {
+        let note = if self.data.is_some() { "" } else { "; payload omitted (over size cap)" };
+        format!("[binary output: {}, {} bytes{}]", self.mime_type, self.size_bytes, note)
+    }
+}
+
+fn sniff_mime(bytes: &[u8], name: Option<&str>) -> &'static str {
+    const MAGIC: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+    ];
+    if let Some((_, mime)) = MAGIC.iter().find(|(magic, _)| bytes.starts_with(magic)) {
+        return mime;
+    }
+    if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+    let ext = name
+        .and_then(|n| n.rsplit('.').next())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Default cap on a single tool's output, in bytes.
+pub const DEFAULT_MAX_OUTPUT_BYTES: usize = 50_000;
+
+/// Truncate `output` to at most `max_bytes` (on a char boundary), appending a
+/// marker that records how much was dropped. Returns true if it truncated.
+pub fn truncate_output(output: &mut String, max_bytes: usize) -> bool {
+    if output.len() <= max_bytes {
+        return false;
+    }
+    let total = output.len();
+    let mut cut = max_bytes;
+    while !output.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    output.truncate(cut);
+    output.push_str(&format!("\n[output truncated: showing {} of {} bytes]", cut, total));
+    true
+}
+
+/// Execute a tool call within a shard's workspace, with the interpreters,
+/// workspace quota and recall weights from `config`.
 /// The workspace is an isolated directory under the keeper's data dir.
+/// Output (or error text) longer than `max_output_bytes` is truncated.
 pub async fn execute_tool(
+    config: &Config,
+    shard_id: &str,
+    call: &ToolCall,
+    max_output_bytes: usize,
+) -> ToolResult {
+    execute_tool_with_languages(
+        &config.data_dir,
+        shard_id,
+        call,
+        max_output_bytes,
+        &config.code_languages,
+        config.workspace_quota_bytes,
+        &config.prefilter_weights,
+    )
+    .await
+}
+
+/// Like `execute_tool`, with `code_languages` extending the built-in
+/// `code_eval` interpreters, `workspace_quota_bytes` capping how large
+/// `file_write` may grow the workspace (0 = no cap) and `prefilter_weights`
+/// ranking `recall_lessons` results.
+pub async fn execute_tool_with_languages(
     data_dir: &str,
     shard_id: &str,
     call: &ToolCall,
+    max_output_bytes: usize,
+    code_languages: &HashMap<String, CodeInterpreter>,
+    workspace_quota_bytes: u64,
+    prefilter_weights: &db::RetrievalWeights,
 ) -> ToolResult {
+    let started = std::time::Instant::now();
     let workspace = shard_workspace(data_dir, shard_id);
     std::fs::create_dir_all(&workspace).ok();
 
     let result = match call.name.as_str() {
-        "code_eval" => execute_code_eval(&call.arguments, &workspace).await,
+        "code_eval" => execute_code_eval(&call.arguments, &workspace, code_languages).await,
         "http_fetch" => execute_http_fetch(&call.arguments).await,
         "file_read" => execute_file_read(&call.arguments, &workspace),
-        "file_write" => execute_file_write(&call.arguments, &workspace),
+        "file_write" => execute_file_write(&call.arguments, &workspace, workspace_quota_bytes).await,
+        "search_files" => execute_search_files(&call.arguments, &workspace),
         "shell_exec" => execute_shell(&call.arguments, &workspace).await,
+        RECALL_LESSONS_TOOL => recall_lessons(&call.arguments, data_dir, shard_id, prefilter_weights),
+        BRAINSTORM_TOOL => Err("brainstorm is only available during task execution".to_string()),
+        ASK_USER_TOOL => Err("ask_user is only available during task execution".to_string()),
         other => Err(format!("Unknown tool: {}", other)),
     };
 
-    match result {
-        Ok(output) => ToolResult {
-            tool_call_id: call.id.clone(),
-            tool_name: call.name.clone(),
-            success: true,
-            output,
-        },
-        Err(err) => ToolResult {
-            tool_call_id: call.id.clone(),
-            tool_name: call.name.clone(),
-            success: false,
-            output: err,
-        },
+    finish_tool_result(call, started, result, max_output_bytes)
+}
+
+/// Name of the inference-backed brainstorm tool.
+pub const BRAINSTORM_TOOL: &str = "brainstorm";
+
+/// Name of the tool that searches the shard's own distilled lessons.
+pub const RECALL_LESSONS_TOOL: &str = "recall_lessons";
+
+/// Tools that only read state. One turn's consecutive calls to these may
+/// run concurrently; every other tool runs alone, in call order.
+pub const READ_ONLY_TOOLS: &[&str] = &["file_read", "search_files", "http_fetch", RECALL_LESSONS_TOOL];
+
+/// Most lessons one `recall_lessons` call returns, so the model can't pull
+/// its whole memory into the conversation.
+pub const RECALL_MAX_LESSONS: usize = 5;
+
+const RECALL_DEFAULT_LESSONS: usize = 3;
+
+/// Name of the tool that pauses an execution to ask the keeper a question.
+/// The agent loop handles it; the answer arrives as its tool result.
+pub const ASK_USER_TOOL: &str = "ask_user";
+
+/// Sampling temperature for `brainstorm`; deliberately high so ideas diverge.
+pub const BRAINSTORM_TEMPERATURE: f64 = 1.2;
+
+const BRAINSTORM_DEFAULT_IDEAS: usize = 5;
+const BRAINSTORM_MAX_IDEAS: usize = 10;
+
+/// Execute a `brainstorm` call: a self-contained, high-temperature inference
+/// sub-call that returns several distinct ideas as a numbered list.
+pub async fn execute_brainstorm(
+    call: &ToolCall,
+    inference_config: &InferenceConfig,
+    max_output_bytes: usize,
+) -> ToolResult {
+    let started = std::time::Instant::now();
+    let result = brainstorm(&call.arguments, inference_config).await;
+    finish_tool_result(call, started, result, max_output_bytes)
+}
+
+fn finish_tool_result(
+    call: &ToolCall,
+    started: std::time::Instant,
+    result: Result<String, String>,
+    max_output_bytes: usize,
+) -> ToolResult {
+    let duration_ms = started.elapsed().as_millis() as u64;
+    let (success, mut output) = match result {
+        Ok(output) => (true, output),
+        Err(err) => (false, err),
+    };
+    let truncated = match BinaryEnvelope::parse(&output) {
+        // Cutting base64 mid-stream would corrupt it; drop the payload instead
+        Some(mut envelope) if output.len() > max_output_bytes => {
+            envelope.data = None;
+            output = envelope.to_output();
+            true
+        }
+        Some(_) => false,
+        None => truncate_output(&mut output, max_output_bytes),
+    };
+    ToolResult {
+        tool_call_id: call.id.clone(),
+        tool_name: call.name.clone(),
+        success,
+        output,
+        duration_ms,
+        truncated,
+    }
+}
+
+async fn brainstorm(args: &serde_json::Value, inference_config: &InferenceConfig) -> Result<String, String> {
+    let topic = args["topic"]
+        .as_str()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .ok_or("Missing 'topic' argument")?;
+    let count = args["count"]
+        .as_u64()
+        .map(|n| (n as usize).clamp(1, BRAINSTORM_MAX_IDEAS))
+        .unwrap_or(BRAINSTORM_DEFAULT_IDEAS);
+
+    let config = InferenceConfig {
+        temperature: BRAINSTORM_TEMPERATURE,
+        ..inference_config.clone()
+    };
+    let prompt = format!(
+        "Brainstorm {} distinct ideas for: {}\nReply with one idea per line and nothing else.",
+        count, topic
+    );
+    let response = inference::generate_response(
+        &config,
+        "You generate varied, unconventional ideas. Avoid repeating yourself.",
+        &[ChatMessage::text("user", &prompt)],
+    )
+    .await?;
+
+    let ideas = parse_ideas(&response, count);
+    if ideas.is_empty() {
+        return Err("Brainstorm returned no ideas".to_string());
+    }
+    Ok(ideas
+        .iter()
+        .enumerate()
+        .map(|(i, idea)| format!("{}. {}", i + 1, idea))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Look up the shard's lessons most relevant to `query`, one line each.
+fn recall_lessons(
+    args: &serde_json::Value,
+    data_dir: &str,
+    shard_id: &str,
+    weights: &db::RetrievalWeights,
+) -> Result<String, String> {
+    let query = args["query"]
+        .as_str()
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .ok_or("Missing 'query' argument")?;
+    let task_type = args["task_type"]
+        .as_str()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .unwrap_or("general");
+    let limit = args["limit"]
+        .as_u64()
+        .map(|n| (n as usize).clamp(1, RECALL_MAX_LESSONS))
+        .unwrap_or(RECALL_DEFAULT_LESSONS);
+
+    let lessons = db::retrieve_relevant_lessons(
+        data_dir,
+        shard_id,
+        query,
+        task_type,
+        limit,
+        weights,
+    )
+    .map_err(|e| format!("Failed to recall lessons: {}", e))?;
+    if lessons.is_empty() {
+        return Ok("No lessons recorded yet.".to_string());
+    }
+    Ok(lessons
+        .iter()
+        .enumerate()
+        .map(|(i, lesson)| {
+            let mut line = format!(
+                "{}. [{}] {} (success={}): {}; outcome: {}",
+                i + 1,
+                lesson.task_type,
+                lesson.goal,
+                lesson.success,
+                lesson.approach,
+                lesson.outcome
+            );
+            if !lesson.fixes.is_empty() {
+                line.push_str(&format!("; fixes: {}", lesson.fixes.join(" | ")));
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Split a model reply into distinct ideas, stripping bullets and numbering.
+fn parse_ideas(text: &str, max: usize) -> Vec<String> {
+    let mut ideas: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let idea = line
+            .trim()
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .trim_start_matches(['.', ')', '-', '*', '•'])
+            .trim();
+        if idea.is_empty() || ideas.iter().any(|i| i.eq_ignore_ascii_case(idea)) {
+            continue;
+        }
+        ideas.push(idea.to_string());
+        if ideas.len() >= max {
+            break;
+        }
     }
+    ideas
 }
 
 fn shard_workspace(data_dir: &str, shard_id: &str) -> PathBuf {
@@ -52,11 +371,81 @@ fn shard_workspace(data_dir: &str, shard_id: &str) -> PathBuf {
     Path::new(&expanded).join("workspaces").join(shard_id)
 }
 
+// ── code_eval interpreters ──────────────────────────────────────────
+
+/// Interpreter used by `code_eval` for one language: the script is written to
+/// `_eval.<extension>` and run as `<command> [args...] <script>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodeInterpreter {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub extension: String,
+}
+
+/// Interpreter commands that configured `code_eval` languages may use.
+pub const ALLOWED_INTERPRETERS: &[&str] = &[
+    "python3", "python", "node", "deno", "bun", "ruby", "perl", "php", "lua", "bash", "sh", "Rscript",
+];
+
+fn builtin_interpreter(language: &str) -> Option<CodeInterpreter> {
+    let (command, extension) = match language {
+        "python" => ("python3", "py"),
+        "javascript" => ("node", "js"),
+        _ => return None,
+    };
+    Some(CodeInterpreter {
+        command: command.to_string(),
+        args: Vec::new(),
+        extension: extension.to_string(),
+    })
+}
+
+/// Check a configured `code_eval` language against the interpreter allowlist.
+pub fn validate_code_language(language: &str, interpreter: &CodeInterpreter) -> Result<(), String> {
+    if language.is_empty() || !language.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(format!("Invalid code_eval language name: '{}'", language));
+    }
+    if !ALLOWED_INTERPRETERS.contains(&interpreter.command.as_str()) {
+        return Err(format!(
+            "Interpreter '{}' for language '{}' is not allowed (allowed: {})",
+            interpreter.command,
+            language,
+            ALLOWED_INTERPRETERS.join(", ")
+        ));
+    }
+    if interpreter.extension.is_empty() || !interpreter.extension.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(format!(
+            "Invalid file extension '{}' for language '{}'",
+            interpreter.extension, language
+        ));
+    }
+    Ok(())
+}
+
+/// Languages `code_eval` accepts: the built-ins plus every configured one,
+/// sorted by name.
+pub fn code_languages(configured: &HashMap<String, CodeInterpreter>) -> Vec<String> {
+    let names: BTreeSet<&str> = ["python", "javascript"]
+        .into_iter()
+        .chain(configured.keys().map(String::as_str))
+        .collect();
+    names.into_iter().map(str::to_string).collect()
+}
+
 // ── Tool implementations ────────────────────────────────────────────
 
+/// `code_eval` wall-clock limit when the call doesn't set `timeout_secs`.
+pub const CODE_EVAL_DEFAULT_TIMEOUT_SECS: u64 = 30;
+/// Ceiling on a requested `code_eval` timeout.
+const CODE_EVAL_MAX_TIMEOUT_SECS: u64 = 120;
+/// Cap on `code_eval`'s combined stdout/stderr, in bytes.
+pub const CODE_EVAL_MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
 async fn execute_code_eval(
     args: &serde_json::Value,
     workspace: &Path,
+    code_languages: &HashMap<String, CodeInterpreter>,
 ) -> Result<String, String> {
     let language = args["language"]
         .as_str()
@@ -65,37 +454,125 @@ async fn execute_code_eval(
         .as_str()
         .ok_or("Missing 'code' argument")?;
 
-    let (cmd, ext) = match language {
-        "python" => ("python3", "py"),
-        "javascript" => ("node", "js"),
-        _ => return Err(format!("Unsupported language: {}", language)),
-    };
+    // Configured languages may override the built-ins
+    let interpreter = code_languages
+        .get(language)
+        .cloned()
+        .or_else(|| builtin_interpreter(language))
+        .ok_or_else(|| format!("Unsupported language: {}", language))?;
+    validate_code_language(language, &interpreter)?;
+    let cmd = interpreter.command.as_str();
 
-    let script_path = workspace.join(format!("_eval.{}", ext));
+    let script_path = workspace.join(format!("_eval.{}", interpreter.extension));
     std::fs::write(&script_path, code)
         .map_err(|e| format!("Failed to write script: {}", e))?;
 
-    let output = Command::new(cmd)
+    let timeout_secs = args["timeout_secs"]
+        .as_u64()
+        .unwrap_or(CODE_EVAL_DEFAULT_TIMEOUT_SECS)
+        .min(CODE_EVAL_MAX_TIMEOUT_SECS);
+
+    let mut command = Command::new(cmd);
+    command
+        .args(&interpreter.args)
         .arg(&script_path)
         .current_dir(workspace)
-        .output()
-        .await
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    // Own process group, so a timeout also takes down anything it forked
+    #[cfg(target_os = "linux")]
+    command.process_group(0);
+
+    let mut child = command
+        .spawn()
         .map_err(|e| format!("Failed to execute {}: {}", cmd, e))?;
+    let pid = child.id();
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+
+    let run = async {
+        let (status, stdout, stderr) = tokio::join!(
+            child.wait(),
+            read_capped(stdout, CODE_EVAL_MAX_OUTPUT_BYTES),
+            read_capped(stderr, CODE_EVAL_MAX_OUTPUT_BYTES),
+        );
+        (status, stdout, stderr)
+    };
+    let result = tokio::time::timeout(std::time::Duration::from_secs(timeout_secs), run).await;
+
+    let (status, stdout, stderr) = match result {
+        Ok(finished) => finished,
+        Err(_) => {
+            if let Some(pid) = pid {
+                kill_process_group(pid);
+            }
+            std::fs::remove_file(&script_path).ok();
+            return Err(format!("Code execution timed out after {}s", timeout_secs));
+        }
+    };
 
     // Clean up
     std::fs::remove_file(&script_path).ok();
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
+    let status = status.map_err(|e| format!("Failed to execute {}: {}", cmd, e))?;
+    let stdout = String::from_utf8_lossy(&stdout);
+    let stderr = String::from_utf8_lossy(&stderr);
 
-    if output.status.success() {
-        Ok(stdout.to_string())
+    if status.success() {
+        Ok(cap_code_output(stdout.to_string()))
     } else {
-        Err(format!("Exit code {}\nstdout: {}\nstderr: {}",
-            output.status.code().unwrap_or(-1), stdout, stderr))
+        Err(cap_code_output(format!("Exit code {}\nstdout: {}\nstderr: {}",
+            status.code().unwrap_or(-1), stdout, stderr)))
+    }
+}
+
+/// Read a child's stream to the end, keeping about `cap` bytes. The rest
+/// is drained and dropped so the child never blocks on a full pipe.
+async fn read_capped<R: AsyncRead + Unpin>(mut reader: R, cap: usize) -> Vec<u8> {
+    let mut kept = Vec::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        match reader.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                // One byte past the cap is kept so truncation stays detectable
+                let room = (cap + 1).saturating_sub(kept.len());
+                kept.extend_from_slice(&buf[..n.min(room)]);
+            }
+        }
+    }
+    kept
+}
+
+/// Cut `code_eval` output to `CODE_EVAL_MAX_OUTPUT_BYTES` with a marker.
+fn cap_code_output(mut output: String) -> String {
+    if output.len() > CODE_EVAL_MAX_OUTPUT_BYTES {
+        let mut cut = CODE_EVAL_MAX_OUTPUT_BYTES;
+        while !output.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        output.truncate(cut);
+        output.push_str("\n[truncated]");
+    }
+    output
+}
+
+/// SIGKILL a timed-out child's whole process group.
+#[cfg(target_os = "linux")]
+fn kill_process_group(pid: u32) {
+    // SAFETY: kill(2) has no memory-safety preconditions; a negative pid
+    // addresses the group the child leads (see `process_group(0)` above).
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
     }
 }
 
+/// Elsewhere the child is killed when its handle is dropped.
+#[cfg(not(target_os = "linux"))]
+fn kill_process_group(_pid: u32) {}
+
 async fn execute_http_fetch(args: &serde_json::Value) -> Result<String, String> {
     let url = args["url"]
         .as_str()
@@ -118,22 +595,19 @@ async fn execute_http_fetch(args: &serde_json::Value) -> Result<String, String>
         .map_err(|e| format!("HTTP request failed: {}", e))?;
 
     let status = resp.status();
-    let body = resp
-        .text()
+    let bytes = resp
+        .bytes()
         .await
         .map_err(|e| format!("Failed to read response: {}", e))?;
-
-    // Truncate very large responses
-    let truncated = if body.len() > 50_000 {
-        format!("{}...\n[truncated at 50KB]", &body[..50_000])
-    } else {
-        body
+    let body = match String::from_utf8(bytes.to_vec()) {
+        Ok(text) => text,
+        Err(_) => BinaryEnvelope::from_bytes(&bytes, Some(url)).to_output(),
     };
 
     if status.is_success() {
-        Ok(truncated)
+        Ok(body)
     } else {
-        Err(format!("HTTP {} — {}", status, truncated))
+        Err(format!("HTTP {} — {}", status, body))
     }
 }
 
@@ -155,13 +629,18 @@ fn execute_file_read(
     }
 
     let resolved = workspace.join(path);
-    std::fs::read_to_string(&resolved)
-        .map_err(|e| format!("Failed to read file: {}", e))
+    let bytes = std::fs::read(&resolved)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        Err(e) => Ok(BinaryEnvelope::from_bytes(e.as_bytes(), Some(path)).to_output()),
+    }
 }
 
-fn execute_file_write(
+async fn execute_file_write(
     args: &serde_json::Value,
     workspace: &Path,
+    quota_bytes: u64,
 ) -> Result<String, String> {
     let path = args["path"]
         .as_str()
@@ -174,7 +653,42 @@ fn execute_file_write(
         return Err("Path traversal not allowed".to_string());
     }
 
+    // The workspace lock blocks until other writers finish, and the quota
+    // check walks the whole workspace, so neither may stall a runtime worker
+    let workspace = workspace.to_path_buf();
     let resolved = workspace.join(path);
+    let content = content.to_string();
+    let len = content.len();
+    tokio::task::spawn_blocking(move || write_within_quota(&workspace, &resolved, &content, quota_bytes))
+        .await
+        .map_err(|e| format!("File write task failed: {}", e))??;
+
+    Ok(format!("Wrote {} bytes to {}", len, path))
+}
+
+/// Write `content` to `resolved`, refusing if it would push the workspace
+/// past `quota_bytes` (0 = unlimited).
+fn write_within_quota(workspace: &Path, resolved: &Path, content: &str, quota_bytes: u64) -> Result<(), String> {
+    // Held from the quota check until the write lands, so concurrent writes
+    // to one workspace can't each pass the check and overshoot together
+    let _lock = lock_workspace(workspace)?;
+
+    // Checked up front so a refused write leaves nothing behind. An
+    // overwritten file's old size is freed by the write.
+    if quota_bytes > 0 {
+        let replaced = std::fs::symlink_metadata(resolved)
+            .map(|m| if m.is_file() { m.len() } else { 0 })
+            .unwrap_or(0);
+        let after = dir_size_bytes(workspace).saturating_sub(replaced) + content.len() as u64;
+        if after > quota_bytes {
+            return Err(format!(
+                "Workspace quota exceeded: writing {} bytes would bring the workspace to {} of {} bytes",
+                content.len(),
+                after,
+                quota_bytes
+            ));
+        }
+    }
 
     // Create parent dirs if needed
     if let Some(parent) = resolved.parent() {
@@ -182,10 +696,152 @@ fn execute_file_write(
             .map_err(|e| format!("Failed to create directories: {}", e))?;
     }
 
-    std::fs::write(&resolved, content)
-        .map_err(|e| format!("Failed to write file: {}", e))?;
+    std::fs::write(resolved, content)
+        .map_err(|e| format!("Failed to write file: {}", e))
+}
+
+/// Take an exclusive lock on the workspace directory, released when the
+/// returned handle drops.
+fn lock_workspace(workspace: &Path) -> Result<std::fs::File, String> {
+    let dir = std::fs::File::open(workspace).map_err(|e| format!("Failed to open workspace: {}", e))?;
+    dir.lock().map_err(|e| format!("Failed to lock workspace: {}", e))?;
+    Ok(dir)
+}
+
+/// Default cap on a shard workspace's total size, in bytes.
+pub const DEFAULT_WORKSPACE_QUOTA_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Total size of the regular files in a shard's workspace (0 if it doesn't
+/// exist yet).
+pub fn workspace_size_bytes(data_dir: &str, shard_id: &str) -> u64 {
+    dir_size_bytes(&shard_workspace(data_dir, shard_id))
+}
+
+/// Sum of file sizes under `dir`, not following symlinks.
+fn dir_size_bytes(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => dir_size_bytes(&entry.path()),
+            Ok(t) if t.is_file() => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Most `path:line:text` lines `search_files` returns.
+pub const SEARCH_MAX_RESULTS: usize = 200;
+/// Files larger than this are skipped by `search_files`.
+const SEARCH_MAX_FILE_BYTES: u64 = 1_000_000;
+
+fn execute_search_files(
+    args: &serde_json::Value,
+    workspace: &Path,
+) -> Result<String, String> {
+    let pattern = args["pattern"]
+        .as_str()
+        .ok_or("Missing 'pattern' argument")?;
+    let regex = regex::Regex::new(pattern)
+        .map_err(|e| format!("Invalid pattern: {}", e))?;
+    let glob = match args["glob"].as_str() {
+        Some(glob) => {
+            if !is_safe_path(glob) {
+                return Err("Path traversal not allowed".to_string());
+            }
+            // Globs without a slash match the file name at any depth
+            Some((glob_to_regex(glob)?, glob.contains('/')))
+        }
+        None => None,
+    };
+
+    let mut files = Vec::new();
+    collect_files(workspace, "", &mut files);
+    files.sort();
+
+    let mut results = Vec::new();
+    let mut capped = false;
+    'files: for rel in &files {
+        if let Some((glob, full_path)) = &glob {
+            let subject = if *full_path { rel.as_str() } else { rel.rsplit('/').next().unwrap_or(rel) };
+            if !glob.is_match(subject) {
+                continue;
+            }
+        }
+        let Ok(text) = std::fs::read_to_string(workspace.join(rel)) else {
+            continue; // binary or unreadable
+        };
+        for (n, line) in text.lines().enumerate() {
+            if regex.is_match(line) {
+                if results.len() == SEARCH_MAX_RESULTS {
+                    capped = true;
+                    break 'files;
+                }
+                results.push(format!("{}:{}:{}", rel, n + 1, line));
+            }
+        }
+    }
+
+    if results.is_empty() {
+        return Ok("No matches".to_string());
+    }
+    let mut output = results.join("\n");
+    if capped {
+        output.push_str(&format!("\n[results capped at {} lines]", SEARCH_MAX_RESULTS));
+    }
+    Ok(output)
+}
+
+/// Relative paths of the regular files under `dir`, without following
+/// symlinks so the search can't leave the workspace.
+fn collect_files(dir: &Path, prefix: &str, out: &mut Vec<String>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let name = entry.file_name().to_string_lossy().to_string();
+        let rel = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+        if !is_safe_path(&rel) {
+            continue;
+        }
+        if file_type.is_dir() {
+            collect_files(&entry.path(), &rel, out);
+        } else if file_type.is_file()
+            && entry.metadata().map(|m| m.len() <= SEARCH_MAX_FILE_BYTES).unwrap_or(false)
+        {
+            out.push(rel);
+        }
+    }
+}
 
-    Ok(format!("Wrote {} bytes to {}", content.len(), path))
+/// Translate a file glob (`*`, `**`, `?`) into an anchored regex.
+fn glob_to_regex(glob: &str) -> Result<regex::Regex, String> {
+    let mut re = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // `**/` also matches zero directories
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    re.push_str("(?:.*/)?");
+                } else {
+                    re.push_str(".*");
+                }
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    regex::Regex::new(&re).map_err(|e| format!("Invalid glob: {}", e))
 }
 
 async fn execute_shell(
@@ -232,18 +888,10 @@ async fn execute_shell(
     }
 }
 
-fn shellexpand(path: &str) -> String {
-    if path.starts_with("~/") {
-        if let Ok(home) = std::env::var("HOME") {
-            return format!("{}{}", home, &path[1..]);
-        }
-    }
-    path.to_string()
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::{mock_inference, text_reply};
 
     #[test]
     fn shard_workspace_path() {
@@ -267,11 +915,11 @@ mod tests {
         assert!(result2.is_err());
     }
 
-    #[test]
-    fn file_write_blocks_traversal() {
+    #[tokio::test]
+    async fn file_write_blocks_traversal() {
         let workspace = Path::new("/tmp/test-workspace");
         let args = serde_json::json!({"path": "../../etc/evil", "content": "bad"});
-        let result = execute_file_write(&args, workspace);
+        let result = execute_file_write(&args, workspace, DEFAULT_WORKSPACE_QUOTA_BYTES).await;
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("traversal"));
     }
@@ -282,7 +930,7 @@ mod tests {
         let workspace = dir.path();
 
         let write_args = serde_json::json!({"path": "test.txt", "content": "hello shard"});
-        let write_result = execute_file_write(&write_args, workspace);
+        let write_result = execute_file_write(&write_args, workspace, DEFAULT_WORKSPACE_QUOTA_BYTES).await;
         assert!(write_result.is_ok());
 
         let read_args = serde_json::json!({"path": "test.txt"});
@@ -290,6 +938,55 @@ mod tests {
         assert_eq!(read_result.unwrap(), "hello shard");
     }
 
+    #[tokio::test]
+    async fn file_write_past_quota_is_refused_without_partial_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        let workspace = shard_workspace(&data_dir, "quota-shard");
+        std::fs::create_dir_all(&workspace).unwrap();
+
+        let write = |path: &str, len: usize| {
+            let args = serde_json::json!({"path": path, "content": "q".repeat(len)});
+            let workspace = workspace.clone();
+            async move { execute_file_write(&args, &workspace, 1000).await }
+        };
+        assert!(write("a.txt", 600).await.is_ok());
+        assert_eq!(workspace_size_bytes(&data_dir, "quota-shard"), 600);
+
+        let err = write("nested/b.txt", 500).await.unwrap_err();
+        assert!(err.contains("quota exceeded"), "{}", err);
+        assert!(!workspace.join("nested/b.txt").exists());
+        assert_eq!(workspace_size_bytes(&data_dir, "quota-shard"), 600);
+
+        // Overwriting only counts the difference
+        assert!(write("a.txt", 900).await.is_ok());
+        assert_eq!(workspace_size_bytes(&data_dir, "quota-shard"), 900);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn concurrent_file_writes_stay_within_quota() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let workspace = dir.path().to_path_buf();
+                tokio::spawn(async move {
+                    let args = serde_json::json!({"path": format!("f{}.txt", i), "content": "q".repeat(300)});
+                    execute_file_write(&args, &workspace, 1000).await.is_ok()
+                })
+            })
+            .collect();
+        let mut written = 0;
+        for handle in handles {
+            if handle.await.unwrap() {
+                written += 1;
+            }
+        }
+
+        assert_eq!(written, 3);
+        assert_eq!(dir_size_bytes(dir.path()), 900);
+    }
+
     #[tokio::test]
     async fn http_fetch_rejects_bad_scheme() {
         let args = serde_json::json!({"url": "ftp://example.com"});
@@ -311,8 +1008,295 @@ mod tests {
     async fn code_eval_missing_language() {
         let dir = tempfile::tempdir().unwrap();
         let args = serde_json::json!({"code": "print(1)"});
-        let result = execute_code_eval(&args, dir.path()).await;
+        let result = execute_code_eval(&args, dir.path(), &HashMap::new()).await;
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("language"));
     }
+
+    #[tokio::test]
+    async fn code_eval_configured_language() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut languages = HashMap::new();
+        languages.insert(
+            "shell".to_string(),
+            CodeInterpreter { command: "sh".to_string(), args: vec![], extension: "sh".to_string() },
+        );
+        let args = serde_json::json!({"language": "shell", "code": "echo $((6 * 7))"});
+        assert!(execute_code_eval(&args, dir.path(), &HashMap::new()).await.is_err());
+
+        if Command::new("sh").arg("-c").arg("true").output().await.is_err() {
+            eprintln!("sh not available; skipping");
+            return;
+        }
+        let output = execute_code_eval(&args, dir.path(), &languages).await.unwrap();
+        assert_eq!(output.trim(), "42");
+        assert_eq!(code_languages(&languages), vec!["javascript", "python", "shell"]);
+    }
+
+    #[tokio::test]
+    async fn code_eval_infinite_loop_times_out() {
+        if Command::new("python3").arg("--version").output().await.is_err() {
+            eprintln!("python3 not available; skipping");
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let args = serde_json::json!({
+            "language": "python",
+            "code": "while True: pass",
+            "timeout_secs": 1
+        });
+        let started = std::time::Instant::now();
+        let err = execute_code_eval(&args, dir.path(), &HashMap::new()).await.unwrap_err();
+        assert!(err.contains("timed out after 1s"), "{}", err);
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        assert!(!dir.path().join("_eval.py").exists());
+    }
+
+    #[test]
+    fn code_output_is_capped_with_marker() {
+        let capped = cap_code_output("y".repeat(CODE_EVAL_MAX_OUTPUT_BYTES + 10));
+        assert!(capped.ends_with("\n[truncated]"));
+        assert_eq!(capped.len(), CODE_EVAL_MAX_OUTPUT_BYTES + "\n[truncated]".len());
+        assert_eq!(cap_code_output("short".to_string()), "short");
+    }
+
+    #[test]
+    fn search_files_finds_matches_within_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src/nested")).unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() {}\n// TODO: tidy\n").unwrap();
+        std::fs::write(dir.path().join("src/nested/lib.rs"), "// TODO: docs\n").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "TODO: call back\n").unwrap();
+
+        let args = serde_json::json!({"pattern": "TODO"});
+        let output = execute_search_files(&args, dir.path()).unwrap();
+        assert_eq!(
+            output,
+            "notes.txt:1:TODO: call back\nsrc/main.rs:2:// TODO: tidy\nsrc/nested/lib.rs:1:// TODO: docs"
+        );
+
+        let args = serde_json::json!({"pattern": "TODO", "glob": "*.rs"});
+        let output = execute_search_files(&args, dir.path()).unwrap();
+        assert!(!output.contains("notes.txt"));
+        assert!(output.contains("src/nested/lib.rs:1:"));
+
+        let args = serde_json::json!({"pattern": "TODO", "glob": "../*"});
+        assert!(execute_search_files(&args, dir.path()).is_err());
+        let args = serde_json::json!({"pattern": "("});
+        assert!(execute_search_files(&args, dir.path()).is_err());
+    }
+
+    #[test]
+    fn search_files_caps_results() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("many.txt"), "hit\n".repeat(SEARCH_MAX_RESULTS + 50)).unwrap();
+
+        let args = serde_json::json!({"pattern": "hit"});
+        let output = execute_search_files(&args, dir.path()).unwrap();
+        assert_eq!(output.lines().filter(|l| l.starts_with("many.txt:")).count(), SEARCH_MAX_RESULTS);
+        assert!(output.ends_with("[results capped at 200 lines]"));
+    }
+
+    #[test]
+    fn code_language_allowlist() {
+        let interp = |command: &str, extension: &str| CodeInterpreter {
+            command: command.to_string(),
+            args: vec![],
+            extension: extension.to_string(),
+        };
+        assert!(validate_code_language("ruby", &interp("ruby", "rb")).is_ok());
+        assert!(validate_code_language("evil", &interp("/tmp/evil", "sh")).is_err());
+        assert!(validate_code_language("rm", &interp("rm", "x")).is_err());
+        assert!(validate_code_language("ruby", &interp("ruby", "../rb")).is_err());
+    }
+
+    #[tokio::test]
+    async fn tool_output_over_cap_is_truncated_with_marker() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        let config = Config { data_dir: data_dir.clone(), ..Config::default() };
+        let workspace = shard_workspace(&data_dir, "cap-shard");
+        std::fs::create_dir_all(&workspace).unwrap();
+        std::fs::write(workspace.join("big.txt"), "x".repeat(500)).unwrap();
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: "file_read".to_string(),
+            arguments: serde_json::json!({"path": "big.txt"}),
+        };
+
+        let result = execute_tool(&config, "cap-shard", &call, 100).await;
+        assert!(result.success);
+        assert!(result.truncated);
+        assert!(result.output.starts_with(&"x".repeat(100)));
+        assert!(result.output.contains("[output truncated: showing 100 of 500 bytes]"));
+
+        let result = execute_tool(&config, "cap-shard", &call, 1000).await;
+        assert!(!result.truncated);
+        assert_eq!(result.output.len(), 500);
+    }
+
+    #[test]
+    fn truncate_output_respects_char_boundaries() {
+        let mut s = "héllo".to_string();
+        assert!(truncate_output(&mut s, 2));
+        assert!(s.starts_with("h\n[output truncated"));
+    }
+
+    #[tokio::test]
+    async fn binary_file_read_returns_base64_envelope() {
+        let dir = tempfile::tempdir().unwrap();
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR\xff\xfe".to_vec();
+        std::fs::write(dir.path().join("pic.png"), &png).unwrap();
+
+        let args = serde_json::json!({"path": "pic.png"});
+        let output = execute_file_read(&args, dir.path()).unwrap();
+        let envelope = BinaryEnvelope::parse(&output).expect("binary output should be an envelope");
+        assert_eq!(envelope.mime_type, "image/png");
+        assert_eq!(envelope.size_bytes, png.len());
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(envelope.data.unwrap())
+            .unwrap();
+        assert_eq!(decoded, png);
+
+        // Plain text is untouched
+        std::fs::write(dir.path().join("note.txt"), "{\"a\": 1}").unwrap();
+        let text = execute_file_read(&serde_json::json!({"path": "note.txt"}), dir.path()).unwrap();
+        assert!(BinaryEnvelope::parse(&text).is_none());
+    }
+
+    #[tokio::test]
+    async fn oversized_binary_drops_payload_instead_of_truncating() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        let config = Config { data_dir: data_dir.clone(), ..Config::default() };
+        let workspace = shard_workspace(&data_dir, "bin-shard");
+        std::fs::create_dir_all(&workspace).unwrap();
+        std::fs::write(workspace.join("blob.bin"), vec![0xffu8; 400]).unwrap();
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: "file_read".to_string(),
+            arguments: serde_json::json!({"path": "blob.bin"}),
+        };
+
+        let result = execute_tool(&config, "bin-shard", &call, 100).await;
+        assert!(result.truncated);
+        let envelope = BinaryEnvelope::parse(&result.output).unwrap();
+        assert_eq!(envelope.size_bytes, 400);
+        assert!(envelope.data.is_none());
+    }
+
+    #[test]
+    fn ideas_are_deduplicated_and_unnumbered() {
+        let text = "1. Paint the walls\n2) Hold a contest\n\n- paint the walls\n* Start a podcast";
+        assert_eq!(
+            parse_ideas(text, 10),
+            vec!["Paint the walls", "Hold a contest", "Start a podcast"]
+        );
+        assert_eq!(parse_ideas(text, 2).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn brainstorm_returns_numbered_list() {
+        let seen_temperature = std::sync::Arc::new(std::sync::Mutex::new(None));
+        let seen = seen_temperature.clone();
+        let url = mock_inference(move |body| {
+            *seen.lock().unwrap() = body["temperature"].as_f64();
+            text_reply("- A rooftop garden\n- A night market\n- A rooftop garden")
+        })
+        .await;
+
+        let config = InferenceConfig {
+            api_url: url,
+            ..Default::default()
+        };
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: BRAINSTORM_TOOL.to_string(),
+            arguments: serde_json::json!({"topic": "uses for an empty lot", "count": 3}),
+        };
+        let result = execute_brainstorm(&call, &config, 1000).await;
+        assert!(result.success, "{}", result.output);
+        assert_eq!(result.output, "1. A rooftop garden\n2. A night market");
+        assert_eq!(*seen_temperature.lock().unwrap(), Some(BRAINSTORM_TEMPERATURE));
+
+        let missing = ToolCall { arguments: serde_json::json!({}), ..call };
+        assert!(!execute_brainstorm(&missing, &config, 1000).await.success);
+    }
+
+    #[tokio::test]
+    async fn recall_lessons_finds_relevant_lessons() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        let config = Config { data_dir: data_dir.clone(), ..Config::default() };
+        db::init_db(&data_dir).unwrap();
+        let shard = crate::shard::Shard::spawn(None);
+        db::insert_shard(&data_dir, &shard).unwrap();
+        let corpus = [
+            ("debug", "Fix failing tests in the CSV parser", "Ran the parser tests and patched quoted-field handling"),
+            ("writing", "Draft a launch announcement", "Outlined three sections then wrote the copy"),
+            ("analysis", "Compare hosting providers", "Tabulated pricing and uptime for each provider"),
+        ];
+        let fixes = vec!["Escape embedded quotes".to_string()];
+        for (task_type, goal, approach) in corpus {
+            let action_id = db::insert_action(&data_dir, &shard.id, goal, None).unwrap();
+            db::insert_task_lesson(
+                &data_dir,
+                &db::NewTaskLesson {
+                    shard_id: &shard.id,
+                    action_id,
+                    task_type,
+                    goal,
+                    approach,
+                    tools_used: &[],
+                    outcome: "Done",
+                    errors: &[],
+                    fixes: &fixes,
+                    duration_ms: 1000,
+                    success: true,
+                    extractor_confidence: 0.8,
+                    applicability_confidence: 0.8,
+                    reusability: 0.8,
+                    artifact_path: "",
+                },
+            )
+            .unwrap();
+        }
+
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: RECALL_LESSONS_TOOL.to_string(),
+            arguments: serde_json::json!({"query": "the CSV parser tests fail", "task_type": "debug", "limit": 1}),
+        };
+        let result = execute_tool(&config, &shard.id, &call, 10_000).await;
+        assert!(result.success, "{}", result.output);
+        assert_eq!(result.output.lines().count(), 1);
+        assert!(result.output.starts_with("1. [debug] Fix failing tests in the CSV parser"));
+        assert!(result.output.contains("fixes: Escape embedded quotes"));
+
+        // The configured weights decide the ranking: here only the task type counts
+        let by_type = db::RetrievalWeights {
+            semantic: 0.0,
+            lexical: 0.0,
+            score: 0.0,
+            helpful: 0.0,
+            recency: 0.0,
+            type_match: 1.0,
+            pinned: 0.0,
+        };
+        let writing = ToolCall {
+            arguments: serde_json::json!({"query": "the CSV parser tests fail", "task_type": "writing", "limit": 1}),
+            ..call.clone()
+        };
+        let result =
+            execute_tool_with_languages(&data_dir, &shard.id, &writing, 10_000, &HashMap::new(), 0, &by_type).await;
+        assert!(result.output.starts_with("1. [writing] Draft a launch announcement"), "{}", result.output);
+
+        // Oversized limits are capped
+        let greedy = ToolCall { arguments: serde_json::json!({"query": "anything", "limit": 100}), ..call.clone() };
+        let result = execute_tool(&config, &shard.id, &greedy, 10_000).await;
+        assert!(result.output.lines().count() <= RECALL_MAX_LESSONS);
+
+        let missing = ToolCall { arguments: serde_json::json!({}), ..call };
+        assert!(!execute_tool(&config, &shard.id, &missing, 10_000).await.success);
+    }
 }
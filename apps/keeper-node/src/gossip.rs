@@ -1,7 +1,10 @@
-use libp2p::gossipsub::{self, IdentTopic, TopicHash};
+use libp2p::gossipsub::{IdentTopic, PublishError, TopicHash};
 use libp2p::Swarm;
+use std::collections::VecDeque;
 
+use crate::drift::DriftMessage;
 use crate::node::KeeperBehaviour;
+use crate::reputation::ReputationWarning;
 use crate::shard::Shard;
 
 /// GossipSub topic constants matching the TypeScript TOPICS.
@@ -9,6 +12,10 @@ pub const TOPIC_SHARD_SPAWN: &str = "/siphon/shard/spawn/1.0.0";
 pub const TOPIC_WILD_DRIFT: &str = "/siphon/wild/drift/1.0.0";
 pub const TOPIC_KEEPER_HEARTBEAT: &str = "/siphon/keeper/heartbeat/1.0.0";
 pub const TOPIC_BATTLE_CHALLENGE: &str = "/siphon/battle/challenge/1.0.0";
+pub const TOPIC_KEEPER_WARNING: &str = "/siphon/keeper/warning/1.0.0";
+
+/// Default for most messages held for retry before the oldest is dropped.
+pub const PUBLISH_QUEUE_CAPACITY: usize = 64;
 
 /// Subscribe the swarm to all Siphon Protocol GossipSub topics.
 pub fn subscribe_topics(swarm: &mut Swarm<KeeperBehaviour>) {
@@ -17,6 +24,7 @@ pub fn subscribe_topics(swarm: &mut Swarm<KeeperBehaviour>) {
         TOPIC_WILD_DRIFT,
         TOPIC_KEEPER_HEARTBEAT,
         TOPIC_BATTLE_CHALLENGE,
+        TOPIC_KEEPER_WARNING,
     ];
 
     for topic_str in &topics {
@@ -73,16 +81,143 @@ pub fn handle_message(
                 data.len()
             );
         }
+        TOPIC_KEEPER_WARNING => {
+            tracing::warn!(
+                "Received keeper warning from {}: {}",
+                &source.to_string()[..8],
+                String::from_utf8_lossy(data)
+            );
+        }
         _ => {
             tracing::trace!("Received message on unknown topic: {}", topic_str);
         }
     }
 }
 
+/// A message waiting to be published.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutboundMessage {
+    pub topic: &'static str,
+    pub data: Vec<u8>,
+}
+
+/// Outbound messages that failed to publish because no peers were
+/// subscribed yet (`InsufficientPeers`, typical before the mesh forms).
+/// They are retried in order on `flush`; when full, the oldest is dropped.
+/// A heartbeat replaces any still queued, since only the latest matters.
+#[derive(Debug)]
+pub struct PublishQueue {
+    pending: VecDeque<OutboundMessage>,
+    capacity: usize,
+    /// Messages dropped to make room since the queue was created
+    pub dropped: u64,
+}
+
+impl PublishQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            pending: VecDeque::new(),
+            capacity: capacity.max(1),
+            dropped: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Queue a message, dropping (and returning) the oldest when full.
+    /// Queued heartbeats are superseded by a new one.
+    pub fn push(&mut self, message: OutboundMessage) -> Option<OutboundMessage> {
+        if message.topic == TOPIC_KEEPER_HEARTBEAT {
+            self.pending.retain(|queued| queued.topic != TOPIC_KEEPER_HEARTBEAT);
+        }
+        let dropped = if self.pending.len() >= self.capacity {
+            self.dropped += 1;
+            self.pending.pop_front()
+        } else {
+            None
+        };
+        self.pending.push_back(message);
+        dropped
+    }
+
+    /// Retry queued messages in order through `publish`. Stops at the first
+    /// `InsufficientPeers`, leaving it and the rest queued; messages failing
+    /// any other way are discarded. Returns how many were sent.
+    pub fn flush<F>(&mut self, mut publish: F) -> usize
+    where
+        F: FnMut(&OutboundMessage) -> Result<(), PublishError>,
+    {
+        let mut sent = 0;
+        while let Some(message) = self.pending.front() {
+            match publish(message) {
+                Ok(()) => sent += 1,
+                Err(PublishError::InsufficientPeers) => break,
+                Err(e) => tracing::warn!("Dropping queued message for {}: {:?}", message.topic, e),
+            }
+            self.pending.pop_front();
+        }
+        sent
+    }
+
+    /// Publish `message` after anything already queued, queueing it instead
+    /// if there are no peers to send to yet.
+    fn publish_or_queue<F>(&mut self, message: OutboundMessage, mut publish: F) -> Result<(), PublishError>
+    where
+        F: FnMut(&OutboundMessage) -> Result<(), PublishError>,
+    {
+        self.flush(&mut publish);
+        if !self.pending.is_empty() {
+            self.push(message);
+            return Ok(());
+        }
+        match publish(&message) {
+            Err(PublishError::InsufficientPeers) => {
+                tracing::debug!("No peers on {} yet; queued for retry", message.topic);
+                if let Some(dropped) = self.push(message) {
+                    tracing::warn!("Publish queue full; dropped oldest message for {}", dropped.topic);
+                }
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
+
+fn swarm_publish(
+    swarm: &mut Swarm<KeeperBehaviour>,
+) -> impl FnMut(&OutboundMessage) -> Result<(), PublishError> + '_ {
+    move |message| {
+        swarm
+            .behaviour_mut()
+            .gossipsub
+            .publish(IdentTopic::new(message.topic), message.data.clone())
+            .map(|_| ())
+    }
+}
+
+/// Retry messages queued while the mesh had no peers.
+pub fn flush_publish_queue(swarm: &mut Swarm<KeeperBehaviour>, queue: &mut PublishQueue) {
+    if queue.is_empty() {
+        return;
+    }
+    let sent = queue.flush(swarm_publish(swarm));
+    if sent > 0 {
+        tracing::debug!("Published {} queued message(s); {} still waiting", sent, queue.len());
+    }
+}
+
 /// Publish a keeper heartbeat to the network.
 /// Includes the keeper's peer ID, hosted shard count, and resource stats.
+/// Queued for retry if no peers are subscribed yet.
 pub fn publish_heartbeat(
     swarm: &mut Swarm<KeeperBehaviour>,
+    queue: &mut PublishQueue,
     hosted_shard_count: usize,
     reputation: u64,
 ) -> Result<(), String> {
@@ -100,11 +235,9 @@ pub fn publish_heartbeat(
     let data = serde_json::to_vec(&heartbeat)
         .map_err(|e| format!("Failed to serialize heartbeat: {}", e))?;
 
-    let topic = IdentTopic::new(TOPIC_KEEPER_HEARTBEAT);
-    swarm
-        .behaviour_mut()
-        .gossipsub
-        .publish(topic, data)
+    let message = OutboundMessage { topic: TOPIC_KEEPER_HEARTBEAT, data };
+    queue
+        .publish_or_queue(message, swarm_publish(swarm))
         .map_err(|e| format!("Failed to publish heartbeat: {:?}", e))?;
 
     tracing::debug!("Published keeper heartbeat");
@@ -112,21 +245,65 @@ pub fn publish_heartbeat(
 }
 
 /// Publish a shard spawn event to the network.
+/// Queued for retry if no peers are subscribed yet.
 pub fn publish_shard_spawn(
     swarm: &mut Swarm<KeeperBehaviour>,
+    queue: &mut PublishQueue,
     shard: &Shard,
 ) -> Result<(), String> {
     let data = serde_json::to_vec(shard)
         .map_err(|e| format!("Failed to serialize shard: {}", e))?;
 
-    let topic = IdentTopic::new(TOPIC_SHARD_SPAWN);
+    let message = OutboundMessage { topic: TOPIC_SHARD_SPAWN, data };
+    queue
+        .publish_or_queue(message, swarm_publish(swarm))
+        .map_err(|e| format!("Failed to publish shard spawn: {:?}", e))?;
+
+    tracing::info!("Published shard spawn: {} [{}]", shard.name, shard.shard_type);
+    Ok(())
+}
+
+/// Publish a wild drift handoff message to the network.
+pub fn publish_wild_drift(
+    swarm: &mut Swarm<KeeperBehaviour>,
+    message: &DriftMessage,
+) -> Result<(), String> {
+    let data = serde_json::to_vec(message)
+        .map_err(|e| format!("Failed to serialize drift message: {}", e))?;
+
+    let topic = IdentTopic::new(TOPIC_WILD_DRIFT);
     swarm
         .behaviour_mut()
         .gossipsub
         .publish(topic, data)
-        .map_err(|e| format!("Failed to publish shard spawn: {:?}", e))?;
+        .map_err(|e| format!("Failed to publish wild drift: {:?}", e))?;
 
-    tracing::info!("Published shard spawn: {} [{}]", shard.name, shard.shard_type);
+    Ok(())
+}
+
+/// Warn the network that this keeper's reputation has fallen below its
+/// configured threshold.
+pub fn publish_reputation_warning(
+    swarm: &mut Swarm<KeeperBehaviour>,
+    warning: &ReputationWarning,
+) -> Result<(), String> {
+    let message = serde_json::json!({
+        "keeper_id": swarm.local_peer_id().to_string(),
+        "kind": "low_reputation",
+        "reputation": warning.reputation,
+        "threshold": warning.threshold,
+    });
+    let data = serde_json::to_vec(&message)
+        .map_err(|e| format!("Failed to serialize keeper warning: {}", e))?;
+
+    let topic = IdentTopic::new(TOPIC_KEEPER_WARNING);
+    swarm
+        .behaviour_mut()
+        .gossipsub
+        .publish(topic, data)
+        .map_err(|e| format!("Failed to publish keeper warning: {:?}", e))?;
+
+    tracing::warn!("Published low-reputation warning ({})", warning.reputation);
     Ok(())
 }
 
@@ -168,6 +345,87 @@ mod tests {
         }
     }
 
+    fn message(n: u8) -> OutboundMessage {
+        OutboundMessage { topic: TOPIC_SHARD_SPAWN, data: vec![n] }
+    }
+
+    #[test]
+    fn queued_messages_retry_in_order_once_peers_arrive() {
+        let mut queue = PublishQueue::new(8);
+        let mut sent = Vec::new();
+        let mut no_peers = |_: &OutboundMessage| -> Result<(), PublishError> { Err(PublishError::InsufficientPeers) };
+
+        // Before the mesh forms everything is queued, not lost
+        assert!(queue.publish_or_queue(message(1), &mut no_peers).is_ok());
+        assert!(queue.publish_or_queue(message(2), &mut no_peers).is_ok());
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.flush(&mut no_peers), 0);
+        assert_eq!(queue.len(), 2);
+
+        // With peers, queued messages go out first, then the new one
+        let mut online = |m: &OutboundMessage| -> Result<(), PublishError> {
+            sent.push(m.data[0]);
+            Ok(())
+        };
+        assert!(queue.publish_or_queue(message(3), &mut online).is_ok());
+        assert!(queue.is_empty());
+        assert_eq!(sent, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn full_queue_drops_oldest() {
+        let mut queue = PublishQueue::new(2);
+        assert_eq!(queue.push(message(1)), None);
+        assert_eq!(queue.push(message(2)), None);
+        assert_eq!(queue.push(message(3)), Some(message(1)));
+        assert_eq!(queue.dropped, 1);
+
+        let mut sent = Vec::new();
+        queue.flush(|m| {
+            sent.push(m.data[0]);
+            Ok(())
+        });
+        assert_eq!(sent, vec![2, 3]);
+    }
+
+    #[test]
+    fn only_the_latest_heartbeat_stays_queued() {
+        let heartbeat = |n: u8| OutboundMessage { topic: TOPIC_KEEPER_HEARTBEAT, data: vec![n] };
+        let mut queue = PublishQueue::new(3);
+        queue.push(heartbeat(1));
+        queue.push(message(2));
+        queue.push(heartbeat(3));
+        queue.push(heartbeat(4));
+        queue.push(message(5));
+        assert_eq!(queue.dropped, 0);
+
+        let mut sent = Vec::new();
+        queue.flush(|m| {
+            sent.push(m.data[0]);
+            Ok(())
+        });
+        assert_eq!(sent, vec![2, 4, 5]);
+    }
+
+    #[test]
+    fn other_publish_errors_are_not_retried() {
+        let mut queue = PublishQueue::new(4);
+        queue.push(message(1));
+        queue.push(message(2));
+
+        let mut attempts = 0;
+        let sent = queue.flush(|m| {
+            attempts += 1;
+            if m.data[0] == 1 {
+                Err(PublishError::Duplicate)
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!((sent, attempts), (1, 2));
+        assert!(queue.is_empty());
+    }
+
     #[test]
     fn topics_end_with_version() {
         for topic in &[TOPIC_SHARD_SPAWN, TOPIC_WILD_DRIFT, TOPIC_KEEPER_HEARTBEAT, TOPIC_BATTLE_CHALLENGE] {
@@ -1,5 +1,11 @@
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Semaphore};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::Stream;
 
 // ── Basic chat types ────────────────────────────────────────────────
 
@@ -12,9 +18,26 @@ struct ChatCompletionRequest {
     temperature: f64,
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<ToolDefinition>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning: Option<ReasoningOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+    /// Ask for server-sent token deltas instead of one body
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ReasoningOptions {
+    max_tokens: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(from = "WireChatMessage")]
 pub struct ChatMessage {
     pub role: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -25,6 +48,61 @@ pub struct ChatMessage {
     pub tool_call_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    /// Multimodal content; when set it is sent as `content` in place of the text
+    #[serde(rename = "content", skip_serializing_if = "Option::is_none")]
+    pub parts: Option<Vec<ContentPart>>,
+}
+
+/// `ChatMessage` as serialized, where `content` is either text or parts.
+#[derive(Deserialize)]
+struct WireChatMessage {
+    role: String,
+    #[serde(default)]
+    content: Option<WireContent>,
+    #[serde(default)]
+    tool_calls: Option<Vec<ToolCallRef>>,
+    #[serde(default)]
+    tool_call_id: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum WireContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+impl From<WireChatMessage> for ChatMessage {
+    fn from(wire: WireChatMessage) -> Self {
+        let (content, parts) = match wire.content {
+            Some(WireContent::Text(text)) => (Some(text), None),
+            Some(WireContent::Parts(parts)) => (None, Some(parts)),
+            None => (None, None),
+        };
+        Self {
+            role: wire.role,
+            content,
+            tool_calls: wire.tool_calls,
+            tool_call_id: wire.tool_call_id,
+            name: wire.name,
+            parts,
+        }
+    }
+}
+
+/// One part of a multimodal message (OpenAI content-part format).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ImageUrl {
+    pub url: String,
 }
 
 /// Compact tool call representation for the OpenAI message format.
@@ -51,6 +129,7 @@ impl ChatMessage {
             tool_calls: None,
             tool_call_id: None,
             name: None,
+            parts: None,
         }
     }
 
@@ -62,11 +141,34 @@ impl ChatMessage {
             tool_calls: None,
             tool_call_id: Some(tool_call_id.to_string()),
             name: Some(name.to_string()),
+            parts: None,
+        }
+    }
+
+    /// Create a user message carrying text plus an image (a `data:` or https URL).
+    /// Tool messages can't hold images, so vision input follows the tool result.
+    pub fn user_image(text: &str, image_url: &str) -> Self {
+        Self {
+            role: "user".to_string(),
+            content: None,
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            parts: Some(vec![
+                ContentPart::Text { text: text.to_string() },
+                ContentPart::ImageUrl {
+                    image_url: ImageUrl { url: image_url.to_string() },
+                },
+            ]),
         }
     }
 
     /// Create an assistant message with tool calls (content may be None).
+    /// Blank or repeated ids are replaced as in `assign_unique_tool_call_ids`;
+    /// run that on `calls` first so tool results reference the same ids.
     pub fn assistant_tool_calls(calls: &[ToolCall]) -> Self {
+        let mut calls = calls.to_vec();
+        assign_unique_tool_call_ids(&mut calls);
         let refs = calls
             .iter()
             .map(|c| ToolCallRef {
@@ -84,6 +186,7 @@ impl ChatMessage {
             tool_calls: Some(refs),
             tool_call_id: None,
             name: None,
+            parts: None,
         }
     }
 }
@@ -98,6 +201,7 @@ struct ChatCompletionResponse {
 struct ChatChoice {
     message: ChatResponseMessage,
     #[serde(default)]
+    #[allow(dead_code)]
     finish_reason: Option<String>,
 }
 
@@ -126,6 +230,8 @@ struct RawFunctionCall {
 struct EmbeddingRequest {
     model: String,
     input: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -163,6 +269,30 @@ pub struct ToolCall {
     pub arguments: serde_json::Value, // parsed JSON
 }
 
+/// Give every call in a turn a distinct, non-blank id. The first call using an
+/// id keeps it; blank and repeated ids become `call_<position>` (suffixed if
+/// that is taken too). Deterministic, so it can be re-run on the same calls.
+pub fn assign_unique_tool_call_ids(calls: &mut [ToolCall]) {
+    let mut seen = std::collections::HashSet::new();
+    let mut needs_id = Vec::new();
+    for (i, call) in calls.iter().enumerate() {
+        let id = call.id.trim();
+        if id.is_empty() || !seen.insert(id.to_string()) {
+            needs_id.push(i);
+        }
+    }
+    for i in needs_id {
+        let mut id = format!("call_{}", i + 1);
+        let mut n = 1;
+        while seen.contains(&id) {
+            n += 1;
+            id = format!("call_{}_{}", i + 1, n);
+        }
+        seen.insert(id.clone());
+        calls[i].id = id;
+    }
+}
+
 /// The result of a tool-calling inference — either plain text or tool calls.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -187,12 +317,75 @@ impl ToolDefinition {
 // ── Config ──────────────────────────────────────────────────────────
 
 /// Configuration for inference requests.
+#[derive(Clone)]
 pub struct InferenceConfig {
     pub api_key: String,
     pub api_url: String,
     pub model: String,
     pub max_tokens: u32,
     pub temperature: f64,
+    /// Whether the model accepts image content parts
+    pub supports_vision: bool,
+    /// "low", "medium", or "high"; only sent to reasoning models
+    pub reasoning_effort: Option<String>,
+    /// Thinking-token budget; only sent to reasoning models
+    pub max_reasoning_tokens: Option<u32>,
+    /// Bounds concurrent completion and embedding requests; None = unlimited
+    pub limiter: Option<InferenceLimiter>,
+    /// How tools are offered to the model
+    pub tool_protocol: ToolProtocol,
+    /// End-user id sent as the `user` field, for provider-side abuse
+    /// monitoring and rate tiers; omitted when empty
+    pub user: Option<String>,
+    /// Ask the provider to constrain replies to a JSON object
+    pub json_mode: bool,
+    /// Character budget for each lesson's embedding text (0 = unbounded)
+    pub max_embedding_input_chars: usize,
+    /// Retries of a completion request after a 429, 500, 502 or 503 response
+    /// or a connection or timeout error
+    pub max_retries: u32,
+    /// Delay before the first retry; doubles with each further retry
+    pub initial_backoff_ms: u64,
+    /// How `api_key` is sent
+    pub auth_style: AuthStyle,
+    /// Extra headers sent with every request, e.g. `HTTP-Referer` and
+    /// `X-Title` for OpenRouter
+    pub headers: HashMap<String, String>,
+}
+
+/// Default character budget for a lesson's embedding text.
+pub const DEFAULT_MAX_EMBEDDING_INPUT_CHARS: usize = 2000;
+
+/// Default retries of a transiently failed completion request.
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Default first retry delay: retries wait 250ms, 500ms, then 1s.
+pub const DEFAULT_INITIAL_BACKOFF_MS: u64 = 250;
+
+/// Longest `Retry-After` honored; longer asks are cut to this.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// How the API key is sent to the inference provider.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthStyle {
+    /// `Authorization: Bearer <key>`
+    #[default]
+    Bearer,
+    /// `api-key: <key>`, as Azure OpenAI expects
+    ApiKey,
+}
+
+/// How tool definitions reach the model and tool calls come back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolProtocol {
+    /// OpenAI-style `tools` field and structured `tool_calls`
+    #[default]
+    Native,
+    /// Tool schemas described in the system prompt; calls parsed out of the
+    /// reply text. For models without native function calling.
+    PromptEmbedded,
 }
 
 impl Default for InferenceConfig {
@@ -203,54 +396,446 @@ impl Default for InferenceConfig {
             model: "gpt-4o-mini".to_string(),
             max_tokens: 512,
             temperature: 0.7,
+            supports_vision: false,
+            reasoning_effort: None,
+            max_reasoning_tokens: None,
+            limiter: None,
+            tool_protocol: ToolProtocol::default(),
+            user: None,
+            json_mode: false,
+            max_embedding_input_chars: DEFAULT_MAX_EMBEDDING_INPUT_CHARS,
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff_ms: DEFAULT_INITIAL_BACKOFF_MS,
+            auth_style: AuthStyle::default(),
+            headers: HashMap::new(),
         }
     }
 }
 
-// ── Core request helper ─────────────────────────────────────────────
+/// Caps how many completion and embedding requests are in flight at once.
+/// Clones share the same permits.
+#[derive(Clone)]
+pub struct InferenceLimiter(Arc<Semaphore>);
 
-/// Send a chat completion request and return the raw response.
-async fn send_completion(
+impl InferenceLimiter {
+    pub fn new(permits: usize) -> Self {
+        Self(Arc::new(Semaphore::new(permits)))
+    }
+
+    pub fn available(&self) -> usize {
+        self.0.available_permits()
+    }
+}
+
+/// A limiter with `permits` slots, or None (unlimited) for 0.
+pub fn limiter_for(permits: usize) -> Option<InferenceLimiter> {
+    (permits > 0).then(|| InferenceLimiter::new(permits))
+}
+
+/// Accepted values for `InferenceConfig::reasoning_effort`.
+pub const REASONING_EFFORTS: [&str; 3] = ["low", "medium", "high"];
+
+/// Whether a model accepts reasoning parameters (OpenAI o-series and GPT-5,
+/// DeepSeek R1, Qwen QwQ). Other models reject or ignore them, so they are
+/// omitted from the request.
+pub fn supports_reasoning(model: &str) -> bool {
+    let model = model.to_ascii_lowercase();
+    let name = model.rsplit('/').next().unwrap_or(&model);
+    ["o1", "o3", "o4", "gpt-5"]
+        .iter()
+        .any(|p| name == *p || name.starts_with(&format!("{}-", p)))
+        || name.contains("deepseek-r1")
+        || name.starts_with("qwq")
+}
+
+fn completion_request(
     config: &InferenceConfig,
     messages: Vec<ChatMessage>,
     tools: Option<Vec<ToolDefinition>>,
-) -> Result<ChatCompletionResponse, String> {
-    let request_body = ChatCompletionRequest {
+) -> ChatCompletionRequest {
+    let reasoning = supports_reasoning(&config.model);
+    ChatCompletionRequest {
         model: config.model.clone(),
         messages,
         max_tokens: config.max_tokens,
         temperature: config.temperature,
         tools,
-    };
+        reasoning_effort: config.reasoning_effort.clone().filter(|_| reasoning),
+        reasoning: config
+            .max_reasoning_tokens
+            .filter(|_| reasoning)
+            .map(|max_tokens| ReasoningOptions { max_tokens }),
+        user: request_user(config),
+        response_format: config
+            .json_mode
+            .then(|| serde_json::json!({"type": "json_object"})),
+        stream: false,
+    }
+}
+
+fn request_user(config: &InferenceConfig) -> Option<String> {
+    config.user.clone().filter(|user| !user.trim().is_empty())
+}
 
+// ── Core request helper ─────────────────────────────────────────────
+
+/// Send a chat completion request and return the raw response.
+async fn send_completion(
+    config: &InferenceConfig,
+    messages: Vec<ChatMessage>,
+    tools: Option<Vec<ToolDefinition>>,
+) -> Result<ChatCompletionResponse, String> {
+    let request_body = completion_request(config, messages, tools);
+
+    // Held until the response body has been read
+    let (response, _permit) = post_completion(config, &request_body).await?;
+    let body = response
+        .text()
+        .await
+        .map_err(|e| format!("Failed to read response: {}", e))?;
+    parse_completion(&body)
+}
+
+async fn acquire_permit(config: &InferenceConfig) -> Result<Option<tokio::sync::SemaphorePermit<'_>>, String> {
+    match &config.limiter {
+        Some(limiter) => limiter
+            .0
+            .acquire()
+            .await
+            .map(Some)
+            .map_err(|e| format!("Inference limiter closed: {}", e)),
+        None => Ok(None),
+    }
+}
+
+/// POST a completion request, turning a non-2xx status into an error.
+/// Transient failures (429, 500, 502, 503, connection and timeout errors)
+/// are retried up to `max_retries` times with exponential backoff; a 429's
+/// `Retry-After` replaces the backoff delay. Each attempt takes a limiter
+/// permit, released while waiting to retry; the one returned with the
+/// response should be held until its body is read.
+async fn post_completion<'a>(
+    config: &'a InferenceConfig,
+    request_body: &ChatCompletionRequest,
+) -> Result<(reqwest::Response, Option<tokio::sync::SemaphorePermit<'a>>), String> {
     let client = Client::new();
-    let mut request = client
-        .post(&config.api_url)
-        .header("Content-Type", "application/json");
+    if !config.headers.is_empty() {
+        tracing::debug!("Inference request headers: {}", describe_headers(&config.headers));
+    }
+    let mut attempt = 0;
+    loop {
+        let request = client
+            .post(&config.api_url)
+            .header("Content-Type", "application/json");
+
+        let permit = acquire_permit(config).await?;
+        let (error, delay) = match authorize(request, config).json(request_body).send().await {
+            Ok(response) if response.status().is_success() => return Ok((response, permit)),
+            Ok(response) => {
+                let status = response.status();
+                let retry_after = if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                    retry_after(response.headers())
+                } else {
+                    None
+                };
+                let body = response
+                    .text()
+                    .await
+                    .unwrap_or_else(|_| "unable to read body".to_string());
+                let error = format!("Inference API error ({}): {}", status, body);
+                if !retryable_status(status) {
+                    return Err(error);
+                }
+                (error, retry_after)
+            }
+            Err(e) => {
+                if !(e.is_connect() || e.is_timeout()) {
+                    return Err(format!("HTTP request failed: {}", e));
+                }
+                (format!("HTTP request failed: {}", e), None)
+            }
+        };
 
+        drop(permit);
+        if attempt >= config.max_retries {
+            return Err(error);
+        }
+        let delay = delay.unwrap_or_else(|| backoff_delay(config.initial_backoff_ms, attempt));
+        attempt += 1;
+        tracing::warn!(
+            "{}; retrying in {}ms (attempt {}/{})",
+            error,
+            delay.as_millis(),
+            attempt,
+            config.max_retries
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Attach the API key in the configured style, then the configured extra headers.
+fn authorize(mut request: RequestBuilder, config: &InferenceConfig) -> RequestBuilder {
     if !config.api_key.is_empty() {
-        request = request.header("Authorization", format!("Bearer {}", config.api_key));
+        request = match config.auth_style {
+            AuthStyle::Bearer => request.header("Authorization", format!("Bearer {}", config.api_key)),
+            AuthStyle::ApiKey => request.header("api-key", &config.api_key),
+        };
+    }
+    for (name, value) in &config.headers {
+        request = request.header(name.as_str(), value.as_str());
     }
+    request
+}
 
-    let response = request
-        .json(&request_body)
-        .send()
-        .await
-        .map_err(|e| format!("HTTP request failed: {}", e))?;
+/// Whether a header probably carries a credential, judged by its name or
+/// the shape of its value.
+pub fn is_secret_header(name: &str, value: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    ["auth", "key", "token", "secret", "password", "signature", "cookie"]
+        .iter()
+        .any(|hint| name.contains(hint))
+        || value.starts_with("Bearer ")
+        || value.starts_with("sk-")
+}
 
-    if !response.status().is_success() {
-        let status = response.status();
+/// `name: value` pairs for logging, with secret-looking values redacted.
+fn describe_headers(headers: &HashMap<String, String>) -> String {
+    let mut pairs: Vec<String> = headers
+        .iter()
+        .map(|(name, value)| {
+            let shown = if is_secret_header(name, value) { crate::config::REDACTED } else { value.as_str() };
+            format!("{}: {}", name, shown)
+        })
+        .collect();
+    pairs.sort();
+    pairs.join(", ")
+}
+
+/// Statuses worth retrying: rate limits and transient server failures.
+fn retryable_status(status: reqwest::StatusCode) -> bool {
+    matches!(status.as_u16(), 429 | 500 | 502 | 503)
+}
+
+/// Delay before retry number `attempt` (0-based): `initial_ms` doubled per attempt.
+fn backoff_delay(initial_ms: u64, attempt: u32) -> Duration {
+    Duration::from_millis(initial_ms.saturating_mul(1u64 << attempt.min(16)))
+}
+
+/// A `Retry-After` header given in seconds, capped at `MAX_RETRY_AFTER`.
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let secs: u64 = headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(secs).min(MAX_RETRY_AFTER))
+}
+
+/// One meaningful line of a streamed completion.
+#[derive(Debug, PartialEq)]
+enum StreamEvent {
+    Delta(String),
+    Done,
+}
+
+/// Parse one line of an SSE completion stream. None for blank lines,
+/// comments, other fields and chunks that carry no text (role headers,
+/// finish reasons).
+fn parse_stream_line(line: &str) -> Option<Result<StreamEvent, String>> {
+    let data = line.trim_end_matches(['\r', '\n']).strip_prefix("data:")?.trim();
+    if data.is_empty() {
+        return None;
+    }
+    if data == "[DONE]" {
+        return Some(Ok(StreamEvent::Done));
+    }
+    let value: serde_json::Value = match serde_json::from_str(data) {
+        Ok(value) => value,
+        Err(e) => return Some(Err(format!("Malformed stream chunk: {}", e))),
+    };
+    if let Some(message) = value["error"]["message"].as_str() {
+        return Some(Err(format!("Inference stream error: {}", message)));
+    }
+    value["choices"][0]["delta"]["content"]
+        .as_str()
+        .filter(|text| !text.is_empty())
+        .map(|text| Ok(StreamEvent::Delta(text.to_string())))
+}
+
+/// Send a streaming completion and forward its token deltas to `tx` until
+/// the stream ends or the receiver goes away. Servers that ignore `stream`
+/// and answer with a plain JSON body are forwarded as a single delta.
+async fn stream_completion(
+    config: &InferenceConfig,
+    request_body: &ChatCompletionRequest,
+    tx: &mpsc::UnboundedSender<Result<String, String>>,
+) -> Result<(), String> {
+    let (mut response, _permit) = post_completion(config, request_body).await?;
+
+    let is_sse = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if !is_sse {
         let body = response
             .text()
             .await
-            .unwrap_or_else(|_| "unable to read body".to_string());
-        return Err(format!("Inference API error ({}): {}", status, body));
+            .map_err(|e| format!("Failed to read response: {}", e))?;
+        let text = parse_completion(&body)?
+            .choices
+            .first()
+            .and_then(|c| c.message.content.clone())
+            .ok_or("No response choices returned")?;
+        let _ = tx.send(Ok(text));
+        return Ok(());
     }
 
-    response
-        .json()
+    // Chunks can split lines (and UTF-8 sequences), so only complete lines
+    // are decoded
+    let mut buffer: Vec<u8> = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
         .await
-        .map_err(|e| format!("Failed to parse response: {}", e))
+        .map_err(|e| format!("Failed to read stream: {}", e))?
+    {
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            match parse_stream_line(&String::from_utf8_lossy(&line)) {
+                Some(Ok(StreamEvent::Delta(text))) => {
+                    if tx.send(Ok(text)).is_err() {
+                        return Ok(());
+                    }
+                }
+                Some(Ok(StreamEvent::Done)) => return Ok(()),
+                Some(Err(e)) => return Err(e),
+                None => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parse a chat completion body. The strict OpenAI shape is tried first; if it
+/// fails or has no choices, fall back to a tolerant reading of common
+/// deviations from llama.cpp, LM Studio, vLLM and Ollama-style servers.
+fn parse_completion(body: &str) -> Result<ChatCompletionResponse, String> {
+    if let Ok(strict) = serde_json::from_str::<ChatCompletionResponse>(body) {
+        let usable = strict.choices.iter().any(|c| {
+            c.message.content.is_some()
+                || c.message.tool_calls.as_ref().is_some_and(|calls| !calls.is_empty())
+        });
+        if usable {
+            return Ok(strict);
+        }
+    }
+
+    let value: serde_json::Value =
+        serde_json::from_str(body).map_err(|e| format!("Failed to parse response: {}", e))?;
+    Ok(normalize_completion(&value))
+}
+
+/// Build a completion from a loosely-shaped JSON body. Handles content given as
+/// an array of parts, tool calls under `function_call` or with object
+/// arguments, completions-style `choices[].text`, and replies with no
+/// `choices` but text in a top-level field. May return zero choices.
+fn normalize_completion(value: &serde_json::Value) -> ChatCompletionResponse {
+    let mut choices: Vec<ChatChoice> = value["choices"]
+        .as_array()
+        .map(|arr| arr.iter().filter_map(normalize_choice).collect())
+        .unwrap_or_default();
+
+    if choices.is_empty() {
+        let top_level = ["message", "response", "output_text", "text", "content"]
+            .iter()
+            .find_map(|key| {
+                let field = &value[*key];
+                content_text(&field["content"]).or_else(|| content_text(field))
+            });
+        if let Some(content) = top_level {
+            choices.push(ChatChoice {
+                message: ChatResponseMessage {
+                    content: Some(content),
+                    tool_calls: None,
+                },
+                finish_reason: None,
+            });
+        }
+    }
+
+    ChatCompletionResponse { choices }
+}
+
+fn normalize_choice(choice: &serde_json::Value) -> Option<ChatChoice> {
+    let message = &choice["message"];
+    let content = content_text(&message["content"]).or_else(|| content_text(&choice["text"]));
+
+    let mut raw_calls: Vec<&serde_json::Value> = Vec::new();
+    for source in [&message["tool_calls"], &choice["tool_calls"]] {
+        if let Some(arr) = source.as_array() {
+            raw_calls.extend(arr);
+        }
+    }
+    if message["function_call"].is_object() {
+        raw_calls.push(&message["function_call"]);
+    }
+
+    let tool_calls: Vec<RawToolCall> = raw_calls
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, call)| {
+            let function = if call["function"].is_object() { &call["function"] } else { call };
+            let name = function["name"].as_str()?.to_string();
+            let arguments = match &function["arguments"] {
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Null => "{}".to_string(),
+                other => other.to_string(),
+            };
+            let id = call["id"]
+                .as_str()
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("call_{}", i));
+            Some(RawToolCall {
+                id,
+                function: RawFunctionCall { name, arguments },
+            })
+        })
+        .collect();
+
+    if content.is_none() && tool_calls.is_empty() {
+        return None;
+    }
+
+    Some(ChatChoice {
+        message: ChatResponseMessage {
+            content,
+            tool_calls: if tool_calls.is_empty() { None } else { Some(tool_calls) },
+        },
+        finish_reason: choice["finish_reason"].as_str().map(str::to_string),
+    })
+}
+
+/// Extract text from a content value that may be a string or an array of
+/// parts (`"..."` or `{"type": "text", "text": "..."}`).
+fn content_text(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Array(parts) => {
+            let text: Vec<&str> = parts
+                .iter()
+                .filter_map(|p| p.as_str().or_else(|| p["text"].as_str()))
+                .collect();
+            if text.is_empty() {
+                None
+            } else {
+                Some(text.join(""))
+            }
+        }
+        _ => None,
+    }
 }
 
 // ── Public API: plain text ──────────────────────────────────────────
@@ -273,26 +858,28 @@ pub async fn generate_response(
         .ok_or_else(|| "No response choices returned".to_string())
 }
 
-/// Generate a response for a shard interaction, using the shard's personality.
-pub async fn generate_shard_response(
-    api_key: &str,
-    api_url: &str,
-    model: &str,
-    personality: &str,
-    user_message: &str,
-    history: &[ChatMessage],
-) -> Result<String, String> {
-    let config = InferenceConfig {
-        api_key: api_key.to_string(),
-        api_url: api_url.to_string(),
-        model: model.to_string(),
-        ..Default::default()
+/// Like `generate_response`, but yields the reply as token deltas while the
+/// provider streams it. An error ends the stream.
+pub fn generate_response_stream(
+    config: &InferenceConfig,
+    system_prompt: &str,
+    conversation: &[ChatMessage],
+) -> impl Stream<Item = Result<String, String>> + Send + 'static {
+    let mut messages = vec![ChatMessage::text("system", system_prompt)];
+    messages.extend_from_slice(conversation);
+    let request_body = ChatCompletionRequest {
+        stream: true,
+        ..completion_request(config, messages, None)
     };
 
-    let mut conversation = history.to_vec();
-    conversation.push(ChatMessage::text("user", user_message));
-
-    generate_response(&config, personality, &conversation).await
+    let (tx, rx) = mpsc::unbounded_channel();
+    let config = config.clone();
+    tokio::spawn(async move {
+        if let Err(e) = stream_completion(&config, &request_body, &tx).await {
+            let _ = tx.send(Err(e));
+        }
+    });
+    UnboundedReceiverStream::new(rx)
 }
 
 // ── Public API: tool calling ────────────────────────────────────────
@@ -306,6 +893,10 @@ pub async fn generate_with_tools(
     conversation: &[ChatMessage],
     tools: &[ToolDefinition],
 ) -> Result<InferenceResult, String> {
+    if config.tool_protocol == ToolProtocol::PromptEmbedded && !tools.is_empty() {
+        return generate_with_embedded_tools(config, system_prompt, conversation, tools).await;
+    }
+
     let mut messages = vec![ChatMessage::text("system", system_prompt)];
     messages.extend_from_slice(conversation);
 
@@ -350,6 +941,138 @@ pub async fn generate_with_tools(
     Ok(InferenceResult::Text { content })
 }
 
+// ── Prompt-embedded tool calling ────────────────────────────────────
+
+/// Fence label for tool calls in `ToolProtocol::PromptEmbedded` mode.
+const TOOL_CALL_FENCE: &str = "tool_call";
+
+/// `generate_with_tools` for models without native function calling: tools
+/// are described in the system prompt, earlier tool traffic is replayed as
+/// plain text, and fenced tool-call blocks in the reply become `ToolCall`s.
+async fn generate_with_embedded_tools(
+    config: &InferenceConfig,
+    system_prompt: &str,
+    conversation: &[ChatMessage],
+    tools: &[ToolDefinition],
+) -> Result<InferenceResult, String> {
+    let system = format!("{}\n\n{}", system_prompt, embedded_tool_instructions(tools));
+    let mut messages = vec![ChatMessage::text("system", &system)];
+    messages.extend(conversation.iter().map(as_plain_text));
+
+    let completion = send_completion(config, messages, None).await?;
+    let content = completion
+        .choices
+        .first()
+        .ok_or("No response choices returned")?
+        .message
+        .content
+        .clone()
+        .unwrap_or_default();
+
+    let calls = parse_embedded_tool_calls(&content);
+    if calls.is_empty() {
+        Ok(InferenceResult::Text { content })
+    } else {
+        Ok(InferenceResult::ToolCalls { calls })
+    }
+}
+
+/// System-prompt section listing the tools and the expected call format.
+fn embedded_tool_instructions(tools: &[ToolDefinition]) -> String {
+    let mut out = String::from("## Tools\nYou can call these tools:\n");
+    for tool in tools {
+        out.push_str(&format!(
+            "- {}: {}\n  parameters: {}\n",
+            tool.function.name, tool.function.description, tool.function.parameters
+        ));
+    }
+    out.push_str(&format!(
+        "\nTo call a tool, reply with one block per call and nothing else:\n\
+         ```{fence}\n{{\"name\": \"<tool name>\", \"arguments\": {{...}}}}\n```\n\
+         Tool results come back in the next user message. When you are done, \
+         answer in plain text without any {fence} block.",
+        fence = TOOL_CALL_FENCE
+    ));
+    out
+}
+
+/// Replay native tool messages as text for a model that doesn't accept them.
+fn as_plain_text(message: &ChatMessage) -> ChatMessage {
+    match (message.role.as_str(), &message.tool_calls) {
+        ("assistant", Some(calls)) => {
+            let blocks: Vec<String> = calls
+                .iter()
+                .map(|c| {
+                    let arguments: serde_json::Value =
+                        serde_json::from_str(&c.function.arguments).unwrap_or_default();
+                    let call = serde_json::json!({"name": c.function.name, "arguments": arguments});
+                    format!("```{}\n{}\n```", TOOL_CALL_FENCE, call)
+                })
+                .collect();
+            ChatMessage::text("assistant", &blocks.join("\n"))
+        }
+        ("tool", _) => ChatMessage::text(
+            "user",
+            &format!(
+                "Result of {}:\n{}",
+                message.name.as_deref().unwrap_or("tool"),
+                message.content.as_deref().unwrap_or_default()
+            ),
+        ),
+        _ => message.clone(),
+    }
+}
+
+/// Pull tool calls out of a text reply. Accepts ```tool_call fences (the
+/// format we ask for), `<tool_call>` tags (common in local chat templates),
+/// and ```json fences holding a `name` + `arguments` object.
+pub fn parse_embedded_tool_calls(text: &str) -> Vec<ToolCall> {
+    let mut bodies = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("```") {
+        let after = &rest[start + 3..];
+        let Some(end) = after.find("```") else { break };
+        let block = &after[..end];
+        let (label, body) = block.split_once('\n').unwrap_or(("", block));
+        match label.trim() {
+            TOOL_CALL_FENCE => bodies.push((body, false)),
+            "json" => bodies.push((body, true)),
+            _ => {}
+        }
+        rest = &after[end + 3..];
+    }
+    let mut rest = text;
+    while let Some(start) = rest.find("<tool_call>") {
+        let after = &rest[start + "<tool_call>".len()..];
+        let Some(end) = after.find("</tool_call>") else { break };
+        bodies.push((&after[..end], false));
+        rest = &after[end + "</tool_call>".len()..];
+    }
+
+    bodies
+        .into_iter()
+        .filter_map(|(body, strict)| {
+            let value: serde_json::Value = serde_json::from_str(body.trim()).ok()?;
+            // A plain JSON block is only a call if it has the full call shape
+            if strict && value.get("arguments").is_none() {
+                return None;
+            }
+            let name = value.get("name")?.as_str()?.to_string();
+            let arguments = match value.get("arguments") {
+                // Some models double-encode the arguments as a JSON string
+                Some(serde_json::Value::String(raw)) => serde_json::from_str(raw).ok()?,
+                Some(args) => args.clone(),
+                None => serde_json::json!({}),
+            };
+            Some(ToolCall {
+                id: format!("call_{}", uuid::Uuid::new_v4().simple()),
+                name,
+                arguments,
+            })
+        })
+        .collect()
+}
+
 /// Generate embeddings for a batch of texts. Returns vectors in input order.
 pub async fn embed_texts(
     config: &InferenceConfig,
@@ -364,18 +1087,17 @@ pub async fn embed_texts(
     let request_body = EmbeddingRequest {
         model,
         input: inputs.to_vec(),
+        user: request_user(config),
     };
 
     let client = Client::new();
-    let mut request = client
+    let request = client
         .post(&endpoint)
         .header("Content-Type", "application/json");
 
-    if !config.api_key.is_empty() {
-        request = request.header("Authorization", format!("Bearer {}", config.api_key));
-    }
-
-    let response = request
+    // Embeddings share the completion budget; held until the body is read
+    let _permit = acquire_permit(config).await?;
+    let response = authorize(request, config)
         .json(&request_body)
         .send()
         .await
@@ -419,7 +1141,8 @@ fn embedding_url_from_chat_url(api_url: &str) -> String {
     format!("{}/embeddings", api_url.trim_end_matches('/'))
 }
 
-fn embedding_model_for(chat_model: &str) -> String {
+/// Embedding model to request for a given chat model.
+pub fn embedding_model_for(chat_model: &str) -> String {
     // OpenAI chat models are not embedding models.
     if chat_model.starts_with("gpt-") {
         "text-embedding-3-small".to_string()
@@ -447,6 +1170,11 @@ pub fn shard_tool_definitions() -> Vec<ToolDefinition> {
                     "code": {
                         "type": "string",
                         "description": "The code to evaluate"
+                    },
+                    "timeout_secs": {
+                        "type": "integer",
+                        "description": "Timeout in seconds (default 30, max 120)",
+                        "default": 30
                     }
                 },
                 "required": ["language", "code"]
@@ -498,6 +1226,24 @@ pub fn shard_tool_definitions() -> Vec<ToolDefinition> {
                 "required": ["path", "content"]
             }),
         ),
+        ToolDefinition::new(
+            "search_files",
+            "Search files in the shard's workspace for lines matching a regex. Returns path:line:text results (at most 200).",
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "pattern": {
+                        "type": "string",
+                        "description": "Regular expression to match against each line"
+                    },
+                    "glob": {
+                        "type": "string",
+                        "description": "Only search files matching this glob, e.g. *.py or src/**/*.rs"
+                    }
+                },
+                "required": ["pattern"]
+            }),
+        ),
         ToolDefinition::new(
             "shell_exec",
             "Execute a shell command in a sandboxed environment. Returns stdout and stderr.",
@@ -517,12 +1263,143 @@ pub fn shard_tool_definitions() -> Vec<ToolDefinition> {
                 "required": ["command"]
             }),
         ),
+        ToolDefinition::new(
+            "brainstorm",
+            "Generate several distinct ideas on a topic in one divergent, high-temperature pass. Returns a numbered list.",
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "topic": {
+                        "type": "string",
+                        "description": "What to brainstorm about"
+                    },
+                    "count": {
+                        "type": "integer",
+                        "description": "Number of ideas (default 5, max 10)",
+                        "default": 5
+                    }
+                },
+                "required": ["topic"]
+            }),
+        ),
+        ToolDefinition::new(
+            "recall_lessons",
+            "Look up lessons distilled from your own past tasks, for when the prior lessons you were given don't cover the problem. Returns at most 5.",
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "What you need to remember, e.g. the problem you are stuck on"
+                    },
+                    "task_type": {
+                        "type": "string",
+                        "description": "Kind of task: debug, writing, analysis, coding or general (default general)",
+                        "default": "general"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Number of lessons (default 3, max 5)",
+                        "default": 3
+                    }
+                },
+                "required": ["query"]
+            }),
+        ),
+        ToolDefinition::new(
+            "ask_user",
+            "Ask the user for information you need and cannot find yourself, such as a missing file path. Execution pauses until they answer; the answer is returned as this tool's result.",
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "question": {
+                        "type": "string",
+                        "description": "The question to ask"
+                    }
+                },
+                "required": ["question"]
+            }),
+        ),
     ]
 }
 
+/// Rewrite the `code_eval` tool's `language` enum to `languages`.
+pub fn with_code_languages(mut tools: Vec<ToolDefinition>, languages: &[String]) -> Vec<ToolDefinition> {
+    for tool in tools.iter_mut().filter(|t| t.function.name == "code_eval") {
+        tool.function.parameters["properties"]["language"]["enum"] = serde_json::json!(languages);
+        tool.function.description = format!(
+            "Evaluate a code snippet and return the output. Supports: {}.",
+            languages.join(", ")
+        );
+    }
+    tools
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::{mock_inference, serve, text_reply};
+
+    #[test]
+    fn parses_prompt_embedded_tool_calls() {
+        let reply = "I'll check the workspace first.\n\
+            ```tool_call\n{\"name\": \"file_read\", \"arguments\": {\"path\": \"notes.txt\"}}\n```\n\
+            <tool_call>{\"name\": \"code_eval\", \"arguments\": \"{\\\"language\\\": \\\"python\\\", \\\"code\\\": \\\"print(1)\\\"}\"}</tool_call>\n\
+            ```json\n{\"name\": \"Ada\", \"age\": 36}\n```\n\
+            ```tool_call\nnot json\n```";
+        let calls = parse_embedded_tool_calls(reply);
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].name, "file_read");
+        assert_eq!(calls[0].arguments["path"], "notes.txt");
+        assert_eq!(calls[1].name, "code_eval");
+        assert_eq!(calls[1].arguments["code"], "print(1)");
+        assert_ne!(calls[0].id, calls[1].id);
+
+        assert!(parse_embedded_tool_calls("The answer is 391.").is_empty());
+    }
+
+    #[tokio::test]
+    async fn prompt_embedded_mode_sends_no_tools_field() {
+        let requests = Arc::new(std::sync::Mutex::new(Vec::<serde_json::Value>::new()));
+        let seen = requests.clone();
+        let url = mock_inference(move |body| {
+            seen.lock().unwrap().push(body);
+            text_reply("```tool_call\n{\"name\": \"shell_exec\", \"arguments\": {\"command\": \"ls\"}}\n```")
+        })
+        .await;
+
+        let config = InferenceConfig {
+            api_url: url,
+            tool_protocol: ToolProtocol::PromptEmbedded,
+            ..Default::default()
+        };
+        let earlier = ToolCall {
+            id: "call_1".to_string(),
+            name: "file_read".to_string(),
+            arguments: serde_json::json!({"path": "a.txt"}),
+        };
+        let conversation = vec![
+            ChatMessage::text("user", "list files"),
+            ChatMessage::assistant_tool_calls(std::slice::from_ref(&earlier)),
+            ChatMessage::tool_result("call_1", "file_read", "contents"),
+        ];
+        let result = generate_with_tools(&config, "system", &conversation, &shard_tool_definitions())
+            .await
+            .unwrap();
+        let InferenceResult::ToolCalls { calls } = result else {
+            panic!("expected tool calls");
+        };
+        assert_eq!(calls[0].name, "shell_exec");
+        assert_eq!(calls[0].arguments["command"], "ls");
+
+        let sent = requests.lock().unwrap()[0].clone();
+        assert!(sent.get("tools").is_none());
+        let messages = sent["messages"].as_array().unwrap();
+        assert!(messages[0]["content"].as_str().unwrap().contains("- shell_exec:"));
+        assert!(messages.iter().all(|m| m["role"] != "tool" && m.get("tool_calls").is_none()));
+        assert!(messages[2]["content"].as_str().unwrap().contains("```tool_call"));
+        assert_eq!(messages[3]["content"], "Result of file_read:\ncontents");
+    }
 
     #[test]
     fn inference_config_defaults() {
@@ -534,6 +1411,50 @@ mod tests {
         assert!((cfg.temperature - 0.7).abs() < f64::EPSILON);
     }
 
+    #[test]
+    fn reasoning_fields_only_for_reasoning_models() {
+        let mut cfg = InferenceConfig {
+            model: "o3-mini".to_string(),
+            reasoning_effort: Some("high".to_string()),
+            max_reasoning_tokens: Some(4096),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(completion_request(&cfg, vec![], None)).unwrap();
+        assert_eq!(json["reasoning_effort"], "high");
+        assert_eq!(json["reasoning"]["max_tokens"], 4096);
+
+        cfg.model = "gpt-4o-mini".to_string();
+        let json = serde_json::to_value(completion_request(&cfg, vec![], None)).unwrap();
+        assert!(json.get("reasoning_effort").is_none());
+        assert!(json.get("reasoning").is_none());
+
+        assert!(supports_reasoning("openai/o1"));
+        assert!(supports_reasoning("deepseek-r1:14b"));
+        assert!(!supports_reasoning("llama3.2"));
+        assert!(!supports_reasoning("o1x"));
+    }
+
+    #[test]
+    fn user_field_only_sent_when_set() {
+        let mut cfg = InferenceConfig::default();
+        let json = serde_json::to_value(completion_request(&cfg, vec![], None)).unwrap();
+        assert!(json.get("user").is_none());
+
+        cfg.user = Some("  ".to_string());
+        let json = serde_json::to_value(completion_request(&cfg, vec![], None)).unwrap();
+        assert!(json.get("user").is_none());
+
+        cfg.user = Some("shard-123".to_string());
+        let json = serde_json::to_value(completion_request(&cfg, vec![], None)).unwrap();
+        assert_eq!(json["user"], "shard-123");
+        let embedding = EmbeddingRequest {
+            model: "text-embedding-3-small".to_string(),
+            input: vec![],
+            user: request_user(&cfg),
+        };
+        assert_eq!(serde_json::to_value(embedding).unwrap()["user"], "shard-123");
+    }
+
     #[test]
     fn ollama_config_has_empty_key() {
         let cfg = InferenceConfig {
@@ -586,6 +1507,26 @@ mod tests {
         assert_eq!(refs[0].function.name, "http_fetch");
     }
 
+    #[test]
+    fn duplicate_and_blank_tool_call_ids_are_made_unique() {
+        let call = |id: &str| ToolCall {
+            id: id.to_string(),
+            name: "file_read".to_string(),
+            arguments: serde_json::json!({"path": "a.txt"}),
+        };
+        let mut calls = vec![call("call_2"), call("call_2"), call(""), call("call_2")];
+        let msg = ChatMessage::assistant_tool_calls(&calls);
+        let ids: Vec<String> = msg.tool_calls.unwrap().into_iter().map(|r| r.id).collect();
+        assert_eq!(ids, ["call_2", "call_2_2", "call_3", "call_4"]);
+
+        // The calls themselves get the same ids, so tool results line up
+        assign_unique_tool_call_ids(&mut calls);
+        let assigned: Vec<&str> = calls.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(assigned, ids);
+        assign_unique_tool_call_ids(&mut calls);
+        assert_eq!(calls.iter().map(|c| c.id.as_str()).collect::<Vec<_>>(), ids);
+    }
+
     #[test]
     fn tool_definition_serializes_correctly() {
         let tool = ToolDefinition::new(
@@ -602,14 +1543,18 @@ mod tests {
     #[test]
     fn shard_tools_are_defined() {
         let tools = shard_tool_definitions();
-        assert_eq!(tools.len(), 5);
+        assert_eq!(tools.len(), 9);
 
         let names: Vec<&str> = tools.iter().map(|t| t.function.name.as_str()).collect();
         assert!(names.contains(&"code_eval"));
         assert!(names.contains(&"http_fetch"));
         assert!(names.contains(&"file_read"));
         assert!(names.contains(&"file_write"));
+        assert!(names.contains(&"search_files"));
         assert!(names.contains(&"shell_exec"));
+        assert!(names.contains(&"brainstorm"));
+        assert!(names.contains(&"recall_lessons"));
+        assert!(names.contains(&"ask_user"));
     }
 
     #[test]
@@ -667,4 +1612,333 @@ mod tests {
         assert_eq!(embedding_model_for("gpt-4o-mini"), "text-embedding-3-small");
         assert_eq!(embedding_model_for("nomic-embed-text"), "nomic-embed-text");
     }
+
+    #[test]
+    fn parse_completion_accepts_content_parts() {
+        // LM Studio / vLLM multimodal-style content array
+        let body = r#"{
+            "id": "chatcmpl-1",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": [{"type": "text", "text": "Hello, "}, {"type": "text", "text": "keeper."}]
+                },
+                "finish_reason": "stop"
+            }]
+        }"#;
+        let completion = parse_completion(body).unwrap();
+        assert_eq!(
+            completion.choices[0].message.content.as_deref(),
+            Some("Hello, keeper.")
+        );
+    }
+
+    #[test]
+    fn parse_completion_accepts_legacy_function_call_with_object_arguments() {
+        // llama.cpp-style legacy function_call with arguments as an object
+        let body = r#"{
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "function_call": {"name": "file_read", "arguments": {"path": "notes.txt"}}
+                },
+                "finish_reason": "function_call"
+            }]
+        }"#;
+        let completion = parse_completion(body).unwrap();
+        let calls = completion.choices[0].message.tool_calls.as_ref().unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id, "call_0");
+        assert_eq!(calls[0].function.name, "file_read");
+        let args: serde_json::Value = serde_json::from_str(&calls[0].function.arguments).unwrap();
+        assert_eq!(args["path"], "notes.txt");
+    }
+
+    #[test]
+    fn parse_completion_falls_back_to_top_level_text() {
+        // Ollama native /api/chat and /api/generate shapes
+        let chat = r#"{"model": "llama3.2", "message": {"role": "assistant", "content": "hi"}, "done": true}"#;
+        let completion = parse_completion(chat).unwrap();
+        assert_eq!(completion.choices[0].message.content.as_deref(), Some("hi"));
+
+        let generate = r#"{"model": "llama3.2", "response": "there", "choices": []}"#;
+        let completion = parse_completion(generate).unwrap();
+        assert_eq!(completion.choices[0].message.content.as_deref(), Some("there"));
+
+        // Nothing usable still yields zero choices for the caller to report
+        let empty = parse_completion(r#"{"choices": []}"#).unwrap();
+        assert!(empty.choices.is_empty());
+        assert!(parse_completion("not json").is_err());
+    }
+
+    #[test]
+    fn image_message_serializes_content_parts() {
+        let msg = ChatMessage::user_image("see attached", "data:image/png;base64,AAAA");
+        let json = serde_json::to_value(&msg).unwrap();
+        assert_eq!(json["role"], "user");
+        assert_eq!(json["content"][0]["type"], "text");
+        assert_eq!(json["content"][1]["type"], "image_url");
+        assert_eq!(json["content"][1]["image_url"]["url"], "data:image/png;base64,AAAA");
+
+        let text = serde_json::to_value(ChatMessage::text("user", "hi")).unwrap();
+        assert_eq!(text["content"], "hi");
+
+        // Stored conversations read back with their images intact
+        let back: ChatMessage = serde_json::from_value(json).unwrap();
+        assert_eq!(back.content, None);
+        assert_eq!(back.parts, msg.parts);
+        let back: ChatMessage = serde_json::from_value(text).unwrap();
+        assert_eq!((back.content.as_deref(), back.parts), (Some("hi"), None));
+    }
+
+    #[tokio::test]
+    async fn limiter_caps_concurrent_completions() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (current, max) = (in_flight.clone(), peak.clone());
+        let app = axum::Router::new().route(
+            "/v1/chat/completions",
+            axum::routing::post(move || {
+                let (current, max) = (current.clone(), max.clone());
+                async move {
+                    let now = current.fetch_add(1, Ordering::SeqCst) + 1;
+                    max.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                    current.fetch_sub(1, Ordering::SeqCst);
+                    axum::Json(serde_json::json!({
+                        "choices": [{"message": {"role": "assistant", "content": "ok"}}]
+                    }))
+                }
+            }),
+        );
+        let url = format!("{}/v1/chat/completions", serve(app).await);
+
+        let config = InferenceConfig {
+            api_url: url,
+            limiter: Some(InferenceLimiter::new(2)),
+            ..Default::default()
+        };
+        let calls = (0..6).map(|_| {
+            let config = config.clone();
+            tokio::spawn(async move {
+                generate_response(&config, "system", &[ChatMessage::text("user", "hi")]).await
+            })
+        });
+        for call in calls.collect::<Vec<_>>() {
+            assert_eq!(call.await.unwrap().unwrap(), "ok");
+        }
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(config.limiter.unwrap().available(), 2);
+    }
+
+    /// A completion server answering with `statuses` in turn (the last one
+    /// repeats), each non-200 carrying `retry_after` when given. Returns
+    /// its URL and the number of requests served.
+    async fn flaky_server(
+        statuses: Vec<u16>,
+        retry_after: Option<&'static str>,
+    ) -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let served = Arc::new(AtomicUsize::new(0));
+        let counter = served.clone();
+        let statuses = Arc::new(statuses);
+        let app = axum::Router::new().route(
+            "/v1/chat/completions",
+            axum::routing::post(move || {
+                let (counter, statuses) = (counter.clone(), statuses.clone());
+                async move {
+                    let idx = counter.fetch_add(1, Ordering::SeqCst).min(statuses.len() - 1);
+                    let status = axum::http::StatusCode::from_u16(statuses[idx]).unwrap();
+                    let mut headers = axum::http::HeaderMap::new();
+                    if let Some(secs) = retry_after.filter(|_| !status.is_success()) {
+                        headers.insert("retry-after", secs.parse().unwrap());
+                    }
+                    let body = serde_json::json!({
+                        "choices": [{"message": {"role": "assistant", "content": "ok"}}]
+                    });
+                    (status, headers, axum::Json(body))
+                }
+            }),
+        );
+        (format!("{}/v1/chat/completions", serve(app).await), served)
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors_with_backoff() {
+        use std::sync::atomic::Ordering;
+
+        let (url, served) = flaky_server(vec![503, 502, 200], None).await;
+        let config = InferenceConfig {
+            api_url: url,
+            initial_backoff_ms: 10,
+            ..Default::default()
+        };
+        let reply = generate_response(&config, "system", &[ChatMessage::text("user", "hi")]).await;
+        assert_eq!(reply.unwrap(), "ok");
+        assert_eq!(served.load(Ordering::SeqCst), 3);
+
+        let (url, served) = flaky_server(vec![500], None).await;
+        let config = InferenceConfig { api_url: url, initial_backoff_ms: 1, ..config };
+        let err = generate_response(&config, "system", &[ChatMessage::text("user", "hi")]).await;
+        assert!(err.unwrap_err().contains("500"));
+        assert_eq!(served.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        use std::sync::atomic::Ordering;
+
+        for status in [400, 401] {
+            let (url, served) = flaky_server(vec![status, 200], None).await;
+            let config = InferenceConfig { api_url: url, ..Default::default() };
+            let reply = generate_response(&config, "system", &[ChatMessage::text("user", "hi")]).await;
+            assert!(reply.is_err());
+            assert_eq!(served.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limit_honors_retry_after() {
+        use std::sync::atomic::Ordering;
+
+        // A backoff this long would time the test out; Retry-After: 0 wins
+        let (url, served) = flaky_server(vec![429, 200], Some("0")).await;
+        let config = InferenceConfig {
+            api_url: url,
+            initial_backoff_ms: 60_000,
+            ..Default::default()
+        };
+        let reply = tokio::time::timeout(
+            Duration::from_secs(5),
+            generate_response(&config, "system", &[ChatMessage::text("user", "hi")]),
+        )
+        .await
+        .expect("Retry-After was not honored");
+        assert_eq!(reply.unwrap(), "ok");
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn retry_wait_releases_the_limiter() {
+        use std::sync::atomic::Ordering;
+
+        // The first request is told to come back in a second; others succeed
+        let (url, served) = flaky_server(vec![429, 200], Some("1")).await;
+        let config = InferenceConfig {
+            api_url: url,
+            limiter: Some(InferenceLimiter::new(1)),
+            ..Default::default()
+        };
+        let waiting = {
+            let config = config.clone();
+            tokio::spawn(async move {
+                generate_response(&config, "system", &[ChatMessage::text("user", "hi")]).await
+            })
+        };
+        while served.load(Ordering::SeqCst) == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let reply = tokio::time::timeout(
+            Duration::from_millis(500),
+            generate_response(&config, "system", &[ChatMessage::text("user", "hi")]),
+        )
+        .await
+        .expect("the permit was held through the Retry-After wait");
+        assert_eq!(reply.unwrap(), "ok");
+        assert_eq!(waiting.await.unwrap().unwrap(), "ok");
+    }
+
+    #[tokio::test]
+    async fn embeddings_wait_for_the_limiter() {
+        let limiter = InferenceLimiter::new(1);
+        let config = InferenceConfig {
+            api_url: "http://127.0.0.1:9/v1/chat/completions".to_string(),
+            limiter: Some(limiter.clone()),
+            ..Default::default()
+        };
+        let _held = limiter.0.acquire().await.unwrap();
+        let embedded = tokio::time::timeout(
+            Duration::from_millis(100),
+            embed_texts(&config, &["text".to_string()]),
+        )
+        .await;
+        assert!(embedded.is_err(), "embedding request ignored the limiter");
+    }
+
+    #[tokio::test]
+    async fn configured_headers_are_attached() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::<axum::http::HeaderMap>::new()));
+        let recorded = seen.clone();
+        let app = axum::Router::new().route(
+            "/v1/chat/completions",
+            axum::routing::post(move |headers: axum::http::HeaderMap| {
+                let recorded = recorded.clone();
+                async move {
+                    recorded.lock().unwrap().push(headers);
+                    axum::Json(serde_json::json!({
+                        "choices": [{"message": {"role": "assistant", "content": "ok"}}]
+                    }))
+                }
+            }),
+        );
+        let url = format!("{}/v1/chat/completions", serve(app).await);
+
+        let config = InferenceConfig {
+            api_url: url,
+            api_key: "azure-secret".into(),
+            auth_style: AuthStyle::ApiKey,
+            headers: HashMap::from([
+                ("HTTP-Referer".to_string(), "https://siphon.example".to_string()),
+                ("X-Title".to_string(), "Siphon".to_string()),
+            ]),
+            ..Default::default()
+        };
+        generate_response(&config, "system", &[ChatMessage::text("user", "hi")]).await.unwrap();
+
+        let seen = seen.lock().unwrap();
+        let headers = &seen[0];
+        assert_eq!(headers["http-referer"], "https://siphon.example");
+        assert_eq!(headers["x-title"], "Siphon");
+        assert_eq!(headers["api-key"], "azure-secret");
+        assert!(headers.get("authorization").is_none());
+    }
+
+    #[test]
+    fn secret_headers_are_redacted_in_logs() {
+        let headers = HashMap::from([
+            ("X-Title".to_string(), "Siphon".to_string()),
+            ("api-key".to_string(), "hunter2".to_string()),
+            ("X-Upstream".to_string(), "Bearer abc".to_string()),
+        ]);
+        assert_eq!(
+            describe_headers(&headers),
+            "X-Title: Siphon, X-Upstream: [redacted], api-key: [redacted]"
+        );
+    }
+
+    #[test]
+    fn backoff_doubles_per_attempt() {
+        let delays: Vec<u128> = (0..3).map(|a| backoff_delay(250, a).as_millis()).collect();
+        assert_eq!(delays, vec![250, 500, 1000]);
+    }
+
+    #[test]
+    fn parses_streamed_completion_lines() {
+        let chunk = r#"data: {"choices":[{"delta":{"content":"Hel"}}]}"#;
+        assert_eq!(parse_stream_line(chunk), Some(Ok(StreamEvent::Delta("Hel".into()))));
+        assert_eq!(parse_stream_line("data: [DONE]\r\n"), Some(Ok(StreamEvent::Done)));
+        // Role headers, keep-alive comments and blank lines carry no text
+        assert_eq!(parse_stream_line(r#"data: {"choices":[{"delta":{"role":"assistant"}}]}"#), None);
+        assert_eq!(parse_stream_line(": keep-alive"), None);
+        assert_eq!(parse_stream_line(""), None);
+        assert!(matches!(parse_stream_line(r#"data: {"error":{"message":"overloaded"}}"#), Some(Err(e)) if e.contains("overloaded")));
+        assert!(matches!(parse_stream_line("data: {not json"), Some(Err(_))));
+    }
 }
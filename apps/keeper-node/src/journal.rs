@@ -0,0 +1,117 @@
+use crate::db::{self, JournalEntry};
+use crate::inference::{self, ChatMessage, InferenceConfig};
+use crate::shard::Shard;
+
+/// Number of recent lessons fed into a reflection prompt.
+const REFLECTION_LESSONS: u32 = 8;
+
+/// Longest journal entry kept, in characters.
+const MAX_ENTRY_CHARS: usize = 1200;
+
+/// Whether a reflection is due after `tasks_completed` executions.
+/// `every == 0` disables automatic journaling.
+pub fn due(tasks_completed: u32, every: u32) -> bool {
+    every > 0 && tasks_completed > 0 && tasks_completed.is_multiple_of(every)
+}
+
+/// Whether inference looks usable: either an API key is set or the endpoint
+/// isn't the hosted OpenAI default (local providers need no key).
+pub fn inference_available(config: &InferenceConfig) -> bool {
+    !config.api_key.is_empty() || !config.api_url.contains("api.openai.com")
+}
+
+/// Build the reflection request from the shard's recent lessons.
+fn reflection_prompt(lessons: &[db::TaskLesson]) -> String {
+    let mut prompt = String::from(
+        "Write a short first-person journal entry (3-5 sentences) reflecting on your recent work \
+         for your keeper: what went well, what went wrong, and what you will do differently. \
+         Do not use lists or headings.\n\nRecent tasks:\n",
+    );
+    if lessons.is_empty() {
+        prompt.push_str("- (no recorded lessons yet)\n");
+    }
+    for lesson in lessons {
+        prompt.push_str(&format!(
+            "- [{}] {} — {}\n",
+            if lesson.success { "success" } else { "failed" },
+            lesson.goal,
+            lesson.outcome
+        ));
+    }
+    prompt
+}
+
+/// Ask the shard to reflect on its recent lessons and store the entry.
+pub async fn write_entry(
+    data_dir: &str,
+    shard: &Shard,
+    inference_config: &InferenceConfig,
+) -> Result<JournalEntry, String> {
+    if !inference_available(inference_config) {
+        return Err("Inference is not configured".to_string());
+    }
+
+    let lessons = db::get_recent_task_lessons(data_dir, &shard.id, REFLECTION_LESSONS)
+        .map_err(|e| format!("Failed to read lessons: {}", e))?;
+    let conversation = vec![ChatMessage::text("user", &reflection_prompt(&lessons))];
+
+    let response = inference::generate_response(inference_config, &shard.personality, &conversation).await?;
+    let content: String = response.trim().chars().take(MAX_ENTRY_CHARS).collect();
+    if content.is_empty() {
+        return Err("Inference returned an empty journal entry".to_string());
+    }
+
+    db::insert_journal_entry(data_dir, &shard.id, &content, shard.tasks_completed)
+        .map_err(|e| format!("Failed to store journal entry: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{mock_inference, text_reply};
+
+    #[test]
+    fn due_on_counter_multiples() {
+        assert!(!due(0, 5));
+        assert!(!due(4, 5));
+        assert!(due(5, 5));
+        assert!(due(10, 5));
+        assert!(!due(5, 0));
+    }
+
+    #[tokio::test]
+    async fn journal_entry_round_trip() {
+        let url = mock_inference(|_| text_reply("  Today I learned to read the error first.  ")).await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        db::init_db(&data_dir).unwrap();
+        let mut shard = Shard::spawn(None);
+        shard.tasks_completed = 5;
+        db::insert_shard(&data_dir, &shard).unwrap();
+
+        let config = InferenceConfig {
+            api_url: url,
+            ..Default::default()
+        };
+        let entry = write_entry(&data_dir, &shard, &config).await.unwrap();
+        assert_eq!(entry.content, "Today I learned to read the error first.");
+
+        let entries = db::get_journal_entries(&data_dir, &shard.id, 10).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, entry.id);
+        assert_eq!(entries[0].tasks_completed, 5);
+        assert!(db::get_journal_entries(&data_dir, "other", 10).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn skipped_without_inference() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        db::init_db(&data_dir).unwrap();
+        let shard = Shard::spawn(None);
+
+        let err = write_entry(&data_dir, &shard, &InferenceConfig::default()).await.unwrap_err();
+        assert!(err.contains("not configured"));
+    }
+}
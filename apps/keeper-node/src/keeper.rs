@@ -2,14 +2,19 @@ use libp2p::futures::StreamExt;
 use libp2p::swarm::SwarmEvent;
 use libp2p::Swarm;
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use crate::chain;
 use crate::config::Config;
 use crate::db;
+use crate::dht;
+use crate::drift::{self, DriftAction, DriftMessage, DriftState};
 use crate::gossip;
 use crate::node::KeeperBehaviour;
-use crate::shard::Shard;
+use crate::reputation::{self, Duty, ReputationWarning};
+use crate::shard::{Shard, DAY_MS};
+use crate::shard_cache::ShardCache;
 
 /// Interval between keeper heartbeats broadcast to the network.
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
@@ -17,6 +22,15 @@ const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
 /// Interval between liquidation checks (1 hour).
 const LIQUIDATION_CHECK_INTERVAL: Duration = Duration::from_secs(3600);
 
+/// Interval between checks for idle wild shards to offer for drift.
+const DRIFT_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Interval between daily action rollups (1 hour).
+const ACTION_ROLLUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Interval between idle decay passes over hosted shards (1 hour).
+const DECAY_INTERVAL: Duration = Duration::from_secs(3600);
+
 /// State of the keeper node, tracking hosted shards and reputation.
 pub struct KeeperState {
     pub config: Config,
@@ -24,16 +38,31 @@ pub struct KeeperState {
     pub reputation: u64,
     pub last_heartbeat: Instant,
     pub started_at: Instant,
+    pub drift: DriftState,
+    /// Shard records this keeper serves on the DHT: id -> `last_interaction`
+    /// of the published copy
+    pub dht_published: HashMap<String, u64>,
+    /// Whether the current low-reputation spell has been gossiped
+    reputation_warned: bool,
+    /// Gossip messages waiting for peers to publish to
+    publish_queue: gossip::PublishQueue,
+    /// The API's shard cache, invalidated by the keeper's own shard writes
+    shard_cache: Arc<ShardCache>,
 }
 
 impl KeeperState {
-    pub fn new(config: Config) -> Self {
+    pub fn new(config: Config, shard_cache: Arc<ShardCache>) -> Self {
         Self {
-            config,
             hosted_shards: HashMap::new(),
-            reputation: 100,
+            reputation: reputation::reputation(&config.data_dir).unwrap_or(reputation::STARTING_REPUTATION),
             last_heartbeat: Instant::now(),
             started_at: Instant::now(),
+            drift: DriftState::default(),
+            dht_published: HashMap::new(),
+            reputation_warned: false,
+            publish_queue: gossip::PublishQueue::new(config.publish_queue_capacity),
+            shard_cache,
+            config,
         }
     }
 
@@ -80,17 +109,54 @@ impl KeeperState {
             // Sync from SQLite to include shards created via HTTP API
             self.sync_from_db();
 
-            let _ = gossip::publish_heartbeat(
+            if let Err(e) = gossip::publish_heartbeat(
                 swarm,
+                &mut self.publish_queue,
                 self.hosted_shards.len(),
                 self.reputation,
-            );
+            ) {
+                tracing::debug!("{}", e);
+            }
             self.last_heartbeat = Instant::now();
         }
     }
 
+    /// Record a liquidation attempt; repeated failures cost reputation.
+    fn record_liquidation(&mut self, ok: bool) {
+        reputation::record(&self.config, Duty::Liquidation, ok);
+        self.reputation = reputation::reputation(&self.config.data_dir).unwrap_or(self.reputation);
+    }
+
+    /// A warning to gossip when reputation has just fallen below
+    /// `reputation_warning_below`. Sent once per low spell; recovering above
+    /// the threshold re-arms it.
+    fn reputation_warning(&mut self) -> Option<ReputationWarning> {
+        self.reputation = reputation::reputation(&self.config.data_dir).unwrap_or(self.reputation);
+        let threshold = self.config.reputation_warning_below;
+        if threshold == 0 || self.reputation >= threshold {
+            self.reputation_warned = false;
+            return None;
+        }
+        if self.reputation_warned {
+            return None;
+        }
+        self.reputation_warned = true;
+        Some(ReputationWarning {
+            reputation: self.reputation,
+            threshold,
+        })
+    }
+
+    fn maybe_warn_reputation(&mut self, swarm: &mut Swarm<KeeperBehaviour>) {
+        if let Some(warning) = self.reputation_warning() {
+            if let Err(e) = gossip::publish_reputation_warning(swarm, &warning) {
+                tracing::warn!("{}", e);
+            }
+        }
+    }
+
     /// Check funded loans for liquidation eligibility and log warnings.
-    async fn check_liquidations(&self) {
+    async fn check_liquidations(&mut self) {
         let loans = match db::get_funded_loans(&self.config.data_dir) {
             Ok(loans) => loans,
             Err(e) => {
@@ -112,6 +178,7 @@ impl KeeperState {
                     match chain::liquidate_loan(&self.config, loan_id).await {
                         Ok(tx) => {
                             tracing::info!("Loan {} liquidated: {}", loan_id, tx);
+                            self.record_liquidation(true);
                             if let Err(e) = db::untrack_loan(&self.config.data_dir, loan_id) {
                                 tracing::warn!(
                                     "Liquidated loan {}, but failed to untrack in DB: {}",
@@ -122,6 +189,7 @@ impl KeeperState {
                         }
                         Err(e) => {
                             tracing::warn!("Auto-liquidation failed for loan {}: {}", loan_id, e);
+                            self.record_liquidation(false);
                         }
                     }
                 }
@@ -135,11 +203,219 @@ impl KeeperState {
         }
     }
 
+    /// Offer wild shards idle past `wild_drift_idle_secs` to other keepers.
+    /// The shard record goes to the DHT first so the taker can pull it.
+    fn offer_idle_shards(&mut self, swarm: &mut Swarm<KeeperBehaviour>) {
+        if self.config.wild_drift_idle_secs == 0 {
+            return;
+        }
+        let shards = match db::get_shards(&self.config.data_dir) {
+            Ok(shards) => shards,
+            Err(e) => {
+                tracing::warn!("Drift check failed to read shards: {}", e);
+                return;
+            }
+        };
+
+        let local = swarm.local_peer_id().to_string();
+        let now = now_millis();
+        for shard in drift::candidates(&shards, self.config.wild_drift_idle_secs * 1000, now) {
+            let Some(offer) = self.drift.offer(shard, &local, now) else {
+                continue;
+            };
+            if let Err(e) = dht::publish_shard_record(swarm, shard) {
+                tracing::debug!("Drift: could not publish {} to DHT: {}", shard.name, e);
+                continue;
+            }
+            match gossip::publish_wild_drift(swarm, &offer) {
+                Ok(()) => tracing::info!("Offered idle wild shard {} for drift", shard.name),
+                Err(e) => tracing::debug!("Drift offer for {} not sent: {}", shard.name, e),
+            }
+        }
+    }
+
+    /// Fold finished actions from before today (UTC) into daily totals and
+    /// prune raw rows past `action_retention_days`.
+    fn roll_up_actions(&self) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let today = now - now % DAY_MS;
+        let retention = self.config.action_retention_days as u64;
+        let prune_before = (retention > 0).then(|| now.saturating_sub(retention * DAY_MS));
+        match db::aggregate_actions_daily(&self.config.data_dir, today, prune_before) {
+            Ok(r) if r.aggregated + r.pruned + r.trimmed > 0 => tracing::info!(
+                "Rolled up {} action(s); pruned {}, trimmed {}",
+                r.aggregated,
+                r.pruned,
+                r.trimmed
+            ),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Action rollup failed: {}", e),
+        }
+    }
+
+    /// Apply idle decay to every hosted shard and persist the ones that
+    /// changed. Returns how many decayed.
+    fn decay_idle_shards(&mut self, now: u64) -> usize {
+        let shards = match db::get_shards(&self.config.data_dir) {
+            Ok(shards) => shards,
+            Err(e) => {
+                tracing::warn!("Decay pass failed to read shards: {}", e);
+                return 0;
+            }
+        };
+        let mut decayed = 0;
+        for mut shard in shards {
+            if !shard.apply_decay(now) {
+                continue;
+            }
+            // Only the factor is written, and only if nothing touched the
+            // shard since it was read
+            match db::set_decay_factor(
+                &self.config.data_dir,
+                &shard.id,
+                shard.decay_factor,
+                shard.last_interaction,
+            ) {
+                Ok(true) => {
+                    self.shard_cache.invalidate(&shard.id);
+                    decayed += 1;
+                    self.hosted_shards.insert(shard.id.clone(), shard);
+                }
+                Ok(false) => {}
+                Err(e) => tracing::warn!("Failed to persist decay of {}: {}", shard.name, e),
+            }
+        }
+        if decayed > 0 {
+            tracing::info!("Idle decay applied to {} shard(s)", decayed);
+        }
+        decayed
+    }
+
+    /// Re-bootstrap the DHT and reconcile published shard records with the
+    /// shards hosted locally.
+    fn audit_dht(&mut self, swarm: &mut Swarm<KeeperBehaviour>) {
+        if let Err(e) = swarm.behaviour_mut().kademlia.bootstrap() {
+            tracing::debug!("DHT re-bootstrap skipped: {:?}", e);
+        }
+
+        let shards = match db::get_shards(&self.config.data_dir) {
+            Ok(shards) => shards,
+            Err(e) => {
+                tracing::warn!("DHT audit failed to read shards: {}", e);
+                return;
+            }
+        };
+
+        let audit = dht::audit_records(&shards, &self.dht_published);
+        for shard_id in &audit.expire {
+            dht::unpublish_shard_record(swarm, shard_id);
+            self.dht_published.remove(shard_id);
+        }
+        for shard in shards.iter().filter(|s| audit.publish.contains(&s.id)) {
+            match dht::publish_shard_record(swarm, shard) {
+                Ok(()) => {
+                    self.dht_published.insert(shard.id.clone(), shard.last_interaction);
+                }
+                Err(e) => tracing::debug!("DHT audit: could not publish {}: {}", shard.name, e),
+            }
+        }
+        if !audit.publish.is_empty() || !audit.expire.is_empty() {
+            tracing::info!(
+                "DHT audit: published {} shard records, expired {}",
+                audit.publish.len(),
+                audit.expire.len()
+            );
+        }
+    }
+
+    /// Run a drift message authored by `sender` through the handoff state
+    /// machine and apply its actions.
+    fn handle_drift_message(&mut self, data: &[u8], sender: &str, swarm: &mut Swarm<KeeperBehaviour>) {
+        let message = match serde_json::from_slice::<DriftMessage>(data) {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::debug!("Ignoring malformed drift message: {}", e);
+                return;
+            }
+        };
+
+        let local = swarm.local_peer_id().to_string();
+        for action in self.drift.handle(&message, sender, &local, self.config.accept_wild_drift) {
+            match action {
+                DriftAction::Publish(reply) => {
+                    if let Err(e) = gossip::publish_wild_drift(swarm, &reply) {
+                        tracing::warn!("Failed to publish drift reply: {}", e);
+                    }
+                }
+                DriftAction::Archive { shard_id, keeper } => {
+                    match db::get_shard_by_id(&self.config.data_dir, &shard_id) {
+                        Ok(Some(shard)) => {
+                            let destination = format!("peer:{}", keeper);
+                            let archived = db::archive_shard(&self.config.data_dir, &shard, &destination);
+                            self.shard_cache.invalidate(&shard.id);
+                            match archived {
+                                Ok(()) => tracing::info!("Shard {} drifted to keeper {}", shard.name, keeper),
+                                Err(e) => tracing::warn!("Failed to archive drifted shard {}: {}", shard.name, e),
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => tracing::warn!("Drift archive lookup failed: {}", e),
+                    }
+                }
+                DriftAction::FetchRecord(shard_id) => {
+                    dht::lookup_shard(swarm, &shard_id);
+                }
+            }
+        }
+    }
+
+    /// Insert a shard fetched from the DHT if we are its confirmed drift host,
+    /// then tell the origin it may archive its copy.
+    fn receive_drifted_shard(&mut self, value: &[u8], swarm: &mut Swarm<KeeperBehaviour>) {
+        if !self.drift.awaiting_records() {
+            return;
+        }
+        let Ok(shard) = serde_json::from_slice::<Shard>(value) else {
+            return;
+        };
+        let Some(shard) = self.drift.record_received(shard) else {
+            return;
+        };
+        match db::insert_shard_within_quota(&self.config.data_dir, &shard, self.config.max_shards_per_owner) {
+            Ok(true) => {}
+            Ok(false) => {
+                tracing::warn!("Not storing drifted shard {}: its owner is at max_shards_per_owner", shard.name);
+                return;
+            }
+            Err(e) => {
+                tracing::warn!("Failed to store drifted shard {}: {}", shard.name, e);
+                return;
+            }
+        }
+        tracing::info!("Wild shard {} drifted in", shard.name);
+
+        let local = swarm.local_peer_id().to_string();
+        if let Some(received) = self.drift.stored(&shard.id, &local) {
+            if let Err(e) = gossip::publish_wild_drift(swarm, &received) {
+                tracing::warn!("Failed to confirm drift receipt of {}: {}", shard.name, e);
+            }
+        }
+    }
+
     /// Main event loop for the keeper node.
     /// Processes swarm events and runs periodic tasks.
     pub async fn run(&mut self, swarm: &mut Swarm<KeeperBehaviour>) {
         let mut heartbeat_interval = tokio::time::interval(HEARTBEAT_INTERVAL);
         let mut liquidation_interval = tokio::time::interval(LIQUIDATION_CHECK_INTERVAL);
+        let mut drift_interval = tokio::time::interval(DRIFT_CHECK_INTERVAL);
+        // A zero period would panic; the tick is ignored when audits are off
+        let audit_secs = self.config.dht_audit_interval_secs;
+        let mut dht_audit_interval = tokio::time::interval(Duration::from_secs(audit_secs.max(1)));
+        let mut rollup_interval = tokio::time::interval(ACTION_ROLLUP_INTERVAL);
+        let mut decay_interval = tokio::time::interval(DECAY_INTERVAL);
 
         loop {
             tokio::select! {
@@ -147,10 +423,26 @@ impl KeeperState {
                     self.handle_swarm_event(event, swarm);
                 }
                 _ = heartbeat_interval.tick() => {
+                    gossip::flush_publish_queue(swarm, &mut self.publish_queue);
                     self.maybe_send_heartbeat(swarm);
+                    // Attestation failures are recorded by the HTTP API
+                    self.maybe_warn_reputation(swarm);
                 }
                 _ = liquidation_interval.tick() => {
                     self.check_liquidations().await;
+                    self.maybe_warn_reputation(swarm);
+                }
+                _ = drift_interval.tick() => {
+                    self.offer_idle_shards(swarm);
+                }
+                _ = dht_audit_interval.tick(), if audit_secs > 0 => {
+                    self.audit_dht(swarm);
+                }
+                _ = rollup_interval.tick() => {
+                    self.roll_up_actions();
+                }
+                _ = decay_interval.tick() => {
+                    self.decay_idle_shards(now_millis());
                 }
             }
         }
@@ -160,7 +452,7 @@ impl KeeperState {
     fn handle_swarm_event(
         &mut self,
         event: SwarmEvent<crate::node::KeeperBehaviourEvent>,
-        _swarm: &mut Swarm<KeeperBehaviour>,
+        swarm: &mut Swarm<KeeperBehaviour>,
     ) {
         match event {
             SwarmEvent::Behaviour(crate::node::KeeperBehaviourEvent::Gossipsub(
@@ -170,11 +462,25 @@ impl KeeperState {
                     ..
                 },
             )) => {
-                gossip::handle_message(
-                    &message.topic,
-                    &message.data,
-                    &propagation_source,
-                );
+                if message.topic.as_str() == gossip::TOPIC_WILD_DRIFT {
+                    // Strict validation: the source is the verified signer
+                    if let Some(source) = message.source {
+                        self.handle_drift_message(&message.data, &source.to_string(), swarm);
+                    }
+                } else {
+                    gossip::handle_message(
+                        &message.topic,
+                        &message.data,
+                        &propagation_source,
+                    );
+                }
+            }
+
+            // A peer joined one of our topics: queued messages can go out now
+            SwarmEvent::Behaviour(crate::node::KeeperBehaviourEvent::Gossipsub(
+                libp2p::gossipsub::Event::Subscribed { .. },
+            )) => {
+                gossip::flush_publish_queue(swarm, &mut self.publish_queue);
             }
 
             SwarmEvent::Behaviour(crate::node::KeeperBehaviourEvent::Kademlia(
@@ -189,6 +495,7 @@ impl KeeperState {
                             peer_record.record.key,
                             peer_record.record.value.len()
                         );
+                        self.receive_drifted_shard(&peer_record.record.value, swarm);
                     }
                     libp2p::kad::QueryResult::PutRecord(Ok(_)) => {
                         tracing::debug!("DHT record stored successfully");
@@ -198,15 +505,13 @@ impl KeeperState {
             }
 
             SwarmEvent::Behaviour(crate::node::KeeperBehaviourEvent::Identify(
-                identify_event,
+                libp2p::identify::Event::Received { peer_id, info, .. },
             )) => {
-                if let libp2p::identify::Event::Received { peer_id, info, .. } = identify_event {
-                    tracing::debug!(
-                        "Identified peer {}: {}",
-                        &peer_id.to_string()[..8],
-                        info.protocol_version
-                    );
-                }
+                tracing::debug!(
+                    "Identified peer {}: {}",
+                    &peer_id.to_string()[..8],
+                    info.protocol_version
+                );
             }
 
             SwarmEvent::NewListenAddr { address, .. } => {
@@ -221,7 +526,90 @@ impl KeeperState {
                 tracing::debug!("Disconnected from peer {}", &peer_id.to_string()[..8]);
             }
 
+            // Includes bootstrap hostnames that fail to resolve
+            SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                let peer = peer_id.map(|p| p.to_string()).unwrap_or_else(|| "unknown peer".to_string());
+                tracing::warn!("Failed to connect to {}: {}", peer, error);
+            }
+
             _ => {}
         }
     }
 }
+
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_liquidation_failures_cost_reputation_and_warn() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        db::init_db(&data_dir).unwrap();
+        let config = Config {
+            data_dir,
+            penalty_failure_threshold: 2,
+            penalty_reputation: 20,
+            reputation_warning_below: 50,
+            ..Config::default()
+        };
+        let mut keeper = KeeperState::new(config, Arc::new(ShardCache::new(0)));
+        assert_eq!(keeper.reputation, 100);
+
+        keeper.record_liquidation(false);
+        assert_eq!(keeper.reputation, 100, "below the threshold");
+        assert_eq!(keeper.reputation_warning(), None);
+
+        for _ in 0..3 {
+            keeper.record_liquidation(false);
+        }
+        assert_eq!(keeper.reputation, 40);
+        assert_eq!(
+            keeper.reputation_warning(),
+            Some(ReputationWarning {
+                reputation: 40,
+                threshold: 50
+            })
+        );
+        assert_eq!(keeper.reputation_warning(), None, "warned once per spell");
+
+        // A success ends the streak and wins back `reputation_recovery`
+        keeper.record_liquidation(true);
+        assert_eq!(keeper.reputation, 41);
+        keeper.record_liquidation(false);
+        assert_eq!(keeper.reputation, 41);
+    }
+
+    #[test]
+    fn decay_pass_persists_idle_shards() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        db::init_db(&data_dir).unwrap();
+        let (mut idle, fresh) = (Shard::spawn(None), Shard::spawn(None));
+        idle.last_interaction = fresh.last_interaction - 20 * DAY_MS;
+        db::insert_shard(&data_dir, &idle).unwrap();
+        db::insert_shard(&data_dir, &fresh).unwrap();
+        let config = Config {
+            data_dir: data_dir.clone(),
+            ..Config::default()
+        };
+        let mut keeper = KeeperState::new(config, Arc::new(ShardCache::new(0)));
+
+        assert_eq!(keeper.decay_idle_shards(fresh.last_interaction), 1);
+        let stored = db::get_shard_by_id(&data_dir, &idle.id).unwrap().unwrap();
+        assert!((stored.decay_factor - 0.8).abs() < 1e-9);
+        assert_eq!(db::get_shard_by_id(&data_dir, &fresh.id).unwrap().unwrap().decay_factor, 1.0);
+        assert_eq!(keeper.decay_idle_shards(fresh.last_interaction), 0);
+
+        // A shard interacted with after it was read keeps its factor
+        assert!(!db::set_decay_factor(&data_dir, &fresh.id, 0.5, fresh.last_interaction - 1).unwrap());
+        assert_eq!(db::get_shard_by_id(&data_dir, &fresh.id).unwrap().unwrap().decay_factor, 1.0);
+    }
+}
@@ -0,0 +1,262 @@
+use rusqlite::Result as SqliteResult;
+use serde::{Deserialize, Serialize};
+
+use crate::db::{self, NewTaskLesson, TaskLesson};
+use crate::shard::Shard;
+
+/// Version written into every pack; imports of any other version are refused.
+pub const PACK_FORMAT_VERSION: u32 = 1;
+
+/// Most lessons exported from a single shard.
+pub const MAX_PACK_LESSONS: u32 = 500;
+
+/// Lessons scoring below this are left out of a pack unless pinned.
+pub const MIN_PACK_SCORE: f64 = 0.3;
+
+/// Imported lessons keep this share of their source score, so they rank
+/// below the importing shard's own experience until they prove useful.
+pub const IMPORT_SCORE_FACTOR: f64 = 0.8;
+
+/// A retired shard's distilled lessons, portable to another shard.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LessonPack {
+    pub version: u32,
+    pub source_shard_id: String,
+    pub source_name: String,
+    pub source_species: String,
+    pub source_type: String,
+    pub created_at: u64,
+    pub lessons: Vec<PackedLesson>,
+}
+
+/// A lesson stripped of everything tied to its source shard (ids, action,
+/// artifacts, retrieval counters).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackedLesson {
+    pub task_type: String,
+    pub goal: String,
+    pub approach: String,
+    #[serde(default)]
+    pub tools_used: Vec<String>,
+    #[serde(default)]
+    pub outcome: String,
+    #[serde(default)]
+    pub errors: Vec<String>,
+    #[serde(default)]
+    pub fixes: Vec<String>,
+    #[serde(default)]
+    pub duration_ms: u64,
+    pub success: bool,
+    pub extractor_confidence: f64,
+    pub applicability_confidence: f64,
+    pub reusability: f64,
+    pub score: f64,
+}
+
+impl PackedLesson {
+    fn from_lesson(lesson: &TaskLesson) -> Self {
+        Self {
+            task_type: lesson.task_type.clone(),
+            goal: lesson.goal.clone(),
+            approach: lesson.approach.clone(),
+            tools_used: lesson.tools_used.clone(),
+            outcome: lesson.outcome.clone(),
+            errors: lesson.errors.clone(),
+            fixes: lesson.fixes.clone(),
+            duration_ms: lesson.duration_ms,
+            success: lesson.success,
+            extractor_confidence: lesson.extractor_confidence,
+            applicability_confidence: lesson.applicability_confidence,
+            reusability: lesson.reusability,
+            score: lesson.score,
+        }
+    }
+
+    /// Just enough of a `TaskLesson` for `db::near_duplicate`.
+    fn as_task_lesson(&self) -> TaskLesson {
+        TaskLesson {
+            task_type: self.task_type.clone(),
+            goal: self.goal.clone(),
+            approach: self.approach.clone(),
+            ..TaskLesson::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub skipped_duplicates: usize,
+    pub lesson_ids: Vec<i64>,
+}
+
+/// Distill a shard's lessons into a pack: successful lessons scoring at
+/// least `MIN_PACK_SCORE` (or pinned), best first, without near-duplicates.
+pub fn build_pack(shard: &Shard, lessons: &[TaskLesson], now: u64) -> LessonPack {
+    let mut candidates: Vec<&TaskLesson> = lessons
+        .iter()
+        .filter(|l| l.pinned || (l.success && l.score >= MIN_PACK_SCORE))
+        .collect();
+    candidates.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut kept: Vec<&TaskLesson> = Vec::new();
+    for lesson in candidates {
+        if kept.iter().any(|k| db::near_duplicate(k, lesson)) {
+            continue;
+        }
+        kept.push(lesson);
+    }
+
+    LessonPack {
+        version: PACK_FORMAT_VERSION,
+        source_shard_id: shard.id.clone(),
+        source_name: shard.name.clone(),
+        source_species: shard.species.clone(),
+        source_type: shard.shard_type.clone(),
+        created_at: now,
+        lessons: kept.into_iter().map(PackedLesson::from_lesson).collect(),
+    }
+}
+
+/// Read a shard's lessons and distill them into a pack.
+pub fn export_pack(data_dir: &str, shard: &Shard) -> SqliteResult<LessonPack> {
+    let lessons = db::get_recent_task_lessons(data_dir, &shard.id, MAX_PACK_LESSONS)?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    Ok(build_pack(shard, &lessons, now))
+}
+
+/// Why a pack could not be imported.
+#[derive(Debug)]
+pub enum ImportError {
+    /// The pack was written by an incompatible format version.
+    UnsupportedVersion(u32),
+    Db(rusqlite::Error),
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::UnsupportedVersion(v) => write!(
+                f,
+                "Unsupported lesson pack version {} (expected {})",
+                v, PACK_FORMAT_VERSION
+            ),
+            ImportError::Db(e) => write!(f, "DB error: {}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for ImportError {
+    fn from(e: rusqlite::Error) -> Self {
+        ImportError::Db(e)
+    }
+}
+
+/// Import a pack into `shard_id`. Lessons that near-duplicate one the shard
+/// already has (or an earlier one in the same pack) are skipped; the rest
+/// are stored with their score scaled by `IMPORT_SCORE_FACTOR`, all hanging
+/// off one synthetic "imported" action in the shard's log.
+pub fn import_pack(data_dir: &str, shard_id: &str, pack: &LessonPack) -> Result<ImportSummary, ImportError> {
+    if pack.version != PACK_FORMAT_VERSION {
+        return Err(ImportError::UnsupportedVersion(pack.version));
+    }
+
+    let mut known = db::get_recent_task_lessons(data_dir, shard_id, MAX_PACK_LESSONS)?;
+    let artifact_path = format!("pack://{}", pack.source_shard_id);
+    let mut action_id = None;
+
+    let mut summary = ImportSummary {
+        imported: 0,
+        skipped_duplicates: 0,
+        lesson_ids: Vec::new(),
+    };
+    for packed in &pack.lessons {
+        let candidate = packed.as_task_lesson();
+        if known.iter().any(|k| db::near_duplicate(k, &candidate)) {
+            summary.skipped_duplicates += 1;
+            continue;
+        }
+
+        let action_id = match action_id {
+            Some(id) => id,
+            None => *action_id.insert(db::insert_synthetic_action(
+                data_dir,
+                shard_id,
+                &format!("Imported lessons from pack of {}", pack.source_name),
+                "lesson_pack",
+            )?),
+        };
+        let lesson = NewTaskLesson {
+            shard_id,
+            action_id,
+            task_type: &packed.task_type,
+            goal: &packed.goal,
+            approach: &packed.approach,
+            tools_used: &packed.tools_used,
+            outcome: &packed.outcome,
+            errors: &packed.errors,
+            fixes: &packed.fixes,
+            duration_ms: packed.duration_ms,
+            success: packed.success,
+            extractor_confidence: packed.extractor_confidence,
+            applicability_confidence: packed.applicability_confidence,
+            reusability: packed.reusability,
+            artifact_path: &artifact_path,
+        };
+        let id = db::import_task_lesson(data_dir, &lesson, packed.score * IMPORT_SCORE_FACTOR)?;
+        summary.imported += 1;
+        summary.lesson_ids.push(id);
+        known.push(candidate);
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lesson(goal: &str, score: f64, success: bool) -> TaskLesson {
+        TaskLesson {
+            task_type: "writing".into(),
+            goal: goal.into(),
+            approach: "outline then draft".into(),
+            success,
+            score,
+            ..TaskLesson::default()
+        }
+    }
+
+    #[test]
+    fn pack_keeps_strong_distinct_lessons() {
+        let shard = Shard::spawn(None);
+        let lessons = vec![
+            lesson("write the release notes", 0.9, true),
+            lesson("write the release notes", 0.7, true),
+            lesson("draft a blog post about caching", 0.6, true),
+            lesson("translate the manual", 0.8, false),
+            lesson("summarize meeting minutes", 0.1, true),
+        ];
+
+        let pack = build_pack(&shard, &lessons, 1);
+        let goals: Vec<&str> = pack.lessons.iter().map(|l| l.goal.as_str()).collect();
+        assert_eq!(goals, ["write the release notes", "draft a blog post about caching"]);
+        assert_eq!(pack.lessons[0].score, 0.9);
+    }
+
+    #[test]
+    fn unknown_version_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        db::init_db(&data_dir).unwrap();
+        let mut pack = build_pack(&Shard::spawn(None), &[], 1);
+        pack.version = PACK_FORMAT_VERSION + 1;
+        assert!(matches!(
+            import_pack(&data_dir, "any", &pack),
+            Err(ImportError::UnsupportedVersion(_))
+        ));
+    }
+}
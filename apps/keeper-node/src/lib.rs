@@ -1,14 +1,31 @@
 pub mod agent_loop;
 pub mod api;
+pub mod battle;
+pub mod benchmark;
 pub mod capture;
 pub mod chain;
+pub mod cleanup;
 pub mod config;
 pub mod db;
 pub mod dht;
+pub mod drift;
+pub mod embeddings;
 pub mod executor;
 pub mod gossip;
 pub mod inference;
+pub mod journal;
 pub mod keeper;
+pub mod lesson_pack;
+pub mod logging;
+pub mod migrate;
 pub mod monitor;
 pub mod node;
+pub mod recommend;
+pub mod reputation;
+pub mod rng;
 pub mod shard;
+pub mod shard_cache;
+#[cfg(test)]
+pub mod test_support;
+pub mod tls;
+pub mod tokens;
@@ -0,0 +1,225 @@
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::sync::OnceLock;
+use tokio::sync::broadcast;
+use tracing::field::{Field, Visit};
+use tracing::{span, Event, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::EnvFilter;
+
+/// How many log lines the live stream holds. A subscriber that falls further
+/// behind skips ahead instead of making the channel grow.
+pub const LOG_STREAM_CAPACITY: usize = 512;
+
+/// Map `-q` / `-v` flag counts to a log level: quiet → warn, default → info,
+/// `-v` → debug, `-vv` and above → trace.
+pub fn level_for_flags(verbose: u8, quiet: bool) -> LevelFilter {
+    if quiet {
+        return LevelFilter::WARN;
+    }
+    match verbose {
+        0 => LevelFilter::INFO,
+        1 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    }
+}
+
+/// Count `-v`/`-vv`/`--verbose` and `-q`/`--quiet` in a raw argument list,
+/// for binaries that don't parse arguments with clap.
+pub fn flags_from_args<I: IntoIterator<Item = String>>(args: I) -> (u8, bool) {
+    let mut verbose = 0u8;
+    let mut quiet = false;
+    for arg in args {
+        match arg.as_str() {
+            "--verbose" => verbose = verbose.saturating_add(1),
+            "--quiet" | "-q" => quiet = true,
+            a if a.len() > 1 && a.starts_with('-') && a[1..].chars().all(|c| c == 'v') => {
+                verbose = verbose.saturating_add((a.len() - 1) as u8);
+            }
+            _ => {}
+        }
+    }
+    (verbose, quiet)
+}
+
+/// Install the global tracing subscriber. An explicit `RUST_LOG` wins;
+/// otherwise `crates` log at the level chosen by the flags and everything
+/// else at warn (or trace with `-vvv`).
+pub fn init(crates: &[&str], verbose: u8, quiet: bool) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        let level = level_for_flags(verbose, quiet);
+        let global = if verbose >= 3 { LevelFilter::TRACE } else { LevelFilter::WARN };
+        crates.iter().fold(
+            EnvFilter::default().add_directive(global.into()),
+            |filter, name| match format!("{}={}", name, level).parse() {
+                Ok(directive) => filter.add_directive(directive),
+                Err(_) => filter,
+            },
+        )
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(BroadcastLayer::new(log_sender().clone()))
+        .init();
+}
+
+/// A structured log line, as sent to live log subscribers.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogLine {
+    pub timestamp: u64,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+    /// From the event itself or the nearest enclosing span with a `shard_id`
+    pub shard_id: Option<String>,
+}
+
+fn log_sender() -> &'static broadcast::Sender<LogLine> {
+    static SENDER: OnceLock<broadcast::Sender<LogLine>> = OnceLock::new();
+    SENDER.get_or_init(|| broadcast::channel(LOG_STREAM_CAPACITY).0)
+}
+
+/// Subscribe to log lines emitted through the global subscriber from now on.
+pub fn subscribe() -> broadcast::Receiver<LogLine> {
+    log_sender().subscribe()
+}
+
+/// Tracing layer that forwards every event to a broadcast channel.
+pub struct BroadcastLayer {
+    sender: broadcast::Sender<LogLine>,
+}
+
+impl BroadcastLayer {
+    pub fn new(sender: broadcast::Sender<LogLine>) -> Self {
+        Self { sender }
+    }
+}
+
+/// `shard_id` recorded on a span, looked up by events inside it.
+struct SpanShard(String);
+
+impl<S> Layer<S> for BroadcastLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let mut fields = LineFields::default();
+        attrs.record(&mut fields);
+        if let (Some(shard_id), Some(span)) = (fields.shard_id, ctx.span(id)) {
+            span.extensions_mut().insert(SpanShard(shard_id));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        // Nobody is watching; skip the formatting work
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+
+        let mut fields = LineFields::default();
+        event.record(&mut fields);
+        let shard_id = fields.shard_id.or_else(|| {
+            ctx.event_scope(event)?
+                .find_map(|span| span.extensions().get::<SpanShard>().map(|s| s.0.clone()))
+        });
+
+        let meta = event.metadata();
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        // Errors only mean every receiver has gone away
+        let _ = self.sender.send(LogLine {
+            timestamp,
+            level: meta.level().to_string(),
+            target: meta.target().to_string(),
+            message: fields.message,
+            shard_id,
+        });
+    }
+}
+
+/// Collects the message, `shard_id`, and any other fields as `key=value`.
+#[derive(Default)]
+struct LineFields {
+    message: String,
+    shard_id: Option<String>,
+}
+
+impl LineFields {
+    fn push(&mut self, field: &Field, value: String) {
+        match field.name() {
+            "message" => {
+                self.message.insert_str(0, &value);
+            }
+            "shard_id" => self.shard_id = Some(value),
+            name => {
+                let _ = write!(self.message, " {}={}", name, value);
+            }
+        }
+    }
+}
+
+impl Visit for LineFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.push(field, format!("{:?}", value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_map_to_levels() {
+        assert_eq!(level_for_flags(0, true), LevelFilter::WARN);
+        assert_eq!(level_for_flags(2, true), LevelFilter::WARN);
+        assert_eq!(level_for_flags(0, false), LevelFilter::INFO);
+        assert_eq!(level_for_flags(1, false), LevelFilter::DEBUG);
+        assert_eq!(level_for_flags(2, false), LevelFilter::TRACE);
+        assert_eq!(level_for_flags(3, false), LevelFilter::TRACE);
+    }
+
+    #[test]
+    fn raw_args_are_counted() {
+        let args = ["siphon-desktop", "-vv", "--verbose", "--other"].map(String::from);
+        assert_eq!(flags_from_args(args), (3, false));
+        assert_eq!(flags_from_args(["app", "-q"].map(String::from)), (0, true));
+        assert_eq!(flags_from_args(["app", "-"].map(String::from)), (0, false));
+    }
+
+    #[test]
+    fn events_reach_broadcast_subscribers() {
+        let (sender, mut receiver) = broadcast::channel(LOG_STREAM_CAPACITY);
+        let subscriber = tracing_subscriber::registry().with(BroadcastLayer::new(sender));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(shard_id = "direct", "spawned");
+            let span = tracing::info_span!("execute", shard_id = %"abc-123");
+            let _guard = span.enter();
+            tracing::warn!(tool = "shell", turn = 2, "tool call failed");
+        });
+
+        let first = receiver.try_recv().unwrap();
+        assert_eq!(first.level, "INFO");
+        assert_eq!(first.message, "spawned");
+        assert_eq!(first.shard_id.as_deref(), Some("direct"));
+
+        let second = receiver.try_recv().unwrap();
+        assert_eq!(second.level, "WARN");
+        assert_eq!(second.target, module_path!());
+        assert_eq!(second.message, "tool call failed tool=shell turn=2");
+        assert_eq!(second.shard_id.as_deref(), Some("abc-123"));
+
+        assert!(receiver.try_recv().is_err());
+    }
+}
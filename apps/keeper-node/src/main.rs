@@ -1,10 +1,11 @@
-use siphon_keeper::{api, chain, config, db, gossip, keeper, monitor, node, shard};
+use siphon_keeper::{
+    api, chain, cleanup, config, db, embeddings, gossip, keeper, logging, migrate, monitor, node, shard, tls,
+};
 
 use clap::{Parser, Subcommand};
 use colored::Colorize;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing_subscriber::EnvFilter;
 
 #[derive(Parser)]
 #[command(
@@ -13,6 +14,14 @@ use tracing_subscriber::EnvFilter;
     version
 )]
 struct Cli {
+    /// Increase log verbosity (-v debug, -vv trace, -vvv trace for all crates)
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Only log warnings and errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -47,6 +56,20 @@ enum Commands {
     /// Show current node status, reputation, and resource usage
     Status,
 
+    /// Refuse new task executions until resumed (in-flight ones keep running)
+    Pause {
+        /// Pause only this shard instead of the whole keeper
+        #[arg(long)]
+        shard: Option<String>,
+    },
+
+    /// Allow task executions again after a pause
+    Resume {
+        /// Resume only this shard
+        #[arg(long)]
+        shard: Option<String>,
+    },
+
     /// Manage hosted shards
     #[command(subcommand)]
     Shards(ShardsCommands),
@@ -54,6 +77,10 @@ enum Commands {
     /// Configuration management
     #[command(subcommand)]
     Config(ConfigCommands),
+
+    /// Data dir housekeeping
+    #[command(subcommand)]
+    Maintenance(MaintenanceCommands),
 }
 
 #[derive(Subcommand)]
@@ -73,6 +100,27 @@ enum ShardsCommands {
         /// ID of the shard to release
         id: String,
     },
+
+    /// Copy shards, their history, workspaces and memories to another data dir
+    Migrate {
+        /// Data dir to copy from
+        #[arg(long)]
+        from: String,
+
+        /// Data dir to copy into
+        #[arg(long)]
+        to: String,
+
+        /// Only migrate this shard
+        #[arg(long)]
+        shard: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum MaintenanceCommands {
+    /// Remove workspace, memory and artifact directories of shards that no longer exist
+    GcWorkspaces,
 }
 
 #[derive(Subcommand)]
@@ -83,11 +131,9 @@ enum ConfigCommands {
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env().add_directive("siphon_keeper=info".parse().unwrap()))
-        .init();
-
+    monitor::mark_process_start();
     let cli = Cli::parse();
+    logging::init(&["siphon_keeper"], cli.verbose, cli.quiet);
 
     match cli.command {
         Commands::Start { port, bootstrap, http_port } => {
@@ -126,13 +172,37 @@ async fn main() {
             }
 
             db::init_db(&cfg.data_dir).expect("Failed to initialize database");
+            match db::fail_interrupted_jobs(&cfg.data_dir) {
+                Ok(0) => {}
+                Ok(n) => tracing::warn!("Marked {} interrupted background job(s) as failed", n),
+                Err(e) => tracing::warn!("Failed to reconcile background jobs: {}", e),
+            }
+
+            if cfg.allowed_inference_hosts.is_none() {
+                tracing::warn!(
+                    "allowed_inference_hosts is not set; execute requests may point inference_url at any host"
+                );
+            }
+
+            let shared_state = Arc::new(RwLock::new(api::AppState::new(cfg.clone())));
+            let shard_cache = shared_state.read().await.shard_cache.clone();
+
+            {
+                let data_dir = cfg.data_dir.clone();
+                let inference_config = shared_state.read().await.inference_config();
+                let prewarm = cfg.prewarm_embeddings;
+                tokio::spawn(async move {
+                    match embeddings::sync_dimension(&data_dir, &inference_config, prewarm).await {
+                        Ok(0) => {}
+                        Ok(n) => tracing::info!("Prewarmed embeddings for {} lesson(s)", n),
+                        Err(e) => tracing::warn!("Embedding dimension probe or prewarm failed: {}", e),
+                    }
+                });
+            }
 
             // Start HTTP API server
             let api_port = cfg.http_port;
-            let shared_state = Arc::new(RwLock::new(api::AppState {
-                config: cfg.clone(),
-                jobs: std::collections::HashMap::new(),
-            }));
+            tokio::spawn(api::run_watchdog(shared_state.clone()));
             let app = api::router(shared_state);
 
             let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", api_port))
@@ -147,18 +217,34 @@ async fn main() {
                     std::process::exit(1);
                 });
 
-            println!(
-                "{} HTTP API listening on {}",
-                "OK".bright_green(),
-                format!("http://0.0.0.0:{}", api_port).bright_white()
-            );
+            if tls::enabled(&cfg) {
+                let tls_config = tls::server_config(&cfg).unwrap_or_else(|e| {
+                    eprintln!("{} {}", "!!".bright_red(), e);
+                    std::process::exit(1);
+                });
+                println!(
+                    "{} HTTPS API listening on {}{}",
+                    "OK".bright_green(),
+                    format!("https://0.0.0.0:{}", api_port).bright_white(),
+                    if cfg.tls_client_ca_path.is_some() { " (client certificates required)" } else { "" }
+                );
+                tokio::spawn(tls::serve(listener, app, tls_config));
+            } else {
+                println!(
+                    "{} HTTP API listening on {}",
+                    "OK".bright_green(),
+                    format!("http://0.0.0.0:{}", api_port).bright_white()
+                );
 
-            tokio::spawn(async move {
-                axum::serve(listener, app).await.ok();
-            });
+                tokio::spawn(async move {
+                    axum::serve(listener, app).await.ok();
+                });
+            }
 
             // Start P2P node
-            match node::create_node(port, &bootstrap).await {
+            let ws_port = node::select_ws_port(cfg.ws_port, port, cfg.http_port);
+            let idle_timeout = std::time::Duration::from_secs(cfg.p2p_idle_timeout_secs);
+            match node::create_node(port, ws_port, idle_timeout, &bootstrap).await {
                 Ok(mut swarm) => {
                     println!(
                         "{} Node started. PeerId: {}",
@@ -168,7 +254,7 @@ async fn main() {
 
                     gossip::subscribe_topics(&mut swarm);
 
-                    let keeper_state = keeper::KeeperState::new(cfg);
+                    let keeper_state = keeper::KeeperState::new(cfg, shard_cache);
 
                     println!(
                         "{} Keeper node is live. Listening for shard events...",
@@ -240,7 +326,7 @@ async fn main() {
             println!("   CPU Usage:    {}", format!("{:.1}%", stats.cpu_usage).bright_yellow());
             println!("   Memory:       {}", format!("{:.1} MB / {:.1} MB", stats.memory_used_mb, stats.memory_total_mb).bright_yellow());
             println!("   Disk:         {}", format!("{:.1} GB free", stats.disk_free_gb).bright_yellow());
-            println!("   Uptime:       {}", format!("{}s", stats.uptime_secs).bright_yellow());
+            println!("   Host uptime:  {}", format!("{}s", stats.host_uptime_secs).bright_yellow());
             println!("{}", "─".repeat(40).dimmed());
             println!("   Shards:       {}", "(run `shards list`)".dimmed());
 
@@ -248,32 +334,24 @@ async fn main() {
             let cfg = config::Config::load().ok();
             if let Some(ref cfg) = cfg {
                 if cfg.keeper_staking_address.is_some() {
-                    // Derive keeper address from private key
-                    match std::fs::read_to_string(
-                        cfg.private_key_path.replace("~/", &format!("{}/", std::env::var("HOME").unwrap_or_default()))
-                    ) {
-                        Ok(key_hex) => {
-                            let key_hex = key_hex.trim().trim_start_matches("0x");
-                            if let Ok(signer) = <alloy::signers::local::PrivateKeySigner as std::str::FromStr>::from_str(key_hex) {
-                                let keeper_addr = format!("{:?}", signer.address());
-                                match chain::get_keeper_info(cfg, &keeper_addr).await {
-                                    Ok((staked, _unstake_at, rewards, active)) => {
-                                        let staked_eth = staked.to_string().parse::<f64>().unwrap_or(0.0) / 1e18;
-                                        let rewards_eth = rewards.to_string().parse::<f64>().unwrap_or(0.0) / 1e18;
-                                        println!("   Stake:        {}", format!("{:.4} ETH", staked_eth).bright_yellow());
-                                        println!("   Rewards:      {}", format!("{:.4} ETH", rewards_eth).bright_yellow());
-                                        println!("   Active:       {}", if active { "Yes".bright_green() } else { "No".bright_red() });
-                                    }
-                                    Err(e) => {
-                                        println!("   Stake:        {}", format!("Error: {}", e).bright_red());
-                                    }
+                    // Derive keeper address from the configured key (raw or mnemonic)
+                    match chain::keeper_address(cfg) {
+                        Ok(keeper_addr) => {
+                            match chain::get_keeper_info(cfg, &keeper_addr).await {
+                                Ok((staked, _unstake_at, rewards, active)) => {
+                                    let staked_eth = staked.to_string().parse::<f64>().unwrap_or(0.0) / 1e18;
+                                    let rewards_eth = rewards.to_string().parse::<f64>().unwrap_or(0.0) / 1e18;
+                                    println!("   Stake:        {}", format!("{:.4} ETH", staked_eth).bright_yellow());
+                                    println!("   Rewards:      {}", format!("{:.4} ETH", rewards_eth).bright_yellow());
+                                    println!("   Active:       {}", if active { "Yes".bright_green() } else { "No".bright_red() });
+                                }
+                                Err(e) => {
+                                    println!("   Stake:        {}", format!("Error: {}", e).bright_red());
                                 }
-                            } else {
-                                println!("   Stake:        {}", "Invalid private key".bright_red());
                             }
                         }
-                        Err(_) => {
-                            println!("   Stake:        {}", "Key file not found".bright_red());
+                        Err(e) => {
+                            println!("   Stake:        {}", e.bright_red());
                         }
                     }
                 } else {
@@ -284,6 +362,10 @@ async fn main() {
             }
         }
 
+        Commands::Pause { shard } => set_paused(shard.as_deref(), true),
+
+        Commands::Resume { shard } => set_paused(shard.as_deref(), false),
+
         Commands::Shards(sub) => match sub {
             ShardsCommands::List => {
                 let cfg = config::Config::load().unwrap_or_default();
@@ -343,15 +425,78 @@ async fn main() {
                 );
 
                 let cfg = config::Config::load().unwrap_or_default();
-                if let Err(e) = db::delete_shard(&cfg.data_dir, &id) {
+                let read_only = matches!(db::get_shard_by_id(&cfg.data_dir, &id), Ok(Some(shard)) if shard.read_only);
+                if read_only {
+                    eprintln!("{} Shard is read-only; release is not allowed", "!!".bright_red());
+                } else if let Err(e) = db::delete_shard(&cfg.data_dir, &id) {
                     eprintln!("{} Failed to release shard: {}", "!!".bright_red(), e);
                 } else {
+                    // The row is deleted, not released to the wild, so this is a delete
+                    if cfg.cleanup_on_delete {
+                        if let Err(e) = cleanup::remove_shard_dirs(&cfg.data_dir, &id) {
+                            eprintln!("{} Failed to clean up shard files: {}", "!!".bright_red(), e);
+                        }
+                    }
                     println!(
                         "{} Shard released back into the wild.",
                         "OK".bright_green()
                     );
                 }
             }
+
+            ShardsCommands::Migrate { from, to, shard } => {
+                println!(
+                    "{} Migrating {} from {} to {}...",
+                    ">>".bright_cyan(),
+                    shard.as_deref().unwrap_or("all shards").bright_cyan(),
+                    from.bright_white(),
+                    to.bright_white()
+                );
+
+                let cfg = config::Config::load().unwrap_or_default();
+                match migrate::migrate(&from, &to, shard.as_deref(), cfg.max_shards_per_owner) {
+                    Ok(report) => {
+                        for s in &report.shards {
+                            println!(
+                                "   {} {} interactions, {} actions, {} lessons, {} journal entries, {} benchmarks",
+                                s.shard_id[..8.min(s.shard_id.len())].bright_cyan(),
+                                s.interactions,
+                                s.actions,
+                                s.lessons,
+                                s.journal_entries,
+                                s.benchmarks
+                            );
+                        }
+                        println!(
+                            "{} Migrated {} shard(s) and {} file(s).",
+                            "OK".bright_green(),
+                            report.shards.len(),
+                            report.files
+                        );
+                    }
+                    Err(e) => eprintln!("{} {}", "!!".bright_red(), e),
+                }
+            }
+        },
+
+        Commands::Maintenance(sub) => match sub {
+            MaintenanceCommands::GcWorkspaces => {
+                let cfg = config::Config::load().unwrap_or_default();
+                match cleanup::gc_orphaned_dirs(&cfg.data_dir) {
+                    Ok(removed) => {
+                        for dir in &removed {
+                            println!("   {} {}", "-".dimmed(), dir.display());
+                        }
+                        println!(
+                            "{} Removed {} orphaned director{}.",
+                            "OK".bright_green(),
+                            removed.len(),
+                            if removed.len() == 1 { "y" } else { "ies" }
+                        );
+                    }
+                    Err(e) => eprintln!("{} {}", "!!".bright_red(), e),
+                }
+            }
         },
 
         Commands::Config(sub) => match sub {
@@ -372,3 +517,18 @@ async fn main() {
         },
     }
 }
+
+/// Set or clear the execution kill-switch for the keeper or a single shard.
+fn set_paused(shard: Option<&str>, paused: bool) {
+    let cfg = config::Config::load().unwrap_or_default();
+    let result = db::init_db(&cfg.data_dir).and_then(|_| db::set_paused(&cfg.data_dir, shard, paused));
+    match result {
+        Ok(()) => println!(
+            "{} Executions {} for {}.",
+            "OK".bright_green(),
+            if paused { "paused" } else { "resumed" },
+            shard.unwrap_or("all shards").bright_cyan()
+        ),
+        Err(e) => eprintln!("{} Failed to update pause state: {}", "!!".bright_red(), e),
+    }
+}
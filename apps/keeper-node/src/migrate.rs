@@ -0,0 +1,287 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::cleanup::shard_dirs;
+use crate::config::shellexpand;
+use crate::db::{self, MigratedShard};
+
+/// Outcome of moving shards from one data dir to another.
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationReport {
+    pub shards: Vec<MigratedShard>,
+    /// Workspace and memory artifact files copied
+    pub files: usize,
+}
+
+/// Copy shards (all, or just `only`) from the data dir `from` into `to`,
+/// keeping each owner within `max_per_owner` shards (0 = no limit):
+/// DB rows via `db::migrate_shards`, plus each shard's workspace and memory
+/// artifacts. The source is left untouched. Files are copied first and
+/// removed again if the DB step fails, so a failed migration leaves `to` as
+/// it was.
+pub fn migrate(from: &str, to: &str, only: Option<&str>, max_per_owner: u32) -> Result<MigrationReport, String> {
+    let (from_root, to_root) = (PathBuf::from(shellexpand(from)), PathBuf::from(shellexpand(to)));
+    if !from_root.join("keeper.db").is_file() {
+        return Err(format!("No keeper database in {}", from));
+    }
+    std::fs::create_dir_all(&to_root).map_err(|e| format!("Failed to create {}: {}", to, e))?;
+    let same_dir = match (from_root.canonicalize(), to_root.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    };
+    if same_dir {
+        return Err("Source and destination data dirs are the same".to_string());
+    }
+
+    let shard_ids: Vec<String> = db::get_shards(from)
+        .map_err(|e| format!("Failed to read shards from {}: {}", from, e))?
+        .into_iter()
+        .map(|s| s.id)
+        .filter(|id| only.is_none_or(|o| o == id))
+        .collect();
+
+    let mut copied_dirs = Vec::new();
+    let mut files = 0;
+    for id in &shard_ids {
+        for dir in shard_dirs(id) {
+            let (src, dst) = (from_root.join(&dir), to_root.join(&dir));
+            if !src.is_dir() {
+                continue;
+            }
+            let result = if dst.exists() {
+                Err(format!("{} already exists", dst.display()))
+            } else {
+                copied_dirs.push(dst.clone());
+                copy_dir(&src, &dst)
+            };
+            match result {
+                Ok(n) => files += n,
+                Err(e) => {
+                    remove_all(&copied_dirs);
+                    return Err(format!("Failed to copy files for shard {}: {}", id, e));
+                }
+            }
+        }
+    }
+
+    match db::migrate_shards(from, to, only, max_per_owner) {
+        Ok(shards) => Ok(MigrationReport { shards, files }),
+        Err(e) => {
+            remove_all(&copied_dirs);
+            Err(format!("Migration failed: {}", e))
+        }
+    }
+}
+
+/// Recursively copy `src` to `dst`, returning how many files were copied.
+/// Symlinks are skipped rather than followed out of the workspace.
+fn copy_dir(src: &Path, dst: &Path) -> Result<usize, String> {
+    std::fs::create_dir_all(dst).map_err(|e| format!("{}: {}", dst.display(), e))?;
+    let mut copied = 0;
+    let entries = std::fs::read_dir(src).map_err(|e| format!("{}: {}", src.display(), e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("{}: {}", src.display(), e))?;
+        let file_type = entry.file_type().map_err(|e| format!("{}: {}", entry.path().display(), e))?;
+        let target = dst.join(entry.file_name());
+        if file_type.is_dir() {
+            copied += copy_dir(&entry.path(), &target)?;
+        } else if file_type.is_file() {
+            std::fs::copy(entry.path(), &target).map_err(|e| format!("{}: {}", target.display(), e))?;
+            copied += 1;
+        }
+    }
+    Ok(copied)
+}
+
+fn remove_all(dirs: &[PathBuf]) {
+    for dir in dirs {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shard::Shard;
+
+    fn populate(data_dir: &str, shard: &Shard) {
+        db::insert_shard(data_dir, shard).unwrap();
+        db::insert_interaction(data_dir, &shard.id, "user", "hi", 0).unwrap();
+        db::insert_interaction(data_dir, &shard.id, "assistant", "hello", 10).unwrap();
+        let action = db::insert_action(data_dir, &shard.id, "fib", None).unwrap();
+        let artifact = Path::new(data_dir)
+            .join("memories/tasks")
+            .join(&shard.id)
+            .join(format!("1-{}.json", action));
+        std::fs::create_dir_all(artifact.parent().unwrap()).unwrap();
+        std::fs::write(&artifact, "{}").unwrap();
+        let artifact_path = artifact.to_string_lossy().to_string();
+        let tools = vec!["code_eval".to_string()];
+        db::insert_task_lesson(
+            data_dir,
+            &db::NewTaskLesson {
+                shard_id: &shard.id,
+                action_id: action,
+                task_type: "coding",
+                goal: "print fibonacci",
+                approach: "loop",
+                tools_used: &tools,
+                outcome: "ok",
+                errors: &[],
+                fixes: &[],
+                duration_ms: 10,
+                success: true,
+                extractor_confidence: 0.8,
+                applicability_confidence: 0.8,
+                reusability: 0.8,
+                artifact_path: &artifact_path,
+            },
+        )
+        .unwrap();
+        db::insert_snapshot(data_dir, shard, Some("before")).unwrap();
+        db::record_relationship(data_dir, &shard.id, "rival", db::RelationshipKind::Battled).unwrap();
+        db::upsert_job(
+            data_dir,
+            &db::JobRecord {
+                id: format!("job-{}", shard.id),
+                shard_id: shard.id.clone(),
+                task: "fib".to_string(),
+                status: "completed".to_string(),
+                result_json: None,
+                error: None,
+                created_at: 1,
+            },
+        )
+        .unwrap();
+        db::set_paused(data_dir, Some(&shard.id), true).unwrap();
+        let workspace = Path::new(data_dir).join("workspaces").join(&shard.id).join("src");
+        std::fs::create_dir_all(&workspace).unwrap();
+        std::fs::write(workspace.join("main.py"), "print(1)").unwrap();
+    }
+
+    #[test]
+    fn migrates_rows_and_files_into_fresh_data_dir() {
+        let (src_dir, dst_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let from = src_dir.path().to_string_lossy().to_string();
+        let to = dst_dir.path().join("keeper").to_string_lossy().to_string();
+        db::init_db(&from).unwrap();
+        let (a, b) = (Shard::spawn(None), Shard::spawn(None));
+        populate(&from, &a);
+        populate(&from, &b);
+
+        // The destination already has a shard with its own history, so copied
+        // actions and lessons get new ids
+        db::init_db(&to).unwrap();
+        populate(&to, &Shard::spawn(None));
+
+        let report = migrate(&from, &to, None, 0).unwrap();
+        assert_eq!(report.shards.len(), 2);
+        assert_eq!(report.files, 4);
+        assert_eq!(db::get_shards(&to).unwrap().len(), 3);
+        for shard in [&a, &b] {
+            assert_eq!(db::get_interactions(&to, &shard.id, 10).unwrap().len(), 2);
+            let actions = db::get_actions(&to, &shard.id, 10).unwrap();
+            assert_eq!(actions.len(), 1);
+            let lessons = db::get_recent_task_lessons(&to, &shard.id, 10).unwrap();
+            assert_eq!(lessons.len(), 1);
+            assert_eq!(lessons[0].action_id, actions[0].id);
+            assert!(lessons[0].artifact_path.starts_with(&to));
+            assert!(Path::new(&lessons[0].artifact_path).is_file());
+            assert!(Path::new(&to).join("workspaces").join(&shard.id).join("src/main.py").is_file());
+            assert_eq!(db::get_snapshots(&to, &shard.id, 10).unwrap().len(), 1);
+            assert_eq!(db::get_relationships(&to, &shard.id).unwrap().len(), 1);
+            assert!(db::get_job(&to, &format!("job-{}", shard.id)).unwrap().is_some());
+            assert!(db::is_paused(&to, Some(&shard.id)).unwrap());
+        }
+        // The source is untouched
+        assert_eq!(db::get_shards(&from).unwrap().len(), 2);
+
+        // Migrating again collides and leaves the destination as it was
+        let err = migrate(&from, &to, Some(&a.id), 0).unwrap_err();
+        assert!(err.contains("already exists"), "{}", err);
+        assert_eq!(db::get_shards(&to).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn migrates_a_single_shard() {
+        let (src_dir, dst_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let from = src_dir.path().to_string_lossy().to_string();
+        let to = dst_dir.path().to_string_lossy().to_string();
+        db::init_db(&from).unwrap();
+        let (a, b) = (Shard::spawn(None), Shard::spawn(None));
+        populate(&from, &a);
+        populate(&from, &b);
+
+        let report = migrate(&from, &to, Some(&b.id), 0).unwrap();
+        assert_eq!(report.shards.len(), 1);
+        assert_eq!(report.shards[0].shard_id, b.id);
+        assert_eq!(report.shards[0].interactions, 2);
+        assert_eq!(report.shards[0].lessons, 1);
+        let migrated = db::get_shards(&to).unwrap();
+        assert_eq!(migrated.len(), 1);
+        assert_eq!(migrated[0].id, b.id);
+
+        assert!(migrate(&from, &to, Some("missing"), 0).unwrap_err().contains("not found"));
+        assert!(migrate(&from, &from, None, 0).unwrap_err().contains("same"));
+    }
+
+    #[test]
+    fn migrates_a_pending_question_with_its_action() {
+        let (src_dir, dst_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let from = src_dir.path().to_string_lossy().to_string();
+        let to = dst_dir.path().to_string_lossy().to_string();
+        db::init_db(&from).unwrap();
+        let shard = Shard::spawn(None);
+        populate(&from, &shard);
+        let paused = db::insert_action(&from, &shard.id, "summarize the file", None).unwrap();
+        db::set_pending_question(
+            &from,
+            &db::PendingQuestionRecord {
+                shard_id: shard.id.clone(),
+                task: "summarize the file".to_string(),
+                call_id: "call_ask".to_string(),
+                question: "Which file?".to_string(),
+                conversation_json: "[]".to_string(),
+                request_json: Some("{\"task\":\"summarize the file\"}".to_string()),
+                action_id: Some(paused),
+                created_at: 1,
+            },
+        )
+        .unwrap();
+        db::init_db(&to).unwrap();
+        populate(&to, &Shard::spawn(None));
+
+        migrate(&from, &to, None, 0).unwrap();
+        let question = db::get_pending_question(&to, &shard.id).unwrap().unwrap();
+        assert_eq!(question.call_id, "call_ask");
+        assert_eq!(question.request_json.as_deref(), Some("{\"task\":\"summarize the file\"}"));
+        let copied = db::get_actions(&to, &shard.id, 10)
+            .unwrap()
+            .into_iter()
+            .find(|a| a.task_description == "summarize the file")
+            .unwrap();
+        assert_eq!(question.action_id, Some(copied.id));
+        assert_ne!(copied.id, paused);
+    }
+
+    #[test]
+    fn migration_past_an_owner_quota_writes_nothing() {
+        let (src_dir, dst_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let from = src_dir.path().to_string_lossy().to_string();
+        let to = dst_dir.path().to_string_lossy().to_string();
+        db::init_db(&from).unwrap();
+        db::init_db(&to).unwrap();
+        let owned = |owner: &str| Shard { owner_id: Some(owner.to_string()), ..Shard::spawn(None) };
+        let (a, b) = (owned("0xabc"), owned("0xabc"));
+        populate(&from, &a);
+        populate(&from, &b);
+        db::insert_shard(&to, &owned("0xABC")).unwrap();
+
+        let err = migrate(&from, &to, None, 2).unwrap_err();
+        assert!(err.contains("max_shards_per_owner"), "{}", err);
+        assert_eq!(db::get_shards(&to).unwrap().len(), 1);
+        assert!(!Path::new(&to).join("workspaces").join(&a.id).exists());
+
+        assert_eq!(migrate(&from, &to, None, 3).unwrap().shards.len(), 2);
+    }
+}
@@ -1,5 +1,24 @@
+use std::sync::OnceLock;
+use std::time::Instant;
 use sysinfo::System;
 
+static PROCESS_START: OnceLock<Instant> = OnceLock::new();
+
+/// Record when this keeper process started. Call once at startup; later calls
+/// are no-ops. If never called, the first uptime query starts the clock.
+pub fn mark_process_start() {
+    PROCESS_START.get_or_init(Instant::now);
+}
+
+/// Seconds since this keeper process started.
+pub fn keeper_uptime_secs() -> u64 {
+    uptime_since(*PROCESS_START.get_or_init(Instant::now), Instant::now())
+}
+
+fn uptime_since(start: Instant, now: Instant) -> u64 {
+    now.saturating_duration_since(start).as_secs()
+}
+
 /// System resource statistics for the keeper node.
 pub struct SystemStats {
     /// CPU usage as a percentage (0.0 - 100.0)
@@ -10,14 +29,16 @@ pub struct SystemStats {
     pub memory_total_mb: f64,
     /// Free disk space in gigabytes
     pub disk_free_gb: f64,
-    /// System uptime in seconds
-    pub uptime_secs: u64,
+    /// Host (operating system) uptime in seconds
+    pub host_uptime_secs: u64,
+    /// Uptime of this keeper process in seconds
+    pub keeper_uptime_secs: u64,
 }
 
 /// Collect current system resource statistics.
 ///
 /// Uses the `sysinfo` crate to read CPU usage, memory consumption,
-/// disk space, and system uptime.
+/// disk space, and host uptime, plus this process's uptime.
 pub fn get_system_stats() -> SystemStats {
     let mut sys = System::new_all();
     sys.refresh_all();
@@ -41,27 +62,26 @@ pub fn get_system_stats() -> SystemStats {
         .map(|d| d.available_space() as f64 / (1024.0 * 1024.0 * 1024.0))
         .sum();
 
-    // System uptime
-    let uptime_secs = System::uptime();
-
     SystemStats {
         cpu_usage,
         memory_used_mb,
         memory_total_mb,
         disk_free_gb,
-        uptime_secs,
+        host_uptime_secs: System::uptime(),
+        keeper_uptime_secs: keeper_uptime_secs(),
     }
 }
 
 /// Format system stats as a human-readable string for logging.
 pub fn format_stats(stats: &SystemStats) -> String {
     format!(
-        "CPU: {:.1}% | RAM: {:.0}/{:.0} MB | Disk free: {:.1} GB | Uptime: {}",
+        "CPU: {:.1}% | RAM: {:.0}/{:.0} MB | Disk free: {:.1} GB | Uptime: {} (host {})",
         stats.cpu_usage,
         stats.memory_used_mb,
         stats.memory_total_mb,
         stats.disk_free_gb,
-        format_duration(stats.uptime_secs)
+        format_duration(stats.keeper_uptime_secs),
+        format_duration(stats.host_uptime_secs)
     )
 }
 
@@ -79,3 +99,25 @@ fn format_duration(secs: u64) -> String {
         format!("{}m {}s", minutes, secs % 60)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn keeper_uptime_counts_from_process_start() {
+        let start = Instant::now();
+        assert_eq!(uptime_since(start, start + Duration::from_secs(42)), 42);
+        // A clock read before the start never underflows
+        assert_eq!(uptime_since(start + Duration::from_secs(1), start), 0);
+
+        mark_process_start();
+        let first = keeper_uptime_secs();
+        std::thread::sleep(Duration::from_millis(1100));
+        let stats = get_system_stats();
+        assert!(stats.keeper_uptime_secs > first);
+        // The host has been up for longer than this test process
+        assert!(stats.host_uptime_secs > stats.keeper_uptime_secs);
+    }
+}
@@ -1,6 +1,6 @@
 use libp2p::{
     gossipsub, identify, kad,
-    noise, tcp, websocket, yamux,
+    noise, tcp, yamux,
     Multiaddr, PeerId, Swarm, SwarmBuilder,
     swarm::NetworkBehaviour,
 };
@@ -18,14 +18,18 @@ pub struct KeeperBehaviour {
 /// Create and configure the libp2p swarm for the keeper node.
 ///
 /// Sets up:
-/// - TCP + WebSocket transports
+/// - TCP + WebSocket transports, with DNS resolution for `/dns*` addresses
 /// - Noise encryption
 /// - Yamux stream multiplexing
 /// - Kademlia DHT for record storage
 /// - GossipSub for pub/sub messaging
 /// - Identify protocol for peer identification
+///
+/// The WebSocket listener binds `ws_port` when given; see `select_ws_port`.
 pub async fn create_node(
     port: u16,
+    ws_port: Option<u16>,
+    idle_timeout: Duration,
     bootstrap_peers: &[String],
 ) -> Result<Swarm<KeeperBehaviour>, Box<dyn Error>> {
     let swarm = SwarmBuilder::with_new_identity()
@@ -35,6 +39,7 @@ pub async fn create_node(
             noise::Config::new,
             yamux::Config::default,
         )?
+        .with_dns()?
         .with_websocket(
             (noise::Config::new, noise::Config::new),
             yamux::Config::default,
@@ -71,23 +76,25 @@ pub async fn create_node(
                 identify,
             }
         })?
-        .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+        .with_swarm_config(|c| c.with_idle_connection_timeout(idle_timeout))
         .build();
 
     // Listen on TCP and WebSocket
     let listen_addr_tcp: Multiaddr = format!("/ip4/0.0.0.0/tcp/{}", port).parse()?;
-    let listen_addr_ws: Multiaddr = format!("/ip4/0.0.0.0/tcp/{}/ws", port + 1).parse()?;
 
     let mut swarm = swarm;
     swarm.listen_on(listen_addr_tcp)?;
-    swarm.listen_on(listen_addr_ws)?;
+    if let Some(ws_port) = ws_port {
+        let listen_addr_ws: Multiaddr = format!("/ip4/0.0.0.0/tcp/{}/ws", ws_port).parse()?;
+        swarm.listen_on(listen_addr_ws)?;
+    }
 
-    // Connect to bootstrap peers
+    // Connect to bootstrap peers. Hostnames resolve when dialed; failures
+    // surface per peer as connection errors rather than stopping startup.
     for addr_str in bootstrap_peers {
-        match addr_str.parse::<Multiaddr>() {
-            Ok(addr) => {
-                // Extract peer ID from the multiaddr if present
-                if let Some(peer_id) = extract_peer_id(&addr) {
+        match parse_bootstrap_addr(addr_str) {
+            Ok((addr, peer_id)) => {
+                if let Some(peer_id) = peer_id {
                     swarm.behaviour_mut().kademlia.add_address(&peer_id, addr.clone());
                     tracing::info!("Added bootstrap peer: {}", addr);
                 }
@@ -104,6 +111,54 @@ pub async fn create_node(
     Ok(swarm)
 }
 
+/// Pick the WebSocket listen port, or `None` to run without the WebSocket
+/// transport: when it is disabled (0), clashes with the P2P TCP or HTTP API
+/// port, or is already bound by another process. Problems are logged rather
+/// than failing the node.
+pub fn select_ws_port(ws_port: u16, tcp_port: u16, http_port: u16) -> Option<u16> {
+    if ws_port == 0 {
+        return None;
+    }
+    let clash = if ws_port == tcp_port {
+        Some("the P2P TCP port")
+    } else if ws_port == http_port {
+        Some("the HTTP API port")
+    } else {
+        None
+    };
+    if let Some(other) = clash {
+        tracing::warn!("ws_port {} is also {}; WebSocket transport disabled", ws_port, other);
+        return None;
+    }
+    if std::net::TcpListener::bind(("0.0.0.0", ws_port)).is_err() {
+        tracing::warn!("ws_port {} is already in use; WebSocket transport disabled", ws_port);
+        return None;
+    }
+    Some(ws_port)
+}
+
+/// Parse a bootstrap peer address, returning it with its peer ID if it has a
+/// `/p2p/` suffix. The address must start with an IP or a DNS name
+/// (`/dns`, `/dns4`, `/dns6` or `/dnsaddr`).
+pub fn parse_bootstrap_addr(addr_str: &str) -> Result<(Multiaddr, Option<PeerId>), String> {
+    use libp2p::multiaddr::Protocol;
+
+    let addr: Multiaddr = addr_str.trim().parse().map_err(|e| format!("{}", e))?;
+    match addr.iter().next() {
+        Some(
+            Protocol::Ip4(_)
+            | Protocol::Ip6(_)
+            | Protocol::Dns(_)
+            | Protocol::Dns4(_)
+            | Protocol::Dns6(_)
+            | Protocol::Dnsaddr(_),
+        ) => {}
+        _ => return Err("address must start with /ip4, /ip6, /dns, /dns4, /dns6 or /dnsaddr".to_string()),
+    }
+    let peer_id = extract_peer_id(&addr);
+    Ok((addr, peer_id))
+}
+
 /// Extract PeerId from a multiaddr that ends with /p2p/<peer_id>
 fn extract_peer_id(addr: &Multiaddr) -> Option<PeerId> {
     addr.iter().find_map(|proto| {
@@ -114,3 +169,41 @@ fn extract_peer_id(addr: &Multiaddr) -> Option<PeerId> {
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ws_port_selection_skips_clashes_and_busy_ports() {
+        let free = std::net::TcpListener::bind("0.0.0.0:0").unwrap();
+        let port = free.local_addr().unwrap().port();
+        drop(free);
+        assert_eq!(select_ws_port(port, 9000, 3001), Some(port));
+
+        assert_eq!(select_ws_port(0, 9000, 3001), None);
+        assert_eq!(select_ws_port(9000, 9000, 3001), None);
+        assert_eq!(select_ws_port(3001, 9000, 3001), None);
+
+        let busy = std::net::TcpListener::bind("0.0.0.0:0").unwrap();
+        let port = busy.local_addr().unwrap().port();
+        assert_eq!(select_ws_port(port, 9000, 3001), None);
+    }
+
+    #[test]
+    fn hostname_bootstrap_addrs_are_accepted() {
+        let peer = PeerId::random();
+        let (addr, peer_id) =
+            parse_bootstrap_addr(&format!("/dns4/boot.siphon.example/tcp/9000/p2p/{}", peer)).unwrap();
+        assert_eq!(peer_id, Some(peer));
+        assert!(addr.to_string().starts_with("/dns4/boot.siphon.example/tcp/9000"));
+
+        let (_, peer_id) = parse_bootstrap_addr(&format!("/dnsaddr/bootstrap.example/p2p/{}", peer)).unwrap();
+        assert_eq!(peer_id, Some(peer));
+        let (_, peer_id) = parse_bootstrap_addr("/ip4/10.0.0.1/tcp/9000").unwrap();
+        assert_eq!(peer_id, None);
+
+        assert!(parse_bootstrap_addr("boot.siphon.example:9000").is_err());
+        assert!(parse_bootstrap_addr("/tcp/9000").is_err());
+    }
+}
@@ -0,0 +1,238 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::db::TaskLesson;
+use crate::shard::{self, Shard};
+
+/// Task types produced by the executor's task classifier.
+const TASK_TYPES: [&str; 4] = ["debug", "coding", "analysis", "writing"];
+
+/// A stat is "weak" when it trails the shard's stat average by this much.
+const WEAK_STAT_MARGIN: u32 = 10;
+
+/// Minimum failures of one task type before suggesting practice.
+const MIN_TYPE_FAILURES: u32 = 3;
+
+/// Aggregate action counts for a shard, as returned by `db::get_action_summary`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ActionStats {
+    pub total: u32,
+    pub success: u32,
+    pub failed: u32,
+}
+
+impl From<(u32, u32, u32)> for ActionStats {
+    fn from((total, success, failed): (u32, u32, u32)) -> Self {
+        Self { total, success, failed }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    High,
+    Medium,
+    Low,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecommendationKind {
+    /// Repeated failures on one task type
+    PracticeTaskType,
+    /// A stat well below the shard's average
+    TrainStat,
+    /// A task type the shard has never recorded a lesson for
+    ExploreTaskType,
+    /// A capability the shard will unlock by levelling
+    Unlock,
+    /// Overall success rate is low
+    ReviewFailures,
+}
+
+/// A single next-step suggestion for a shard's keeper.
+#[derive(Debug, Clone, Serialize)]
+pub struct Recommendation {
+    pub kind: RecommendationKind,
+    pub priority: Priority,
+    /// Stat, task type or tool the recommendation is about
+    pub target: String,
+    pub message: String,
+}
+
+/// Produce training recommendations for a shard from its stats, action
+/// history and lessons. Pure and rule-based, so the same inputs always
+/// yield the same list, ordered by priority.
+pub fn for_shard(shard: &Shard, stats: &ActionStats, lessons: &[TaskLesson]) -> Vec<Recommendation> {
+    let mut recs = Vec::new();
+
+    // Per-type outcomes from lessons (BTreeMap keeps output order stable)
+    let mut by_type: BTreeMap<&str, (u32, u32)> = BTreeMap::new();
+    for lesson in lessons {
+        let entry = by_type.entry(lesson.task_type.as_str()).or_default();
+        if lesson.success {
+            entry.0 += 1;
+        } else {
+            entry.1 += 1;
+        }
+    }
+
+    for (task_type, &(success, failed)) in &by_type {
+        if failed >= MIN_TYPE_FAILURES && failed >= success {
+            recs.push(Recommendation {
+                kind: RecommendationKind::PracticeTaskType,
+                priority: Priority::High,
+                target: task_type.to_string(),
+                message: format!(
+                    "Practice {} tasks: {} of the last {} failed",
+                    task_type,
+                    failed,
+                    success + failed
+                ),
+            });
+        }
+    }
+
+    let named = [
+        ("intelligence", shard.stats.intelligence),
+        ("creativity", shard.stats.creativity),
+        ("precision", shard.stats.precision),
+        ("resilience", shard.stats.resilience),
+        ("charisma", shard.stats.charisma),
+    ];
+    let average = named.iter().map(|(_, v)| v).sum::<u32>() / named.len() as u32;
+    for (stat, value) in named {
+        if value + WEAK_STAT_MARGIN <= average {
+            recs.push(Recommendation {
+                kind: RecommendationKind::TrainStat,
+                priority: Priority::Medium,
+                target: stat.to_string(),
+                message: format!(
+                    "Raise {} ({} vs. average {}): {}",
+                    stat,
+                    value,
+                    average,
+                    stat_training_hint(stat)
+                ),
+            });
+        }
+    }
+
+    if stats.total >= 5 && stats.failed * 2 > stats.total {
+        recs.push(Recommendation {
+            kind: RecommendationKind::ReviewFailures,
+            priority: Priority::Medium,
+            target: "actions".to_string(),
+            message: format!(
+                "{} of {} actions failed; review recent failures and try smaller tasks",
+                stats.failed, stats.total
+            ),
+        });
+    }
+
+    if !lessons.is_empty() {
+        for task_type in TASK_TYPES {
+            if !by_type.contains_key(task_type) {
+                recs.push(Recommendation {
+                    kind: RecommendationKind::ExploreTaskType,
+                    priority: Priority::Low,
+                    target: task_type.to_string(),
+                    message: format!("Try some {} tasks; no lessons recorded yet", task_type),
+                });
+            }
+        }
+    }
+
+    let shell_level = shard::shell_unlock_level();
+    if !shard.capabilities.can_shell && shard.level < shell_level {
+        recs.push(Recommendation {
+            kind: RecommendationKind::Unlock,
+            priority: Priority::Low,
+            target: "shell_exec".to_string(),
+            message: format!(
+                "Unlock shell at level {} ({} more level(s))",
+                shell_level,
+                shell_level - shard.level
+            ),
+        });
+    }
+
+    // Stable sort keeps rule order within a priority
+    recs.sort_by_key(|r| r.priority);
+    recs
+}
+
+/// How each stat is earned (mirrors the executor's stat bonuses).
+fn stat_training_hint(stat: &str) -> &'static str {
+    match stat {
+        "intelligence" => "run analysis or coding tasks that use code_eval and http_fetch",
+        "precision" => "practice debug tasks that read and fix files",
+        "resilience" => "run shell tasks once shell access unlocks",
+        "creativity" | "charisma" => "train through conversation",
+        _ => "keep training",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lesson(task_type: &str, success: bool) -> TaskLesson {
+        TaskLesson {
+            id: 0,
+            shard_id: "s".to_string(),
+            action_id: 0,
+            task_type: task_type.to_string(),
+            goal: String::new(),
+            approach: String::new(),
+            tools_used: vec![],
+            outcome: String::new(),
+            errors: vec![],
+            fixes: vec![],
+            duration_ms: 0,
+            success,
+            extractor_confidence: 0.5,
+            applicability_confidence: 0.5,
+            reusability: 0.5,
+            score: 0.5,
+            artifact_path: String::new(),
+            times_retrieved: 0,
+            times_helpful: 0,
+            times_unhelpful: 0,
+            created_at: 0,
+            updated_at: 0,
+            pinned: false,
+        }
+    }
+
+    #[test]
+    fn low_precision_and_debug_failures_recommend_debug_practice() {
+        let mut shard = Shard::spawn(None);
+        shard.stats.intelligence = 80;
+        shard.stats.creativity = 80;
+        shard.stats.precision = 50;
+        shard.stats.resilience = 80;
+        shard.stats.charisma = 80;
+
+        let mut lessons: Vec<TaskLesson> = (0..4).map(|_| lesson("debug", false)).collect();
+        lessons.push(lesson("debug", true));
+        lessons.push(lesson("writing", true));
+        let stats = ActionStats { total: 6, success: 2, failed: 4 };
+
+        let recs = for_shard(&shard, &stats, &lessons);
+
+        assert_eq!(recs[0].kind, RecommendationKind::PracticeTaskType);
+        assert_eq!(recs[0].target, "debug");
+        assert!(recs[0].message.contains("Practice debug tasks"));
+        assert!(recs.iter().any(|r| r.kind == RecommendationKind::TrainStat
+            && r.target == "precision"
+            && r.message.contains("debug")));
+        assert!(!recs
+            .iter()
+            .any(|r| r.kind == RecommendationKind::TrainStat && r.target == "charisma"));
+        assert!(recs
+            .iter()
+            .any(|r| r.kind == RecommendationKind::ExploreTaskType && r.target == "coding"));
+        assert!(recs.windows(2).all(|w| w[0].priority <= w[1].priority));
+    }
+}
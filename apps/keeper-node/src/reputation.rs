@@ -0,0 +1,176 @@
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::db;
+
+/// Reputation a keeper starts with.
+pub const STARTING_REPUTATION: u64 = 100;
+
+const REPUTATION_KEY: &str = "reputation";
+
+/// Keeper duties whose repeated failure costs reputation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Duty {
+    Liquidation,
+    Attestation,
+}
+
+impl Duty {
+    fn failures_key(self) -> &'static str {
+        match self {
+            Duty::Liquidation => "failures:liquidation",
+            Duty::Attestation => "failures:attestation",
+        }
+    }
+}
+
+/// How failed and successful duties move reputation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PenaltyPolicy {
+    /// Consecutive failures of one duty before each further failure is
+    /// penalized (0 disables penalties)
+    pub threshold: u32,
+    /// Reputation lost per penalized failure
+    pub penalty: u64,
+    /// Reputation regained per successful duty, up to `STARTING_REPUTATION`
+    pub recovery: u64,
+}
+
+/// A gossip warning that the keeper's reputation has fallen too low.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReputationWarning {
+    pub reputation: u64,
+    pub threshold: u64,
+}
+
+/// The keeper's current reputation.
+pub fn reputation(data_dir: &str) -> Result<u64, String> {
+    let value = db::get_keeper_state(data_dir, REPUTATION_KEY).map_err(|e| format!("DB error: {}", e))?;
+    Ok(value.and_then(|v| v.parse().ok()).unwrap_or(STARTING_REPUTATION))
+}
+
+/// Move reputation by `delta`, keeping it within `0..=STARTING_REPUTATION`.
+fn adjust_reputation(data_dir: &str, delta: i64) -> Result<u64, String> {
+    db::adjust_keeper_state(
+        data_dir,
+        REPUTATION_KEY,
+        STARTING_REPUTATION as i64,
+        delta,
+        STARTING_REPUTATION as i64,
+    )
+    .map(|value| value as u64)
+    .map_err(|e| format!("DB error: {}", e))
+}
+
+/// Record a failed duty. From `threshold` consecutive failures on, each
+/// failure costs `penalty` reputation. Returns the reputation afterwards.
+fn record_failure(data_dir: &str, duty: Duty, policy: PenaltyPolicy) -> Result<u64, String> {
+    let failures = db::adjust_keeper_state(data_dir, duty.failures_key(), 0, 1, i64::MAX)
+        .map_err(|e| format!("DB error: {}", e))?;
+    if policy.threshold == 0 || failures < policy.threshold as i64 || policy.penalty == 0 {
+        return reputation(data_dir);
+    }
+    let current = adjust_reputation(data_dir, -(policy.penalty.min(STARTING_REPUTATION) as i64))?;
+    tracing::warn!(
+        "{} consecutive {:?} failures; keeper reputation now {}",
+        failures,
+        duty,
+        current
+    );
+    Ok(current)
+}
+
+/// Record a successful duty: reset its failure streak and win back
+/// `recovery` reputation. Returns the reputation afterwards.
+fn record_success(data_dir: &str, duty: Duty, policy: PenaltyPolicy) -> Result<u64, String> {
+    db::set_keeper_state(data_dir, duty.failures_key(), "0").map_err(|e| format!("DB error: {}", e))?;
+    adjust_reputation(data_dir, policy.recovery.min(STARTING_REPUTATION) as i64)
+}
+
+/// Record a duty's outcome under the configured penalty policy. Every duty
+/// goes through here; keepers without a valuation contract have no
+/// attestation duty to fail, so those outcomes are ignored. Storage errors
+/// are logged rather than returned.
+pub fn record(config: &Config, duty: Duty, ok: bool) {
+    if duty == Duty::Attestation && config.shard_valuation_address.is_none() {
+        return;
+    }
+    let policy = config.penalty_policy();
+    let result = if ok {
+        record_success(&config.data_dir, duty, policy)
+    } else {
+        record_failure(&config.data_dir, duty, policy)
+    };
+    if let Err(e) = result {
+        tracing::warn!("Failed to record {:?} outcome: {}", duty, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn penalties_start_at_the_threshold_and_success_resets() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        db::init_db(&data_dir).unwrap();
+        let policy = PenaltyPolicy {
+            threshold: 2,
+            penalty: 10,
+            recovery: 0,
+        };
+
+        assert_eq!(record_failure(&data_dir, Duty::Attestation, policy).unwrap(), 100);
+        assert_eq!(record_failure(&data_dir, Duty::Attestation, policy).unwrap(), 90);
+        record_success(&data_dir, Duty::Attestation, policy).unwrap();
+        assert_eq!(record_failure(&data_dir, Duty::Attestation, policy).unwrap(), 90);
+
+        let off = PenaltyPolicy { threshold: 0, ..policy };
+        for _ in 0..5 {
+            record_failure(&data_dir, Duty::Liquidation, off).unwrap();
+        }
+        assert_eq!(reputation(&data_dir).unwrap(), 90);
+    }
+
+    #[test]
+    fn successes_recover_reputation_up_to_the_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_string_lossy().to_string();
+        db::init_db(&data_dir).unwrap();
+        let policy = PenaltyPolicy {
+            threshold: 1,
+            penalty: 60,
+            recovery: 25,
+        };
+
+        assert_eq!(record_failure(&data_dir, Duty::Liquidation, policy).unwrap(), 40);
+        assert_eq!(record_failure(&data_dir, Duty::Liquidation, policy).unwrap(), 0);
+        assert_eq!(record_success(&data_dir, Duty::Liquidation, policy).unwrap(), 25);
+        for _ in 0..5 {
+            record_success(&data_dir, Duty::Liquidation, policy).unwrap();
+        }
+        assert_eq!(reputation(&data_dir).unwrap(), STARTING_REPUTATION);
+    }
+
+    #[test]
+    fn attestations_only_count_with_a_valuation_contract() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config {
+            data_dir: dir.path().to_string_lossy().to_string(),
+            penalty_failure_threshold: 1,
+            penalty_reputation: 10,
+            ..Config::default()
+        };
+        db::init_db(&config.data_dir).unwrap();
+
+        record(&config, Duty::Attestation, false);
+        assert_eq!(reputation(&config.data_dir).unwrap(), STARTING_REPUTATION);
+
+        config.shard_valuation_address = Some("0x0000000000000000000000000000000000000001".to_string());
+        record(&config, Duty::Attestation, false);
+        record(&config, Duty::Liquidation, false);
+        assert_eq!(reputation(&config.data_dir).unwrap(), 80);
+    }
+}
@@ -1,7 +1,11 @@
 use serde::{Deserialize, Serialize};
 use sha3::{Digest, Keccak256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
 use uuid::Uuid;
 
+use crate::rng::Rng;
+
 /// Shard types mirroring the TypeScript ShardType enum.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ShardType {
@@ -101,22 +105,150 @@ pub struct ShardStats {
     pub charisma: u32,
 }
 
+/// Default ceiling for a single stat.
+pub const DEFAULT_STAT_CAP: u32 = 250;
+
+/// What happens to stat bonus points that would push a stat past the cap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatOverflow {
+    /// Give the points to the lowest stats still under the cap; once every
+    /// stat is capped the rest becomes XP
+    #[default]
+    Redistribute,
+    /// Convert the points to XP, one XP per point
+    Xp,
+}
+
+/// Cap and overflow policy applied to every stat gain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatLimits {
+    pub cap: u32,
+    pub overflow: StatOverflow,
+}
+
+impl Default for StatLimits {
+    fn default() -> Self {
+        Self {
+            cap: DEFAULT_STAT_CAP,
+            overflow: StatOverflow::default(),
+        }
+    }
+}
+
+/// Genome byte that sets a shard's growth potential; spawn derives nothing
+/// else from it.
+const POTENTIAL_BYTE: usize = 16;
+
+/// Whether `genome_hash` has the shape `spawn` produces: `0x` followed by
+/// 64 hex digits.
+pub fn is_valid_genome_hash(genome_hash: &str) -> bool {
+    genome_hash
+        .strip_prefix("0x")
+        .is_some_and(|hex| hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// Per-shard stat ceiling set by the genome: between 70% and 100% of
+/// `keeper_cap`, from one genome byte. A malformed genome gets the full cap.
+pub fn genome_stat_cap(genome_hash: &str, keeper_cap: u32) -> u32 {
+    let hex = genome_hash.trim_start_matches("0x");
+    let start = POTENTIAL_BYTE * 2;
+    let Some(byte) = hex.get(start..start + 2).and_then(|b| u8::from_str_radix(b, 16).ok()) else {
+        return keeper_cap;
+    };
+    let percent = 70 + byte as u64 * 30 / 255;
+    (keeper_cap as u64 * percent / 100) as u32
+}
+
+/// Throttle for a shard that keeps failing: from `threshold` consecutive
+/// failed executions on, it cools down for `base_secs`, doubling with each
+/// further failure up to `max_secs`. A threshold of 0 disables it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FailureCooldown {
+    pub threshold: u32,
+    pub base_secs: u64,
+    pub max_secs: u64,
+}
+
+impl FailureCooldown {
+    /// Cooldown owed after `consecutive_failures` failures in a row (0 = none).
+    pub fn cooldown_secs(&self, consecutive_failures: u32) -> u64 {
+        if self.threshold == 0 || consecutive_failures < self.threshold {
+            return 0;
+        }
+        let doublings = (consecutive_failures - self.threshold).min(32);
+        self.base_secs.saturating_mul(1 << doublings).min(self.max_secs)
+    }
+}
+
+impl ShardStats {
+    pub const NAMES: [&'static str; 5] = ["intelligence", "creativity", "precision", "resilience", "charisma"];
+
+    fn get(&self, name: &str) -> Option<u32> {
+        match name {
+            "intelligence" => Some(self.intelligence),
+            "creativity" => Some(self.creativity),
+            "precision" => Some(self.precision),
+            "resilience" => Some(self.resilience),
+            "charisma" => Some(self.charisma),
+            _ => None,
+        }
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut u32> {
+        match name {
+            "intelligence" => Some(&mut self.intelligence),
+            "creativity" => Some(&mut self.creativity),
+            "precision" => Some(&mut self.precision),
+            "resilience" => Some(&mut self.resilience),
+            "charisma" => Some(&mut self.charisma),
+            _ => None,
+        }
+    }
+
+    /// Add stat bonuses without taking any stat past `limits.cap`. Unknown
+    /// stat names are ignored. Returns the overflow converted to XP.
+    pub fn apply_bonuses(&mut self, bonuses: &HashMap<String, u32>, limits: StatLimits) -> u32 {
+        let mut overflow = 0u32;
+        for (name, bonus) in bonuses {
+            let Some(stat) = self.get_mut(name) else { continue };
+            let room = limits.cap.saturating_sub(*stat);
+            let gained = (*bonus).min(room);
+            *stat += gained;
+            overflow = overflow.saturating_add(bonus - gained);
+        }
+
+        if limits.overflow == StatOverflow::Redistribute {
+            while overflow > 0 {
+                let lowest = Self::NAMES
+                    .iter()
+                    .filter_map(|name| {
+                        let value = *self.get_mut(name)?;
+                        (value < limits.cap).then_some((value, *name))
+                    })
+                    .min_by_key(|(value, _)| *value);
+                let Some((_, name)) = lowest else { break };
+                if let Some(stat) = self.get_mut(name) {
+                    *stat += 1;
+                }
+                overflow -= 1;
+            }
+        }
+        overflow
+    }
+}
+
 /// What execution state a shard is currently in.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecutionState {
+    #[default]
     Idle,
     Executing,
     WaitingForInput,
     Cooldown,
 }
 
-impl Default for ExecutionState {
-    fn default() -> Self {
-        Self::Idle
-    }
-}
-
 /// Capabilities that a shard has unlocked through training and task execution.
 /// Each capability maps to tool access and task types the shard can handle.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -125,10 +257,22 @@ pub struct ShardCapabilities {
     pub can_fetch: bool,
     pub can_file_io: bool,
     pub can_shell: bool,
+    #[serde(default = "default_can_brainstorm")]
+    pub can_brainstorm: bool,
+    #[serde(default = "default_can_recall")]
+    pub can_recall: bool,
     pub max_concurrent_tasks: u32,
     pub learned_context: Vec<String>, // things the shard has learned from past tasks
 }
 
+fn default_can_brainstorm() -> bool {
+    true
+}
+
+fn default_can_recall() -> bool {
+    true
+}
+
 impl Default for ShardCapabilities {
     fn default() -> Self {
         Self {
@@ -136,22 +280,87 @@ impl Default for ShardCapabilities {
             can_fetch: true,
             can_file_io: true,
             can_shell: false, // shell access unlocked at level 5
+            can_brainstorm: true,
+            can_recall: true,
             max_concurrent_tasks: 1,
             learned_context: Vec::new(),
         }
     }
 }
 
+/// What a shard gains on reaching a level milestone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Unlock {
+    /// Access to a tool
+    Tool { tool: &'static str },
+    /// A higher cap on concurrent tasks
+    ConcurrentTasks { max: u32 },
+}
+
+/// A capability unlocked at a given level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Milestone {
+    pub level: u32,
+    #[serde(flatten)]
+    pub unlock: Unlock,
+}
+
+impl Milestone {
+    /// Short player-facing description, e.g. "shell_exec at level 5".
+    pub fn describe(&self) -> String {
+        match self.unlock {
+            Unlock::Tool { tool } => format!("{} at level {}", tool, self.level),
+            Unlock::ConcurrentTasks { max } => {
+                format!("{} concurrent tasks at level {}", max, self.level)
+            }
+        }
+    }
+}
+
+/// Level milestones, in ascending level order. The single source of truth
+/// for `ShardCapabilities::update_for_level` and upcoming-unlock queries.
+pub const LEVEL_MILESTONES: [Milestone; 4] = [
+    Milestone { level: 5, unlock: Unlock::Tool { tool: "shell_exec" } },
+    Milestone { level: 5, unlock: Unlock::ConcurrentTasks { max: 2 } },
+    Milestone { level: 10, unlock: Unlock::ConcurrentTasks { max: 3 } },
+    Milestone { level: 20, unlock: Unlock::ConcurrentTasks { max: 5 } },
+];
+
+/// Level at which shell access unlocks.
+pub fn shell_unlock_level() -> u32 {
+    LEVEL_MILESTONES
+        .iter()
+        .find(|m| m.unlock == Unlock::Tool { tool: "shell_exec" })
+        .map(|m| m.level)
+        .unwrap_or(u32::MAX)
+}
+
+/// The milestones at the lowest level above `level`; empty once every
+/// milestone has been reached.
+pub fn next_milestones(level: u32) -> Vec<Milestone> {
+    let Some(next_level) = LEVEL_MILESTONES.iter().map(|m| m.level).find(|&l| l > level) else {
+        return Vec::new();
+    };
+    LEVEL_MILESTONES
+        .iter()
+        .filter(|m| m.level == next_level)
+        .copied()
+        .collect()
+}
+
 impl ShardCapabilities {
     /// Update capabilities based on shard level.
     pub fn update_for_level(&mut self, level: u32) {
-        self.can_shell = level >= 5;
-        self.max_concurrent_tasks = match level {
-            0..=4 => 1,
-            5..=9 => 2,
-            10..=19 => 3,
-            _ => 5,
-        };
+        self.can_shell = false;
+        self.max_concurrent_tasks = 1;
+        for milestone in LEVEL_MILESTONES.iter().filter(|m| m.level <= level) {
+            match milestone.unlock {
+                Unlock::Tool { tool: "shell_exec" } => self.can_shell = true,
+                Unlock::Tool { .. } => {}
+                Unlock::ConcurrentTasks { max } => self.max_concurrent_tasks = max,
+            }
+        }
     }
 
     /// Get the list of tool names this shard is allowed to use.
@@ -166,10 +375,19 @@ impl ShardCapabilities {
         if self.can_file_io {
             tools.push("file_read");
             tools.push("file_write");
+            tools.push("search_files");
         }
         if self.can_shell {
             tools.push("shell_exec");
         }
+        if self.can_brainstorm {
+            tools.push("brainstorm");
+        }
+        if self.can_recall {
+            tools.push("recall_lessons");
+        }
+        // Asking the keeper needs no capability
+        tools.push("ask_user");
         tools
     }
 
@@ -211,16 +429,198 @@ pub struct Shard {
     pub tasks_completed: u32,
     #[serde(default)]
     pub tasks_failed: u32,
+    /// Number of times this shard has prestiged (reset from the level cap)
+    #[serde(default)]
+    pub prestige: u32,
+    /// Permanent XP gain multiplier earned through prestige
+    #[serde(default = "default_xp_multiplier")]
+    pub xp_multiplier: f64,
+    /// Demo policy: the shard can be trained (chatted with) but not executed,
+    /// released, transferred or otherwise changed. Toggled by an admin via
+    /// `db::set_shard_read_only`; `db::update_shard` leaves it untouched.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+fn default_xp_multiplier() -> f64 {
+    1.0
+}
+
+/// XP required per level.
+pub const XP_PER_LEVEL: u64 = 100;
+
+/// Extra XP multiplier granted per prestige.
+pub const PRESTIGE_XP_BONUS: f64 = 0.1;
+
+/// `decay_factor` lost per whole day without an interaction.
+pub const DECAY_PER_IDLE_DAY: f64 = 0.01;
+
+/// Lowest `decay_factor` idleness can bring a shard to.
+pub const MIN_DECAY_FACTOR: f64 = 0.5;
+
+/// Milliseconds in a day.
+pub const DAY_MS: u64 = 86_400_000;
+
+/// Lowest Elo rating a shard can fall to.
+pub const MIN_ELO: u32 = 100;
+
+/// Level from which a shard's rating moves at the settled K-factor.
+pub const ELO_SETTLED_LEVEL: u32 = 10;
+
+/// How rare a species is in the collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rarity {
+    Common,
+    Uncommon,
+    Rare,
+    Epic,
+    Legendary,
 }
 
-const SEA_CREATURE_SPECIES: &[&str] = &[
-    "Abyssal Jellyfish", "Lantern Squid", "Phantom Ray", "Crystal Nautilus",
-    "Ember Eel", "Void Angler", "Sapphire Seahorse", "Drift Medusa",
-    "Prism Cuttlefish", "Shadow Leviathan", "Biolume Starfish", "Echo Dolphin",
-    "Coral Wraith", "Vortex Mantis", "Glacial Kraken", "Neon Serpent",
-    "Tidal Chimera", "Obsidian Urchin", "Spectral Whale", "Luminous Polyp",
+/// Collection metadata for a species.
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeciesInfo {
+    pub name: &'static str,
+    pub rarity: Rarity,
+    pub lore: &'static str,
+    /// Stat raised by `SPECIES_AFFINITY_BONUS` at spawn
+    pub affinity: &'static str,
+}
+
+/// Spawn bonus to a species' affinity stat. Kept small so species never
+/// outweigh the shard type bonus.
+pub const SPECIES_AFFINITY_BONUS: u32 = 3;
+
+/// Every species a shard can spawn as, in genome index order.
+pub const SPECIES: &[SpeciesInfo] = &[
+    SpeciesInfo {
+        name: "Abyssal Jellyfish",
+        rarity: Rarity::Common,
+        lore: "Drifts through the deepest trenches, untroubled by pressure that crushes steel.",
+        affinity: "resilience",
+    },
+    SpeciesInfo {
+        name: "Lantern Squid",
+        rarity: Rarity::Common,
+        lore: "Lights its own way through the dark and remembers every path it has lit.",
+        affinity: "intelligence",
+    },
+    SpeciesInfo {
+        name: "Phantom Ray",
+        rarity: Rarity::Uncommon,
+        lore: "Glides unseen along the seabed and strikes exactly where it means to.",
+        affinity: "precision",
+    },
+    SpeciesInfo {
+        name: "Crystal Nautilus",
+        rarity: Rarity::Rare,
+        lore: "Its chambered shell records every current it has ever ridden.",
+        affinity: "intelligence",
+    },
+    SpeciesInfo {
+        name: "Ember Eel",
+        rarity: Rarity::Common,
+        lore: "Carries a spark from the volcanic vents wherever it swims.",
+        affinity: "creativity",
+    },
+    SpeciesInfo {
+        name: "Void Angler",
+        rarity: Rarity::Uncommon,
+        lore: "Lures answers out of the black with a single patient light.",
+        affinity: "precision",
+    },
+    SpeciesInfo {
+        name: "Sapphire Seahorse",
+        rarity: Rarity::Common,
+        lore: "Courts whole reefs with its shimmer and is never short of allies.",
+        affinity: "charisma",
+    },
+    SpeciesInfo {
+        name: "Drift Medusa",
+        rarity: Rarity::Common,
+        lore: "Lets the tide choose its shape, and no two blooms look alike.",
+        affinity: "creativity",
+    },
+    SpeciesInfo {
+        name: "Prism Cuttlefish",
+        rarity: Rarity::Uncommon,
+        lore: "Speaks in shifting colour, inventing a new language every hour.",
+        affinity: "creativity",
+    },
+    SpeciesInfo {
+        name: "Shadow Leviathan",
+        rarity: Rarity::Legendary,
+        lore: "Older than the charts; sailors only ever see the wake it leaves.",
+        affinity: "resilience",
+    },
+    SpeciesInfo {
+        name: "Biolume Starfish",
+        rarity: Rarity::Common,
+        lore: "Regrows whatever it loses and glows a little brighter each time.",
+        affinity: "resilience",
+    },
+    SpeciesInfo {
+        name: "Echo Dolphin",
+        rarity: Rarity::Common,
+        lore: "Sings to the pod across miles of open water and always hears a reply.",
+        affinity: "charisma",
+    },
+    SpeciesInfo {
+        name: "Coral Wraith",
+        rarity: Rarity::Uncommon,
+        lore: "Haunts the reef edges, mending broken coral one polyp at a time.",
+        affinity: "precision",
+    },
+    SpeciesInfo {
+        name: "Vortex Mantis",
+        rarity: Rarity::Rare,
+        lore: "Strikes faster than the eye can follow and never misses twice.",
+        affinity: "precision",
+    },
+    SpeciesInfo {
+        name: "Glacial Kraken",
+        rarity: Rarity::Epic,
+        lore: "Sleeps beneath polar ice, dreaming through problems for centuries.",
+        affinity: "intelligence",
+    },
+    SpeciesInfo {
+        name: "Neon Serpent",
+        rarity: Rarity::Uncommon,
+        lore: "Its glow draws crowds of lesser fish that follow where it leads.",
+        affinity: "charisma",
+    },
+    SpeciesInfo {
+        name: "Tidal Chimera",
+        rarity: Rarity::Epic,
+        lore: "Part of every creature it has met, and stranger for each of them.",
+        affinity: "creativity",
+    },
+    SpeciesInfo {
+        name: "Obsidian Urchin",
+        rarity: Rarity::Common,
+        lore: "Wears a coat of volcanic glass that nothing in the sea can crack.",
+        affinity: "resilience",
+    },
+    SpeciesInfo {
+        name: "Spectral Whale",
+        rarity: Rarity::Rare,
+        lore: "Its song carries between oceans and old voices answer it.",
+        affinity: "charisma",
+    },
+    SpeciesInfo {
+        name: "Luminous Polyp",
+        rarity: Rarity::Uncommon,
+        lore: "A single polyp of a colony that thinks as one across the reef.",
+        affinity: "intelligence",
+    },
 ];
 
+/// Metadata for a species name, if it is one of `SPECIES`.
+pub fn species_info(name: &str) -> Option<&'static SpeciesInfo> {
+    SPECIES.iter().find(|s| s.name == name)
+}
+
 const SHARD_TYPE_COLORS: &[(&str, &str)] = &[
     ("Oracle", "#00d4aa"),
     ("Cipher", "#7c3aed"),
@@ -247,25 +647,193 @@ const NAME_SUFFIXES: &[&str] = &[
     "drift", "deep", "tide", "glow", "spark", "shade", "wave", "bloom",
 ];
 
+/// A shard's colour scheme. `primary` is the shard type's anchor colour;
+/// the rest vary with species and genome so same-type shards look distinct.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Palette {
+    pub primary: String,
+    pub secondary: String,
+    pub accent: String,
+    pub highlight: String,
+}
+
+/// Derive a deterministic palette from a shard's genome, type and species.
+/// The species sets the base hue; genome bytes add per-shard variation.
+pub fn palette(genome_hash: &str, shard_type: &str, species: &str) -> Palette {
+    let primary = SHARD_TYPE_COLORS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(shard_type))
+        .map(|(_, color)| *color)
+        .unwrap_or(SHARD_TYPE_COLORS[0].1)
+        .to_string();
+
+    let species_bytes = Keccak256::digest(species.as_bytes());
+    let genome = Keccak256::digest(genome_hash.to_ascii_lowercase().as_bytes());
+    let base_hue = u16::from_be_bytes([species_bytes[0], species_bytes[1]]) as u32 % 360;
+
+    let secondary_hue = base_hue + 345 + genome[0] as u32 % 30;
+    let accent_hue = base_hue + 150 + genome[3] as u32 % 60;
+    let highlight_hue = base_hue + 30;
+
+    Palette {
+        primary,
+        secondary: hsl_to_hex(secondary_hue, 55 + genome[1] as u32 % 30, 45 + genome[2] as u32 % 15),
+        accent: hsl_to_hex(accent_hue, 70 + genome[4] as u32 % 25, 55 + genome[5] as u32 % 15),
+        highlight: hsl_to_hex(highlight_hue, 30 + genome[6] as u32 % 20, 80 + genome[7] as u32 % 10),
+    }
+}
+
+/// Convert HSL (hue in degrees, saturation/lightness in percent) to "#rrggbb".
+fn hsl_to_hex(hue: u32, saturation: u32, lightness: u32) -> String {
+    let h = (hue % 360) as f64 / 60.0;
+    let s = saturation.min(100) as f64 / 100.0;
+    let l = lightness.min(100) as f64 / 100.0;
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = l - c / 2.0;
+    let channel = |v: f64| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    format!("#{:02x}{:02x}{:02x}", channel(r), channel(g), channel(b))
+}
+
+/// Identity key from a genome and the traits fixed at spawn (type and
+/// species): `0x` plus 32 hex chars. Case-insensitive in the genome.
+pub fn fingerprint_of(genome_hash: &str, shard_type: &str, species: &str) -> String {
+    let digest = Keccak256::digest(
+        format!(
+            "{}:{}:{}",
+            genome_hash.to_ascii_lowercase(),
+            shard_type.to_ascii_lowercase(),
+            species.to_ascii_lowercase()
+        )
+        .as_bytes(),
+    );
+    format!("0x{}", hex::encode(&digest[..16]))
+}
+
 impl Shard {
-    /// Sum of all 5 stats (for valuation attestation).
+    /// Stat cap for this shard: the keeper's, lowered to the genome's
+    /// potential when `genome_caps` is on.
+    pub fn stat_limits(&self, keeper: StatLimits, genome_caps: bool) -> StatLimits {
+        if !genome_caps {
+            return keeper;
+        }
+        StatLimits {
+            cap: genome_stat_cap(&self.genome_hash, keeper.cap),
+            ..keeper
+        }
+    }
+
+    /// Cheap equality key for duplicate detection. Unaffected by training,
+    /// ownership, renames or anything else that changes after spawn.
+    pub fn fingerprint(&self) -> String {
+        fingerprint_of(&self.genome_hash, &self.shard_type, &self.species)
+    }
+
+    /// Sum of all 5 stats scaled by `decay_factor` (for valuation
+    /// attestation and battles).
     pub fn stats_sum(&self) -> u32 {
-        self.stats.intelligence
+        let raw = self.stats.intelligence
             + self.stats.creativity
             + self.stats.precision
             + self.stats.resilience
-            + self.stats.charisma
+            + self.stats.charisma;
+        (raw as f64 * self.decay_factor.clamp(0.0, 1.0)).round() as u32
+    }
+
+    /// Lower `decay_factor` by `DECAY_PER_IDLE_DAY` for each whole day since
+    /// `last_interaction`, down to `MIN_DECAY_FACTOR`. Repeated calls at the
+    /// same time have no further effect. Returns true if the factor changed.
+    pub fn apply_decay(&mut self, now_ms: u64) -> bool {
+        let idle_days = now_ms.saturating_sub(self.last_interaction) / DAY_MS;
+        let target = (1.0 - DECAY_PER_IDLE_DAY * idle_days as f64).max(MIN_DECAY_FACTOR);
+        if self.decay_factor <= target {
+            return false;
+        }
+        self.decay_factor = target;
+        true
+    }
+
+    /// Record an interaction at `now_ms`, restoring `decay_factor` to 1.0.
+    pub fn touch(&mut self, now_ms: u64) {
+        self.last_interaction = self.last_interaction.max(now_ms);
+        self.decay_factor = 1.0;
+    }
+
+    /// `base_xp` scaled by the prestige multiplier.
+    pub fn scaled_xp(&self, base_xp: u32) -> u32 {
+        scale_xp(base_xp, self.xp_multiplier)
+    }
+
+    /// Award XP scaled by the prestige multiplier and recompute the level,
+    /// which never exceeds `level_cap`. Returns the XP actually awarded.
+    pub fn gain_xp(&mut self, base_xp: u32, level_cap: u32) -> u32 {
+        let awarded = self.scaled_xp(base_xp);
+        self.xp += awarded as u64;
+        self.level = ((self.xp / XP_PER_LEVEL) as u32 + 1).min(level_cap.max(1));
+        awarded
+    }
+
+    /// Reset a shard at the level cap back to level 1, keeping a permanent
+    /// XP multiplier and incrementing its prestige count.
+    pub fn prestige(&mut self, level_cap: u32) -> Result<(), String> {
+        if self.level < level_cap {
+            return Err(format!(
+                "Shard must reach level {} to prestige (currently {})",
+                level_cap, self.level
+            ));
+        }
+        self.prestige += 1;
+        self.xp_multiplier = 1.0 + PRESTIGE_XP_BONUS * self.prestige as f64;
+        self.level = 1;
+        self.xp = 0;
+        self.capabilities.update_for_level(self.level);
+        Ok(())
+    }
+
+    /// Elo K-factor: young shards' ratings move faster.
+    pub fn elo_k_factor(&self) -> f64 {
+        if self.level < ELO_SETTLED_LEVEL {
+            32.0
+        } else {
+            16.0
+        }
     }
 
     /// Spawn a new shard, optionally of a specific type.
     /// Mirrors the TypeScript `spawnShard()` function.
     pub fn spawn(type_name: Option<&str>) -> Self {
-        let id = Uuid::new_v4().to_string();
-        let seed = Uuid::new_v4().to_string();
-        let entropy = Uuid::new_v4().to_string();
+        Self::spawn_from(type_name, None, now_millis())
+    }
+
+    /// Spawn deterministically: the same seed always yields the same id,
+    /// genome and traits.
+    pub fn spawn_seeded(type_name: Option<&str>, seed: u64) -> Self {
+        Self::spawn_from(type_name, Some(&mut Rng::from_seed(seed)), now_millis())
+    }
+
+    fn spawn_from(type_name: Option<&str>, rng: Option<&mut Rng>, now: u64) -> Self {
+        let (id, input) = match rng {
+            Some(rng) => {
+                let id = rng.uuid().to_string();
+                (id, format!("{}:{}", rng.uuid(), rng.uuid()))
+            }
+            None => {
+                let id = Uuid::new_v4().to_string();
+                let seed = Uuid::new_v4().to_string();
+                let entropy = Uuid::new_v4().to_string();
+                (id, unseeded_genome_input(&seed, &entropy, now))
+            }
+        };
 
         // Generate genome hash via keccak256
-        let input = format!("{}:{}:{}", seed, entropy, now_millis());
         let mut hasher = Keccak256::new();
         hasher.update(input.as_bytes());
         let hash_bytes = hasher.finalize();
@@ -281,15 +849,13 @@ impl Shard {
         let type_index = shard_type_enum as usize;
 
         // Species from hash
-        let species_idx = (hash_bytes[1] as usize) % SEA_CREATURE_SPECIES.len();
-        let species = SEA_CREATURE_SPECIES[species_idx].to_string();
+        let species_info = &SPECIES[(hash_bytes[1] as usize) % SPECIES.len()];
+        let species = species_info.name.to_string();
 
         // Avatar params
-        let primary_color = SHARD_TYPE_COLORS[type_index].1.to_string();
-        let secondary_color = format!(
-            "#{:02x}{:02x}{:02x}",
-            hash_bytes[3], hash_bytes[4], hash_bytes[5]
-        );
+        let colors = palette(&genome_hash, shard_type_enum.name(), &species);
+        let primary_color = colors.primary;
+        let secondary_color = colors.secondary;
         let glow_intensity = (hash_bytes[6] as f64 / 255.0) * 0.8 + 0.2;
         let size = (hash_bytes[7] as f64 / 255.0) * 0.5 + 0.75;
         let pattern = hash_bytes[8] % 8;
@@ -317,6 +883,9 @@ impl Shard {
             ShardType::Sentinel => { stats.precision += 10; stats.resilience += 5; }
             ShardType::Mirror => { stats.charisma += 10; stats.creativity += 5; }
         }
+        if let Some(stat) = stats.get_mut(species_info.affinity) {
+            *stat += SPECIES_AFFINITY_BONUS;
+        }
 
         // Name from hash
         let prefixes = NAME_PREFIXES[type_index];
@@ -324,8 +893,6 @@ impl Shard {
         let suffix_idx = (hash_bytes[15] as usize) % NAME_SUFFIXES.len();
         let name = format!("{}-{}", prefixes[prefix_idx], NAME_SUFFIXES[suffix_idx]);
 
-        let now = now_millis();
-
         Shard {
             id,
             genome_hash,
@@ -353,10 +920,95 @@ impl Shard {
             capabilities: ShardCapabilities::default(),
             tasks_completed: 0,
             tasks_failed: 0,
+            prestige: 0,
+            xp_multiplier: 1.0,
+            read_only: false,
         }
     }
 }
 
+/// `base_xp` scaled by a prestige `xp_multiplier`.
+pub fn scale_xp(base_xp: u32, xp_multiplier: f64) -> u32 {
+    (base_xp as f64 * xp_multiplier).round() as u32
+}
+
+/// Update both ratings after `winner` beat `loser`, using the standard Elo
+/// formula. One K-factor applies to the whole match, the lower of the two
+/// sides', so the winner gains exactly what the loser gives up. The loser
+/// never drops below `MIN_ELO` and the winner saturates at `u32::MAX`.
+pub fn apply_elo(winner: &mut Shard, loser: &mut Shard) {
+    let expected = 1.0 / (1.0 + 10f64.powf((loser.elo_rating as f64 - winner.elo_rating as f64) / 400.0));
+    let k = winner.elo_k_factor().min(loser.elo_k_factor());
+    let transfer = ((k * (1.0 - expected)).round() as u32)
+        .min(loser.elo_rating.saturating_sub(MIN_ELO))
+        .min(u32::MAX - winner.elo_rating);
+    winner.elo_rating += transfer;
+    loser.elo_rating -= transfer;
+}
+
+/// What changed between two states of a shard. Numbers are `to - from`;
+/// stats that didn't move are left out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ShardDiff {
+    pub level: i64,
+    pub xp: i64,
+    pub prestige: i64,
+    pub elo_rating: i64,
+    pub tasks_completed: i64,
+    pub tasks_failed: i64,
+    pub stats: BTreeMap<&'static str, i64>,
+    pub tools_gained: Vec<&'static str>,
+    pub tools_lost: Vec<&'static str>,
+    pub max_concurrent_tasks: i64,
+    /// Learned-context entries present in `to` but not in `from`
+    pub learned: Vec<String>,
+}
+
+/// Compare two states of a shard, typically a snapshot and a later one.
+pub fn diff(from: &Shard, to: &Shard) -> ShardDiff {
+    let delta = |a: u64, b: u64| b as i64 - a as i64;
+    let stats = ShardStats::NAMES
+        .iter()
+        .filter_map(|&name| {
+            let change = delta(from.stats.get(name)? as u64, to.stats.get(name)? as u64);
+            (change != 0).then_some((name, change))
+        })
+        .collect();
+    let (before, after) = (from.capabilities.allowed_tools(), to.capabilities.allowed_tools());
+
+    ShardDiff {
+        level: delta(from.level as u64, to.level as u64),
+        xp: delta(from.xp, to.xp),
+        prestige: delta(from.prestige as u64, to.prestige as u64),
+        elo_rating: delta(from.elo_rating as u64, to.elo_rating as u64),
+        tasks_completed: delta(from.tasks_completed as u64, to.tasks_completed as u64),
+        tasks_failed: delta(from.tasks_failed as u64, to.tasks_failed as u64),
+        stats,
+        tools_gained: after.iter().filter(|t| !before.contains(t)).copied().collect(),
+        tools_lost: before.iter().filter(|t| !after.contains(t)).copied().collect(),
+        max_concurrent_tasks: delta(
+            from.capabilities.max_concurrent_tasks as u64,
+            to.capabilities.max_concurrent_tasks as u64,
+        ),
+        learned: to
+            .capabilities
+            .learned_context
+            .iter()
+            .filter(|c| !from.capabilities.learned_context.contains(c))
+            .cloned()
+            .collect(),
+    }
+}
+
+/// Bumped on every unseeded spawn so two spawns in the same process never hash
+/// the same input, even with a frozen clock or a weak entropy source.
+static SPAWN_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+fn unseeded_genome_input(seed: &str, entropy: &str, now: u64) -> String {
+    let count = SPAWN_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{}:{}:{}:{}:{}", seed, entropy, now, std::process::id(), count)
+}
+
 fn now_millis() -> u64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -393,6 +1045,61 @@ mod tests {
         assert!(shard.decay_factor == 1.0);
     }
 
+    #[test]
+    fn spawn_applies_species_affinity() {
+        for seed in 0..20 {
+            let shard = Shard::spawn_seeded(Some("oracle"), seed);
+            let info = species_info(&shard.species).unwrap();
+            let byte = |i: usize| u8::from_str_radix(&shard.genome_hash[2 + i * 2..4 + i * 2], 16).unwrap();
+            let mut expected = ShardStats {
+                intelligence: (byte(9) as u32) * 50 / 255 + 50 + 15,
+                creativity: (byte(10) as u32) * 50 / 255 + 50,
+                precision: (byte(11) as u32) * 50 / 255 + 50,
+                resilience: (byte(12) as u32) * 50 / 255 + 50,
+                charisma: (byte(13) as u32) * 50 / 255 + 50,
+            };
+            *expected.get_mut(info.affinity).unwrap() += SPECIES_AFFINITY_BONUS;
+            assert_eq!(shard.stats.intelligence, expected.intelligence);
+            assert_eq!(shard.stats.creativity, expected.creativity);
+            assert_eq!(shard.stats.precision, expected.precision);
+            assert_eq!(shard.stats.resilience, expected.resilience);
+            assert_eq!(shard.stats.charisma, expected.charisma);
+        }
+        assert!(SPECIES.iter().all(|s| ShardStats::NAMES.contains(&s.affinity)));
+    }
+
+    #[test]
+    fn diff_reports_deltas_between_states() {
+        let before = Shard::spawn_seeded(Some("oracle"), 11);
+        let mut after = before.clone();
+        after.level = 6;
+        after.xp = before.xp + 520;
+        after.tasks_completed = 12;
+        after.tasks_failed = 2;
+        after.elo_rating = 1180;
+        after.stats.intelligence += 7;
+        after.stats.charisma -= 1;
+        after.capabilities.can_shell = true;
+        after.capabilities.can_brainstorm = false;
+        after.capabilities.max_concurrent_tasks = 2;
+        after.capabilities.learn("prefers jq for JSON".to_string());
+
+        let d = diff(&before, &after);
+        assert_eq!(d.level, 5);
+        assert_eq!(d.xp, 520);
+        assert_eq!(d.tasks_completed, 12);
+        assert_eq!(d.tasks_failed, 2);
+        assert_eq!(d.elo_rating, -20);
+        assert_eq!(d.prestige, 0);
+        assert_eq!(d.stats, BTreeMap::from([("intelligence", 7), ("charisma", -1)]));
+        assert_eq!(d.tools_gained, vec!["shell_exec"]);
+        assert_eq!(d.tools_lost, vec!["brainstorm"]);
+        assert_eq!(d.max_concurrent_tasks, 1);
+        assert_eq!(d.learned, vec!["prefers jq for JSON".to_string()]);
+
+        assert_eq!(diff(&after, &after), ShardDiff::default());
+    }
+
     #[test]
     fn spawn_specific_type() {
         let shard = Shard::spawn(Some("oracle"));
@@ -436,6 +1143,46 @@ mod tests {
         }
     }
 
+    #[test]
+    fn genome_cap_limits_training() {
+        let keeper = StatLimits {
+            cap: 250,
+            overflow: StatOverflow::Xp,
+        };
+        let genome = |potential: &str| format!("0x{}{}{}", "00".repeat(16), potential, "00".repeat(15));
+        assert_eq!(genome_stat_cap(&genome("00"), 250), 175);
+        assert_eq!(genome_stat_cap(&genome("ff"), 250), 250);
+        assert_eq!(genome_stat_cap("0xabc", 250), 250);
+
+        let mut shard = Shard::spawn_seeded(None, 3);
+        shard.genome_hash = genome("00");
+        assert_eq!(shard.stat_limits(keeper, false).cap, 250);
+        let limits = shard.stat_limits(keeper, true);
+        assert_eq!(limits.cap, 175);
+
+        let overflow = shard
+            .stats
+            .apply_bonuses(&HashMap::from([("charisma".to_string(), 500)]), limits);
+        assert_eq!(shard.stats.charisma, 175);
+        assert!(overflow > 0);
+        assert!(shard.stats_sum() <= 5 * 175);
+    }
+
+    #[test]
+    fn fingerprint_follows_genome_not_training() {
+        let a = Shard::spawn_seeded(Some("oracle"), 7);
+        let mut b = Shard::spawn_seeded(Some("oracle"), 7);
+        b.id = "another-instance".to_string();
+        b.gain_xp(500, 100);
+        b.owner_id = Some("0xabc".to_string());
+        b.genome_hash = b.genome_hash.to_ascii_uppercase();
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        assert_eq!(a.fingerprint().len(), 34);
+
+        let c = Shard::spawn_seeded(Some("oracle"), 8);
+        assert_ne!(a.fingerprint(), c.fingerprint());
+    }
+
     #[test]
     fn each_spawn_is_unique() {
         let a = Shard::spawn(None);
@@ -444,6 +1191,48 @@ mod tests {
         assert_ne!(a.genome_hash, b.genome_hash);
     }
 
+    #[test]
+    fn stat_bonuses_clamp_at_cap_and_handle_overflow() {
+        let base = ShardStats {
+            intelligence: 98,
+            creativity: 60,
+            precision: 100,
+            resilience: 70,
+            charisma: 100,
+        };
+        let bonuses = HashMap::from([
+            ("intelligence".to_string(), 5),
+            ("precision".to_string(), 2),
+            ("bogus".to_string(), 9),
+        ]);
+
+        // Xp: 3 points past the cap on intelligence, 2 on precision
+        let mut stats = base.clone();
+        let xp = stats.apply_bonuses(&bonuses, StatLimits { cap: 100, overflow: StatOverflow::Xp });
+        assert_eq!((stats.intelligence, stats.precision), (100, 100));
+        assert_eq!(stats.creativity, 60);
+        assert_eq!(xp, 5);
+
+        // Redistribute: the 5 overflow points go to the lowest stat
+        let mut stats = base.clone();
+        let xp = stats.apply_bonuses(&bonuses, StatLimits { cap: 100, overflow: StatOverflow::Redistribute });
+        assert_eq!(xp, 0);
+        assert_eq!((stats.intelligence, stats.precision), (100, 100));
+        assert_eq!((stats.creativity, stats.resilience), (65, 70));
+
+        // Once every stat is capped the remainder becomes XP
+        let mut stats = ShardStats {
+            intelligence: 100,
+            creativity: 99,
+            precision: 100,
+            resilience: 100,
+            charisma: 100,
+        };
+        let xp = stats.apply_bonuses(&bonuses, StatLimits { cap: 100, overflow: StatOverflow::Redistribute });
+        assert_eq!(stats.creativity, 100);
+        assert_eq!(xp, 6);
+    }
+
     #[test]
     fn spawn_has_default_capabilities() {
         let shard = Shard::spawn(None);
@@ -457,6 +1246,23 @@ mod tests {
         assert_eq!(shard.tasks_failed, 0);
     }
 
+    #[test]
+    fn batch_spawns_under_frozen_clock_never_collide() {
+        const FROZEN: u64 = 1_700_000_000_000;
+        let genomes: std::collections::HashSet<String> = (0..500)
+            .map(|_| Shard::spawn_from(None, None, FROZEN))
+            .inspect(|shard| assert_eq!(shard.created_at, FROZEN))
+            .map(|shard| shard.genome_hash)
+            .collect();
+        assert_eq!(genomes.len(), 500);
+
+        // Even with no entropy at all, the counter keeps inputs distinct
+        let inputs: std::collections::HashSet<String> = (0..500)
+            .map(|_| unseeded_genome_input("seed", "entropy", FROZEN))
+            .collect();
+        assert_eq!(inputs.len(), 500);
+    }
+
     #[test]
     fn capabilities_update_for_level() {
         let mut caps = ShardCapabilities::default();
@@ -474,6 +1280,17 @@ mod tests {
         assert_eq!(caps.max_concurrent_tasks, 5);
     }
 
+    #[test]
+    fn next_milestones_follow_level_table() {
+        let next = next_milestones(3);
+        assert_eq!(next.len(), 2);
+        assert_eq!(next[0].describe(), "shell_exec at level 5");
+        assert_eq!(next[1].describe(), "2 concurrent tasks at level 5");
+        assert_eq!(next_milestones(5), vec![LEVEL_MILESTONES[2]]);
+        assert!(next_milestones(20).is_empty());
+        assert_eq!(shell_unlock_level(), 5);
+    }
+
     #[test]
     fn capabilities_allowed_tools() {
         let caps = ShardCapabilities::default();
@@ -482,11 +1299,28 @@ mod tests {
         assert!(tools.contains(&"http_fetch"));
         assert!(tools.contains(&"file_read"));
         assert!(tools.contains(&"file_write"));
+        assert!(tools.contains(&"search_files"));
         assert!(!tools.contains(&"shell_exec")); // not unlocked
 
-        let mut caps2 = ShardCapabilities::default();
-        caps2.can_shell = true;
+        let caps2 = ShardCapabilities {
+            can_shell: true,
+            ..Default::default()
+        };
         assert!(caps2.allowed_tools().contains(&"shell_exec"));
+
+        assert!(tools.contains(&"brainstorm"));
+        let caps3 = ShardCapabilities {
+            can_brainstorm: false,
+            ..Default::default()
+        };
+        assert!(!caps3.allowed_tools().contains(&"brainstorm"));
+
+        assert!(tools.contains(&"recall_lessons"));
+        let caps4 = ShardCapabilities {
+            can_recall: false,
+            ..Default::default()
+        };
+        assert!(!caps4.allowed_tools().contains(&"recall_lessons"));
     }
 
     #[test]
@@ -510,4 +1344,135 @@ mod tests {
         let back: ExecutionState = serde_json::from_str(&json).unwrap();
         assert_eq!(back, ExecutionState::Executing);
     }
+
+    #[test]
+    fn xp_gain_respects_level_cap_and_multiplier() {
+        let mut shard = Shard::spawn(None);
+        assert_eq!(shard.gain_xp(250, 10), 250);
+        assert_eq!(shard.level, 3);
+
+        shard.gain_xp(5_000, 10);
+        assert_eq!(shard.level, 10);
+
+        shard.xp_multiplier = 1.5;
+        assert_eq!(shard.gain_xp(10, 10), 15);
+    }
+
+    #[test]
+    fn prestige_resets_level_and_sets_multiplier() {
+        let mut shard = Shard::spawn(None);
+        shard.gain_xp(150, 10);
+        assert!(shard.prestige(10).is_err());
+        assert_eq!(shard.prestige, 0);
+
+        shard.gain_xp(5_000, 10);
+        shard.capabilities.update_for_level(shard.level);
+        shard.prestige(10).unwrap();
+        assert_eq!(shard.level, 1);
+        assert_eq!(shard.xp, 0);
+        assert_eq!(shard.prestige, 1);
+        assert!((shard.xp_multiplier - 1.1).abs() < 1e-9);
+        assert!(!shard.capabilities.can_shell);
+    }
+
+    #[test]
+    fn palette_varies_with_species_within_a_type() {
+        let genome = "0x1111111111111111111111111111111111111111111111111111111111111111";
+        let jelly = palette(genome, "Oracle", "Abyssal Jellyfish");
+        let squid = palette(genome, "Oracle", "Lantern Squid");
+
+        assert_eq!(jelly.primary, "#00d4aa");
+        assert_eq!(jelly.primary, squid.primary);
+        assert_ne!(jelly.secondary, squid.secondary);
+        assert_ne!(jelly.accent, squid.accent);
+        assert_eq!(jelly, palette(genome, "oracle", "Abyssal Jellyfish"));
+    }
+
+    #[test]
+    fn hsl_to_hex_known_values() {
+        assert_eq!(hsl_to_hex(0, 100, 50), "#ff0000");
+        assert_eq!(hsl_to_hex(120, 100, 50), "#00ff00");
+        assert_eq!(hsl_to_hex(240, 100, 50), "#0000ff");
+        assert_eq!(hsl_to_hex(0, 0, 100), "#ffffff");
+    }
+
+    #[test]
+    fn elo_transfers_points_symmetrically() {
+        let (mut winner, mut loser) = (Shard::spawn(None), Shard::spawn(None));
+        winner.elo_rating = 1200;
+        loser.elo_rating = 1200;
+        apply_elo(&mut winner, &mut loser);
+        assert_eq!(winner.elo_rating, 1216);
+        assert_eq!(loser.elo_rating, 1184);
+
+        // Settled shards move at half the rate
+        winner.level = ELO_SETTLED_LEVEL;
+        loser.level = ELO_SETTLED_LEVEL;
+        let before = (winner.elo_rating, loser.elo_rating);
+        apply_elo(&mut winner, &mut loser);
+        let gained = winner.elo_rating - before.0;
+        assert_eq!(gained, before.1 - loser.elo_rating);
+        assert!(gained > 0 && gained < 16);
+
+        // A young shard against a settled one moves both by the same amount
+        let (mut young, mut settled) = (Shard::spawn(None), Shard::spawn(None));
+        young.level = 5;
+        settled.level = 15;
+        let transfer = |winner: &mut Shard, loser: &mut Shard| {
+            let before = (winner.elo_rating, loser.elo_rating);
+            apply_elo(winner, loser);
+            (winner.elo_rating - before.0, before.1 - loser.elo_rating)
+        };
+        for (gain, loss) in [transfer(&mut young, &mut settled), transfer(&mut settled, &mut young)] {
+            assert_eq!(gain, loss);
+            assert!(gain > 0 && gain <= 16);
+        }
+    }
+
+    #[test]
+    fn upsets_swing_more_than_expected_results() {
+        let rated = |elo| {
+            let mut shard = Shard::spawn(None);
+            shard.elo_rating = elo;
+            shard
+        };
+        let (mut underdog, mut favourite) = (rated(1000), rated(1400));
+        apply_elo(&mut underdog, &mut favourite);
+        let upset = underdog.elo_rating - 1000;
+
+        let (mut favourite, mut underdog) = (rated(1400), rated(1000));
+        apply_elo(&mut favourite, &mut underdog);
+        let expected = favourite.elo_rating - 1400;
+        assert!(upset > expected, "upset {} vs expected {}", upset, expected);
+
+        // Ratings floor at MIN_ELO and never overflow
+        let (mut top, mut bottom) = (rated(u32::MAX), rated(MIN_ELO + 1));
+        apply_elo(&mut top, &mut bottom);
+        assert_eq!(top.elo_rating, u32::MAX);
+        assert_eq!(bottom.elo_rating, MIN_ELO + 1);
+        let (mut winner, mut loser) = (rated(MIN_ELO + 5), rated(MIN_ELO + 5));
+        apply_elo(&mut winner, &mut loser);
+        assert_eq!(loser.elo_rating, MIN_ELO);
+    }
+
+    #[test]
+    fn idle_shards_decay_until_touched() {
+        let mut shard = Shard::spawn(None);
+        let raw = shard.stats_sum();
+        let start = shard.last_interaction;
+
+        assert!(!shard.apply_decay(start + DAY_MS - 1));
+        assert!(shard.apply_decay(start + 10 * DAY_MS));
+        assert!((shard.decay_factor - 0.9).abs() < 1e-9);
+        assert!(!shard.apply_decay(start + 10 * DAY_MS), "idempotent");
+        assert_eq!(shard.stats_sum(), (raw as f64 * 0.9).round() as u32);
+
+        shard.apply_decay(start + 365 * DAY_MS);
+        assert_eq!(shard.decay_factor, MIN_DECAY_FACTOR);
+
+        shard.touch(start + 365 * DAY_MS);
+        assert_eq!(shard.decay_factor, 1.0);
+        assert_eq!(shard.stats_sum(), raw);
+        assert!(!shard.apply_decay(start + 365 * DAY_MS + DAY_MS / 2));
+    }
 }
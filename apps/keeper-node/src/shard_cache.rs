@@ -0,0 +1,238 @@
+use rusqlite::Result as SqliteResult;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use crate::db;
+use crate::shard::Shard;
+
+/// Invalidations remembered for lookups still reading the database. A lookup
+/// that started before the oldest of them does not cache what it read.
+const INVALIDATION_LOG: usize = 256;
+
+/// Bounded least-recently-used cache of shards by id, in front of
+/// `db::get_shard_by_id`.
+///
+/// Whatever writes a shard row (the API, the keeper loop, a background job)
+/// calls `invalidate` once the write is committed, so the next lookup goes
+/// back to the database. A lookup racing a write does not cache the copy it
+/// read. Writes from another process, such as the CLI, are not seen until
+/// the entry is evicted.
+pub struct ShardCache {
+    capacity: usize,
+    inner: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<String, Entry>,
+    /// Bumped on every hit or insert; the smallest `last_used` is evicted
+    tick: u64,
+    /// Bumped on every invalidation
+    clock: u64,
+    /// The latest invalidations, oldest first: a shard id, or `None` for all
+    invalidations: VecDeque<(Option<String>, u64)>,
+}
+
+impl Entries {
+    /// Whether `shard_id` may have been written since the clock read `stamp`.
+    fn invalidated_since(&self, shard_id: &str, stamp: u64) -> bool {
+        if self.clock == stamp {
+            return false;
+        }
+        // Some of the invalidations since `stamp` were already forgotten
+        if self.invalidations.front().is_none_or(|(_, clock)| *clock > stamp + 1) {
+            return true;
+        }
+        self.invalidations
+            .iter()
+            .any(|(id, clock)| *clock > stamp && id.as_deref().is_none_or(|id| id == shard_id))
+    }
+
+    fn record_invalidation(&mut self, shard_id: Option<&str>) {
+        self.clock += 1;
+        self.invalidations.push_back((shard_id.map(str::to_string), self.clock));
+        if self.invalidations.len() > INVALIDATION_LOG {
+            self.invalidations.pop_front();
+        }
+    }
+}
+
+struct Entry {
+    shard: Shard,
+    last_used: u64,
+}
+
+impl ShardCache {
+    /// A cache holding up to `capacity` shards (0 disables caching).
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Look a shard up, from the cache when the cached copy is still current.
+    pub fn get(&self, data_dir: &str, shard_id: &str) -> SqliteResult<Option<Shard>> {
+        if self.capacity == 0 {
+            return db::get_shard_by_id(data_dir, shard_id);
+        }
+
+        let stamp = {
+            let mut inner = self.lock();
+            inner.tick += 1;
+            let tick = inner.tick;
+            if let Some(entry) = inner.map.get_mut(shard_id) {
+                entry.last_used = tick;
+                return Ok(Some(entry.shard.clone()));
+            }
+            inner.clock
+        };
+
+        let shard = db::get_shard_by_id(data_dir, shard_id)?;
+        let mut inner = self.lock();
+        match &shard {
+            Some(shard) if !inner.invalidated_since(shard_id, stamp) => {
+                inner.tick += 1;
+                let last_used = inner.tick;
+                inner.map.insert(
+                    shard_id.to_string(),
+                    Entry { shard: shard.clone(), last_used },
+                );
+                if inner.map.len() > self.capacity {
+                    if let Some(oldest) = inner
+                        .map
+                        .iter()
+                        .min_by_key(|(_, entry)| entry.last_used)
+                        .map(|(id, _)| id.clone())
+                    {
+                        inner.map.remove(&oldest);
+                    }
+                }
+            }
+            _ => {
+                inner.map.remove(shard_id);
+            }
+        }
+        Ok(shard)
+    }
+
+    /// Drop a shard's cached copy after its row was written.
+    pub fn invalidate(&self, shard_id: &str) {
+        let mut inner = self.lock();
+        inner.map.remove(shard_id);
+        inner.record_invalidation(Some(shard_id));
+    }
+
+    /// Drop every cached shard after a write that may have touched any row.
+    pub fn invalidate_all(&self) {
+        let mut inner = self.lock();
+        inner.map.clear();
+        inner.record_invalidation(None);
+    }
+
+    /// Number of shards currently cached.
+    pub fn len(&self) -> usize {
+        self.lock().map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_data_dir() -> (tempfile::TempDir, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy().to_string();
+        db::init_db(&path).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn cached_read_reflects_prior_update() {
+        let (_dir, data_dir) = temp_data_dir();
+        let mut shard = Shard::spawn(None);
+        db::insert_shard(&data_dir, &shard).unwrap();
+
+        let cache = ShardCache::new(8);
+        assert_eq!(cache.get(&data_dir, &shard.id).unwrap().unwrap().xp, 0);
+        assert_eq!(cache.len(), 1);
+
+        shard.xp = 250;
+        db::update_shard(&data_dir, &shard).unwrap();
+        cache.invalidate(&shard.id);
+        assert_eq!(cache.get(&data_dir, &shard.id).unwrap().unwrap().xp, 250);
+    }
+
+    #[test]
+    fn read_racing_a_write_is_not_cached() {
+        let (_dir, data_dir) = temp_data_dir();
+        let shard = Shard::spawn(None);
+        let cache = ShardCache::new(8);
+
+        let mut inner = cache.lock();
+        let stamp = inner.clock;
+        assert!(!inner.invalidated_since(&shard.id, stamp));
+        inner.record_invalidation(Some("another-shard"));
+        assert!(!inner.invalidated_since(&shard.id, stamp));
+        inner.record_invalidation(Some(&shard.id));
+        assert!(inner.invalidated_since(&shard.id, stamp));
+
+        // Once the log has moved past a stamp, every id counts as written
+        let stamp = inner.clock;
+        for _ in 0..=INVALIDATION_LOG {
+            inner.record_invalidation(Some("another-shard"));
+        }
+        assert_eq!(inner.invalidations.len(), INVALIDATION_LOG);
+        assert!(inner.invalidated_since(&shard.id, stamp));
+        drop(inner);
+
+        db::insert_shard(&data_dir, &shard).unwrap();
+        cache.invalidate_all();
+        assert!(cache.get(&data_dir, &shard.id).unwrap().is_some());
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn deletion_evicts_entry() {
+        let (_dir, data_dir) = temp_data_dir();
+        let shard = Shard::spawn(None);
+        db::insert_shard(&data_dir, &shard).unwrap();
+
+        let cache = ShardCache::new(8);
+        assert!(cache.get(&data_dir, &shard.id).unwrap().is_some());
+
+        db::delete_shard(&data_dir, &shard.id).unwrap();
+        cache.invalidate(&shard.id);
+        assert!(cache.get(&data_dir, &shard.id).unwrap().is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn least_recently_used_is_evicted() {
+        let (_dir, data_dir) = temp_data_dir();
+        let shards: Vec<Shard> = (0..3).map(|_| Shard::spawn(None)).collect();
+        for shard in &shards {
+            db::insert_shard(&data_dir, shard).unwrap();
+        }
+
+        let cache = ShardCache::new(2);
+        cache.get(&data_dir, &shards[0].id).unwrap();
+        cache.get(&data_dir, &shards[1].id).unwrap();
+        // Touch the first so the second becomes the oldest
+        cache.get(&data_dir, &shards[0].id).unwrap();
+        cache.get(&data_dir, &shards[2].id).unwrap();
+
+        let inner = cache.lock();
+        assert_eq!(inner.map.len(), 2);
+        assert!(inner.map.contains_key(&shards[0].id));
+        assert!(!inner.map.contains_key(&shards[1].id));
+    }
+}
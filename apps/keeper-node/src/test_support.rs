@@ -0,0 +1,164 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::RwLock;
+
+use crate::api::{AppState, JobStatus, SharedState};
+use crate::config::Config;
+use crate::db;
+use crate::shard::Shard;
+
+/// A keeper database initialized in a fresh temp dir. Returns the dir, which
+/// must outlive the test, and its path.
+pub fn keeper_data_dir() -> (tempfile::TempDir, String) {
+    let dir = tempfile::tempdir().unwrap();
+    let data_dir = dir.path().to_string_lossy().to_string();
+    db::init_db(&data_dir).unwrap();
+    (dir, data_dir)
+}
+
+/// Spawn a shard and store it in `data_dir`.
+pub fn seed_shard(data_dir: &str) -> Shard {
+    let shard = Shard::spawn(None);
+    db::insert_shard(data_dir, &shard).unwrap();
+    shard
+}
+
+/// Keeper state for `config`, as the API handlers receive it.
+pub fn test_state(config: Config) -> SharedState {
+    Arc::new(RwLock::new(AppState::new(config)))
+}
+
+/// Wait up to five seconds for a background job to finish. Returns its
+/// final status.
+pub async fn wait_for_job(state: &SharedState, job_id: &str) -> JobStatus {
+    for _ in 0..500 {
+        let status = state.read().await.jobs[job_id].status.clone();
+        if status != JobStatus::Running {
+            return status;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("job {} did not finish", job_id);
+}
+
+/// Record an action on `shard_id` and a successful lesson distilled from it,
+/// with middling confidence scores. Returns the lesson id.
+pub fn seed_lesson(data_dir: &str, shard_id: &str, task_type: &str, goal: &str) -> i64 {
+    let action_id = db::insert_action(data_dir, shard_id, goal, None).unwrap();
+    db::insert_task_lesson(
+        data_dir,
+        &db::NewTaskLesson {
+            shard_id,
+            action_id,
+            task_type,
+            goal,
+            approach: "read then act",
+            tools_used: &[],
+            outcome: "ok",
+            errors: &[],
+            fixes: &[],
+            duration_ms: 10,
+            success: true,
+            extractor_confidence: 0.8,
+            applicability_confidence: 0.8,
+            reusability: 0.8,
+            artifact_path: "memory://seed",
+        },
+    )
+    .unwrap()
+}
+
+/// Serve `app` on a free local port. Returns its base URL.
+pub async fn serve(app: axum::Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.ok();
+    });
+    format!("http://{}", addr)
+}
+
+/// A `/v1/chat/completions` route answering each request body with
+/// `reply(body)`, for tests that serve more routes alongside it.
+pub fn inference_router<F>(reply: F) -> axum::Router
+where
+    F: Fn(serde_json::Value) -> serde_json::Value + Clone + Send + Sync + 'static,
+{
+    axum::Router::new().route(
+        "/v1/chat/completions",
+        axum::routing::post(move |axum::Json(body): axum::Json<serde_json::Value>| async move {
+            axum::Json(reply(body))
+        }),
+    )
+}
+
+/// Serve a mock inference endpoint answering with `reply(request body)`.
+/// Returns the completions URL.
+pub async fn mock_inference<F>(reply: F) -> String
+where
+    F: Fn(serde_json::Value) -> serde_json::Value + Clone + Send + Sync + 'static,
+{
+    format!("{}/v1/chat/completions", serve(inference_router(reply)).await)
+}
+
+/// A completion answering with plain text.
+pub fn text_reply(content: &str) -> serde_json::Value {
+    serde_json::json!({
+        "choices": [{"message": {"role": "assistant", "content": content}, "finish_reason": "stop"}]
+    })
+}
+
+/// Serve a mock inference endpoint answering with `responses` in order,
+/// repeating the last once they run out, after sleeping `delay_ms`. Returns
+/// the completions URL and every request body received.
+pub async fn mock_inference_sequence(
+    responses: Vec<serde_json::Value>,
+    delay_ms: u64,
+) -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+    let responses = Arc::new(responses);
+    let counter = Arc::new(AtomicUsize::new(0));
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let recorded = requests.clone();
+    let app = axum::Router::new().route(
+        "/v1/chat/completions",
+        axum::routing::post(move |axum::Json(body): axum::Json<serde_json::Value>| {
+            let (responses, counter, requests) = (responses.clone(), counter.clone(), requests.clone());
+            async move {
+                requests.lock().unwrap().push(body);
+                tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
+                let idx = counter.fetch_add(1, Ordering::SeqCst).min(responses.len() - 1);
+                axum::Json(responses[idx].clone())
+            }
+        }),
+    );
+    (format!("{}/v1/chat/completions", serve(app).await), recorded)
+}
+
+/// One entry of a completion's `tool_calls`.
+pub fn tool_call(id: &str, name: &str, arguments: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "id": id,
+        "type": "function",
+        "function": {"name": name, "arguments": arguments.to_string()}
+    })
+}
+
+/// A completion making every call in `calls`.
+pub fn tool_calls_reply(calls: Vec<serde_json::Value>) -> serde_json::Value {
+    serde_json::json!({
+        "choices": [{
+            "message": {"role": "assistant", "content": null, "tool_calls": calls},
+            "finish_reason": "tool_calls"
+        }]
+    })
+}
+
+/// A completion making one tool call; `arguments` is the call's JSON text.
+pub fn tool_call_reply(id: &str, name: &str, arguments: &str) -> serde_json::Value {
+    tool_calls_reply(vec![serde_json::json!({
+        "id": id,
+        "type": "function",
+        "function": {"name": name, "arguments": arguments}
+    })])
+}
@@ -0,0 +1,200 @@
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, UnixTime};
+use rustls::server::danger::ClientCertVerifier;
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+
+use crate::config::{shellexpand, Config};
+
+/// Identity of a client authenticated by a verified TLS client certificate.
+/// Inserted as a request extension on mTLS connections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIdentity {
+    /// Certificate subject, e.g. "CN=ops-laptop, O=Example"
+    pub subject: String,
+}
+
+/// Whether the config asks for TLS termination in the keeper.
+pub fn enabled(config: &Config) -> bool {
+    config.tls_cert_path.is_some() || config.tls_key_path.is_some()
+}
+
+/// Build the rustls server config from the configured cert, key and optional
+/// client-CA bundle. With a client CA, every connection must present a
+/// certificate chaining to it.
+pub fn server_config(config: &Config) -> Result<Arc<ServerConfig>, String> {
+    let (Some(cert_path), Some(key_path)) = (&config.tls_cert_path, &config.tls_key_path) else {
+        return Err("TLS requires both tls_cert_path and tls_key_path".to_string());
+    };
+
+    let certs = load_certs(&shellexpand(cert_path))?;
+    let key = PrivateKeyDer::from_pem_file(shellexpand(key_path))
+        .map_err(|e| format!("Failed to read TLS key {}: {}", key_path, e))?;
+
+    let builder = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("TLS protocol setup failed: {}", e))?;
+
+    let builder = match config.tls_client_ca_path {
+        Some(ref ca_path) => {
+            let ca_certs = load_certs(&shellexpand(ca_path))?;
+            builder.with_client_cert_verifier(client_verifier(ca_certs)?)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server = builder
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("Invalid TLS certificate/key: {}", e))?;
+    server.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(server))
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read certificates from {}: {}", path, e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", path));
+    }
+    Ok(certs)
+}
+
+/// Build a verifier that requires client certificates issued by `ca_certs`.
+pub fn client_verifier(
+    ca_certs: Vec<CertificateDer<'static>>,
+) -> Result<Arc<dyn ClientCertVerifier>, String> {
+    let mut roots = RootCertStore::empty();
+    for cert in ca_certs {
+        roots
+            .add(cert)
+            .map_err(|e| format!("Invalid client CA certificate: {}", e))?;
+    }
+    WebPkiClientVerifier::builder_with_provider(
+        Arc::new(roots),
+        Arc::new(rustls::crypto::ring::default_provider()),
+    )
+    .build()
+    .map_err(|e| format!("Failed to build client verifier: {}", e))
+}
+
+/// Verify a client certificate chain (end entity first) against `verifier`
+/// and return the authenticated identity.
+pub fn verify_client_cert(
+    verifier: &dyn ClientCertVerifier,
+    chain: &[CertificateDer<'_>],
+) -> Result<ClientIdentity, String> {
+    let (end_entity, intermediates) = chain
+        .split_first()
+        .ok_or_else(|| "No client certificate presented".to_string())?;
+    verifier
+        .verify_client_cert(end_entity, intermediates, UnixTime::now())
+        .map_err(|e| format!("Client certificate rejected: {}", e))?;
+    client_identity(end_entity)
+}
+
+/// Extract the subject of an (already verified) client certificate.
+pub fn client_identity(cert: &CertificateDer<'_>) -> Result<ClientIdentity, String> {
+    let (_, parsed) = x509_parser::parse_x509_certificate(cert.as_ref())
+        .map_err(|e| format!("Failed to parse client certificate: {}", e))?;
+    Ok(ClientIdentity {
+        subject: parsed.subject().to_string(),
+    })
+}
+
+/// Serve `app` over TLS. Verified client certificates are exposed to
+/// handlers as a `ClientIdentity` request extension.
+pub async fn serve(listener: TcpListener, app: Router, tls: Arc<ServerConfig>) {
+    let acceptor = TlsAcceptor::from(tls);
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                tracing::warn!("TLS accept failed: {}", e);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(s) => s,
+                Err(e) => {
+                    tracing::debug!("TLS handshake with {} failed: {}", peer, e);
+                    return;
+                }
+            };
+
+            let identity = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(|cert| client_identity(cert).ok());
+            if let Some(ref id) = identity {
+                tracing::debug!("mTLS client {} authenticated as {}", peer, id.subject);
+            }
+
+            let service = hyper::service::service_fn(move |mut req: hyper::Request<hyper::body::Incoming>| {
+                if let Some(ref id) = identity {
+                    req.extensions_mut().insert(id.clone());
+                }
+                app.clone().oneshot(req)
+            });
+
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!("TLS connection with {} ended: {}", peer, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa};
+
+    fn test_ca(name: &str) -> Certificate {
+        let mut params = CertificateParams::new(vec![]);
+        params.distinguished_name.push(DnType::CommonName, name);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        Certificate::from_params(params).unwrap()
+    }
+
+    fn client_cert(ca: &Certificate, common_name: &str) -> CertificateDer<'static> {
+        let mut params = CertificateParams::new(vec![]);
+        params.distinguished_name.push(DnType::CommonName, common_name);
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        let cert = Certificate::from_params(params).unwrap();
+        CertificateDer::from(cert.serialize_der_with_signer(ca).unwrap())
+    }
+
+    #[test]
+    fn client_cert_verification_against_test_ca() {
+        let ca = test_ca("Siphon Test CA");
+        let ca_der = CertificateDer::from(ca.serialize_der().unwrap());
+        let verifier = client_verifier(vec![ca_der]).unwrap();
+
+        let good = client_cert(&ca, "ops-laptop");
+        let identity = verify_client_cert(verifier.as_ref(), &[good]).unwrap();
+        assert_eq!(identity.subject, "CN=ops-laptop");
+
+        let rogue_ca = test_ca("Rogue CA");
+        let rogue = client_cert(&rogue_ca, "intruder");
+        assert!(verify_client_cert(verifier.as_ref(), &[rogue]).is_err());
+
+        assert!(verify_client_cert(verifier.as_ref(), &[]).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+use tiktoken_rs::CoreBPE;
+
+/// Estimate how many tokens `text` costs for `model`.
+///
+/// OpenAI models are counted exactly with their BPE vocabulary. Other models
+/// (Llama, Mistral, Qwen, ...) use tokenizers we don't ship, so they fall back
+/// to a character/word heuristic that tends to overestimate slightly.
+pub fn estimate(text: &str, model: &str) -> usize {
+    if text.is_empty() {
+        return 0;
+    }
+    match bpe_for(model) {
+        Some(bpe) => bpe.encode_with_special_tokens(text).len(),
+        None => heuristic(text),
+    }
+}
+
+fn bpe_for(model: &str) -> Option<&'static CoreBPE> {
+    let model = model.to_ascii_lowercase();
+    // Strip router prefixes such as "openai/gpt-4o"
+    let name = model.rsplit('/').next().unwrap_or(&model);
+    let tokenizer = get_tokenizer(name).or_else(|| {
+        (name.starts_with("gpt-5") || name == "o1" || name == "o3" || name == "o4-mini")
+            .then_some(Tokenizer::O200kBase)
+    })?;
+    match tokenizer {
+        Tokenizer::O200kBase => Some(tiktoken_rs::o200k_base_singleton()),
+        Tokenizer::Cl100kBase => Some(tiktoken_rs::cl100k_base_singleton()),
+        Tokenizer::P50kBase => Some(tiktoken_rs::p50k_base_singleton()),
+        Tokenizer::P50kEdit => Some(tiktoken_rs::p50k_edit_singleton()),
+        Tokenizer::R50kBase | Tokenizer::Gpt2 => Some(tiktoken_rs::r50k_base_singleton()),
+    }
+}
+
+/// Roughly four characters per token for English text, but never fewer
+/// tokens than words plus standalone punctuation.
+fn heuristic(text: &str) -> usize {
+    let by_chars = text.chars().count().div_ceil(4);
+    let by_words = text.split_whitespace().count()
+        + text.chars().filter(|c| c.is_ascii_punctuation()).count();
+    by_chars.max(by_words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn openai_models_use_exact_bpe_counts() {
+        assert_eq!(estimate("hello world", "gpt-4o-mini"), 2);
+        assert_eq!(estimate("Hello, world!", "gpt-4o"), 4);
+        assert_eq!(estimate("Hello, world!", "gpt-4"), 4);
+        assert_eq!(estimate("Hello, world!", "openai/gpt-4o-mini"), 4);
+        assert_eq!(estimate("", "gpt-4o"), 0);
+    }
+
+    #[test]
+    fn heuristic_is_close_for_other_models() {
+        let samples = [
+            "The quick brown fox jumps over the lazy dog.",
+            "Fix the bug in this Python function so it returns the sum of a list.",
+            "Compare bubble sort and merge sort by time complexity, then pick one for 10,000 items.",
+        ];
+        for sample in samples {
+            let exact = estimate(sample, "gpt-4") as f64;
+            let rough = estimate(sample, "llama3.2") as f64;
+            let error = (rough - exact).abs() / exact;
+            assert!(error <= 0.35, "{}: exact {} vs heuristic {}", sample, exact, rough);
+        }
+    }
+}